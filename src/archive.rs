@@ -0,0 +1,148 @@
+use crate::chunker::{self, Chunk, ChunkError};
+use anyhow::Result;
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Chunk every supported file in a `.tar` or `.tar.gz` archive without extracting it.
+/// Entry paths are joined onto `root_dir` after dropping `strip_components` leading
+/// components (like `tar --strip-components`), so chunks line up with a checkout at `root_dir`.
+pub fn chunk_archive<R: Read>(
+    reader: R,
+    root_dir: &str,
+    strip_components: usize,
+) -> Result<Vec<Chunk>> {
+    let mut reader = BufReader::new(reader);
+
+    // Sniff the gzip header rather than trusting the file extension
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        chunk_tar(GzDecoder::new(reader), root_dir, strip_components)
+    } else {
+        chunk_tar(reader, root_dir, strip_components)
+    }
+}
+
+pub fn chunk_archive_file(
+    archive_path: &str,
+    root_dir: &str,
+    strip_components: usize,
+) -> Result<Vec<Chunk>> {
+    chunk_archive(File::open(archive_path)?, root_dir, strip_components)
+}
+
+fn chunk_tar<R: Read>(reader: R, root_dir: &str, strip_components: usize) -> Result<Vec<Chunk>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut chunks = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        // Ignore `./` and `..` components so entries can't escape root_dir
+        let relative_path: PathBuf = entry
+            .path()?
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .skip(strip_components)
+            .collect();
+        if relative_path.as_os_str().is_empty() {
+            continue;
+        }
+
+        // Same size limits as chunk_file
+        let size = entry.size();
+        if !chunker::is_indexed_size(size, crate::config::SETTINGS.get()) {
+            continue;
+        }
+
+        let mut bytes = Vec::with_capacity(size as usize);
+        entry.read_to_end(&mut bytes)?;
+        let Ok(content) = std::str::from_utf8(&bytes) else {
+            continue; // Skip binary files
+        };
+
+        // Archives only record modification time
+        let mtime = entry.header().mtime().unwrap_or(0);
+        let path = Path::new(root_dir).join(&relative_path);
+        match chunker::chunk_with_timestamps(content, &path, mtime, mtime) {
+            Ok(file_chunks) => chunks.extend(file_chunks),
+            Err(ChunkError::UnsupportedExtension(_)) => {}
+            Err(e) => crate::warnln!("Error processing {}: {}", relative_path.display(), e),
+        }
+    }
+
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    fn build_tar(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(1_700_000_000);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_chunk_archive_tar() {
+        let tar = build_tar(&[
+            ("src/lib.rs", "fn hello() {\n    println!(\"hi\");\n}\n"),
+            ("scripts/run.py", "def run():\n    pass\n"),
+            ("README.txt", "not indexed"),
+        ]);
+
+        let chunks = chunk_archive(tar.as_slice(), "/checkout", 0).unwrap();
+        assert_eq!(chunks.len(), 2);
+
+        let rust_chunk = chunks
+            .iter()
+            .find(|c| c.path == "/checkout/src/lib.rs")
+            .expect("Rust file should be chunked");
+        assert_eq!(rust_chunk.start_line, 1);
+        assert_eq!(rust_chunk.end_line, 3);
+        assert_eq!(rust_chunk.file_mtime, 1_700_000_000);
+        assert!(rust_chunk.content.as_ref().unwrap().contains("fn hello"));
+
+        assert!(chunks.iter().any(|c| c.path == "/checkout/scripts/run.py"));
+    }
+
+    #[test]
+    fn test_chunk_archive_gzip_with_strip_components() {
+        let tar = build_tar(&[("repo-1234/src/main.rs", "fn main() {}\n")]);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        std::io::Write::write_all(&mut encoder, &tar).unwrap();
+        let tar_gz = encoder.finish().unwrap();
+
+        let chunks = chunk_archive(tar_gz.as_slice(), "/checkout", 1).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].path, "/checkout/src/main.rs");
+    }
+
+    #[test]
+    fn test_chunk_archive_matches_checkout_ids() {
+        // Chunks from an archive should diff cleanly against the same files on disk
+        let content = "fn same() {}\n";
+        let tar = build_tar(&[("same.rs", content)]);
+        let from_archive = chunk_archive(tar.as_slice(), "/checkout", 0).unwrap();
+        let from_disk =
+            chunker::chunk_with_timestamps(content, Path::new("/checkout/same.rs"), 0, 0).unwrap();
+        assert_eq!(from_archive[0].id, from_disk[0].id);
+    }
+}
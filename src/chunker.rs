@@ -1,24 +1,27 @@
 use anyhow::Result;
-use globset::{Glob, GlobSet, GlobSetBuilder};
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
 use ignore::WalkBuilder;
 use ignore::types::{FileTypeDef, TypesBuilder};
 use num_cpus;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::Path;
-use std::sync::{Arc, Mutex, OnceLock};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 use std::time::Instant;
 use tree_sitter::{Language, Node, Parser, Query, QueryCursor, Tree};
 use xxhash_rust::xxh3::xxh3_64;
 
-/// Extracts function content with preceding comments.
+/// Extracts function content with preceding comments (and, in Julia, docstrings).
 /// Returns the combined text (comments + function) but with minimal allocations.
 /// The content includes preceding comments, but metadata should be about the function only.
 pub fn extract_function_with_comments<'a>(
     tree: &Tree,
     function_node: Node,
     source: &'a str,
+    lang_name: &str,
 ) -> &'a str {
     let function_start_byte = function_node.start_byte();
     let function_end_byte = function_node.end_byte();
@@ -58,7 +61,9 @@ pub fn extract_function_with_comments<'a>(
                     | "block_comment"
                     | "doc_comment"
                     | "documentation_comment"
-            ) {
+                    | "js_comment"
+            ) || (lang_name == "julia" && is_docstring(*node, source))
+            {
                 let comment_start_line = node.start_position().row;
                 let comment_end_line = node.end_position().row;
 
@@ -91,6 +96,31 @@ pub fn extract_function_with_comments<'a>(
     result
 }
 
+/// Name of the function or type defined at `node`, e.g. `parse` for `fn parse()`, `Point`
+/// for `impl Display for Point`, or `handler` for `const handler = function () {}`
+fn symbol_name(node: Node, source: &str) -> Option<String> {
+    let node = match node.child_by_field_name("definition") {
+        Some(definition) if node.kind() == "decorated_definition" => definition,
+        _ => node,
+    };
+    let name = node
+        .child_by_field_name("name")
+        // Rust impl blocks are named by the type they implement for
+        .or_else(|| (node.kind() == "impl_item").then(|| node.child_by_field_name("type"))?)
+        // Anonymous JS/TS functions by the variable they're assigned to
+        .or_else(|| {
+            node.parent()
+                .filter(|parent| parent.kind() == "variable_declarator")?
+                .child_by_field_name("name")
+        })?;
+    Some(source[name.byte_range()].to_string())
+}
+
+/// Julia docstrings are plain triple-quoted strings placed right before the definition
+fn is_docstring(node: Node, source: &str) -> bool {
+    node.kind() == "string_literal" && source[node.byte_range()].starts_with("\"\"\"")
+}
+
 /// Markdown specific extraction that keeps the surrounding header in context of each paragraph chunk
 fn extract_paragraph_with_heading<'a>(
     paragraph_node: Node,
@@ -170,25 +200,141 @@ pub enum ChunkError {
     UnsupportedExtension(String),
     #[error("Parse error: {0}")]
     ParseFailed(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("no supported source files found in {0}; run with --help to see supported languages")]
+    NoSupportedFiles(String),
 }
 
+// Every attribute but the optional ones must be present to deserialize; rows of queries
+// that don't ask for all of them are completed by `turbopuffer::parse_row`
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Chunk {
-    pub id: u64, // xxhash of "path:start_line:end_line:chunk_hash"
+    // xxhash of "path:start_line:end_line:file_hash:chunk_hash", see [`IdHash`]
+    #[serde(with = "row_id")]
+    pub id: u128,
     pub vector: Option<Vec<f32>>,
     // TODO: should be obfuscated for prod, we don't want to store paths
     pub path: String,
     pub start_line: u32,
     pub end_line: u32,
+    // Byte offsets of the chunk's node in the file, None for chunks indexed before they
+    // were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_byte: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_byte: Option<usize>,
     pub file_hash: u64,  // xxhash of file content
     pub chunk_hash: u64, // xxhash of chunk content
     pub file_mtime: u64, // File modification time (Unix timestamp)
     pub file_ctime: u64, // File creation time (Unix timestamp)
-    // Content is kept locally but not stored on server for privacy
+    // Git commit the chunk was indexed at, only recorded with --record-commit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    // What sort of definition the chunk is, None for chunks indexed before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<ChunkKind>,
+    // Name of the function or type the chunk defines, e.g. `parse` for `fn parse()`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    // Content is kept locally, not stored on server for privacy (unless --store-content)
     pub content: Option<String>,
     // Distance score from similarity search (lower is better, None if not from search)
     #[serde(rename = "$dist")]
     pub distance: Option<f64>,
+    // Cross-encoder relevance from --rerank (higher is better), never stored
+    #[serde(skip, default)]
+    pub rerank_score: Option<f64>,
+    // Vectors of the config's extra `embedding_models`, by vector column
+    #[serde(skip, default)]
+    pub extra_vectors: BTreeMap<String, Vec<f32>>,
+}
+
+/// What sort of definition a chunk is, told from its tree-sitter node's kind so that one
+/// set of names covers every grammar (`struct_item`, `struct_specifier`, ...)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkKind {
+    Function,
+    /// A function defined in a class, impl or similar
+    Method,
+    /// A struct, enum, union or type definition
+    Struct,
+    /// A class, interface, trait, impl or module
+    Class,
+    /// A definition's leading comment block, see the `index_comments` setting
+    Comment,
+    /// Anything else, e.g. a markdown section
+    #[serde(other)]
+    Other,
+}
+
+impl ChunkKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Function => "function",
+            Self::Method => "method",
+            Self::Struct => "struct",
+            Self::Class => "class",
+            Self::Comment => "comment",
+            Self::Other => "other",
+        }
+    }
+
+    /// Kind of a definition named `node_kind` in its grammar, ignoring where it's defined
+    fn of_node_kind(node_kind: &str) -> Self {
+        let has = |part: &str| node_kind.contains(part);
+        if has("method") || has("constructor") {
+            Self::Method
+        } else if has("function") || has("lambda") || has("arrow") {
+            Self::Function
+        } else if has("struct")
+            || has("enum")
+            || has("union")
+            || has("record")
+            || node_kind.starts_with("type_")
+        {
+            Self::Struct
+        } else if has("class") || has("interface") || has("trait") || has("impl") || has("module") {
+            Self::Class
+        } else {
+            Self::Other
+        }
+    }
+
+    /// Kind of the definition at `node`: functions inside a class-like definition are
+    /// methods, and decorated definitions take the kind of what they decorate
+    fn of_node(node: Node) -> Self {
+        let node = match node.child_by_field_name("definition") {
+            Some(definition) if node.kind() == "decorated_definition" => definition,
+            _ => node,
+        };
+        let kind = Self::of_node_kind(node.kind());
+        let in_class = std::iter::successors(node.parent(), Node::parent)
+            .any(|ancestor| Self::of_node_kind(ancestor.kind()) == Self::Class);
+        match kind {
+            Self::Function if in_class => Self::Method,
+            kind => kind,
+        }
+    }
+}
+
+impl std::str::FromStr for ChunkKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "function" => Ok(Self::Function),
+            "method" => Ok(Self::Method),
+            "struct" => Ok(Self::Struct),
+            "class" => Ok(Self::Class),
+            "comment" => Ok(Self::Comment),
+            "other" => Ok(Self::Other),
+            _ => Err(format!(
+                "unknown kind '{s}', expected function, method, struct, class, comment or other"
+            )),
+        }
+    }
 }
 
 struct FiletypeMatcher {
@@ -197,124 +343,378 @@ struct FiletypeMatcher {
 }
 
 impl FiletypeMatcher {
-    fn detect_language(&self, path: &Path) -> Option<(&'static str, Language, &'static str)> {
+    /// Language for `path`. A project's `.editorconfig` overrides take precedence over
+    /// ripgrep's default types. Extensions shared between languages (`.m` is Objective-C
+    /// and MATLAB, `.h` is Objective-C and C) are disambiguated by `content` when given;
+    /// without it any candidate counts.
+    fn detect_language(
+        &self,
+        path: &Path,
+        content: Option<&str>,
+    ) -> Option<(&'static str, Language, &'static str)> {
+        if let Some(language) = overridden_language(path) {
+            return Some(language);
+        }
+
         let filename = path.file_name()?.to_str()?;
         let matches = self.glob_set.matches(filename).into_iter();
 
         // Check matches in order of precedence (last match wins, like ripgrep)
-        for match_idx in matches.rev() {
-            let def = &self.index_to_def[match_idx];
-
-            match def.name() {
-                "rust" => {
-                    return Some((
-                        "rust",
-                        tree_sitter_rust::LANGUAGE.into(),
-                        r#"
-                        (function_item) @function
-                        (struct_item) @function
-                        (impl_item) @function
-                        "#,
-                    ));
-                }
-                // The default definitio holds multiple definitions for shorthands, we don't really
-                // know which one wins.
-                "py" | "python" => {
-                    return Some((
-                        "python",
-                        tree_sitter_python::LANGUAGE.into(),
-                        r#"
-                        (function_definition) @function
-                        "#,
-                    ));
-                }
-                "js" => {
-                    return Some((
-                        "js",
-                        tree_sitter_javascript::LANGUAGE.into(),
-                        r#"
-                        (function_declaration) @function
-                        (function_expression) @function
-                        "#,
-                    ));
-                }
-                "ts" | "typescript" => {
-                    return Some((
-                        "ts",
-                        tree_sitter_typescript::LANGUAGE_TSX.into(),
-                        r#"
-                        (function_declaration) @function
-                        (function_expression) @function
-                        "#,
-                    ));
-                }
-                "go" => {
-                    return Some((
-                        "go",
-                        tree_sitter_go::LANGUAGE.into(),
-                        r#"
-                        (function_declaration) @function
-                        (method_declaration) @function
-                        "#,
-                    ));
-                }
-                "java" => {
-                    return Some((
-                        "java",
-                        tree_sitter_java::LANGUAGE.into(),
-                        "(method_declaration) @function",
-                    ));
-                }
-                "c" => {
-                    return Some((
-                        "c",
-                        tree_sitter_c::LANGUAGE.into(),
-                        "(function_definition) @function",
-                    ));
-                }
-                "cpp" => {
-                    return Some((
-                        "cpp",
-                        tree_sitter_cpp::LANGUAGE.into(),
-                        "(function_definition) @function",
-                    ));
-                }
-                "ruby" => {
-                    return Some((
-                        "ruby",
-                        tree_sitter_ruby::LANGUAGE.into(),
-                        r#"
-                        (method) @function
-                        (singleton_method) @function
-                        "#,
-                    ));
-                }
-                "bash" | "sh" => {
-                    return Some((
-                        "bash",
-                        tree_sitter_bash::LANGUAGE.into(),
-                        "(function_definition) @function",
-                    ));
-                }
-                "md" | "markdown" => {
-                    return Some((
-                        "markdown",
-                        tree_sitter_md::LANGUAGE.into(),
-                        r#"
-                        (fenced_code_block) @function
-                        (list) @function
-                        (paragraph) @function
-                        "#,
-                    ));
-                }
-                _ => continue,
+        matches
+            .rev()
+            .map(|match_idx| self.index_to_def[match_idx].name())
+            .filter(|type_name| content.is_none_or(|content| content_matches(type_name, content)))
+            .find_map(language_for_type)
+    }
+}
+
+/// Per-project file type overrides, read from `turbogrep_language` properties in the
+/// project's `.editorconfig`:
+///
+/// ```ini
+/// [*.inc]
+/// turbogrep_language = c
+/// ```
+///
+/// Values are ripgrep type names (`c`, `cpp`, `py`, `rust`, ...). As in editorconfig, a
+/// section glob without `/` matches file names anywhere in the project, and later
+/// sections win. Other editorconfig properties are ignored.
+#[derive(Debug, Default)]
+pub struct LanguageOverrides {
+    globs: GlobSet,
+    type_names: Vec<String>,
+}
+
+impl LanguageOverrides {
+    pub fn from_editorconfig(content: &str) -> Self {
+        let mut builder = GlobSetBuilder::new();
+        let mut type_names = Vec::new();
+        let mut section: Option<String> = None;
+
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = Some(header.to_string());
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            if !key.trim().eq_ignore_ascii_case("turbogrep_language") {
+                continue;
+            }
+            let Some(pattern) = &section else {
+                continue;
+            };
+            let pattern = match pattern.strip_prefix('/') {
+                Some(anchored) => anchored.to_string(),
+                None if pattern.contains('/') => pattern.clone(),
+                None => format!("**/{pattern}"),
+            };
+            if let Ok(glob) = GlobBuilder::new(&pattern).literal_separator(true).build() {
+                builder.add(glob);
+                type_names.push(value.trim().to_ascii_lowercase());
             }
         }
 
-        None
+        Self {
+            globs: builder.build().unwrap_or_default(),
+            type_names,
+        }
+    }
+
+    /// Overrides from `root_dir/.editorconfig`, or none when there isn't one
+    pub fn load(root_dir: &Path) -> Self {
+        fs::read_to_string(root_dir.join(".editorconfig"))
+            .map(|content| Self::from_editorconfig(&content))
+            .unwrap_or_default()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.type_names.is_empty()
+    }
+
+    /// Type name forced for `relative_path`, if any section matches it
+    fn type_for(&self, relative_path: &Path) -> Option<&str> {
+        let index = self.globs.matches(relative_path).into_iter().max()?;
+        Some(&self.type_names[index])
+    }
+}
+
+/// Overrides of every project walked so far, by project root
+static LANGUAGE_OVERRIDES: RwLock<Vec<(PathBuf, LanguageOverrides)>> = RwLock::new(Vec::new());
+
+/// Load the `.editorconfig` overrides for `root_dir` so files under it are detected with them
+pub fn register_language_overrides(root_dir: &Path) {
+    let overrides = LanguageOverrides::load(root_dir);
+    let mut registered = LANGUAGE_OVERRIDES.write().unwrap();
+    registered.retain(|(root, _)| root != root_dir);
+    if !overrides.is_empty() {
+        registered.push((root_dir.to_path_buf(), overrides));
+    }
+}
+
+/// Language forced for `path` by its project's overrides. Overrides naming a type
+/// turbogrep can't parse are ignored.
+fn overridden_language(path: &Path) -> Option<(&'static str, Language, &'static str)> {
+    let registered = LANGUAGE_OVERRIDES.read().unwrap();
+    registered
+        .iter()
+        .filter_map(|(root, overrides)| Some((path.strip_prefix(root).ok()?, overrides)))
+        .min_by_key(|(relative, _)| relative.components().count())
+        .and_then(|(relative, overrides)| overrides.type_for(relative))
+        .and_then(language_for_type)
+}
+
+/// Whether `content` plausibly is of the ripgrep file type, for types whose extensions
+/// are shared with other languages
+fn content_matches(type_name: &str, content: &str) -> bool {
+    match type_name {
+        "objc" | "objcpp" => looks_like_objc(content),
+        _ => true,
+    }
+}
+
+/// Objective-C files almost always have an `@interface`/`@implementation`/`@protocol`
+/// block or an `#import`, none of which appear in C headers or MATLAB
+fn looks_like_objc(content: &str) -> bool {
+    content.lines().map(str::trim_start).any(|line| {
+        ["@interface", "@implementation", "@protocol", "#import"]
+            .iter()
+            .any(|keyword| line.starts_with(keyword))
+    })
+}
+
+/// Language, grammar and chunk query for a ripgrep file type name
+fn language_for_type(type_name: &str) -> Option<(&'static str, Language, &'static str)> {
+    match type_name {
+        "rust" => Some((
+            "rust",
+            tree_sitter_rust::LANGUAGE.into(),
+            r#"
+            (function_item) @function
+            (struct_item) @function
+            (enum_item) @function
+            (trait_item) @function
+            (impl_item) @function
+            "#,
+        )),
+        // The default definitio holds multiple definitions for shorthands, we don't really
+        // know which one wins.
+        "py" | "python" => Some((
+            "python",
+            tree_sitter_python::LANGUAGE.into(),
+            r#"
+            (function_definition) @function
+            "#,
+        )),
+        "js" => Some((
+            "js",
+            tree_sitter_javascript::LANGUAGE.into(),
+            r#"
+            (function_declaration) @function
+            (function_expression) @function
+            "#,
+        )),
+        "ts" | "typescript" => Some((
+            "ts",
+            tree_sitter_typescript::LANGUAGE_TSX.into(),
+            r#"
+            (function_declaration) @function
+            (function_expression) @function
+            "#,
+        )),
+        "go" => Some((
+            "go",
+            tree_sitter_go::LANGUAGE.into(),
+            r#"
+            (function_declaration) @function
+            (method_declaration) @function
+            "#,
+        )),
+        "java" => Some((
+            "java",
+            tree_sitter_java::LANGUAGE.into(),
+            "(method_declaration) @function",
+        )),
+        "cs" | "csharp" => Some((
+            "csharp",
+            tree_sitter_c_sharp::LANGUAGE.into(),
+            r#"
+            (method_declaration) @function
+            (constructor_declaration) @function
+            (class_declaration) @function
+            "#,
+        )),
+        "c" => Some((
+            "c",
+            tree_sitter_c::LANGUAGE.into(),
+            "(function_definition) @function",
+        )),
+        "cpp" => Some((
+            "cpp",
+            tree_sitter_cpp::LANGUAGE.into(),
+            "(function_definition) @function",
+        )),
+        "ruby" => Some((
+            "ruby",
+            tree_sitter_ruby::LANGUAGE.into(),
+            r#"
+            (method) @function
+            (singleton_method) @function
+            "#,
+        )),
+        "php" => Some((
+            "php",
+            tree_sitter_php::LANGUAGE_PHP.into(),
+            r#"
+            (function_definition) @function
+            (method_declaration) @function
+            "#,
+        )),
+        // Methods sit in an `implementation_definition`, which their leading comments
+        // are siblings of
+        "objc" | "objcpp" => Some((
+            "objc",
+            tree_sitter_objc::LANGUAGE.into(),
+            r#"
+            (implementation_definition (method_definition)) @function
+            (class_interface) @function
+            (class_implementation) @function
+            "#,
+        )),
+        // Definitions are plain lists, so match top-level forms on their head symbol
+        "clojure" => Some((
+            "clojure",
+            tree_sitter_clojure::LANGUAGE.into(),
+            r#"
+            (source
+              (list_lit
+                .
+                (sym_lit) @_head
+                (#any-of? @_head "defn" "defn-" "def" "defmethod")) @function)
+            "#,
+        )),
+        // Covers SCSS too: mixins and other at-rules parse as generic `at_rule`s
+        "css" | "scss" => Some((
+            "css",
+            tree_sitter_css::LANGUAGE.into(),
+            r#"
+            (stylesheet (rule_set) @function)
+            (stylesheet (media_statement) @function)
+            (stylesheet (supports_statement) @function)
+            (stylesheet (keyframes_statement) @function)
+            (stylesheet (at_rule) @function)
+            "#,
+        )),
+        // Chunk by the elements that usually hold a page's distinct parts, at any depth
+        "html" => Some((
+            "html",
+            tree_sitter_html::LANGUAGE.into(),
+            r#"
+            (element
+              (start_tag (tag_name) @_tag)
+              (#match? @_tag "^(?i)(section|template|form|article|main|nav|header|footer|aside|dialog|table)$")) @function
+            (script_element) @function
+            "#,
+        )),
+        // Short-form `f(x) = ...` definitions parse as an assignment to a call
+        "julia" => Some((
+            "julia",
+            tree_sitter_julia::LANGUAGE.into(),
+            r#"
+            (function_definition) @function
+            (assignment . (call_expression)) @function
+            (struct_definition) @function
+            (module_definition) @function
+            "#,
+        )),
+        "bash" | "sh" => Some((
+            "bash",
+            tree_sitter_bash::LANGUAGE.into(),
+            "(function_definition) @function",
+        )),
+        "md" | "markdown" => Some((
+            "markdown",
+            tree_sitter_md::LANGUAGE.into(),
+            r#"
+            (fenced_code_block) @function
+            (list) @function
+            (paragraph) @function
+            "#,
+        )),
+        _ => None,
+    }
+}
+
+/// Ripgrep file type for the interpreter on a `#!` line, e.g. `#!/usr/bin/env python3`
+fn shebang_type(first_line: &str) -> Option<&'static str> {
+    let command = first_line.strip_prefix("#!")?;
+    let mut words = command.split_whitespace();
+    let mut interpreter = Path::new(words.next()?).file_name()?.to_str()?;
+    if interpreter == "env" {
+        // Skip env flags such as `-S`
+        interpreter = words.find(|word| !word.starts_with('-'))?;
+    }
+    // python3.11 -> python, node -> node
+    let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+    match interpreter {
+        "python" => Some("py"),
+        "node" | "nodejs" | "deno" | "bun" => Some("js"),
+        "ruby" => Some("ruby"),
+        "bash" | "sh" | "zsh" | "dash" | "ksh" => Some("sh"),
+        _ => None,
+    }
+}
+
+/// Language of an extensionless script, from the interpreter on its first line
+fn detect_shebang_language(content: &str) -> Option<(&'static str, Language, &'static str)> {
+    language_for_type(shebang_type(content.lines().next()?)?)
+}
+
+/// Whether a file the filename rules don't recognise is a script with a known shebang.
+/// Only files without an extension are read.
+fn has_known_shebang(path: &Path) -> bool {
+    use std::io::Read;
+
+    if path.extension().is_some() {
+        return false;
+    }
+    let mut head = [0u8; 256];
+    let read = fs::File::open(path).and_then(|mut file| file.read(&mut head));
+    match read {
+        Ok(n) => detect_shebang_language(&String::from_utf8_lossy(&head[..n])).is_some(),
+        Err(_) => false,
     }
 }
 
+/// Every tree-sitter grammar bundled into the binary, for diagnostics
+pub fn bundled_languages() -> Vec<(&'static str, Language)> {
+    vec![
+        ("rust", tree_sitter_rust::LANGUAGE.into()),
+        ("python", tree_sitter_python::LANGUAGE.into()),
+        ("javascript", tree_sitter_javascript::LANGUAGE.into()),
+        ("typescript", tree_sitter_typescript::LANGUAGE_TSX.into()),
+        ("go", tree_sitter_go::LANGUAGE.into()),
+        ("java", tree_sitter_java::LANGUAGE.into()),
+        ("csharp", tree_sitter_c_sharp::LANGUAGE.into()),
+        ("c", tree_sitter_c::LANGUAGE.into()),
+        ("cpp", tree_sitter_cpp::LANGUAGE.into()),
+        ("ruby", tree_sitter_ruby::LANGUAGE.into()),
+        ("php", tree_sitter_php::LANGUAGE_PHP.into()),
+        ("bash", tree_sitter_bash::LANGUAGE.into()),
+        ("clojure", tree_sitter_clojure::LANGUAGE.into()),
+        ("objc", tree_sitter_objc::LANGUAGE.into()),
+        ("css", tree_sitter_css::LANGUAGE.into()),
+        ("html", tree_sitter_html::LANGUAGE.into()),
+        ("julia", tree_sitter_julia::LANGUAGE.into()),
+        ("markdown", tree_sitter_md::LANGUAGE.into()),
+    ]
+}
+
 static FILETYPE_MATCHER: OnceLock<FiletypeMatcher> = OnceLock::new();
 
 // Avoid maintaining our own definition. A bit awkward to get the ones from the `ignore` crate
@@ -348,13 +748,267 @@ fn get_filetype_matcher() -> &'static FiletypeMatcher {
     })
 }
 
+/// Whether `path` is a Vue or Svelte single-file component, chunked by [`chunk_component`]
+/// rather than by a single grammar
+fn is_single_file_component(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("vue" | "svelte")
+    )
+}
+
+/// Top-level sections of a single-file component
+const COMPONENT_SECTIONS: &[&str] = &["script", "template", "style"];
+
+/// A top-level `<script>`, `<template>` or `<style>` section of a component
+struct ComponentSection<'a> {
+    tag: &'static str,
+    /// Attributes of the opening tag, e.g. `lang="ts" setup`
+    attributes: &'a str,
+    /// The whole section, from `<tag` to the end of `</tag>`
+    outer: Range<usize>,
+    /// Between the opening and the closing tag
+    inner: Range<usize>,
+}
+
+/// Opening `<tag` of a section at the start of `rest`, if any
+fn section_tag(rest: &str) -> Option<&'static str> {
+    COMPONENT_SECTIONS.iter().copied().find(|tag| {
+        rest.strip_prefix('<')
+            .and_then(|rest| rest.strip_prefix(tag))
+            .and_then(|rest| rest.chars().next())
+            .is_some_and(|c| c == '>' || c == '/' || c.is_whitespace())
+    })
+}
+
+/// Split a component into its top-level sections. Nested tags of the same name (Vue's
+/// `<template #slot>`) stay inside their parent; an unterminated section ends the scan.
+fn component_sections(content: &str) -> Vec<ComponentSection<'_>> {
+    let mut sections = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = content[pos..].find('<') {
+        let start = pos + offset;
+        let Some(tag) = section_tag(&content[start..]) else {
+            pos = start + 1;
+            continue;
+        };
+        let Some(open_len) = content[start..].find('>') else {
+            break;
+        };
+        let attributes = content[start + 1 + tag.len()..start + open_len].trim();
+        let inner_start = start + open_len + 1;
+        if attributes.ends_with('/') {
+            pos = inner_start;
+            continue;
+        }
+
+        let opening = format!("<{tag}");
+        let closing = format!("</{tag}");
+        let mut depth = 1;
+        let mut cursor = inner_start;
+        let mut inner_end = None;
+        while let Some(offset) = content[cursor..].find('<') {
+            let at = cursor + offset;
+            let rest = &content[at..];
+            if rest.starts_with(&closing) {
+                depth -= 1;
+                if depth == 0 {
+                    inner_end = Some(at);
+                    break;
+                }
+            } else if rest.starts_with(&opening) && section_tag(rest) == Some(tag) {
+                depth += 1;
+            }
+            cursor = at + 1;
+        }
+        let Some(inner_end) = inner_end else {
+            break;
+        };
+        let end = content[inner_end..]
+            .find('>')
+            .map_or(content.len(), |offset| inner_end + offset + 1);
+
+        sections.push(ComponentSection {
+            tag,
+            attributes,
+            outer: start..end,
+            inner: inner_start..inner_end,
+        });
+        pos = end;
+    }
+    sections
+}
+
+/// Chunk a Vue or Svelte component: functions in `<script>` sections are chunked by the
+/// JS/TS grammar, while `<template>` and `<style>` sections, and markup outside any
+/// section (Svelte's template), are kept as one block chunk each
+fn chunk_component(
+    content: &str,
+    file_path: &Path,
+    file_mtime: u64,
+    file_ctime: u64,
+    settings: Option<&crate::config::Settings>,
+) -> Result<Vec<Chunk>, ChunkError> {
+    let file_hash = xxh3_64(content.as_bytes());
+    let path_str = file_path.to_string_lossy();
+    let line_at = |byte: usize| content[..byte].matches('\n').count();
+
+    let block = |range: Range<usize>| {
+        let text = &content[range.clone()];
+        let chunk_hash = xxh3_64(text.as_bytes());
+        let (start_row, end_row) = (line_at(range.start), line_at(range.end));
+        Chunk {
+            id: chunk_id(&path_str, start_row, end_row, file_hash, chunk_hash),
+            path: path_str.to_string(),
+            start_line: start_row as u32 + 1,
+            end_line: end_row as u32 + 1,
+            start_byte: Some(range.start),
+            end_byte: Some(range.end),
+            file_hash,
+            chunk_hash,
+            file_mtime,
+            file_ctime,
+            content: Some(text.to_string()),
+            ..Default::default()
+        }
+    };
+    // Markup between sections, trimmed of surrounding whitespace
+    let markup = |range: Range<usize>| {
+        let text = &content[range.clone()];
+        let trimmed = text.trim();
+        (!trimmed.is_empty()).then(|| {
+            let start = range.start + (text.len() - text.trim_start().len());
+            block(start..start + trimmed.len())
+        })
+    };
+
+    let mut chunks = Vec::new();
+    let mut markup_start = 0;
+    for section in component_sections(content) {
+        chunks.extend(markup(markup_start..section.outer.start));
+        markup_start = section.outer.end;
+
+        if section.tag != "script" {
+            chunks.push(block(section.outer));
+            continue;
+        }
+        let is_ts = ["lang=\"ts\"", "lang='ts'", "lang=\"typescript\""]
+            .iter()
+            .any(|lang| section.attributes.contains(lang));
+        let language = language_for_type(if is_ts { "ts" } else { "js" })
+            .expect("JS and TS grammars are bundled");
+
+        // Chunk the script on its own, then place its chunks back in the whole file
+        let script = &content[section.inner.clone()];
+        let (row_offset, byte_offset) = (line_at(section.inner.start), section.inner.start);
+        let script_chunks = chunk_with_language(
+            script, file_path, language, file_mtime, file_ctime, settings,
+        )?;
+        for mut chunk in script_chunks {
+            chunk.start_line += row_offset as u32;
+            chunk.end_line += row_offset as u32;
+            chunk.start_byte = chunk.start_byte.map(|byte| byte + byte_offset);
+            chunk.end_byte = chunk.end_byte.map(|byte| byte + byte_offset);
+            chunk.file_hash = file_hash;
+            chunk.id = chunk_id(
+                &path_str,
+                chunk.start_line as usize - 1,
+                chunk.end_line as usize - 1,
+                file_hash,
+                chunk.chunk_hash,
+            );
+            chunks.push(chunk);
+        }
+    }
+    chunks.extend(markup(markup_start..content.len()));
+
+    Ok(chunks)
+}
+
 pub fn chunk(
     content: &str,
     file_path: &Path,
     metadata: std::fs::Metadata,
 ) -> Result<Vec<Chunk>, ChunkError> {
-    let (lang_name, language, query_str) = get_filetype_matcher()
-        .detect_language(file_path)
+    // Extract file timestamps first (cheaper than hashing)
+    let file_mtime = metadata
+        .modified()
+        .unwrap_or(std::time::UNIX_EPOCH)
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let file_ctime = metadata
+        .created()
+        .unwrap_or(std::time::UNIX_EPOCH)
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    chunk_with_timestamps(content, file_path, file_mtime, file_ctime)
+}
+
+/// Same as [`chunk`], for content that doesn't come from the local filesystem
+/// (e.g. archive entries) and so has no `std::fs::Metadata`
+pub fn chunk_with_timestamps(
+    content: &str,
+    file_path: &Path,
+    file_mtime: u64,
+    file_ctime: u64,
+) -> Result<Vec<Chunk>, ChunkError> {
+    chunk_with_settings(
+        content,
+        file_path,
+        file_mtime,
+        file_ctime,
+        crate::config::SETTINGS.get(),
+    )
+}
+
+/// Chunk of the outermost definition in `content` containing lines (1-based, inclusive)
+/// `start_line..=end_line`, e.g. the function a nested function's chunk sits in. `None`
+/// when the file can't be parsed or no definition contains the range.
+pub fn enclosing_definition(
+    content: &str,
+    file_path: &Path,
+    start_line: u32,
+    end_line: u32,
+) -> Option<Chunk> {
+    chunk_with_timestamps(content, file_path, 0, 0)
+        .ok()?
+        .into_iter()
+        .filter(|chunk| chunk.start_line <= start_line && chunk.end_line >= end_line)
+        .max_by_key(|chunk| chunk.end_line - chunk.start_line)
+}
+
+fn chunk_with_settings(
+    content: &str,
+    file_path: &Path,
+    file_mtime: u64,
+    file_ctime: u64,
+    settings: Option<&crate::config::Settings>,
+) -> Result<Vec<Chunk>, ChunkError> {
+    let normalized = normalize_line_endings(content.as_bytes(), settings);
+    let content = match &normalized {
+        Cow::Borrowed(_) => content,
+        Cow::Owned(bytes) => {
+            std::str::from_utf8(bytes).expect("dropping a CR before a LF keeps UTF-8 valid")
+        }
+    };
+
+    if is_single_file_component(file_path) {
+        return chunk_component(content, file_path, file_mtime, file_ctime, settings);
+    }
+
+    let language = get_filetype_matcher()
+        .detect_language(file_path, Some(content))
+        .or_else(|| {
+            file_path
+                .extension()
+                .is_none()
+                .then(|| detect_shebang_language(content))
+                .flatten()
+        })
         .ok_or_else(|| {
             let ext = file_path
                 .extension()
@@ -362,7 +1016,273 @@ pub fn chunk(
                 .unwrap_or("no extension");
             ChunkError::UnsupportedExtension(ext.to_string())
         })?;
+    chunk_with_language(
+        content, file_path, language, file_mtime, file_ctime, settings,
+    )
+}
 
+/// `content` with CRLF line endings turned into LF when the `normalize_line_endings`
+/// setting is on, so the same file hashes alike checked out on Windows and on Linux.
+/// Chunks' byte offsets are then into the normalized content.
+fn normalize_line_endings<'a>(
+    content: &'a [u8],
+    settings: Option<&crate::config::Settings>,
+) -> Cow<'a, [u8]> {
+    let normalize = settings
+        .and_then(|s| s.normalize_line_endings)
+        .unwrap_or(false);
+    if !normalize || !content.windows(2).any(|pair| pair == b"\r\n") {
+        return Cow::Borrowed(content);
+    }
+    let mut normalized = Vec::with_capacity(content.len());
+    for (i, &byte) in content.iter().enumerate() {
+        if !(byte == b'\r' && content.get(i + 1) == Some(&b'\n')) {
+            normalized.push(byte);
+        }
+    }
+    Cow::Owned(normalized)
+}
+
+/// How chunk IDs are hashed. 64-bit IDs are compact, but in indexes of many millions of
+/// chunks a collision becomes conceivable, and colliding chunks silently replace each other.
+/// Switching an existing index to another scheme needs a `--reset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdHash {
+    #[default]
+    Xxh3_64,
+    Xxh3_128,
+}
+
+static ID_HASH: OnceLock<IdHash> = OnceLock::new();
+
+impl IdHash {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "xxh3_64" => Some(Self::Xxh3_64),
+            "xxh3_128" => Some(Self::Xxh3_128),
+            _ => None,
+        }
+    }
+
+    /// The scheme from the config file, 64-bit unless it names a known one
+    pub fn from_settings(settings: Option<&crate::config::Settings>) -> Self {
+        let Some(name) = settings.and_then(|s| s.id_hash.as_deref()) else {
+            return Self::default();
+        };
+        Self::parse(name).unwrap_or_else(|| {
+            crate::warnln!("<(°!°)> Ignoring unknown id_hash {} from config", name);
+            Self::default()
+        })
+    }
+
+    /// The scheme chunks are identified with
+    pub fn active() -> Self {
+        *ID_HASH.get_or_init(|| Self::from_settings(crate::config::SETTINGS.get()))
+    }
+
+    /// Chunk ID: a hash of the path, the chunk's 0-based first and last rows, the file
+    /// hash AND the chunk content hash, so the ID changes when ANY part of the file changes
+    pub fn chunk_id(
+        self,
+        path: &str,
+        start_row: usize,
+        end_row: usize,
+        file_hash: u64,
+        chunk_hash: u64,
+    ) -> u128 {
+        let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+        hasher.update(path.as_bytes());
+        hasher.update(b":");
+        hasher.update(&start_row.to_le_bytes());
+        hasher.update(b":");
+        hasher.update(&end_row.to_le_bytes());
+        hasher.update(b":");
+        hasher.update(&file_hash.to_le_bytes());
+        hasher.update(b":");
+        hasher.update(&chunk_hash.to_le_bytes());
+        match self {
+            Self::Xxh3_64 => hasher.digest() as u128,
+            Self::Xxh3_128 => hasher.digest128(),
+        }
+    }
+}
+
+/// Chunk ID under the active [`IdHash`]
+pub fn chunk_id(
+    path: &str,
+    start_row: usize,
+    end_row: usize,
+    file_hash: u64,
+    chunk_hash: u64,
+) -> u128 {
+    IdHash::active().chunk_id(path, start_row, end_row, file_hash, chunk_hash)
+}
+
+/// turbopuffer row IDs are u64s or UUIDs: 64-bit IDs are sent as numbers, 128-bit ones
+/// as UUIDs. Either form is read back.
+pub mod row_id {
+    use super::IdHash;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn to_json(id: u128) -> serde_json::Value {
+        match (IdHash::active(), u64::try_from(id)) {
+            (IdHash::Xxh3_64, Ok(id)) => id.into(),
+            _ => to_uuid(id).into(),
+        }
+    }
+
+    fn to_uuid(id: u128) -> String {
+        let hex = format!("{id:032x}");
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+
+    pub fn serialize<S: Serializer>(id: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        match (IdHash::active(), u64::try_from(*id)) {
+            (IdHash::Xxh3_64, Ok(id)) => serializer.serialize_u64(id),
+            _ => serializer.serialize_str(&to_uuid(*id)),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum RowId {
+            Number(u64),
+            Uuid(String),
+        }
+        match RowId::deserialize(deserializer)? {
+            RowId::Number(id) => Ok(id as u128),
+            RowId::Uuid(uuid) => u128::from_str_radix(&uuid.replace('-', ""), 16)
+                .map_err(|_| serde::de::Error::custom(format!("invalid row id {uuid}"))),
+        }
+    }
+}
+
+fn chunk_with_language(
+    content: &str,
+    file_path: &Path,
+    language: (&'static str, Language, &'static str),
+    file_mtime: u64,
+    file_ctime: u64,
+    settings: Option<&crate::config::Settings>,
+) -> Result<Vec<Chunk>, ChunkError> {
+    let lang_name = language.0;
+    let mut chunks = chunk_with_match_limit(
+        content,
+        file_path,
+        language,
+        file_mtime,
+        file_ctime,
+        MATCH_LIMIT,
+    )?;
+    retain_min_code_lines(&mut chunks, lang_name, settings);
+    // Markdown chunks lead with their section's heading, which isn't a comment
+    let index_comments = settings.and_then(|s| s.index_comments).unwrap_or(false);
+    if index_comments && lang_name != "markdown" {
+        let comments = comment_chunks(&chunks, content);
+        chunks.extend(comments);
+    }
+    Ok(chunks)
+}
+
+/// The leading comment block of each chunk (see [`extract_function_with_comments`]) as a
+/// chunk of its own, of kind [`ChunkKind::Comment`], so prose can be searched on its own
+fn comment_chunks(chunks: &[Chunk], content: &str) -> Vec<Chunk> {
+    chunks
+        .iter()
+        .filter_map(|chunk| {
+            let (start_byte, end_byte) = (chunk.start_byte?, chunk.end_byte?);
+            let leading = chunk
+                .content
+                .as_deref()?
+                .strip_suffix(content.get(start_byte..end_byte)?)?;
+            let comment = leading.trim_end();
+            if comment.is_empty() {
+                return None;
+            }
+            let comment_start = start_byte - leading.len();
+            let start_line = chunk.start_line - leading.matches('\n').count() as u32;
+            let end_line = start_line + comment.matches('\n').count() as u32;
+            let chunk_hash = xxh3_64(comment.as_bytes());
+            Some(Chunk {
+                id: chunk_id(
+                    &chunk.path,
+                    start_line as usize - 1,
+                    end_line as usize - 1,
+                    chunk.file_hash,
+                    chunk_hash,
+                ),
+                path: chunk.path.clone(),
+                start_line,
+                end_line,
+                start_byte: Some(comment_start),
+                end_byte: Some(comment_start + comment.len()),
+                file_hash: chunk.file_hash,
+                chunk_hash,
+                file_mtime: chunk.file_mtime,
+                file_ctime: chunk.file_ctime,
+                content: Some(comment.to_string()),
+                kind: Some(ChunkKind::Comment),
+                // What the comment documents
+                symbol: chunk.symbol.clone(),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Drop chunks with fewer code lines than the `min_chunk_lines` setting asks for in
+/// `lang_name`, such as one-line accessors that only dilute results
+fn retain_min_code_lines(
+    chunks: &mut Vec<Chunk>,
+    lang_name: &str,
+    settings: Option<&crate::config::Settings>,
+) {
+    let Some(min_lines) = settings
+        .and_then(|s| s.min_chunk_lines.as_ref())
+        .and_then(|min_lines| min_lines.get(lang_name).or_else(|| min_lines.get("*")))
+    else {
+        return;
+    };
+    chunks.retain(|chunk| code_lines(chunk.content.as_deref().unwrap_or_default()) >= *min_lines);
+}
+
+/// Non-blank lines of `content` that don't start with a comment
+fn code_lines(content: &str) -> usize {
+    const COMMENT_STARTS: &[&str] = &["//", "/*", "*", "#", "--", ";", "<!--"];
+    content
+        .lines()
+        .map(str::trim_start)
+        .filter(|line| !line.is_empty() && !COMMENT_STARTS.iter().any(|c| line.starts_with(c)))
+        .count()
+}
+
+/// In-progress query matches a cursor may track at first. Patterns anchored at a parent
+/// (e.g. Clojure's top-level forms) keep a match per child in progress, so huge files can
+/// exhaust it and silently lose matches.
+const MATCH_LIMIT: u32 = 10_000;
+
+/// Highest match limit a file that keeps exhausting it is retried with, after which it's
+/// chunked partially with a warning
+const MAX_MATCH_LIMIT: u32 = 1_000_000;
+
+/// [`chunk_with_language`], querying with `match_limit` first and raising it as long as
+/// it's exceeded, up to [`MAX_MATCH_LIMIT`]
+fn chunk_with_match_limit(
+    content: &str,
+    file_path: &Path,
+    (lang_name, language, query_str): (&'static str, Language, &'static str),
+    file_mtime: u64,
+    file_ctime: u64,
+    mut match_limit: u32,
+) -> Result<Vec<Chunk>, ChunkError> {
     let mut parser = Parser::new();
     parser
         .set_language(&language)
@@ -375,26 +1295,6 @@ pub fn chunk(
     let query = Query::new(&language, query_str)
         .map_err(|e| ChunkError::ParseFailed(format!("Query error: {}", e)))?;
 
-    let mut cursor = QueryCursor::new();
-    cursor.set_match_limit(10000); // Prevent runaway matches
-
-    let mut captures = cursor.captures(&query, tree.root_node(), content.as_bytes());
-
-    // Extract file timestamps first (cheaper than hashing)
-    let file_mtime = metadata
-        .modified()
-        .unwrap_or(std::time::UNIX_EPOCH)
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
-
-    let file_ctime = metadata
-        .created()
-        .unwrap_or(std::time::UNIX_EPOCH)
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
-
     // Only calculate file hash if we find chunks (lazy evaluation)
     let file_hash = xxh3_64(content.as_bytes());
 
@@ -403,11 +1303,20 @@ pub fn chunk(
 
     // Pre-allocate chunks vector with reasonable capacity
     let mut chunks = Vec::with_capacity(32); // Most files have < 32 functions
+    let mut cursor = QueryCursor::new();
 
     use tree_sitter::StreamingIterator;
     let mut _function_count = 0;
-    while let Some((match_, _)) = captures.next() {
-        for capture in match_.captures {
+    // Other captures, like a Clojure form's head symbol, only feed query predicates
+    let function_capture = query.capture_index_for_name("function");
+    loop {
+        cursor.set_match_limit(match_limit); // Prevent runaway matches
+        let mut captures = cursor.captures(&query, tree.root_node(), content.as_bytes());
+        while let Some((match_, capture_index)) = captures.next() {
+            let capture = match_.captures[*capture_index];
+            if Some(capture.index) != function_capture {
+                continue;
+            }
             _function_count += 1;
 
             // Extract function content with preceding comments
@@ -419,7 +1328,12 @@ pub fn chunk(
                 };
                 chunk
             } else {
-                Cow::Borrowed(extract_function_with_comments(&tree, capture.node, content))
+                Cow::Borrowed(extract_function_with_comments(
+                    &tree,
+                    capture.node,
+                    content,
+                    lang_name,
+                ))
             };
 
             let start_pos = capture.node.start_position();
@@ -428,46 +1342,334 @@ pub fn chunk(
             // Calculate chunk hash using the full content (including comments)
             let chunk_hash = xxh3_64(function_with_comments.as_bytes());
 
-            // Create ID by hashing path, line numbers, file hash, AND chunk content hash
-            // This ensures the ID changes when ANY part of the file changes
-            let id = {
-                let mut hasher = xxhash_rust::xxh3::Xxh3::new();
-                hasher.update(path_str.as_bytes());
-                hasher.update(b":");
-                hasher.update(&start_pos.row.to_le_bytes()); // Use function line, not comment line
-                hasher.update(b":");
-                hasher.update(&end_pos.row.to_le_bytes());
-                hasher.update(b":");
-                hasher.update(&file_hash.to_le_bytes()); // Include file hash
-                hasher.update(b":");
-                hasher.update(&chunk_hash.to_le_bytes());
-                hasher.digest()
-            };
+            // Use function line, not comment line
+            let id = chunk_id(&path_str, start_pos.row, end_pos.row, file_hash, chunk_hash);
+
+            chunks.push(Chunk {
+                id,
+                vector: None,               // Vector will be set later during embedding
+                path: path_str.to_string(), // Only convert to String when storing
+                start_line: (start_pos.row + 1) as u32, // Always the function line, not comment line
+                end_line: (end_pos.row + 1) as u32, // Always the function line, not comment line
+                start_byte: Some(capture.node.start_byte()),
+                end_byte: Some(capture.node.end_byte()),
+                file_hash,
+                chunk_hash,
+                file_mtime,
+                file_ctime,
+                // TODO: chunk() could take ownership of the file str and probably just trim that
+                // string to this, to avoid a second allocation.
+                content: Some(function_with_comments.to_string()),
+                commit: None,
+                kind: Some(ChunkKind::of_node(capture.node)),
+                symbol: symbol_name(capture.node, content),
+                distance: None, // Not from search, so no distance score
+                rerank_score: None,
+                extra_vectors: Default::default(),
+            });
+        }
+
+        if !cursor.did_exceed_match_limit() {
+            break;
+        }
+        if match_limit >= MAX_MATCH_LIMIT {
+            crate::warnln!(
+                "<(°!°)> {} has too many {} matches, only partially chunked",
+                path_str,
+                lang_name
+            );
+            break;
+        }
+        match_limit = match_limit.saturating_mul(10).min(MAX_MATCH_LIMIT);
+        crate::vprintln!(
+            "<(°~°)> {} exceeded the query match limit, retrying with {}",
+            path_str,
+            match_limit
+        );
+        chunks.clear();
+    }
+
+    // Nothing recognizable and a broken tree: report it rather than silently indexing nothing
+    if chunks.is_empty() && tree.root_node().has_error() {
+        return Err(ChunkError::ParseFailed(format!(
+            "{} syntax errors, no chunks found",
+            lang_name
+        )));
+    }
+
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_min_chunk_lines_drops_one_line_functions() {
+        let code = r#"
+/// Name of the user
+fn name(&self) -> &str { &self.name }
+
+fn greet(&self) -> String {
+    format!("hi {}", self.name)
+}
+"#;
+        let chunks = chunk_with_timestamps(code, Path::new("user.rs"), 0, 0).unwrap();
+        assert_eq!(chunks.len(), 2);
+
+        let settings = crate::config::Settings {
+            min_chunk_lines: Some([("rust".to_string(), 2)].into()),
+            ..Default::default()
+        };
+        let mut kept = chunks.clone();
+        retain_min_code_lines(&mut kept, "rust", Some(&settings));
+        assert_eq!(kept.len(), 1);
+        assert!(kept[0].content.as_ref().unwrap().contains("fn greet"));
+
+        // Other languages are left alone unless there's a `*` entry
+        let mut kept = chunks.clone();
+        retain_min_code_lines(&mut kept, "python", Some(&settings));
+        assert_eq!(kept.len(), 2);
+        let settings = crate::config::Settings {
+            min_chunk_lines: Some([("*".to_string(), 2)].into()),
+            ..Default::default()
+        };
+        let mut kept = chunks;
+        retain_min_code_lines(&mut kept, "python", Some(&settings));
+        assert_eq!(kept.len(), 1);
+    }
+
+    #[test]
+    fn test_normalized_line_endings_hash_alike() {
+        let lf = "// Parse the config\nfn parse() {\n    load()\n}\n";
+        let crlf = lf.replace('\n', "\r\n");
+        let path = Path::new("config.rs");
+
+        // Raw bytes differ, so do the hashes
+        let lf_chunks = chunk_with_settings(lf, path, 0, 0, None).unwrap();
+        let crlf_chunks = chunk_with_settings(&crlf, path, 0, 0, None).unwrap();
+        assert_ne!(lf_chunks[0].file_hash, crlf_chunks[0].file_hash);
+        assert_ne!(lf_chunks[0].chunk_hash, crlf_chunks[0].chunk_hash);
+
+        let settings = crate::config::Settings {
+            normalize_line_endings: Some(true),
+            ..Default::default()
+        };
+        let crlf_chunks = chunk_with_settings(&crlf, path, 0, 0, Some(&settings)).unwrap();
+        assert_eq!(crlf_chunks[0].file_hash, lf_chunks[0].file_hash);
+        assert_eq!(crlf_chunks[0].chunk_hash, lf_chunks[0].chunk_hash);
+        assert_eq!(crlf_chunks[0].id, lf_chunks[0].id);
+        assert_eq!(crlf_chunks[0].content, lf_chunks[0].content);
+
+        // The hash-only walk agrees with the chunks, so a sync sees nothing changed
+        let hashed = xxh3_64(&normalize_line_endings(crlf.as_bytes(), Some(&settings)));
+        assert_eq!(hashed, lf_chunks[0].file_hash);
+    }
+
+    #[test]
+    fn test_chunk_kinds() {
+        let content = "struct Point {\n    x: i32,\n}\n\nimpl Point {\n    fn norm(&self) -> i32 {\n        self.x\n    }\n}\n\nfn origin() -> Point {\n    Point { x: 0 }\n}\n";
+        let chunks = chunk_with_settings(content, Path::new("point.rs"), 0, 0, None).unwrap();
+        let kind_at = |line: u32| chunks.iter().find(|c| c.start_line == line).unwrap().kind;
+        assert_eq!(kind_at(1), Some(ChunkKind::Struct));
+        assert_eq!(kind_at(5), Some(ChunkKind::Class));
+        assert_eq!(kind_at(6), Some(ChunkKind::Method));
+        assert_eq!(kind_at(11), Some(ChunkKind::Function));
+
+        // Recorded for the server as a plain string
+        let json = serde_json::to_value(&chunks[0]).unwrap();
+        assert_eq!(json["kind"], "struct");
+    }
+
+    #[test]
+    fn test_symbol_names() {
+        let symbols = |content: &str, file_name: &str| -> Vec<Option<String>> {
+            chunk_with_settings(content, Path::new(file_name), 0, 0, None)
+                .unwrap()
+                .into_iter()
+                .map(|chunk| chunk.symbol)
+                .collect()
+        };
+        let names = |names: &[&str]| -> Vec<Option<String>> {
+            names.iter().map(|name| Some(name.to_string())).collect()
+        };
+
+        assert_eq!(
+            symbols(
+                "struct Point {\n    x: i32,\n}\n\nimpl Display for Point {\n    fn fmt(&self) {}\n}\n\nfn origin() {}\n",
+                "point.rs"
+            ),
+            names(&["Point", "Point", "fmt", "origin"])
+        );
+        assert_eq!(
+            symbols(
+                "package main\n\nfunc Parse() {}\n\nfunc (s *Server) Serve() {}\n",
+                "main.go"
+            ),
+            names(&["Parse", "Serve"])
+        );
+        assert_eq!(
+            symbols(
+                "class Cache:\n    @property\n    def size(self):\n        return 0\n\ndef load():\n    pass\n",
+                "cache.py"
+            ),
+            names(&["size", "load"])
+        );
+        assert_eq!(
+            symbols(
+                "function render(): void {}\n\nconst handler = function (req: Request) {\n    return req;\n};\n",
+                "app.ts"
+            ),
+            names(&["render", "handler"])
+        );
+    }
+
+    #[test]
+    fn test_doc_comments_become_comment_chunks() {
+        let content = "use std::fs;\n\n/// Read the settings file, falling back to defaults\n/// when it's missing.\nfn load() -> Settings {\n    todo!()\n}\n\nfn save() {}\n";
+        let path = Path::new("settings.rs");
+        let chunks = chunk_with_settings(content, path, 0, 0, None).unwrap();
+        assert!(chunks.iter().all(|c| c.kind != Some(ChunkKind::Comment)));
+
+        let settings = crate::config::Settings {
+            index_comments: Some(true),
+            ..Default::default()
+        };
+        let chunks = chunk_with_settings(content, path, 0, 0, Some(&settings)).unwrap();
+        let comments: Vec<_> = chunks
+            .iter()
+            .filter(|c| c.kind == Some(ChunkKind::Comment))
+            .collect();
+        assert_eq!(comments.len(), 1, "only load() has a doc comment");
+        let comment = comments[0];
+        assert_eq!(
+            comment.content.as_deref(),
+            Some("/// Read the settings file, falling back to defaults\n/// when it's missing.")
+        );
+        assert_eq!((comment.start_line, comment.end_line), (3, 4));
+        assert_eq!(
+            &content[comment.start_byte.unwrap()..comment.end_byte.unwrap()],
+            comment.content.as_deref().unwrap()
+        );
+
+        // The function keeps its own chunk, comment included, under a different id
+        let load = chunks.iter().find(|c| c.start_line == 5).unwrap();
+        assert_eq!(load.kind, Some(ChunkKind::Function));
+        assert_ne!(load.id, comment.id);
+    }
+
+    #[test]
+    fn test_enclosing_definition_of_nested_function() {
+        let content = "def outer(items):\n    total = 0\n\n    def add(item):\n        return item + 1\n\n    for item in items:\n        total += add(item)\n    return total\n\n\ndef other():\n    pass\n";
+        let path = Path::new("nested.py");
+
+        // `add`'s chunk expands to all of `outer`
+        let chunks = chunk_with_settings(content, path, 0, 0, None).unwrap();
+        let add = chunks.iter().find(|c| c.start_line == 4).unwrap();
+        assert_eq!(add.end_line, 5);
+        let lines = |chunk: Chunk| (chunk.start_line, chunk.end_line);
+        assert_eq!(
+            enclosing_definition(content, path, add.start_line, add.end_line).map(lines),
+            Some((1, 9))
+        );
+
+        // A top-level definition is its own enclosing definition
+        assert_eq!(
+            enclosing_definition(content, path, 12, 13).map(lines),
+            Some((12, 13))
+        );
+        assert!(enclosing_definition(content, Path::new("notes.unknown"), 1, 2).is_none());
+    }
+
+    #[test]
+    fn test_exceeded_match_limit_is_raised_for_full_coverage() {
+        use tree_sitter::StreamingIterator;
+
+        // Every pair of functions is a match, far more than a low limit keeps in progress
+        let language = (
+            "rust",
+            tree_sitter_rust::LANGUAGE.into(),
+            "(source_file (function_item) @function (function_item) @_next)",
+        );
+        let code: String = (0..40).map(|i| format!("fn f{i}() {{}}\n")).collect();
+        let pairs = 40 * 39 / 2;
+
+        let mut parser = Parser::new();
+        parser.set_language(&language.1).unwrap();
+        let tree = parser.parse(&code, None).unwrap();
+        let query = Query::new(&language.1, language.2).unwrap();
+        let mut cursor = QueryCursor::new();
+        cursor.set_match_limit(4);
+        let mut matches = cursor.matches(&query, tree.root_node(), code.as_bytes());
+        let mut truncated = 0;
+        while matches.next().is_some() {
+            truncated += 1;
+        }
+        assert!(cursor.did_exceed_match_limit());
+        assert!(truncated < pairs);
+
+        let chunks =
+            chunk_with_match_limit(&code, Path::new("many.rs"), language, 0, 0, 4).unwrap();
+        assert_eq!(chunks.len(), pairs);
+    }
+
+    #[test]
+    fn test_chunk_byte_offsets_match_nodes() {
+        let metadata = std::fs::metadata("Cargo.toml").unwrap();
+        let content = "use std::io;\n\n/// Reads a line\nfn read() -> String {\n    String::new()\n}\n\nfn write(s: &str) {\n    print!(\"{s}\");\n}\n";
 
-            chunks.push(Chunk {
-                id,
-                vector: None,               // Vector will be set later during embedding
-                path: path_str.to_string(), // Only convert to String when storing
-                start_line: (start_pos.row + 1) as u32, // Always the function line, not comment line
-                end_line: (end_pos.row + 1) as u32, // Always the function line, not comment line
-                file_hash,
-                chunk_hash,
-                file_mtime,
-                file_ctime,
-                // TODO: chunk() could take ownership of the file str and probably just trim that
-                // string to this, to avoid a second allocation.
-                content: Some(function_with_comments.to_string()),
-                distance: None, // Not from search, so no distance score
-            });
-        }
+        let chunks = chunk(content, Path::new("io.rs"), metadata).unwrap();
+
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_rust::LANGUAGE.into())
+            .unwrap();
+        let tree = parser.parse(content, None).unwrap();
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let functions: Vec<(usize, usize)> = root
+            .children(&mut cursor)
+            .filter(|node| node.kind() == "function_item")
+            .map(|node| (node.start_byte(), node.end_byte()))
+            .collect();
+
+        let offsets: Vec<(usize, usize)> = chunks
+            .iter()
+            .map(|c| (c.start_byte.unwrap(), c.end_byte.unwrap()))
+            .collect();
+        assert_eq!(offsets, functions);
+        // The doc comment is part of the content but not of the node's range
+        let (start, end) = offsets[0];
+        assert!(content[start..end].starts_with("fn read()"));
+        assert!(
+            chunks[0]
+                .content
+                .as_ref()
+                .unwrap()
+                .starts_with("/// Reads a line")
+        );
     }
 
-    Ok(chunks)
-}
+    #[test]
+    fn test_row_id_formats() {
+        #[derive(Serialize, Deserialize)]
+        struct Row {
+            #[serde(with = "row_id")]
+            id: u128,
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // 64-bit IDs are plain numbers, wider ones UUIDs
+        assert_eq!(
+            serde_json::to_string(&Row { id: 42 }).unwrap(),
+            r#"{"id":42}"#
+        );
+        let wide = 0x0123_4567_89ab_cdef_0011_2233_4455_6677;
+        let json = serde_json::to_string(&Row { id: wide }).unwrap();
+        assert_eq!(json, r#"{"id":"01234567-89ab-cdef-0011-223344556677"}"#);
+        assert_eq!(serde_json::from_str::<Row>(&json).unwrap().id, wide);
+        assert_eq!(serde_json::from_str::<Row>(r#"{"id":42}"#).unwrap().id, 42);
+        assert!(serde_json::from_str::<Row>(r#"{"id":"nope"}"#).is_err());
+    }
 
     #[test]
     fn test_chunk_id_changes_with_content() {
@@ -577,6 +1779,353 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_chunk_files_reports_parse_failures() {
+        use std::fs;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_dir = temp_dir.path();
+
+        fs::write(test_dir.join("good.rs"), "fn good() {}").unwrap();
+        fs::write(test_dir.join("bad.rs"), "@@@ }}} ((( ### $$$").unwrap();
+
+        let report =
+            chunk_files_with_report(test_dir.to_str().unwrap(), &WalkOptions::default()).unwrap();
+
+        assert_eq!(report.chunks.len(), 1);
+        assert!(report.chunks[0].path.ends_with("good.rs"));
+        assert_eq!(report.failures.len(), 1);
+        assert!(report.failures[0].0.ends_with("bad.rs"));
+        assert!(matches!(report.failures[0].1, ChunkError::ParseFailed(_)));
+    }
+
+    #[test]
+    fn test_chunk_files_coverage() {
+        use std::fs;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_dir = temp_dir.path();
+
+        fs::write(test_dir.join("lib.rs"), "fn lib() {}").unwrap();
+        fs::write(test_dir.join("tool.py"), "def tool():\n    pass\n").unwrap();
+        // Supported, but nothing to chunk
+        fs::write(test_dir.join("empty.rs"), "").unwrap();
+        fs::write(test_dir.join("package.json"), "{}").unwrap();
+        fs::write(test_dir.join("tsconfig.json"), "{}").unwrap();
+        fs::write(test_dir.join("notes.txt"), "todo").unwrap();
+        fs::write(test_dir.join("LICENSE"), "MIT").unwrap();
+
+        let report =
+            chunk_files_with_report(test_dir.to_str().unwrap(), &WalkOptions::default()).unwrap();
+        let coverage = &report.coverage;
+
+        assert_eq!(coverage.files_walked, 7);
+        assert_eq!(coverage.files_supported, 3);
+        assert_eq!(coverage.files_chunked, 2);
+        assert_eq!(coverage.top_skipped(2), vec![(".json", 2), ("(none)", 1)]);
+        assert!(coverage.summary()[0].contains("walked 7 files"));
+        assert!(coverage.summary()[0].contains("2 chunked (29%)"));
+    }
+
+    #[test]
+    fn test_editorconfig_forces_language() {
+        use std::fs;
+
+        let overrides = LanguageOverrides::from_editorconfig(
+            "root = true\n\n[*]\nindent_style = space\n\n[*.inc]\nturbogrep_language = c\n\n[/legacy/*.inc]\nturbogrep_language = CPP\n",
+        );
+        assert_eq!(overrides.type_for(Path::new("util.inc")), Some("c"));
+        assert_eq!(
+            overrides.type_for(Path::new("src/deep/util.inc")),
+            Some("c")
+        );
+        // Later sections win
+        assert_eq!(overrides.type_for(Path::new("legacy/old.inc")), Some("cpp"));
+        assert_eq!(overrides.type_for(Path::new("main.rs")), None);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_dir = temp_dir.path();
+        fs::write(
+            test_dir.join(".editorconfig"),
+            "[*.inc]\nturbogrep_language = c\n",
+        )
+        .unwrap();
+        fs::write(
+            test_dir.join("checksum.inc"),
+            "static int checksum(const char *data) {\n    return data[0];\n}\n",
+        )
+        .unwrap();
+
+        let report =
+            chunk_files_with_report(test_dir.to_str().unwrap(), &WalkOptions::default()).unwrap();
+        assert_eq!(report.chunks.len(), 1);
+        assert!(report.chunks[0].path.ends_with("checksum.inc"));
+        assert!(
+            report.chunks[0]
+                .content
+                .as_ref()
+                .unwrap()
+                .contains("int checksum")
+        );
+    }
+
+    #[test]
+    fn test_chunk_files_detects_shebang() {
+        use std::fs;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_dir = temp_dir.path();
+
+        fs::write(
+            test_dir.join("deploy"),
+            "#!/usr/bin/env python3\n\ndef deploy():\n    print('shipping')\n",
+        )
+        .unwrap();
+        fs::write(test_dir.join("README"), "#!not a script\n").unwrap();
+
+        let chunks =
+            chunk_files_with_options(test_dir.to_str().unwrap(), &WalkOptions::default()).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].path.ends_with("deploy"));
+        assert!(
+            chunks[0]
+                .content
+                .as_ref()
+                .unwrap()
+                .contains("def deploy():")
+        );
+
+        let options = WalkOptions {
+            detect_shebang: false,
+            ..Default::default()
+        };
+        let chunks = chunk_files_with_options(test_dir.to_str().unwrap(), &options).unwrap();
+        assert!(chunks.is_empty());
+
+        assert_eq!(shebang_type("#!/usr/bin/env -S python3.11 -u"), Some("py"));
+        assert_eq!(shebang_type("#!/bin/bash -e"), Some("sh"));
+        assert_eq!(shebang_type("#!/usr/bin/env node"), Some("js"));
+        assert_eq!(shebang_type("# comment"), None);
+    }
+
+    #[test]
+    fn test_files_under_min_file_bytes_are_skipped() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let stub = temp_dir.path().join("stub.rs");
+        fs::write(&stub, "fn id() {}\n").unwrap();
+        let stub_size = fs::metadata(&stub).unwrap().len();
+
+        // Indexed by default
+        assert!(is_indexed_size(stub_size, None));
+        assert!(!chunk_file(&stub).unwrap().chunks.is_empty());
+
+        let settings = crate::config::Settings {
+            min_file_bytes: Some(32),
+            ..Default::default()
+        };
+        assert!(!is_indexed_size(stub_size, Some(&settings)));
+        assert!(is_indexed_size(32, Some(&settings)));
+        // Empty and oversized files stay skipped either way
+        assert!(!is_indexed_size(0, None));
+        assert!(!is_indexed_size(MAX_FILE_SIZE + 1, None));
+    }
+
+    #[test]
+    fn test_max_file_bytes_raises_the_size_cap() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("fixtures.rs");
+        let fixture = format!(
+            "fn fixture() -> &'static str {{\n    \"{}\"\n}}\n",
+            "x".repeat(1_200_000)
+        );
+        fs::write(&path, fixture).unwrap();
+
+        // Skipped at the default cap
+        let chunks = chunk_file_with_settings(&path, None, None).unwrap().chunks;
+        assert!(chunks.is_empty());
+
+        let settings = crate::config::Settings {
+            max_file_bytes: Some(2_000_000),
+            ..Default::default()
+        };
+        let chunks = chunk_file_with_settings(&path, None, Some(&settings))
+            .unwrap()
+            .chunks;
+        assert!(!chunks.is_empty());
+    }
+
+    #[test]
+    fn test_generated_files_are_skipped() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("api.pb.go");
+        fs::write(
+            &path,
+            "// Code generated by protoc-gen-go. DO NOT EDIT.\n\
+             // source: api.proto\n\n\
+             package api\n\n\
+             func (x *Request) GetName() string {\n\treturn x.Name\n}\n",
+        )
+        .unwrap();
+
+        assert!(chunk_file(&path).unwrap().chunks.is_empty());
+        assert!(
+            !chunk_file_with_markers(&path, None)
+                .unwrap()
+                .chunks
+                .is_empty()
+        );
+
+        let root = temp_dir.path().to_str().unwrap();
+        assert!(
+            chunk_files_with_options(root, &WalkOptions::default())
+                .unwrap()
+                .is_empty()
+        );
+        let options = WalkOptions {
+            include_generated: true,
+            ..Default::default()
+        };
+        assert_eq!(chunk_files_with_options(root, &options).unwrap().len(), 1);
+
+        // Only the head of a file is searched for markers
+        let markers = GeneratedMarkers::default();
+        assert!(
+            !markers
+                .is_generated("package api\n\n\n\n\n\n// Code generated by hand. DO NOT EDIT.\n")
+        );
+        assert!(markers.is_generated("/**\n * @generated\n */\n"));
+    }
+
+    #[test]
+    fn test_chunk_files_glob_scope() {
+        use std::fs;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_dir = temp_dir.path().canonicalize().unwrap();
+
+        fs::create_dir_all(test_dir.join("src/api/handlers")).unwrap();
+        fs::create_dir_all(test_dir.join("src/handlers")).unwrap();
+        fs::write(test_dir.join("src/api/handlers/auth.rs"), "fn auth() {}").unwrap();
+        fs::write(test_dir.join("src/handlers/user.rs"), "fn user() {}").unwrap();
+        fs::write(test_dir.join("src/main.rs"), "fn main() {}").unwrap();
+
+        let scope =
+            PathScope::new(vec![format!("{}/src/**/handlers", test_dir.display())]).unwrap();
+        let options = WalkOptions {
+            scope: Some(scope),
+            ..Default::default()
+        };
+        let chunks = chunk_files_with_options(test_dir.to_str().unwrap(), &options).unwrap();
+
+        let mut paths: Vec<_> = chunks.iter().map(|c| c.path.clone()).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                test_dir.join("src/api/handlers/auth.rs").to_string_lossy(),
+                test_dir.join("src/handlers/user.rs").to_string_lossy(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chunk_files_skips_turbogrep_dirs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let home = temp_dir.path().canonicalize().unwrap();
+        let cache_dir = home.join(".cache/turbogrep");
+        fs::create_dir_all(cache_dir.join("manifests")).unwrap();
+        fs::write(cache_dir.join("manifests/tg_project.json"), "{}").unwrap();
+        fs::write(cache_dir.join("helper.py"), "def helper(): pass").unwrap();
+        fs::write(home.join("main.rs"), "fn main() {}").unwrap();
+
+        let options = WalkOptions {
+            excluded_dirs: vec![cache_dir],
+            ..Default::default()
+        };
+        let chunks = chunk_files_with_options(home.to_str().unwrap(), &options).unwrap();
+        let paths: Vec<_> = chunks.iter().map(|c| c.path.clone()).collect();
+        assert_eq!(paths, [home.join("main.rs").to_string_lossy()]);
+    }
+
+    #[test]
+    fn test_chunk_files_skips_submodules() {
+        use std::fs;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_dir = temp_dir.path();
+
+        fs::write(test_dir.join("main.rs"), "fn main() {}").unwrap();
+        fs::write(
+            test_dir.join(".gitmodules"),
+            "[submodule \"vendor/lib\"]\n\tpath = vendor/lib\n\turl = ../lib.git\n",
+        )
+        .unwrap();
+        fs::create_dir_all(test_dir.join("vendor/lib")).unwrap();
+        fs::write(
+            test_dir.join("vendor/lib/.git"),
+            "gitdir: ../../.git/modules/lib\n",
+        )
+        .unwrap();
+        fs::write(test_dir.join("vendor/lib/lib.rs"), "fn vendored() {}").unwrap();
+
+        let root = test_dir.to_str().unwrap();
+
+        let skipped = chunk_files_with_options(root, &WalkOptions::default()).unwrap();
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].path.ends_with("main.rs"));
+
+        let followed = chunk_files_with_options(
+            root,
+            &WalkOptions {
+                skip_submodules: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(followed.len(), 2);
+        assert!(
+            followed
+                .iter()
+                .any(|c| c.path.ends_with("vendor/lib/lib.rs"))
+        );
+    }
+
+    #[test]
+    fn test_chunk_files_hidden_toggle() {
+        use std::fs;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_dir = temp_dir.path();
+
+        fs::write(test_dir.join("visible.rs"), "fn visible() {}").unwrap();
+        fs::write(test_dir.join(".hidden.rs"), "fn dotfile() {}").unwrap();
+        fs::create_dir(test_dir.join(".github")).unwrap();
+        fs::write(
+            test_dir.join(".github/script.py"),
+            "def workflow():\n    pass\n",
+        )
+        .unwrap();
+
+        let root = test_dir.to_str().unwrap();
+
+        // Hidden paths are included by default
+        let included = chunk_files_with_options(root, &WalkOptions::default()).unwrap();
+        assert_eq!(included.len(), 3);
+        assert!(included.iter().any(|c| c.path.ends_with(".hidden.rs")));
+        assert!(included.iter().any(|c| c.path.ends_with("script.py")));
+
+        let excluded = chunk_files_with_options(
+            root,
+            &WalkOptions {
+                include_hidden: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(excluded.len(), 1);
+        assert!(excluded[0].path.ends_with("visible.rs"));
+    }
+
     #[test]
     fn test_extract_function_with_comments() {
         let rust_code = r#"use std::collections::HashMap;
@@ -621,7 +2170,7 @@ fn process_users(users: Vec<String>) -> HashMap<String, u32> {
         if let Some((match_, _)) = captures_iter.next() {
             let function_node = match_.captures[0].node;
             let content_with_comments =
-                extract_function_with_comments(&tree, function_node, rust_code);
+                extract_function_with_comments(&tree, function_node, rust_code, "rust");
 
             // Should include the doc comments
             assert!(content_with_comments.contains("/// A helper function to calculate factorial"));
@@ -638,7 +2187,7 @@ fn process_users(users: Vec<String>) -> HashMap<String, u32> {
         if let Some((match_, _)) = captures_iter.next() {
             let function_node = match_.captures[0].node;
             let content_with_comments =
-                extract_function_with_comments(&tree, function_node, rust_code);
+                extract_function_with_comments(&tree, function_node, rust_code, "rust");
 
             // Should include the line comment
             assert!(content_with_comments.contains("// Process users and return stats"));
@@ -691,7 +2240,7 @@ impl Calculator {
         if let Some((match_, _)) = captures_iter.next() {
             let function_node = match_.captures[0].node;
             let content_with_comments =
-                extract_function_with_comments(&tree, function_node, rust_code);
+                extract_function_with_comments(&tree, function_node, rust_code, "rust");
 
             // Should include the doc comment
             assert!(
@@ -756,7 +2305,7 @@ func buildFiltersForFastPathCheck(uniqueCheckExpr RelExpr) FiltersExpr {
         if let Some((match_, _)) = captures_iter.next() {
             let function_node = match_.captures[0].node;
             let content_with_comments =
-                extract_function_with_comments(&tree, function_node, go_code);
+                extract_function_with_comments(&tree, function_node, go_code, "go");
 
             // Should include the entire long comment block
             assert!(
@@ -955,13 +2504,107 @@ pub struct ChunkFileResult {
     pub file_size: u64,
 }
 
-pub fn chunk_file(path: &Path) -> Result<ChunkFileResult> {
+/// Files larger than this are skipped (likely not source code), unless the config sets
+/// `max_file_bytes`
+pub const MAX_FILE_SIZE: u64 = 1_000_000;
+
+/// Largest file chunked: the `max_file_bytes` setting, else [`MAX_FILE_SIZE`]
+fn max_file_bytes(settings: Option<&crate::config::Settings>) -> u64 {
+    settings
+        .and_then(|s| s.max_file_bytes)
+        .unwrap_or(MAX_FILE_SIZE)
+}
+
+/// Whether a file of `file_size` bytes is chunked: empty files, those over the
+/// `max_file_bytes` setting and those under the `min_file_bytes` setting are skipped
+pub fn is_indexed_size(file_size: u64, settings: Option<&crate::config::Settings>) -> bool {
+    let min_file_bytes = settings.and_then(|s| s.min_file_bytes).unwrap_or(0);
+    file_size > 0 && file_size >= min_file_bytes && file_size <= max_file_bytes(settings)
+}
+
+/// Markers generated files carry near the top, e.g. Go's `// Code generated ... DO NOT EDIT.`
+pub const DEFAULT_GENERATED_MARKERS: &[&str] = &[
+    r"^// Code generated .* DO NOT EDIT\.$",
+    r"@generated\b",
+    r"(?i)(auto-?generated|generated by).*do not (edit|modify)",
+];
+
+/// Only this many leading lines are searched for a generated-code marker
+const GENERATED_MARKER_LINES: usize = 5;
+
+/// Regexes that mark a file as generated when one matches a line of its head
+#[derive(Debug, Clone)]
+pub struct GeneratedMarkers(regex::RegexSet);
+
+impl GeneratedMarkers {
+    pub fn new<I, S>(patterns: I) -> Result<Self, regex::Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        regex::RegexSet::new(patterns).map(Self)
+    }
+
+    /// Markers from the config file, or the defaults when none (or invalid ones) are set
+    pub fn from_settings(settings: Option<&crate::config::Settings>) -> Self {
+        let Some(patterns) = settings.and_then(|s| s.generated_markers.as_ref()) else {
+            return Self::default();
+        };
+        Self::new(patterns).unwrap_or_else(|e| {
+            crate::warnln!("<(°!°)> Ignoring generated_markers from config: {}", e);
+            Self::default()
+        })
+    }
+
+    pub fn is_generated(&self, content: &str) -> bool {
+        content
+            .lines()
+            .take(GENERATED_MARKER_LINES)
+            .any(|line| self.0.is_match(line))
+    }
+}
+
+impl Default for GeneratedMarkers {
+    fn default() -> Self {
+        Self::new(DEFAULT_GENERATED_MARKERS).expect("default generated markers are valid")
+    }
+}
+
+/// Chunk a file, skipping it if it's generated code with one of the configured markers
+pub fn chunk_file(path: &Path) -> Result<ChunkFileResult, ChunkError> {
+    static MARKERS: OnceLock<GeneratedMarkers> = OnceLock::new();
+    let markers =
+        MARKERS.get_or_init(|| GeneratedMarkers::from_settings(crate::config::SETTINGS.get()));
+    chunk_file_with_markers(path, Some(markers))
+}
+
+/// Chunk a file, skipping it when `generated_markers` mark it as generated code
+pub fn chunk_file_with_markers(
+    path: &Path,
+    generated_markers: Option<&GeneratedMarkers>,
+) -> Result<ChunkFileResult, ChunkError> {
+    chunk_file_with_settings(path, generated_markers, crate::config::SETTINGS.get())
+}
+
+fn chunk_file_with_settings(
+    path: &Path,
+    generated_markers: Option<&GeneratedMarkers>,
+    settings: Option<&crate::config::Settings>,
+) -> Result<ChunkFileResult, ChunkError> {
     // Fast path: check file size first to skip empty/huge files
     let metadata = fs::metadata(path)?;
     let file_size = metadata.len();
 
-    // Skip empty files and files larger than 1MB (likely not source code)
-    if file_size == 0 || file_size > 1_000_000 {
+    // Skip empty and trivial files, and huge ones (likely not source code)
+    if !is_indexed_size(file_size, settings) {
+        if file_size > max_file_bytes(settings) {
+            crate::vprintln!(
+                "skipping {} ({} bytes, over max_file_bytes {})",
+                path.display(),
+                file_size,
+                max_file_bytes(settings)
+            );
+        }
         return Ok(ChunkFileResult {
             chunks: vec![],
             read_time_ms: 0,
@@ -1002,12 +2645,21 @@ pub fn chunk_file(path: &Path) -> Result<ChunkFileResult> {
     };
     let utf_time = utf_instant.elapsed();
 
+    if generated_markers.is_some_and(|markers| markers.is_generated(content_str)) {
+        crate::vprintln!("skipping generated file {}", path.display());
+        return Ok(ChunkFileResult {
+            chunks: vec![],
+            file_size,
+            ..Default::default()
+        });
+    }
+
     // Time parsing
     let parse_instant = Instant::now();
     let chunks = match chunk(content_str, path, metadata) {
         Ok(chunks) => chunks,
         Err(ChunkError::UnsupportedExtension(_)) => vec![],
-        Err(e) => return Err(e.into()),
+        Err(e) => return Err(e),
     };
     let parse_time = parse_instant.elapsed();
 
@@ -1020,20 +2672,146 @@ pub fn chunk_file(path: &Path) -> Result<ChunkFileResult> {
     })
 }
 
-/// Generic parallel directory walker that processes files and collects chunks
-fn parallel_walk_files<F>(
+/// Options controlling which files the directory walker visits
+#[derive(Debug, Clone)]
+pub struct WalkOptions {
+    /// Include hidden files and directories (dotfiles, `.github/`, ...).
+    /// This is independent of ignore-file handling.
+    pub include_hidden: bool,
+    /// Skip git submodules declared in `.gitmodules`; each is its own project with its own index
+    pub skip_submodules: bool,
+    /// Only visit files inside this scope (from glob or multiple PATH arguments)
+    pub scope: Option<PathScope>,
+    /// Index extensionless scripts by their `#!` interpreter line
+    pub detect_shebang: bool,
+    /// Index generated files too instead of skipping them (see [`GeneratedMarkers`])
+    pub include_generated: bool,
+    /// Directories never walked, such as turbogrep's own ([`crate::config::data_dirs`]),
+    /// which the CLI excludes; none by default
+    pub excluded_dirs: Vec<PathBuf>,
+}
+
+/// A set of absolute path globs; a file is in scope if it matches one, or lives in a
+/// directory that matches one
+#[derive(Debug, Clone)]
+pub struct PathScope {
+    patterns: Vec<String>,
+    matcher: GlobSet,
+}
+
+impl PathScope {
+    pub fn new(patterns: Vec<String>) -> Result<Self, globset::Error> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &patterns {
+            builder.add(Glob::new(pattern)?);
+            builder.add(Glob::new(&format!("{}/**", pattern.trim_end_matches('/')))?);
+        }
+        Ok(Self {
+            matcher: builder.build()?,
+            patterns,
+        })
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    pub fn is_match(&self, path: &Path) -> bool {
+        self.matcher.is_match(path)
+    }
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        Self {
+            include_hidden: true,
+            skip_submodules: true,
+            scope: None,
+            detect_shebang: true,
+            include_generated: false,
+            excluded_dirs: Vec::new(),
+        }
+    }
+}
+
+/// How much of a directory walk ended up in the index, to find blind spots
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WalkCoverage {
+    /// Files visited (inside the scope, if any)
+    pub files_walked: usize,
+    /// Files in a language the chunker supports
+    pub files_supported: usize,
+    /// Files that produced at least one chunk
+    pub files_chunked: usize,
+    /// Unsupported files by extension (`.json`), or `(none)` for files without one
+    pub skipped_extensions: HashMap<String, usize>,
+}
+
+impl WalkCoverage {
+    fn record_skipped(&mut self, path: &Path) {
+        let extension = path
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy().to_lowercase()))
+            .unwrap_or_else(|| "(none)".to_string());
+        *self.skipped_extensions.entry(extension).or_default() += 1;
+    }
+
+    /// The `n` most often skipped extensions, most frequent first
+    pub fn top_skipped(&self, n: usize) -> Vec<(&str, usize)> {
+        let mut skipped: Vec<_> = self
+            .skipped_extensions
+            .iter()
+            .map(|(ext, count)| (ext.as_str(), *count))
+            .collect();
+        skipped.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        skipped.truncate(n);
+        skipped
+    }
+
+    pub fn summary(&self) -> Vec<String> {
+        let percent_chunked = if self.files_walked == 0 {
+            0.0
+        } else {
+            100.0 * self.files_chunked as f64 / self.files_walked as f64
+        };
+        let mut lines = vec![format!(
+            "coverage: walked {} files, {} in a supported language, {} chunked ({:.0}%)",
+            self.files_walked, self.files_supported, self.files_chunked, percent_chunked
+        )];
+        let top_skipped = self.top_skipped(5);
+        if !top_skipped.is_empty() {
+            let skipped: Vec<String> = top_skipped
+                .iter()
+                .map(|(ext, count)| format!("{ext} ({count})"))
+                .collect();
+            lines.push(format!(
+                "coverage: most skipped unsupported extensions: {}",
+                skipped.join(", ")
+            ));
+        }
+        lines
+    }
+}
+
+/// Generic parallel directory walker that processes files and collects chunks, or
+/// whatever else `processor` makes of each supported file
+fn parallel_walk_files<T, F>(
     root_dir: &str,
+    options: &WalkOptions,
     use_progress_bar: bool,
     processor: F,
-) -> Result<Vec<Chunk>>
+) -> Result<(Vec<T>, WalkCoverage)>
 where
-    F: Fn(&std::path::Path) -> Option<Vec<Chunk>> + Send + Sync + 'static,
+    T: std::fmt::Debug + Send + 'static,
+    F: Fn(&std::path::Path) -> Option<Vec<T>> + Send + Sync + 'static,
 {
     let _instant = Instant::now();
+    let _span = crate::profile::span("walk", format!("walk {root_dir}"));
+    register_language_overrides(Path::new(root_dir));
 
     // Shared results collected from all threads
     let all_chunks = Arc::new(Mutex::new(Vec::new()));
-    let file_count = Arc::new(Mutex::new(0usize));
+    let coverage = Arc::new(Mutex::new(WalkCoverage::default()));
     let pb = if use_progress_bar {
         Some(crate::progress::tg_progress_bar(0))
     } else {
@@ -1043,18 +2821,36 @@ where
     // Wrap the processor in Arc outside the closure
     let processor = Arc::new(processor);
 
+    let submodules: Vec<PathBuf> = if options.skip_submodules {
+        crate::project::git_submodule_paths(root_dir)
+            .into_iter()
+            .map(|p| Path::new(root_dir).join(p))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let scope = options.scope.clone();
+    let detect_shebang = options.detect_shebang;
+    let excluded_dirs = options.excluded_dirs.clone();
+
     // Simple parallel directory walking with inline processing
     WalkBuilder::new(root_dir)
         .follow_links(false)
-        .hidden(false)
+        .hidden(!options.include_hidden)
+        .filter_entry(move |entry| {
+            !submodules.iter().any(|s| entry.path() == s)
+                && !excluded_dirs.iter().any(|dir| entry.path() == dir)
+        })
         .threads(num_cpus::get())
         .build_parallel()
         .run(|| {
             let all_chunks = all_chunks.clone();
-            let file_count = file_count.clone();
+            let coverage = coverage.clone();
             let filetype_matcher = get_filetype_matcher();
             let pb_clone = pb.clone();
             let processor = processor.clone();
+            let scope = scope.clone();
 
             Box::new(move |result| {
                 match result {
@@ -1064,19 +2860,35 @@ where
                             pb.inc(1);
                         }
 
-                        // Pre-filter by supported file types
-                        if filetype_matcher.detect_language(path).is_some() {
-                            if let Some(chunks) = processor(path) {
-                                if !chunks.is_empty() {
-                                    all_chunks.lock().unwrap().extend(chunks);
-                                    *file_count.lock().unwrap() += 1;
-                                }
-                            }
+                        // Pre-filter by scope and supported file types
+                        if scope.as_ref().is_some_and(|scope| !scope.is_match(path)) {
+                            return ignore::WalkState::Continue;
+                        }
+                        coverage.lock().unwrap().files_walked += 1;
+                        let language = filetype_matcher.detect_language(path, None);
+                        if language.is_none()
+                            && !is_single_file_component(path)
+                            && !(detect_shebang && has_known_shebang(path))
+                        {
+                            coverage.lock().unwrap().record_skipped(path);
+                            return ignore::WalkState::Continue;
+                        }
+                        coverage.lock().unwrap().files_supported += 1;
+
+                        let _span = crate::profile::span(
+                            "chunk",
+                            format!("chunk {}", language.map_or("other", |(name, ..)| name)),
+                        );
+                        if let Some(chunks) = processor(path)
+                            && !chunks.is_empty()
+                        {
+                            all_chunks.lock().unwrap().extend(chunks);
+                            coverage.lock().unwrap().files_chunked += 1;
                         }
                     }
                     Ok(_) => {} // Directory or other non-file entry
                     Err(err) => {
-                        eprintln!("Error walking directory: {}", err);
+                        crate::warnln!("Error walking directory: {}", err);
                     }
                 }
                 ignore::WalkState::Continue
@@ -1084,38 +2896,131 @@ where
         });
 
     let chunks = Arc::try_unwrap(all_chunks).unwrap().into_inner().unwrap();
-    let _files_processed = *file_count.lock().unwrap();
+    let coverage = Arc::try_unwrap(coverage).unwrap().into_inner().unwrap();
 
     let _total_time = _instant.elapsed();
 
-    Ok(chunks)
+    Ok((chunks, coverage))
 }
 
 pub fn chunk_files(root_dir: &str) -> Result<Vec<Chunk>> {
-    parallel_walk_files(root_dir, true, |path| match chunk_file(path) {
-        Ok(result) => {
-            if !result.chunks.is_empty() {
-                Some(result.chunks)
+    chunk_files_with_options(root_dir, &WalkOptions::default())
+}
+
+pub fn chunk_files_with_options(root_dir: &str, options: &WalkOptions) -> Result<Vec<Chunk>> {
+    let report = chunk_files_with_report(root_dir, options)?;
+    report.warn_failures();
+    report.print_coverage(false);
+    Ok(report.chunks)
+}
+
+/// Chunks from a directory walk, along with the files that could not be chunked
+#[derive(Debug, Default)]
+pub struct ChunkRunReport {
+    pub chunks: Vec<Chunk>,
+    /// Files that failed to read or parse, sorted by path
+    pub failures: Vec<(PathBuf, ChunkError)>,
+    pub coverage: WalkCoverage,
+}
+
+impl ChunkRunReport {
+    /// Print each failure followed by a one-line summary, unless running with --quiet.
+    /// Files that failed to parse are only listed when verbose: broken syntax would
+    /// otherwise be repeated on every sync until it's fixed.
+    pub fn warn_failures(&self) {
+        for (path, e) in &self.failures {
+            if matches!(e, ChunkError::ParseFailed(_)) {
+                crate::vprintln!("Error processing {}: {}", path.display(), e);
             } else {
-                None
+                crate::warnln!("Error processing {}: {}", path.display(), e);
+            }
+        }
+        if !self.failures.is_empty() {
+            crate::warnln!(
+                "<(°!°)> {} files failed to parse (--verbose lists them)",
+                self.failures.len()
+            );
+        }
+    }
+
+    /// Warn when the walk of `root_dir` produced no chunks at all, which otherwise leaves
+    /// an empty index and searches that silently find nothing. With `require` it's an error.
+    pub fn check_supported(&self, root_dir: &str, require: bool) -> Result<(), ChunkError> {
+        if !self.chunks.is_empty() {
+            return Ok(());
+        }
+        let error = ChunkError::NoSupportedFiles(root_dir.to_string());
+        if require {
+            return Err(error);
+        }
+        crate::warnln!("<(°!°)> Warning: {}", error);
+        Ok(())
+    }
+
+    /// Print the coverage summary to stderr with `stats`, otherwise only when verbose
+    pub fn print_coverage(&self, stats: bool) {
+        for line in self.coverage.summary() {
+            if stats {
+                eprintln!("{line}");
+            } else {
+                crate::vprintln!("{}", line);
             }
         }
-        Err(e) => {
-            eprintln!("Error processing {}: {}", path.display(), e);
-            None
+    }
+}
+
+/// Like [`chunk_files_with_options`], but collects per-file failures instead of printing them
+pub fn chunk_files_with_report(root_dir: &str, options: &WalkOptions) -> Result<ChunkRunReport> {
+    let failures = Arc::new(Mutex::new(Vec::new()));
+
+    let (chunks, coverage) = parallel_walk_files(root_dir, options, true, {
+        let failures = failures.clone();
+        let include_generated = options.include_generated;
+        move |path| {
+            let result = if include_generated {
+                chunk_file_with_markers(path, None)
+            } else {
+                chunk_file(path)
+            };
+            match result {
+                Ok(result) => {
+                    if !result.chunks.is_empty() {
+                        Some(result.chunks)
+                    } else {
+                        None
+                    }
+                }
+                Err(e) => {
+                    failures.lock().unwrap().push((path.to_path_buf(), e));
+                    None
+                }
+            }
         }
+    })?;
+
+    let mut failures = std::mem::take(&mut *failures.lock().unwrap());
+    failures.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Ok(ChunkRunReport {
+        chunks,
+        failures,
+        coverage,
     })
 }
 
 /// Create chunks with metadata only (no content) for efficient diffing
 /// This is much faster than full chunking since we don't need to parse content
 pub fn hash_chunk_files(root_dir: &str) -> Result<Vec<Chunk>> {
-    parallel_walk_files(root_dir, false, |path| {
+    parallel_walk_files(root_dir, &WalkOptions::default(), false, |path| {
         // Get file content to calculate hash
         match fs::read(path) {
             Ok(content) => {
                 let path_str = path.to_string_lossy();
-                let file_hash = xxh3_64(&content); // Use actual file content hash
+                // Use actual file content hash, matching chunk()'s
+                let file_hash = xxh3_64(&normalize_line_endings(
+                    &content,
+                    crate::config::SETTINGS.get(),
+                ));
                 let metadata = match fs::metadata(path) {
                     Ok(m) => m,
                     Err(_) => return None,
@@ -1135,25 +3040,60 @@ pub fn hash_chunk_files(root_dir: &str) -> Result<Vec<Chunk>> {
 
                 // Create a single chunk per file for hash tracking
                 let chunk = Chunk {
-                    id: file_hash,
+                    id: file_hash.into(),
                     vector: None,
                     path: path_str.to_string(),
                     start_line: 1,
                     end_line: 1,
+                    start_byte: None,
+                    end_byte: None,
                     file_hash,
                     chunk_hash: file_hash, // Use file_hash as chunk_hash for hash chunks
                     file_mtime,
                     file_ctime,
-                    content: None,  // No content for hash chunks
+                    content: None, // No content for hash chunks
+                    commit: None,
+                    kind: None,
+                    symbol: None,
                     distance: None, // Not from search, so no distance score
+                    rerank_score: None,
+                    extra_vectors: Default::default(),
                 };
 
                 Some(vec![chunk])
             }
             Err(e) => {
-                eprintln!("Error reading file {}: {}", path.display(), e);
+                crate::warnln!("Error reading file {}: {}", path.display(), e);
                 None
             }
         }
     })
+    .map(|(chunks, _coverage)| chunks)
+}
+
+/// Size and modification time, in nanoseconds, of a walked file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStat {
+    pub path: String,
+    pub size: u64,
+    pub mtime_nanos: u64,
+}
+
+/// [`FileStat`]s of the files a walk with `options` would chunk, without reading them
+pub fn stat_files_with_options(root_dir: &str, options: &WalkOptions) -> Result<Vec<FileStat>> {
+    parallel_walk_files(root_dir, options, false, |path| {
+        let metadata = fs::metadata(path).ok()?;
+        let mtime_nanos = metadata
+            .modified()
+            .ok()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        Some(vec![FileStat {
+            path: path.to_string_lossy().to_string(),
+            size: metadata.len(),
+            mtime_nanos,
+        }])
+    })
+    .map(|(files, _coverage)| files)
 }
@@ -1,13 +1,91 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Settings {
     pub turbopuffer_region: Option<String>,
     pub embedding_provider: Option<String>,
+    /// Chunk content longer than this many bytes is truncated before embedding (at least 64)
+    pub max_chunk_bytes: Option<usize>,
+    /// Pace embedding requests to stay under this many tokens per minute (no limit by default)
+    pub embedding_tokens_per_minute: Option<usize>,
+    /// Chunks per turbopuffer write request (default 1000)
+    pub write_batch_size: Option<usize>,
+    /// Concurrent turbopuffer write requests (default 4)
+    pub write_concurrency: Option<usize>,
+    /// Store chunk content (compressed) in turbopuffer, for previews without the files
+    pub store_content: Option<bool>,
+    /// Store chunk content as full-text indexed text, which the lexical fallback searches
+    /// when a query can't be embedded (default true; false keeps source text out of
+    /// turbopuffer unless `store_content` is set)
+    pub index_text: Option<bool>,
+    /// Vector distance metric of new indexes: "cosine_distance" (default) or
+    /// "euclidean_squared"
+    pub distance_metric: Option<String>,
+    /// Chunk ID hash: "xxh3_64" (default) or "xxh3_128" for fewer collisions in huge
+    /// indexes (changing this needs a --reset)
+    pub id_hash: Option<String>,
+    /// Prefix embedded chunk text with its file path (changing this needs a --reset)
+    pub embed_path_context: Option<bool>,
+    /// Prefix the embedded query with the searched directory in the same format, which
+    /// can help retrieval when documents carry paths (defaults to `embed_path_context`)
+    pub query_path_context: Option<bool>,
+    /// More Voyage models to embed every chunk with, each into its own vector column and
+    /// selectable with `--model` (changing this needs a --reset)
+    pub embedding_models: Option<Vec<String>>,
+    /// Seconds to reuse results of an identical query (default 300, 0 disables the cache)
+    pub query_cache_ttl_secs: Option<u64>,
+    /// Warm-up pings sent to turbopuffer and Voyage before a search (default 5, 0 disables)
+    pub warmup_pings: Option<usize>,
+    /// Name namespaces after the project's git repository (its `origin` URL or root commit)
+    /// and its place in the checkout rather than its absolute path, so Windows, WSL and
+    /// macOS share an index (changing this needs a sync into the new namespace)
+    pub portable_namespaces: Option<bool>,
+    /// Regions pinned per namespace, overriding `turbopuffer_region` for that namespace
+    pub namespace_regions: Option<BTreeMap<String, String>>,
+    /// Minimum code lines (not counting blank and comment lines) a function needs to be
+    /// indexed, keyed by language (e.g. `rust`, `python`) or `*` for every language, to
+    /// keep one-line accessors out of results
+    pub min_chunk_lines: Option<BTreeMap<String, usize>>,
+    /// Hash and chunk files with CRLF line endings as LF, so a checkout on Windows shares
+    /// chunk IDs with one on Linux (changing this re-syncs files with CRLF endings)
+    pub normalize_line_endings: Option<bool>,
+    /// Skip files smaller than this many bytes, such as stubs and one-line re-export
+    /// modules (default 0, indexing every non-empty file)
+    pub min_file_bytes: Option<u64>,
+    /// Skip files larger than this many bytes, such as bundles and data dumps (default
+    /// 1000000)
+    pub max_file_bytes: Option<u64>,
+    /// Regexes marking generated files to skip, replacing the built-in markers
+    pub generated_markers: Option<Vec<String>>,
+    /// Start of every project namespace's name (default `tg`), e.g. a team's name so teams
+    /// sharing a turbopuffer account don't collide (changing this needs a sync into the new
+    /// namespaces)
+    pub namespace_prefix: Option<String>,
+    /// Also index each definition's leading comment block as a chunk of its own, to search
+    /// the project's doc comments with `--kind comment` (changing this needs a --reset)
+    pub index_comments: Option<bool>,
+    /// Most results one search asks turbopuffer for, however large `--max-count` is
+    /// (default 1000)
+    pub max_top_k: Option<usize>,
+    /// Pings per region when picking the closest one, whose median latency is compared
+    /// (default 3)
+    pub region_ping_samples: Option<usize>,
+}
+
+impl Settings {
+    /// Region `namespace` lives in: its pinned region, else the global one
+    pub fn region_for(&self, namespace: &str) -> Option<&str> {
+        self.namespace_regions
+            .as_ref()
+            .and_then(|regions| regions.get(namespace))
+            .or(self.turbopuffer_region.as_ref())
+            .map(String::as_str)
+    }
 }
 
 pub static SETTINGS: OnceLock<Settings> = OnceLock::new();
@@ -36,20 +114,101 @@ fn get_config_dir() -> Result<PathBuf> {
     }
 }
 
+/// Settings from the config file at `path`, defaults if there is none. A corrupt file is
+/// copied to `config.json.bak` with a warning, rather than silently dropping settings
+/// such as a pinned region.
+fn read_settings(path: &Path) -> Result<Settings> {
+    if !path.exists() {
+        return Ok(Settings::default());
+    }
+    let content = fs::read_to_string(path)?;
+    match serde_json::from_str(&content) {
+        Ok(settings) => Ok(settings),
+        Err(e) => {
+            let backup = path.with_extension("json.bak");
+            crate::warnln!(
+                "<(°!°)> Config file {} is corrupt ({}), falling back to defaults",
+                path.display(),
+                e
+            );
+            match fs::copy(path, &backup) {
+                Ok(_) => crate::warnln!("<(°!°)> The old config was saved to {}", backup.display()),
+                Err(e) => crate::warnln!("<(°!°)> Couldn't back up the old config: {}", e),
+            }
+            Ok(Settings::default())
+        }
+    }
+}
+
+/// Settings from the config file as they are, without picking a region or saving
+/// anything like [`load_or_init_settings`] does, e.g. for `tg version --verbose`
+pub fn read_config() -> Result<Settings> {
+    read_settings(&config_path()?)
+}
+
+/// Store `region` as the turbopuffer region in the config file at `path`, keeping every
+/// other setting. Returns the region that was configured before, if any.
+fn persist_region(path: &Path, region: &str) -> Result<Option<String>> {
+    let mut settings = read_settings(path)?;
+
+    let old_region = settings.turbopuffer_region.replace(region.to_string());
+    fs::write(path, serde_json::to_string_pretty(&settings)?)?;
+    Ok(old_region)
+}
+
+/// Re-pick the lowest-latency turbopuffer region and save it to the config file.
+/// Returns the previously configured region and the new one.
+pub async fn update_region() -> Result<(Option<String>, String)> {
+    let region = crate::turbopuffer::find_closest_region(SETTINGS.get()).await?;
+    let old_region = persist_region(&config_path()?, &region)?;
+    Ok((old_region, region))
+}
+
+/// Directory for disposable data such as cached query results
+pub fn cache_dir() -> Result<PathBuf> {
+    if cfg!(target_os = "windows") {
+        // Windows: %LOCALAPPDATA%\turbogrep
+        let local_appdata =
+            std::env::var("LOCALAPPDATA").context("LOCALAPPDATA environment variable not set")?;
+        Ok(PathBuf::from(local_appdata).join("turbogrep"))
+    } else if let Ok(xdg_cache_home) = std::env::var("XDG_CACHE_HOME") {
+        Ok(PathBuf::from(xdg_cache_home).join("turbogrep"))
+    } else {
+        let home = std::env::var("HOME").context("HOME environment variable not set")?;
+        Ok(PathBuf::from(home).join(".cache/turbogrep"))
+    }
+}
+
+/// Directories holding saved queries for `tg :NAME`, one file per query, most specific
+/// first: `.turbogrep/queries` in `directory` and each directory above it, then `queries`
+/// in the config directory
+pub fn saved_query_dirs(directory: &Path) -> Vec<PathBuf> {
+    let project_dirs = directory
+        .ancestors()
+        .map(|dir| dir.join(".turbogrep").join("queries"));
+    project_dirs
+        .chain(get_config_dir().ok().map(|dir| dir.join("queries")))
+        .collect()
+}
+
+/// turbogrep's own config and cache directories, those that exist, so that walking a
+/// directory containing them (e.g. `tg ~/`) doesn't index the config or cached data
+pub fn data_dirs() -> Vec<PathBuf> {
+    [get_config_dir(), cache_dir()]
+        .into_iter()
+        .filter_map(|dir| dir.ok()?.canonicalize().ok())
+        .collect()
+}
+
 pub async fn load_or_init_settings() -> Result<()> {
     let path = config_path()?;
-    let mut settings = if path.exists() {
-        let content = fs::read_to_string(&path)?;
-        serde_json::from_str(&content).unwrap_or_default()
-    } else {
-        Settings::default()
-    };
+    let mut settings = read_settings(&path)?;
 
 
     let mut config_changed = false;
 
     if settings.turbopuffer_region.is_none() {
-        match crate::turbopuffer::find_closest_region().await {
+        match crate::turbopuffer::find_closest_region(Some(&settings)).await {
             Ok(best_region) => {
                 settings.turbopuffer_region = Some(best_region);
                 config_changed = true;
@@ -71,6 +230,7 @@ pub async fn load_or_init_settings() -> Result<()> {
         fs::write(&path, content)?;
     }
 
+    check_embedding_models(&settings)?;
     SETTINGS
         .set(settings)
         .map_err(|_| anyhow::anyhow!("Failed to set SETTINGS"))?;
@@ -78,11 +238,33 @@ pub async fn load_or_init_settings() -> Result<()> {
     Ok(())
 }
 
+/// Reject `embedding_models` that aren't Voyage's, the only embedding API turbogrep uses
+fn check_embedding_models(settings: &Settings) -> Result<()> {
+    let mut models = settings.embedding_models.iter().flatten();
+    if let Some(model) = models.find(|m| !crate::embeddings::is_voyage_model(m)) {
+        return Err(anyhow::anyhow!(
+            "embedding_models: '{model}' isn't a Voyage model (voyage-...), the only kind turbogrep can embed with"
+        ));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::env;
 
+    #[test]
+    fn test_check_embedding_models() {
+        let settings = |models: &[&str]| Settings {
+            embedding_models: Some(models.iter().map(|m| m.to_string()).collect()),
+            ..Default::default()
+        };
+        assert!(check_embedding_models(&Settings::default()).is_ok());
+        assert!(check_embedding_models(&settings(&["voyage-3.5", "voyage-3-large"])).is_ok());
+        assert!(check_embedding_models(&settings(&["voyage-3.5", "text-3-small"])).is_err());
+    }
+
     #[test]
     fn test_settings_default() {
         let settings = Settings::default();
@@ -95,6 +277,7 @@ mod tests {
         let settings = Settings {
             turbopuffer_region: Some("test-region".to_string()),
             embedding_provider: Some("voyage".to_string()),
+            ..Default::default()
         };
 
         let json = serde_json::to_string(&settings).unwrap();
@@ -112,6 +295,7 @@ mod tests {
         let settings = Settings {
             turbopuffer_region: None,
             embedding_provider: None,
+            ..Default::default()
         };
 
         let json = serde_json::to_string(&settings).unwrap();
@@ -161,6 +345,57 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_corrupt_config_is_backed_up() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("config.json");
+        let corrupt = r#"{"turbopuffer_region": "aws-eu-central-1", "#;
+        fs::write(&path, corrupt).unwrap();
+
+        let settings = read_settings(&path).unwrap();
+        assert_eq!(settings.turbopuffer_region, None);
+
+        let backup = temp_dir.path().join("config.json.bak");
+        assert_eq!(fs::read_to_string(backup).unwrap(), corrupt);
+
+        // Missing and valid files don't produce a backup
+        fs::remove_file(temp_dir.path().join("config.json.bak")).unwrap();
+        fs::write(&path, r#"{"turbopuffer_region": "gcp-us-east4"}"#).unwrap();
+        let settings = read_settings(&path).unwrap();
+        assert_eq!(settings.turbopuffer_region.as_deref(), Some("gcp-us-east4"));
+        assert!(read_settings(&temp_dir.path().join("missing.json")).is_ok());
+        assert!(!temp_dir.path().join("config.json.bak").exists());
+    }
+
+    #[test]
+    fn test_persist_region_keeps_other_settings() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("config.json");
+        let settings = Settings {
+            turbopuffer_region: Some("gcp-us-east4".to_string()),
+            embedding_provider: Some("voyage".to_string()),
+            write_batch_size: Some(500),
+            ..Default::default()
+        };
+        fs::write(&path, serde_json::to_string_pretty(&settings).unwrap()).unwrap();
+
+        let old_region = persist_region(&path, "aws-eu-central-1").unwrap();
+        assert_eq!(old_region.as_deref(), Some("gcp-us-east4"));
+
+        let saved: Settings = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            saved.turbopuffer_region.as_deref(),
+            Some("aws-eu-central-1")
+        );
+        assert_eq!(saved.embedding_provider.as_deref(), Some("voyage"));
+        assert_eq!(saved.write_batch_size, Some(500));
+
+        // Without a config file yet, one is created
+        let new_path = temp_dir.path().join("fresh.json");
+        assert_eq!(persist_region(&new_path, "gcp-us-central1").unwrap(), None);
+        assert!(new_path.exists());
+    }
+
     #[tokio::test]
     async fn test_get_config_dir_cross_platform() {
         let result = get_config_dir();
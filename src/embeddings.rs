@@ -4,10 +4,13 @@ use base64::{Engine as _, engine::general_purpose};
 use futures::stream::{self, Stream, StreamExt};
 use reqwest::Client;
 use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::env;
 use std::pin::Pin;
-use std::sync::OnceLock;
-use std::time::Instant;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
 /// Result from embedding operation including token usage
 pub struct EmbedResult {
@@ -30,33 +33,55 @@ pub trait Embedding: Clone + Send + 'static {
     /// Maximum number of chunks per batch
     fn max_batch_size(&self) -> usize;
 
+    /// Maximum content length (in bytes) of a single chunk; longer content is truncated
+    fn max_content_bytes(&self) -> usize {
+        DEFAULT_MAX_CONTENT_BYTES
+    }
+
     async fn ping(&self) -> Result<(), EmbeddingError> {
         // Default implementation does nothing
         Ok(())
     }
 
-    /// Default implementation of embed_stream using the core methods
-    /// Implementations typically don't need to override this
+    /// Whether chunks also get vectors of extra models, which a renamed chunk's stored
+    /// `vector` alone can't stand in for
+    fn embeds_extra_models(&self) -> bool {
+        false
+    }
+
+    /// Default implementation of embed_stream using the core methods, adding the tokens
+    /// each batch used to `tokens`. Implementations typically don't need to override this
     fn embed_stream<S>(
         self,
         chunks: S,
         embedding_type: EmbeddingType,
-    ) -> impl Stream<Item = Result<Chunk, EmbeddingError>>
+        tokens: Arc<AtomicUsize>,
+    ) -> impl Stream<Item = Result<Chunk, EmbeddingError>> + Send
     where
         S: Stream<Item = Chunk> + Send + 'static,
     {
         let concurrency = self.concurrency();
         let max_batch_size = self.max_batch_size();
+        let max_content_bytes = self.max_content_bytes();
 
         chunks
+            .map(move |chunk| truncate_chunk_content(chunk, max_content_bytes))
             .chunks(max_batch_size)
             .map(move |batch| {
                 let embedding_impl = self.clone();
-                embedding_impl.embed(batch, embedding_type)
+                async move {
+                    let _span =
+                        crate::profile::span("embed", format!("embed {} chunks", batch.len()));
+                    embedding_impl.embed(batch, embedding_type).await
+                }
             })
             .buffer_unordered(concurrency)
-            .map(|result| match result {
-                Ok(embed_result) => stream::iter(embed_result.chunks.into_iter().map(Ok)).boxed(),
+            .map(move |result| match result {
+                Ok(embed_result) => {
+                    let used = embed_result.total_tokens.unwrap_or_default();
+                    tokens.fetch_add(used, Ordering::Relaxed);
+                    stream::iter(embed_result.chunks.into_iter().map(Ok)).boxed()
+                }
                 Err(e) => stream::once(async move { Err(e) }).boxed(),
             })
             .flatten()
@@ -64,17 +89,135 @@ pub trait Embedding: Clone + Send + 'static {
     }
 }
 
+/// Voyage AI model used for both documents and queries
+pub const VOYAGE_MODEL: &str = "voyage-code-3";
+
+/// Voyage AI cross-encoder used by `--rerank`
+pub const VOYAGE_RERANK_MODEL: &str = "rerank-2.5";
+
+/// Most chunks tg sends Voyage in one embedding request
+pub const VOYAGE_MAX_BATCH_SIZE: usize = 256;
+
+/// Shape of a model's vectors and how much text one request to it may carry, from the
+/// provider's documentation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelInfo {
+    pub provider: &'static str,
+    pub model: &'static str,
+    /// Length of each vector
+    pub dimension: usize,
+    /// Tokens of a single input the model reads, the rest is truncated
+    pub max_input_tokens: usize,
+    /// Tokens all inputs of one request may add up to
+    pub max_batch_tokens: usize,
+    /// Inputs tg sends per request
+    pub max_batch_size: usize,
+}
+
+impl ModelInfo {
+    /// Known limits of Voyage model `model`, `None` for one tg doesn't know
+    pub fn voyage(model: &str) -> Option<Self> {
+        let (model, dimension, max_input_tokens, max_batch_tokens) = match model {
+            "voyage-code-3" => ("voyage-code-3", 1024, 32_000, 120_000),
+            "voyage-code-2" => ("voyage-code-2", 1536, 16_000, 120_000),
+            "voyage-3-large" => ("voyage-3-large", 1024, 32_000, 120_000),
+            "voyage-3.5" => ("voyage-3.5", 1024, 32_000, 320_000),
+            "voyage-3.5-lite" => ("voyage-3.5-lite", 1024, 32_000, 1_000_000),
+            "voyage-3" => ("voyage-3", 1024, 32_000, 320_000),
+            "voyage-3-lite" => ("voyage-3-lite", 512, 32_000, 1_000_000),
+            _ => return None,
+        };
+        Some(Self {
+            provider: "voyage",
+            model,
+            dimension,
+            max_input_tokens,
+            max_batch_tokens,
+            max_batch_size: VOYAGE_MAX_BATCH_SIZE,
+        })
+    }
+}
+
+impl std::fmt::Display for ModelInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "provider: {}", self.provider)?;
+        writeln!(f, "model: {}", self.model)?;
+        writeln!(f, "dimension: {}", self.dimension)?;
+        writeln!(f, "max input tokens: {}", self.max_input_tokens)?;
+        writeln!(f, "max batch tokens: {}", self.max_batch_tokens)?;
+        write!(f, "max batch size: {}", self.max_batch_size)
+    }
+}
+
+/// Default per-chunk content cap, comfortably below voyage-code-3's 32k token context
+pub const DEFAULT_MAX_CONTENT_BYTES: usize = 64 * 1024;
+
+const TRUNCATION_MARKER: &str = "\n... [truncated]";
+
+/// Smallest content cap honored, so truncated content keeps some code before its marker
+pub const MIN_MAX_CONTENT_BYTES: usize = 64;
+
+/// Truncate oversized chunk content (on a char boundary) so that a single huge
+/// function can't exceed the per-input token limit and fail its whole batch. Caps
+/// below [`MIN_MAX_CONTENT_BYTES`] are raised to it.
+pub fn truncate_chunk_content(mut chunk: Chunk, max_bytes: usize) -> Chunk {
+    let max_bytes = max_bytes.max(MIN_MAX_CONTENT_BYTES);
+    if let Some(content) = chunk.content.as_mut()
+        && content.len() > max_bytes
+    {
+        let mut cut = max_bytes.saturating_sub(TRUNCATION_MARKER.len());
+        while !content.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        crate::vprintln!(
+            "<(°~°)> truncating {}:{}-{} from {} to {} bytes before embedding",
+            chunk.path,
+            chunk.start_line,
+            chunk.end_line,
+            content.len(),
+            max_bytes
+        );
+        content.truncate(cut);
+        content.push_str(TRUNCATION_MARKER);
+    }
+    chunk
+}
+
+/// Text sent to the embedding model for a chunk. With a `path_context_root`, the content
+/// is preceded by a `// file: <path relative to root>` header so that the model can use
+/// the file's location (e.g. `payments/refund.rs`) as a hint; the chunk itself is untouched.
+pub fn embedding_text<'a>(chunk: &'a Chunk, path_context_root: Option<&str>) -> Cow<'a, str> {
+    let content = chunk
+        .content
+        .as_ref()
+        .expect("Chunk missing content for embedding")
+        .as_str();
+
+    match path_context_root {
+        Some(root) => {
+            let path = std::path::Path::new(&chunk.path);
+            let relative_path = match path.strip_prefix(root).unwrap_or(path) {
+                relative if relative.as_os_str().is_empty() => std::path::Path::new("."),
+                relative => relative,
+            };
+            Cow::Owned(format!("// file: {}\n{}", relative_path.display(), content))
+        }
+        None => Cow::Borrowed(content),
+    }
+}
+
 /// Embedding type for Voyage AI API - determines how the model processes the text
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum EmbeddingType {
     /// For query text - adds "Represent the query for retrieving supporting documents:" prompt
+    #[default]
     Query,
     /// For document text - adds "Represent the document for retrieval:" prompt  
     Document,
 }
 
 impl EmbeddingType {
-    fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &'static str {
         match self {
             EmbeddingType::Query => "query",
             EmbeddingType::Document => "document",
@@ -82,6 +225,35 @@ impl EmbeddingType {
     }
 }
 
+impl std::str::FromStr for EmbeddingType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "query" => Ok(EmbeddingType::Query),
+            "document" => Ok(EmbeddingType::Document),
+            _ => Err(format!(
+                "unknown embedding type '{s}', expected query or document"
+            )),
+        }
+    }
+}
+
+/// Body of a Voyage AI embeddings request
+pub fn voyage_request_body(
+    texts: &[Cow<str>],
+    model: &str,
+    embedding_type: EmbeddingType,
+) -> serde_json::Value {
+    serde_json::json!({
+        "input": texts,
+        "model": model,
+        "input_type": embedding_type.as_str(),
+        "output_dtype": "float",
+        "encoding_format": "base64"
+    })
+}
+
 /// Choose the embedding provider based on available environment variables
 pub fn choose_embedding_provider() -> Option<String> {
     // Check for Voyage AI API key
@@ -123,6 +295,160 @@ struct VoyageData {
     embedding: String, // Base64-encoded numpy array
 }
 
+#[derive(Debug, Deserialize)]
+struct VoyageRerankResponse {
+    data: Vec<VoyageRerankData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VoyageRerankData {
+    index: usize,
+    relevance_score: f64,
+}
+
+/// Rough bytes per token of source code, to estimate a batch's tokens before sending it
+const ESTIMATED_BYTES_PER_TOKEN: usize = 3;
+
+/// Window over which Voyage counts tokens against the tokens-per-minute limit
+const TOKEN_BUDGET_WINDOW: Duration = Duration::from_secs(60);
+
+/// Estimated tokens of the texts in one embedding request
+pub fn estimate_tokens(texts: &[Cow<str>]) -> usize {
+    texts
+        .iter()
+        .map(|text| text.len().div_ceil(ESTIMATED_BYTES_PER_TOKEN))
+        .sum()
+}
+
+/// Tokens-per-minute limiter shared by concurrent embedding requests. Each request
+/// reserves its estimated tokens before it's sent, waiting while that would exceed the
+/// budget over the last minute, and the reservation is corrected to the tokens Voyage
+/// reports having used once the response is in.
+#[derive(Debug)]
+pub struct TokenBudget {
+    tokens_per_minute: usize,
+    state: Mutex<TokenWindow>,
+}
+
+#[derive(Debug, Default)]
+struct TokenWindow {
+    next_id: u64,
+    /// `(id, reserved at, tokens)`, oldest first
+    reservations: VecDeque<(u64, Instant, usize)>,
+}
+
+impl TokenBudget {
+    pub fn new(tokens_per_minute: usize) -> Self {
+        Self {
+            tokens_per_minute,
+            state: Mutex::new(TokenWindow::default()),
+        }
+    }
+
+    /// Reserve `tokens` at `now` if the budget allows, returning the reservation's id,
+    /// or else how long to wait before trying again. A request larger than the whole
+    /// budget goes through once nothing else is in the window, rather than never.
+    pub fn try_reserve_at(&self, tokens: usize, now: Instant) -> Result<u64, Duration> {
+        let mut window = self.state.lock().unwrap();
+        while let Some((_, at, _)) = window.reservations.front()
+            && now.duration_since(*at) >= TOKEN_BUDGET_WINDOW
+        {
+            window.reservations.pop_front();
+        }
+
+        let mut used: usize = window.reservations.iter().map(|(_, _, t)| t).sum();
+        if used + tokens > self.tokens_per_minute && !window.reservations.is_empty() {
+            // Wait until enough of the oldest reservations have left the window
+            let leaves_room = window.reservations.iter().find_map(|(_, at, reserved)| {
+                used -= reserved;
+                (used == 0 || used + tokens <= self.tokens_per_minute).then_some(*at)
+            });
+            let at = leaves_room.expect("the window empties eventually");
+            return Err(TOKEN_BUDGET_WINDOW.saturating_sub(now.duration_since(at)));
+        }
+
+        window.next_id += 1;
+        let id = window.next_id;
+        window.reservations.push_back((id, now, tokens));
+        Ok(id)
+    }
+
+    /// Wait until `tokens` fit in the budget and reserve them
+    pub async fn reserve(&self, tokens: usize) -> u64 {
+        loop {
+            match self.try_reserve_at(tokens, Instant::now()) {
+                Ok(id) => return id,
+                Err(wait) => {
+                    crate::vprintln!("<(°~°)> embedding token budget spent, waiting {:.1?}", wait);
+                    tokio::time::sleep(wait).await;
+                }
+            }
+        }
+    }
+
+    /// Correct reservation `id` to the tokens actually used
+    pub fn reconcile(&self, id: u64, tokens: usize) {
+        let mut window = self.state.lock().unwrap();
+        if let Some(reservation) = window.reservations.iter_mut().find(|r| r.0 == id) {
+            reservation.2 = tokens;
+        }
+    }
+}
+
+/// How the embedding requests of a sync went, for `--embedding-stats`: the batches
+/// Voyage embedded, and how often a batch over the token limit was split in half and
+/// retried
+#[derive(Debug, Default)]
+pub struct EmbeddingStats {
+    state: Mutex<BatchOutcomes>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct BatchOutcomes {
+    /// Chunks in each embedded batch
+    sizes: Vec<usize>,
+    splits: usize,
+    latency: Duration,
+}
+
+impl EmbeddingStats {
+    /// A batch of `size` chunks embedded in `latency`
+    pub fn record_batch(&self, size: usize, latency: Duration) {
+        let mut outcomes = self.state.lock().unwrap();
+        outcomes.sizes.push(size);
+        outcomes.latency += latency;
+    }
+
+    /// A batch rejected for exceeding the token limit, retried as two halves
+    pub fn record_split(&self) {
+        self.state.lock().unwrap().splits += 1;
+    }
+
+    pub fn batches(&self) -> usize {
+        self.state.lock().unwrap().sizes.len()
+    }
+}
+
+impl std::fmt::Display for EmbeddingStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let outcomes = self.state.lock().unwrap();
+        let batches = outcomes.sizes.len();
+        if batches == 0 {
+            return write!(f, "embedding: no batches, {} splits", outcomes.splits);
+        }
+        let chunks: usize = outcomes.sizes.iter().sum();
+        let smallest = outcomes.sizes.iter().min().unwrap_or(&0);
+        let largest = outcomes.sizes.iter().max().unwrap_or(&0);
+        write!(
+            f,
+            "embedding: {batches} batches of {smallest}-{largest} chunks (avg {:.1}), {} token-limit splits, {:.2?} avg batch latency",
+            chunks as f64 / batches as f64,
+            outcomes.splits,
+            outcomes.latency / batches as u32,
+        )
+    }
+}
+
 static CLIENT: OnceLock<Client> = OnceLock::new();
 
 /// Get a shared HTTP client with optimized configuration
@@ -165,21 +491,111 @@ fn decode_base64_floats(base64_data: &str) -> Result<Vec<f32>, EmbeddingError> {
 }
 
 /// Voyage AI embedding implementation
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct VoyageEmbedding {
     concurrency: usize,
+    model: Arc<str>,
+    extra_models: Arc<[String]>,
+    max_content_bytes: usize,
+    path_context_root: Option<Arc<str>>,
+    token_budget: Option<Arc<TokenBudget>>,
+    stats: Option<Arc<EmbeddingStats>>,
 }
 
 impl VoyageEmbedding {
     pub fn new() -> Self {
-        Self { concurrency: 8 }
+        Self::with_concurrency(8)
     }
 
     pub fn with_concurrency(concurrency: usize) -> Self {
-        Self { concurrency }
+        let max_content_bytes = crate::config::SETTINGS
+            .get()
+            .and_then(|s| s.max_chunk_bytes)
+            .unwrap_or(DEFAULT_MAX_CONTENT_BYTES);
+        Self {
+            concurrency,
+            model: Arc::from(VOYAGE_MODEL),
+            extra_models: Arc::from([]),
+            max_content_bytes,
+            path_context_root: None,
+            token_budget: None,
+            stats: None,
+        }
+    }
+
+    /// Embed with `model` instead of [`VOYAGE_MODEL`], e.g. a query for `--model`
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = Arc::from(model);
+        self
+    }
+
+    /// Also embed every chunk with each of `models`, into
+    /// [`Chunk::extra_vectors`] under its [`vector_column`](crate::turbopuffer::vector_column)
+    pub fn with_extra_models(mut self, models: &[String]) -> Self {
+        self.extra_models = Arc::from(models);
+        self
+    }
+
+    /// Prefix document text with the chunk's path relative to `root` (see [`embedding_text`])
+    pub fn with_path_context(mut self, root: &str) -> Self {
+        self.path_context_root = Some(Arc::from(root));
+        self
+    }
+
+    /// Pace requests to stay under `tokens_per_minute` (see [`TokenBudget`])
+    pub fn with_token_budget(mut self, tokens_per_minute: usize) -> Self {
+        self.token_budget = Some(Arc::new(TokenBudget::new(tokens_per_minute)));
+        self
+    }
+
+    /// Record every batch's outcome in `stats`
+    pub fn with_stats(mut self, stats: Arc<EmbeddingStats>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Correct the token budget's reservation, if any, to the tokens a request used
+    fn reconcile_tokens(&self, reservation: Option<u64>, tokens: usize) {
+        if let (Some(budget), Some(id)) = (&self.token_budget, reservation) {
+            budget.reconcile(id, tokens);
+        }
     }
 }
 
+/// Store the vectors `model` gave `embedded` in the `column` of `chunks`, which must be
+/// the same chunks in the same order
+fn attach_extra_vectors(
+    chunks: &mut [Chunk],
+    column: &str,
+    model: &str,
+    embedded: Vec<Chunk>,
+) -> Result<(), EmbeddingError> {
+    if embedded.len() != chunks.len() {
+        return Err(EmbeddingError::ApiError(format!(
+            "{model} returned {} embeddings for {} chunks",
+            embedded.len(),
+            chunks.len()
+        )));
+    }
+    for (chunk, embedded) in chunks.iter_mut().zip(embedded) {
+        if embedded.id != chunk.id {
+            return Err(EmbeddingError::ApiError(format!(
+                "{model} returned embeddings out of order at {}:{}",
+                chunk.path, chunk.start_line
+            )));
+        }
+        if let Some(vector) = embedded.vector {
+            chunk.extra_vectors.insert(column.to_string(), vector);
+        }
+    }
+    Ok(())
+}
+
+/// Whether `model` is one of Voyage's, the only embedding API turbogrep talks to
+pub fn is_voyage_model(model: &str) -> bool {
+    model.starts_with("voyage-")
+}
+
 impl Embedding for VoyageEmbedding {
     fn embed(
         self,
@@ -189,7 +605,26 @@ impl Embedding for VoyageEmbedding {
         async move {
             let api_key =
                 std::env::var("VOYAGE_API_KEY").map_err(|_| EmbeddingError::MissingApiKey)?;
-            self.embed_batch_impl(chunks, embedding_type, api_key).await
+            let originals = (!self.extra_models.is_empty()).then(|| chunks.clone());
+            let mut result = self
+                .embed_batch_impl(chunks, embedding_type, api_key.clone())
+                .await?;
+
+            for model in self.extra_models.iter() {
+                let chunks = originals.clone().unwrap_or_default();
+                let column = crate::turbopuffer::vector_column(model);
+                let extra = self
+                    .clone()
+                    .with_model(model)
+                    .embed_batch_impl(chunks, embedding_type, api_key.clone())
+                    .await?;
+                attach_extra_vectors(&mut result.chunks, &column, model, extra.chunks)?;
+                result.total_tokens = match (result.total_tokens, extra.total_tokens) {
+                    (Some(a), b) => Some(a + b.unwrap_or_default()),
+                    (None, b) => b,
+                };
+            }
+            Ok(result)
         }
     }
 
@@ -198,7 +633,15 @@ impl Embedding for VoyageEmbedding {
     }
 
     fn max_batch_size(&self) -> usize {
-        256
+        VOYAGE_MAX_BATCH_SIZE
+    }
+
+    fn max_content_bytes(&self) -> usize {
+        self.max_content_bytes
+    }
+
+    fn embeds_extra_models(&self) -> bool {
+        !self.extra_models.is_empty()
     }
 
     async fn ping(&self) -> Result<(), EmbeddingError> {
@@ -215,6 +658,39 @@ impl Embedding for VoyageEmbedding {
 }
 
 impl VoyageEmbedding {
+    /// Score how relevant each of `documents` is to `query` with Voyage's cross-encoder.
+    /// Scores are returned in the order of `documents`, higher is more relevant.
+    pub async fn rerank(
+        &self,
+        query: &str,
+        documents: &[&str],
+    ) -> Result<Vec<f64>, EmbeddingError> {
+        let api_key = std::env::var("VOYAGE_API_KEY").map_err(|_| EmbeddingError::MissingApiKey)?;
+        let response = get_client()
+            .post("https://api.voyageai.com/v1/rerank")
+            .header("Authorization", format!("Bearer {api_key}"))
+            .json(&serde_json::json!({
+                "query": query,
+                "documents": documents,
+                "model": VOYAGE_RERANK_MODEL,
+                "truncation": true
+            }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(EmbeddingError::ApiError(response.text().await?));
+        }
+
+        let resp: VoyageRerankResponse = response.json().await?;
+        let mut scores = vec![f64::MIN; documents.len()];
+        for data in resp.data {
+            if let Some(score) = scores.get_mut(data.index) {
+                *score = data.relevance_score;
+            }
+        }
+        Ok(scores)
+    }
+
     /// Internal boxed-future implementation to allow recursive splitting
     fn embed_batch_impl(
         &self,
@@ -224,35 +700,34 @@ impl VoyageEmbedding {
     ) -> Pin<Box<dyn std::future::Future<Output = Result<EmbedResult, EmbeddingError>> + Send + '_>>
     {
         Box::pin(async move {
-            let _instant = Instant::now();
-            let _batch_size = chunks.len();
+            let instant = Instant::now();
             let client = get_client();
 
-            // Extract texts for the API call
-            let texts: Vec<&str> = chunks
+            // Extract texts for the API call, queries never get a path header
+            let path_context_root = match embedding_type {
+                EmbeddingType::Document => self.path_context_root.as_deref(),
+                EmbeddingType::Query => None,
+            };
+            let texts: Vec<Cow<str>> = chunks
                 .iter()
-                .map(|c| {
-                    c.content
-                        .as_ref()
-                        .expect("Chunk missing content for embedding")
-                        .as_str()
-                })
+                .map(|c| embedding_text(c, path_context_root))
                 .collect();
+            let estimated_tokens = estimate_tokens(&texts);
+            let reservation = match &self.token_budget {
+                Some(budget) => Some(budget.reserve(estimated_tokens).await),
+                None => None,
+            };
 
             let response = client
                 .post("https://api.voyageai.com/v1/embeddings")
                 .header("Authorization", format!("Bearer {api_key}"))
-                .json(&serde_json::json!({
-                    "input": texts,
-                    "model": "voyage-code-3",
-                    "input_type": embedding_type.as_str(),
-                    "output_dtype": "float",
-                    "encoding_format": "base64"
-                }))
+                .json(&voyage_request_body(&texts, &self.model, embedding_type))
                 .send()
                 .await?;
 
             if !response.status().is_success() {
+                // Failed requests aren't billed, and a split batch reserves its halves anew
+                self.reconcile_tokens(reservation, 0);
                 let error_text = response.text().await?;
                 // If batch exceeds model token limit, split in half and retry recursively
                 if error_text
@@ -260,6 +735,9 @@ impl VoyageEmbedding {
                     .contains("max allowed tokens per submitted batch")
                     && chunks.len() > 1
                 {
+                    if let Some(stats) = &self.stats {
+                        stats.record_split();
+                    }
                     let mid = chunks.len() / 2;
                     let left_chunks = chunks[..mid].to_vec();
                     let right_chunks = chunks[mid..].to_vec();
@@ -293,6 +771,12 @@ impl VoyageEmbedding {
             }
 
             let resp: VoyageResponse = response.json().await?;
+            if let Some(usage) = &resp.usage {
+                self.reconcile_tokens(reservation, usage.total_tokens);
+            }
+            if let Some(stats) = &self.stats {
+                stats.record_batch(chunks.len(), instant.elapsed());
+            }
 
             // Combine chunks with their embeddings, decoding base64 to f32
             let embedded_chunks = chunks
@@ -337,6 +821,17 @@ mod tests {
         assert_eq!(embedding.concurrency(), 8);
     }
 
+    #[test]
+    fn test_voyage_model_info() {
+        let info = ModelInfo::voyage(VOYAGE_MODEL).unwrap();
+        assert_eq!(info.dimension, 1024);
+        assert_eq!(info.max_batch_tokens, 120_000);
+        assert_eq!(info.max_batch_size, VoyageEmbedding::new().max_batch_size());
+        assert!(info.to_string().contains("dimension: 1024"));
+
+        assert_eq!(ModelInfo::voyage("my-finetune"), None);
+    }
+
     #[test]
     fn test_embedding_error_display() {
         let missing_key_error = EmbeddingError::MissingApiKey;
@@ -436,10 +931,217 @@ mod tests {
         });
 
         let embedding = VoyageEmbedding::new();
-        let _stream = embedding.embed_stream(chunks, EmbeddingType::Query);
+        let _stream = embedding.embed_stream(chunks, EmbeddingType::Query, Arc::default());
         // Just test that it compiles and returns a stream
     }
 
+    #[test]
+    fn test_embedding_text_path_context() {
+        let chunk = Chunk {
+            path: "/repo/payments/refund.rs".to_string(),
+            content: Some("fn refund() {}".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(embedding_text(&chunk, None), "fn refund() {}");
+        assert_eq!(
+            embedding_text(&chunk, Some("/repo")),
+            "// file: payments/refund.rs\nfn refund() {}"
+        );
+        // Only the embedded text gets the header, not the displayed content
+        assert_eq!(chunk.content.as_deref(), Some("fn refund() {}"));
+    }
+
+    #[test]
+    fn test_token_budget_paces_submissions() {
+        let budget = TokenBudget::new(1000);
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        assert!(budget.try_reserve_at(600, at(0)).is_ok());
+        let second = budget.try_reserve_at(300, at(10)).unwrap();
+        // The first reservation leaves room once it's a minute old
+        assert_eq!(
+            budget.try_reserve_at(600, at(20)),
+            Err(Duration::from_secs(40))
+        );
+        assert!(budget.try_reserve_at(600, at(60)).is_ok());
+
+        // Tokens reported after the response replace the estimate
+        assert_eq!(
+            budget.try_reserve_at(200, at(61)),
+            Err(Duration::from_secs(9))
+        );
+        budget.reconcile(second, 100);
+        assert!(budget.try_reserve_at(200, at(61)).is_ok());
+
+        // A request over the whole budget waits for an empty window, not forever
+        assert_eq!(
+            budget.try_reserve_at(5000, at(62)),
+            Err(Duration::from_secs(59))
+        );
+        assert!(budget.try_reserve_at(5000, at(121)).is_ok());
+    }
+
+    #[test]
+    fn test_embedding_stats_aggregate_batches_and_splits() {
+        let stats = EmbeddingStats::default();
+        assert_eq!(stats.to_string(), "embedding: no batches, 0 splits");
+
+        // A full batch over the token limit split into halves, then a smaller batch
+        stats.record_split();
+        stats.record_batch(128, Duration::from_millis(300));
+        stats.record_batch(128, Duration::from_millis(500));
+        stats.record_batch(44, Duration::from_millis(100));
+        assert_eq!(stats.batches(), 3);
+        assert_eq!(
+            stats.to_string(),
+            "embedding: 3 batches of 44-128 chunks (avg 100.0), 1 token-limit splits, 300.00ms avg batch latency"
+        );
+    }
+
+    #[test]
+    fn test_attach_extra_vectors_checks_alignment() {
+        let chunk = |id: u128| Chunk {
+            id,
+            path: "lib.rs".to_string(),
+            ..Default::default()
+        };
+        let embedded = |id: u128| Chunk {
+            vector: Some(vec![id as f32]),
+            ..chunk(id)
+        };
+
+        let mut chunks = vec![chunk(1), chunk(2)];
+        attach_extra_vectors(
+            &mut chunks,
+            "vector_b",
+            "voyage-b",
+            vec![embedded(1), embedded(2)],
+        )
+        .unwrap();
+        assert_eq!(chunks[1].extra_vectors["vector_b"], vec![2.0]);
+
+        // Missing or reordered embeddings would put vectors on the wrong chunks
+        assert!(
+            attach_extra_vectors(&mut chunks, "vector_b", "voyage-b", vec![embedded(1)]).is_err()
+        );
+        assert!(
+            attach_extra_vectors(
+                &mut chunks,
+                "vector_b",
+                "voyage-b",
+                vec![embedded(2), embedded(1)]
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        let texts = [Cow::Borrowed("fn refund() {}"), Cow::Borrowed("")];
+        assert_eq!(estimate_tokens(&texts), 5);
+    }
+
+    #[test]
+    fn test_truncate_chunk_content() {
+        let chunk = Chunk {
+            content: Some("é".repeat(100)), // 200 bytes of 2-byte chars
+            ..Default::default()
+        };
+
+        let truncated = truncate_chunk_content(chunk.clone(), 64);
+        let content = truncated.content.unwrap();
+        assert!(content.len() <= 64);
+        assert!(content.ends_with(TRUNCATION_MARKER));
+
+        // Content under the cap is untouched
+        let untouched = truncate_chunk_content(chunk.clone(), 1024);
+        assert_eq!(untouched.content, chunk.content);
+
+        // A cap too small for the marker still keeps some of the content
+        for max_bytes in [0, 4, TRUNCATION_MARKER.len()] {
+            let content = truncate_chunk_content(chunk.clone(), max_bytes)
+                .content
+                .unwrap();
+            assert!(content.len() <= MIN_MAX_CONTENT_BYTES);
+            assert!(content.starts_with("éé"));
+            assert!(content.ends_with(TRUNCATION_MARKER));
+        }
+    }
+
+    /// Rejects any batch containing content over its limit, like an API would
+    #[derive(Clone)]
+    struct StrictEmbedding;
+
+    impl Embedding for StrictEmbedding {
+        async fn embed(
+            self,
+            chunks: Vec<Chunk>,
+            _embedding_type: EmbeddingType,
+        ) -> Result<EmbedResult, EmbeddingError> {
+            if chunks
+                .iter()
+                .any(|c| c.content.as_ref().unwrap().len() > self.max_content_bytes())
+            {
+                return Err(EmbeddingError::ApiError("input too long".to_string()));
+            }
+            let chunks = chunks
+                .into_iter()
+                .map(|mut c| {
+                    c.vector = Some(vec![0.0]);
+                    c
+                })
+                .collect();
+            Ok(EmbedResult {
+                chunks,
+                total_tokens: None,
+            })
+        }
+
+        fn concurrency(&self) -> usize {
+            1
+        }
+
+        fn max_batch_size(&self) -> usize {
+            8
+        }
+
+        fn max_content_bytes(&self) -> usize {
+            100
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embed_stream_truncates_oversized_chunk() {
+        let chunks = vec![
+            Chunk {
+                content: Some("fn small() {}".to_string()),
+                ..Default::default()
+            },
+            Chunk {
+                content: Some("x".repeat(10_000)),
+                ..Default::default()
+            },
+        ];
+
+        let results: Vec<_> = StrictEmbedding
+            .embed_stream(
+                futures::stream::iter(chunks),
+                EmbeddingType::Document,
+                Arc::default(),
+            )
+            .collect()
+            .await;
+
+        assert_eq!(results.len(), 2);
+        for result in results {
+            let chunk = result.expect("oversized chunk should not fail the batch");
+            assert!(chunk.vector.is_some());
+            assert!(chunk.content.unwrap().len() <= 100);
+        }
+    }
+
     #[test]
     fn test_choose_embedding_provider_with_voyage_key() {
         // Test with VOYAGE_API_KEY set
@@ -0,0 +1,125 @@
+use crate::chunker::Chunk;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Share of an index's chunks a language needs to count as dominant for `--auto-lang`
+const DOMINANT_SHARE: f64 = 0.2;
+
+/// How an index's chunks are spread over languages, by file extension as `--lang` takes
+/// them. Recorded by every full sync so `--auto-lang` can scope searches to the project's
+/// main languages without asking turbopuffer.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LanguageMix {
+    chunks: BTreeMap<String, usize>,
+}
+
+impl LanguageMix {
+    pub fn from_chunks(chunks: &[Chunk]) -> Self {
+        let mut mix = Self::default();
+        for chunk in chunks {
+            if let Some(extension) = Path::new(&chunk.path).extension() {
+                let extension = extension.to_string_lossy().to_string();
+                *mix.chunks.entry(extension).or_default() += 1;
+            }
+        }
+        mix
+    }
+
+    /// Extensions with at least [`DOMINANT_SHARE`] of the chunks, most common first
+    pub fn dominant(&self) -> Vec<String> {
+        let total: usize = self.chunks.values().sum();
+        let mut dominant: Vec<(&String, &usize)> = self
+            .chunks
+            .iter()
+            .filter(|&(_, &count)| count as f64 >= total as f64 * DOMINANT_SHARE)
+            .collect();
+        dominant.sort_by(|a, b| b.1.cmp(a.1));
+        dominant
+            .into_iter()
+            .map(|(extension, _)| extension.clone())
+            .collect()
+    }
+}
+
+/// On-disk language mixes of the last full sync, one file per namespace
+#[derive(Debug, Clone)]
+pub struct LanguageMixStore {
+    dir: PathBuf,
+}
+
+impl LanguageMixStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Mixes in the user's cache directory, `None` if it can't be located
+    pub fn from_cache_dir() -> Option<Self> {
+        let dir = crate::config::cache_dir().ok()?.join("languages");
+        Some(Self::new(dir))
+    }
+
+    fn path(&self, namespace: &str) -> PathBuf {
+        self.dir.join(format!("{namespace}.json"))
+    }
+
+    pub fn load(&self, namespace: &str) -> Option<LanguageMix> {
+        let bytes = std::fs::read(self.path(namespace)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    pub fn save(&self, namespace: &str, mix: &LanguageMix) {
+        let written = std::fs::create_dir_all(&self.dir).and_then(|_| {
+            let json = serde_json::to_vec(mix).map_err(std::io::Error::other)?;
+            std::fs::write(self.path(namespace), json)
+        });
+        if let Err(e) = written {
+            crate::vprintln!("<(°~°)> Couldn't write language mix: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(files: &[(&str, usize)]) -> Vec<Chunk> {
+        files
+            .iter()
+            .flat_map(|&(path, count)| {
+                (0..count).map(move |_| Chunk {
+                    path: path.to_string(),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_auto_scope_excludes_minority_language() {
+        let mix = LanguageMix::from_chunks(&chunks(&[
+            ("/repo/server/main.go", 60),
+            ("/repo/server/handler.go", 35),
+            ("/repo/scripts/deploy.sh", 5),
+        ]));
+        assert_eq!(mix.dominant(), vec!["go"]);
+
+        // A real split keeps both, the bigger share first
+        let mix = LanguageMix::from_chunks(&chunks(&[
+            ("/repo/web/app.ts", 40),
+            ("/repo/api/main.py", 60),
+        ]));
+        assert_eq!(mix.dominant(), vec!["py", "ts"]);
+    }
+
+    #[test]
+    fn test_mix_round_trips_through_store() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = LanguageMixStore::new(temp_dir.path().to_path_buf());
+        assert_eq!(store.load("tg_test"), None);
+
+        let mix = LanguageMix::from_chunks(&chunks(&[("/repo/lib.rs", 3)]));
+        store.save("tg_test", &mix);
+        assert_eq!(store.load("tg_test"), Some(mix));
+    }
+}
@@ -2,9 +2,14 @@ use std::sync::OnceLock;
 use std::time::Instant;
 
 static VERBOSE: OnceLock<bool> = OnceLock::new();
+static QUIET: OnceLock<bool> = OnceLock::new();
+static COLOR: OnceLock<String> = OnceLock::new();
 pub static START_TIME: OnceLock<Instant> = OnceLock::new();
 
 pub fn is_verbose() -> bool {
+    if is_quiet() {
+        return false;
+    }
     // TURBOGREP_VERBOSE environment variable OR TG_VERBOSE
     for var in ["TURBOGREP_VERBOSE", "TG_VERBOSE"] {
         if let Ok(verbose) = std::env::var(var) {
@@ -18,6 +23,59 @@ pub fn set_verbose(verbose: bool) {
     VERBOSE.set(verbose).ok();
 }
 
+pub fn is_quiet() -> bool {
+    // TURBOGREP_QUIET environment variable OR TG_QUIET
+    for var in ["TURBOGREP_QUIET", "TG_QUIET"] {
+        if let Ok(quiet) = std::env::var(var) {
+            return quiet == "1" || quiet.to_lowercase() == "true";
+        }
+    }
+    *QUIET.get().unwrap_or(&false)
+}
+
+pub fn set_quiet(quiet: bool) {
+    QUIET.set(quiet).ok();
+}
+
+/// Whether to colorize output. An explicit `--color always|never` wins, then `NO_COLOR`
+/// (any non-empty value) turns color off and `CLICOLOR_FORCE` (anything but `0`) turns it
+/// on, see https://no-color.org. Otherwise color only goes to a terminal.
+pub fn should_color(
+    flag: Option<&str>,
+    no_color: Option<&str>,
+    clicolor_force: Option<&str>,
+    stdout_is_terminal: bool,
+) -> bool {
+    match flag {
+        Some("always") => return true,
+        Some("never") => return false,
+        _ => {}
+    }
+    if no_color.is_some_and(|value| !value.is_empty()) {
+        return false;
+    }
+    if clicolor_force.is_some_and(|value| !value.is_empty() && value != "0") {
+        return true;
+    }
+    stdout_is_terminal
+}
+
+pub fn use_color() -> bool {
+    use std::io::IsTerminal;
+
+    should_color(
+        COLOR.get().map(String::as_str),
+        std::env::var("NO_COLOR").ok().as_deref(),
+        std::env::var("CLICOLOR_FORCE").ok().as_deref(),
+        std::io::stdout().is_terminal(),
+    )
+}
+
+/// `--color` choice: `auto`, `always` or `never`
+pub fn set_color(choice: &str) {
+    COLOR.set(choice.to_string()).ok();
+}
+
 #[macro_export]
 macro_rules! vprintln {
     ($($arg:tt)*) => {
@@ -29,17 +87,55 @@ macro_rules! vprintln {
     };
 }
 
+/// Print a warning or note to stderr, unless running with --quiet
+#[macro_export]
+macro_rules! warnln {
+    ($($arg:tt)*) => {
+        if !$crate::is_quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
 // Re-export project functions for backward compatibility
 pub use project::{find_project_root, namespace_and_dir, validate_directory};
 
 // Re-export progress bar function for backward compatibility
 pub use progress::tg_progress_bar;
 
+pub mod archive;
 pub mod chunker;
 pub mod config;
 pub mod embeddings;
+pub mod language_mix;
+pub mod manifest;
+pub mod profile;
 pub mod progress;
 pub mod project;
+pub mod query_cache;
 pub mod search;
 pub mod sync;
 pub mod turbopuffer;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_color_overrides_terminal() {
+        assert!(should_color(None, None, None, true));
+        assert!(!should_color(None, None, None, false));
+
+        // NO_COLOR disables color even on a terminal, unless it's empty
+        assert!(!should_color(None, Some("1"), None, true));
+        assert!(!should_color(Some("auto"), Some("yes"), None, true));
+        assert!(should_color(None, Some(""), None, true));
+        // ... and beats CLICOLOR_FORCE, but not an explicit --color always
+        assert!(!should_color(None, Some("1"), Some("1"), true));
+        assert!(should_color(Some("always"), Some("1"), None, true));
+
+        assert!(should_color(None, None, Some("1"), false));
+        assert!(!should_color(None, None, Some("0"), false));
+        assert!(!should_color(Some("never"), None, Some("1"), true));
+    }
+}
@@ -5,20 +5,89 @@ use owo_colors::OwoColorize;
 use rand::prelude::*;
 use rand::rngs::StdRng;
 use std::path::Path;
-use turbogrep::{config, is_verbose, namespace_and_dir, vprintln};
+use std::process::ExitCode;
+use turbogrep::{config, is_verbose, namespace_and_dir, profile, vprintln, warnln};
 
+mod archive;
 mod chunker;
 mod embeddings;
+mod language_mix;
+mod manifest;
 mod progress;
 mod project;
+mod query_cache;
 mod search;
 mod sync;
 mod turbopuffer;
 
+/// Read a semantic query from a file, ignoring surrounding whitespace
+fn read_query_file(path: &str) -> Result<String, String> {
+    let query = std::fs::read_to_string(path)
+        .map_err(|e| format!("Can't read query file '{path}': {e}"))?;
+    let query = query.trim();
+    if query.is_empty() {
+        return Err(format!("Query file '{path}' is empty"));
+    }
+    Ok(query.to_string())
+}
+
+/// Text of the saved query NAME (`tg :NAME`), from the first of
+/// [`config::saved_query_dirs`] that has it
+fn read_saved_query(name: &str, directory: &str) -> Result<String, String> {
+    let dirs = config::saved_query_dirs(Path::new(directory));
+    match dirs
+        .iter()
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+    {
+        Some(path) => read_query_file(&path.to_string_lossy()),
+        None => Err(format!(
+            "No saved query '{name}' in .turbogrep/queries or the config's queries directory"
+        )),
+    }
+}
+
+/// The query, the directory to index from and, when PATH is a glob, a file or several
+/// paths, the absolute glob patterns that scope indexing and search
+type ParsedArgs = (Option<String>, String, Option<Vec<String>>);
+
 /// Parse CLI arguments with ripgrep-style logic
-fn parse_cli_args(cli: &Cli) -> Result<(Option<String>, String), String> {
-    let (query, start_directory) = match (&cli.pattern, &cli.path) {
-        (None, None) => {
+fn parse_cli_args(cli: &Cli) -> Result<ParsedArgs, String> {
+    if cli.query_file.is_some() || cli.browse {
+        // With --query-file or --browse every positional argument is a PATH, so one that
+        // is neither an existing path nor a glob is a stray PATTERN
+        if let Some(pattern) = &cli.pattern
+            && !project::is_glob(pattern)
+            && !Path::new(pattern).exists()
+        {
+            let flag = if cli.browse {
+                "--browse"
+            } else {
+                "--query-file"
+            };
+            return Err(format!("{flag} can't be combined with a PATTERN argument"));
+        }
+        let paths: Vec<String> = cli.pattern.iter().chain(&cli.path).cloned().collect();
+        let (start_directory, scope) = if paths.is_empty() {
+            (
+                std::env::current_dir()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                None,
+            )
+        } else {
+            project::resolve_paths(&paths)?
+        };
+        let query = match &cli.query_file {
+            Some(query_file) => Some(read_query_file(query_file)?),
+            None => None,
+        };
+        return Ok((query, start_directory, scope));
+    }
+
+    let (query, start_directory, scope) = match (&cli.pattern, cli.path.as_slice()) {
+        (None, _) => {
             // No arguments - index current directory
             (
                 None,
@@ -26,52 +95,104 @@ fn parse_cli_args(cli: &Cli) -> Result<(Option<String>, String), String> {
                     .unwrap_or_default()
                     .to_string_lossy()
                     .to_string(),
+                None,
             )
         }
-        (Some(pattern), None) => {
+        (Some(pattern), []) => {
             // Single argument - check if it's a directory or a query
             if Path::new(pattern).is_dir() {
                 // turbogrep PATH - index directory only
-                (None, pattern.clone())
+                (None, pattern.clone(), None)
             } else if Path::new(pattern).exists() {
                 // Path exists but is not a directory - this is an error
                 return Err(format!(r#"'{pattern}' exists but is not a directory"#));
             } else if pattern.starts_with('/') || pattern.starts_with('.') {
                 // Argument looks like a path but doesn't exist - warn user
-                eprintln!(
+                warnln!(
                     "<(°~°)> Warning: '{pattern}' looks like a directory path but doesn't exist.",
                 );
-                eprintln!(
+                warnln!(
                     "<(°◯°)> Treating '{pattern}' as a search query and searching current directory.",
                 );
-                eprintln!("<(°◯°)> If you meant to specify a directory, please check the path.");
+                warnln!("<(°◯°)> If you meant to specify a directory, please check the path.");
                 let directory = std::env::current_dir()
                     .unwrap_or_default()
                     .to_string_lossy()
                     .to_string();
-                (Some(pattern.clone()), directory)
+                (Some(pattern.clone()), directory, None)
             } else {
                 // turbogrep PATTERN - search current directory
                 let directory = std::env::current_dir()
                     .unwrap_or_default()
                     .to_string_lossy()
                     .to_string();
-                (Some(pattern.clone()), directory)
+                (Some(pattern.clone()), directory, None)
             }
         }
-        (Some(pattern), Some(path)) => {
-            // turbogrep PATTERN PATH - validate directory exists
-            project::validate_directory(path)?;
-            (Some(pattern.clone()), path.clone())
+        (Some(pattern), paths) => {
+            // turbogrep PATTERN PATH... - a directory, or files/globs scoping the search
+            let (directory, scope) = project::resolve_paths(paths)?;
+            (Some(pattern.clone()), directory, scope)
         }
-        (None, Some(path)) => {
-            // turbogrep PATH - index directory only
-            project::validate_directory(path)?;
-            (None, path.clone())
+    };
+
+    // `:NAME` runs the saved query NAME
+    let query = match query.as_deref().and_then(|q| q.strip_prefix(':')) {
+        Some(name) if !name.is_empty() && !name.contains(char::is_whitespace) => {
+            Some(read_saved_query(name, &start_directory)?)
         }
+        _ => query,
     };
+    // Caught here rather than by the search, so that no index sync is started for it
+    if query.as_deref().is_some_and(|q| q.trim().is_empty()) {
+        return Err(
+            "empty query, describe the code to find (e.g. tg \"retry failed uploads\"), \
+             or leave out PATTERN to only index"
+                .to_string(),
+        );
+    }
+    Ok((query, start_directory, scope))
+}
 
-    Ok((query, start_directory))
+/// How `--sample` prints chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum SampleFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for SampleFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(SampleFormat::Text),
+            "json" => Ok(SampleFormat::Json),
+            _ => Err(format!("unknown format '{s}', expected text or json")),
+        }
+    }
+}
+
+/// Sampled chunks as a JSON array, for scripts evaluating chunk quality
+fn sampled_chunks_json(chunks: &[chunker::Chunk]) -> serde_json::Value {
+    chunks
+        .iter()
+        .map(|chunk| {
+            serde_json::json!({
+                "path": chunk.path,
+                "start_line": chunk.start_line,
+                "end_line": chunk.end_line,
+                "start_byte": chunk.start_byte,
+                "end_byte": chunk.end_byte,
+                "file_hash": chunk.file_hash,
+                "chunk_hash": chunk.chunk_hash,
+                "kind": chunk.kind,
+                "symbol": chunk.symbol,
+                "content": chunk.content,
+            })
+        })
+        .collect()
 }
 
 /// Sample N random chunks with deterministic seeding based on directory path
@@ -102,10 +223,23 @@ fn sample_random_chunks(
     sampled
 }
 
+/// Commands besides searching and indexing, named before any other argument
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Print the version; with --verbose also the grammars, embedding model and region
+    Version,
+    /// List turbogrep namespaces and when each was last synced
+    Namespaces {
+        /// Only list namespaces not synced within AGE (e.g. 30d, 12h)
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        stale: Option<u64>,
+    },
+}
+
 /// Fast semantic code search powered by AI embeddings and turbopuffer
 #[derive(Parser)]
 #[command(name = "tg")]
-#[command(version = "0.1.0")]
+#[command(version, disable_version_flag = true)]
 #[command(about = "Fast semantic code search powered by AI embeddings and turbopuffer")]
 #[command(long_about = "
 turbogrep uses AI embeddings via Voyage AI to enable semantic code search.
@@ -116,7 +250,14 @@ EXAMPLES:
     tg \"error handling\" ./src               Search specific directory
     tg ./src                               Index directory only
     tg --reset .                           Reset index and sync
+    tg --diff .                            Show what the next sync would change
     tg --no-sync \"query\" .                  Search without syncing
+    tg --at 1a2b3c \"query\" .                Search chunks recorded at a commit
+    tg --query-file query.txt ./src        Search with a query read from a file
+    tg :auth                               Run the query saved in .turbogrep/queries/auth
+    tg --sync-into app ./web ./api         Index two directories as the one index \"app\"
+    tg \"auth\" 'src/**/handlers'            Search (and index) only matching paths
+    tg namespaces --stale 30d              List indexes not synced in the last 30 days
 
 REGIONS:
     Common turbopuffer regions: gcp-us-central1, gcp-us-east1, gcp-us-west1,
@@ -125,20 +266,47 @@ REGIONS:
 ENVIRONMENT:
     TURBOPUFFER_API_KEY                     Required for vector storage
     VOYAGE_API_KEY                          Required for AI embeddings
+
+EXIT STATUS:
+    0    Success, results were found (or indexing completed)
+    1    Search succeeded but returned no results
+    2    Usage or configuration error (bad path, missing API key, ...)
+    3    API or network error
 ")]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Search query (semantic search using AI embeddings)
     #[arg(value_name = "PATTERN")]
     pattern: Option<String>,
 
-    /// Directory to search/index (default: current directory)
+    /// Directory to search/index (default: current directory).
+    /// Also accepts files, globs like 'src/**/handlers' or several paths to narrow the search
     #[arg(value_name = "PATH")]
-    path: Option<String>,
+    path: Vec<String>,
 
     /// Enable verbose output
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     verbose: bool,
 
+    /// Suppress everything except results and hard errors
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Always draw the live progress bar, even in CI or when stderr is redirected
+    #[arg(long)]
+    progress: bool,
+
+    /// When to color output; `auto` honours NO_COLOR and CLICOLOR_FORCE, then colors
+    /// only a terminal
+    #[arg(long, value_name = "WHEN", default_value = "auto", value_parser = ["auto", "always", "never"])]
+    color: String,
+
+    /// Print version (see `tg version --verbose` for grammar, model and region details)
+    #[arg(short = 'V', long)]
+    version: bool,
+
     /// Only chunk files (no embedding/indexing)
     #[arg(long)]
     chunk_only: bool,
@@ -159,160 +327,1456 @@ struct Cli {
     #[arg(short = 'm', long = "max-count", default_value = "20")]
     max_count: usize,
 
+    /// Show up to N lines of each result's content, marking longer ones as truncated
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    max_content_lines: Option<u64>,
+
+    /// Show each result as the whole top-level definition containing it, e.g. a nested
+    /// function's enclosing function, re-parsing the local file
+    #[arg(long, conflicts_with_all = ["bytes", "namespace"])]
+    context_symbol: bool,
+
+    /// Only print the paths of files with results, best first, fetching just their
+    /// locations from the index
+    #[arg(short = 'l', long, conflicts_with_all = [
+        "browse", "rerank", "scores", "bytes", "snapshot", "max_content_lines",
+        "context_symbol",
+    ])]
+    files_only: bool,
+
+    /// Write a timing trace of the run's phases to FILE, in Chrome trace format (open it
+    /// in chrome://tracing, Perfetto or speedscope)
+    #[arg(long, value_name = "FILE")]
+    profile: Option<std::path::PathBuf>,
+
+    /// Read the search query from FILE instead of the PATTERN argument
+    #[arg(long, value_name = "FILE")]
+    query_file: Option<String>,
+
     /// Output N random (seeded) chunks to stdout
     #[arg(long = "sample")]
     sample: Option<usize>,
 
+    /// Print --sample chunks as FORMAT: text (default) or json, an array of records with
+    /// each chunk's path, lines, bytes, hashes and content
+    #[arg(long, value_name = "FORMAT", requires = "sample")]
+    format: Option<SampleFormat>,
+
     /// Override embedding provider concurrency (default: 2)
     /// Higher values = faster embedding but more API load
     #[arg(long = "embedding-concurrency")]
     embedding_concurrency: Option<usize>,
 
-    /// Show distance scores in output (lower is better)
+    /// Pace embedding to stay under Voyage's tokens-per-minute limit, for repos that hit
+    /// it before the request limit
+    #[arg(long, value_name = "TOKENS")]
+    embedding_tpm: Option<usize>,
+
+    /// Files read at once when loading result previews (default: 16)
+    #[arg(long, value_name = "N")]
+    content_concurrency: Option<usize>,
+
+    /// Show distance scores in output (lower is better, under the index's distance metric)
     #[arg(long)]
     scores: bool,
+
+    /// Show each result's byte offsets in its file (start-end) after the line number
+    #[arg(long)]
+    bytes: bool,
+
+    /// Show the name of the function or type each result defines (e.g. `parse_config`)
+    /// after its location, `-` for chunks indexed before names were recorded
+    #[arg(long)]
+    show_symbol: bool,
+
+    /// Print results sorted by file and line, with scores, for diffable snapshots
+    #[arg(long)]
+    snapshot: bool,
+
+    /// Index and search exactly the given directory, instead of the project root it's in
+    #[arg(long, visible_alias = "no-root-detection")]
+    flat: bool,
+
+    /// Keep a separate index per git branch: NAME's, or the checked-out branch's
+    #[arg(long, value_name = "NAME", num_args = 0..=1)]
+    branch: Option<Option<String>>,
+
+    /// Sync every PATH, each a directory, into the one index NS, for projects split over
+    /// directories with no common root. Search it with --namespace NS
+    #[arg(long, value_name = "NS", conflicts_with_all = [
+        "namespace", "query_file", "browse", "chunk_only", "sample", "reset", "no_sync",
+        "flat", "branch", "delete", "diff", "verify", "embedding_dry_run", "export", "import",
+        "archive",
+    ])]
+    sync_into: Option<String>,
+
+    /// Search the index in namespace NS (e.g. a teammate's) instead of this directory's,
+    /// without syncing. Previews come from content it was synced with --store-content
+    #[arg(long, value_name = "NS", conflicts_with_all = [
+        "chunk_only", "reset", "no_search", "flat", "branch", "delete", "diff",
+        "embedding_dry_run", "export", "import", "archive",
+    ])]
+    namespace: Option<String>,
+
+    /// Return only the strong matches, up to --max-count, by cutting off results where
+    /// the distances jump
+    #[arg(long)]
+    adaptive: bool,
+
+    /// Re-order the nearest candidates with Voyage's reranker, slower but more precise;
+    /// with --scores the rerank score (higher is better) follows the distance
+    #[arg(long)]
+    rerank: bool,
+
+    /// Rank recently modified files higher; the boost halves every DAYS of file age
+    #[arg(long, value_name = "DAYS", value_parser = parse_half_life)]
+    recency_half_life: Option<f64>,
+
+    /// Index from a .tar/.tar.gz archive instead of the working tree.
+    /// Files are indexed as if extracted into PATH; previews need the files on disk.
+    #[arg(long, value_name = "FILE")]
+    archive: Option<String>,
+
+    /// Strip N leading path components from archive entries (like tar)
+    #[arg(long, value_name = "N", default_value = "0", requires = "archive")]
+    strip_components: usize,
+
+    /// Don't warn when the query looks like a regular expression
+    #[arg(long)]
+    no_regex_hint: bool,
+
+    /// When the query can't be embedded, show the chunks that best match its words
+    /// instead (default)
+    #[arg(long, overrides_with = "no_lexical_fallback")]
+    lexical_fallback: bool,
+
+    /// Fail the search when the query can't be embedded
+    #[arg(long, overrides_with = "lexical_fallback")]
+    no_lexical_fallback: bool,
+
+    /// Index hidden files and directories (default)
+    #[arg(long, overrides_with = "no_hidden")]
+    include_hidden: bool,
+
+    /// Skip hidden files and directories
+    #[arg(long, overrides_with = "include_hidden")]
+    no_hidden: bool,
+
+    /// Skip git submodules listed in .gitmodules, they're indexed under their own root (default)
+    #[arg(long, overrides_with = "no_skip_submodules")]
+    skip_submodules: bool,
+
+    /// Descend into git submodules and index them as part of this project
+    #[arg(long, overrides_with = "skip_submodules")]
+    no_skip_submodules: bool,
+
+    /// Don't index extensionless scripts by their #! line, only by file name
+    #[arg(long)]
+    no_shebang: bool,
+
+    /// Index generated files (e.g. "Code generated ... DO NOT EDIT.") instead of skipping them
+    #[arg(long)]
+    include_generated: bool,
+
+    /// Record the git HEAD commit on every indexed chunk
+    #[arg(long)]
+    record_commit: bool,
+
+    /// Store chunk content (brotli-compressed) in the index, so results can be previewed
+    /// without the files. Only affects newly indexed chunks
+    #[arg(long)]
+    store_content: bool,
+
+    /// Include each chunk's file path in the text that gets embedded.
+    /// Only affects newly indexed chunks, use with --reset to re-embed everything
+    #[arg(long)]
+    embed_path_context: bool,
+
+    /// Prefix the embedded query with the searched directory, like --embed-path-context
+    /// does for chunks (defaults to whether chunks are embedded with their paths)
+    #[arg(long, value_name = "BOOL", num_args = 0..=1)]
+    query_path_context: Option<Option<bool>>,
+
+    /// Only return results at least this similar to the query, from 1 (identical) down to
+    /// -1, whatever the index's distance metric (without a value, the metric's default:
+    /// 0.3, i.e. 0.7 in cosine or 1.4 in squared euclidean distance)
+    #[arg(long, value_name = "SCORE", num_args = 0..=1)]
+    min_score: Option<Option<f64>>,
+
+    /// Only return results starting between lines A and B (either end may be omitted, e.g. ':100')
+    #[arg(long, value_name = "A:B", value_parser = parse_line_range)]
+    line_range: Option<std::ops::RangeInclusive<u32>>,
+
+    /// Report how many walked files were in a supported language and got chunked,
+    /// and which unsupported extensions were skipped most
+    #[arg(long)]
+    stats: bool,
+
+    /// Report how embedding went: batches and their sizes, token-limit splits and the
+    /// average batch latency (also shown with --verbose)
+    #[arg(long)]
+    embedding_stats: bool,
+
+    /// After syncing, list the files that were added to, changed in and removed from the
+    /// index (always shown with --verbose)
+    #[arg(long)]
+    show_changes: bool,
+
+    /// Fail the sync if any chunk can't be embedded, instead of skipping it
+    #[arg(long)]
+    strict: bool,
+
+    /// Fail, rather than warn, when the directory has no files in a supported language
+    #[arg(long)]
+    require_language: bool,
+
+    /// Embed the query as TYPE (query or document) instead of query, for retrieval experiments
+    #[arg(long, value_name = "TYPE", hide = true)]
+    embedding_type: Option<embeddings::EmbeddingType>,
+
+    /// Print the embedding model's vector dimension and request limits (with --model,
+    /// that model's)
+    #[arg(long)]
+    embedding_provider_info: bool,
+
+    /// Search the vectors of embedding model NAME, one of the config's embedding_models
+    #[arg(long, value_name = "NAME")]
+    model: Option<String>,
+
+    /// Always embed and query, ignoring results cached from identical recent queries
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Only return results indexed at this git commit (abbreviated SHAs work)
+    #[arg(long, value_name = "SHA")]
+    at: Option<String>,
+
+    /// Only return chunks of files modified within AGE (e.g. 7d, 12h)
+    #[arg(long, value_name = "AGE", value_parser = parse_age)]
+    since: Option<u64>,
+
+    /// Only return chunks of files changed since they were indexed, or with uncommitted
+    /// changes in git
+    #[arg(long, conflicts_with_all = ["namespace", "at"])]
+    only_changed: bool,
+
+    /// Only return chunks of files with extension EXT (repeatable, e.g. --lang rs --lang py)
+    #[arg(long, value_name = "EXT")]
+    lang: Vec<String>,
+
+    /// Only return chunks of kind KIND: function, method, struct, class, comment (with the
+    /// index_comments setting) or other
+    /// (repeatable; indexes synced before kinds were recorded need a --reset)
+    #[arg(long, value_name = "KIND")]
+    kind: Vec<chunker::ChunkKind>,
+
+    /// Rank chunks of kind KIND higher, e.g. struct for questions about a project's
+    /// types (repeatable)
+    #[arg(long, value_name = "KIND")]
+    boost_kind: Vec<chunker::ChunkKind>,
+
+    /// Without --lang, only search the languages that make up most of the index
+    #[arg(long)]
+    auto_lang: bool,
+
+    /// List the indexed chunks matching PATH, --since, --lang, --at and --lines instead
+    /// of searching; every positional argument is a PATH
+    #[arg(long, conflicts_with_all = ["query_file", "archive", "diff", "delete"])]
+    browse: bool,
+
+    /// Chunks per turbopuffer write request (default: 1000)
+    #[arg(long, value_name = "N", value_parser = parse_write_batch_size)]
+    write_batch_size: Option<usize>,
+
+    /// Concurrent turbopuffer write requests (default: 4)
+    #[arg(long, value_name = "N", value_parser = parse_write_concurrency)]
+    write_concurrency: Option<usize>,
+
+    /// Re-pick the closest turbopuffer region and save it to the config
+    #[arg(long)]
+    update_region: bool,
+
+    /// Don't send background warm-up pings before searching
+    #[arg(long)]
+    no_warmup: bool,
+
+    /// Delete the index of the current directory's project, without re-syncing
+    #[arg(long)]
+    delete: bool,
+
+    /// Don't ask for confirmation before --delete
+    #[arg(short = 'y', long, requires = "delete")]
+    yes: bool,
+
+    /// Print which files a sync would add, change or delete chunks for, without syncing
+    #[arg(long, conflicts_with_all = ["delete", "reset", "no_sync"])]
+    diff: bool,
+
+    /// Check the index against the local files without syncing: list chunks only in the
+    /// index (orphans), only on disk (gaps) and sharing an ID. Exits 1 if any are found
+    #[arg(long, conflicts_with_all = ["delete", "reset", "no_sync", "diff", "namespace"])]
+    verify: bool,
+
+    /// Chunk and embed what a sync would index, reporting tokens used and embedding
+    /// failures, without writing to the index or searching. Unlike --diff, this calls Voyage
+    #[arg(long, conflicts_with_all = ["delete", "reset", "no_sync", "diff", "archive", "export", "import"])]
+    embedding_dry_run: bool,
+
+    /// Write the project's index, vectors included, to FILE for a teammate to --import
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["delete", "diff", "import"])]
+    export: Option<String>,
+
+    /// Load an index written by --export into this project's index, skipping embedding
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["delete", "diff"])]
+    import: Option<String>,
+}
+
+/// Parse a line range like `10:200`, `:200` or `10:` into an inclusive range
+fn parse_line_range(s: &str) -> Result<std::ops::RangeInclusive<u32>, String> {
+    let (start, end) = s
+        .split_once(':')
+        .ok_or_else(|| format!("invalid line range '{s}', expected A:B"))?;
+    let parse_bound = |bound: &str, default: u32| {
+        if bound.trim().is_empty() {
+            Ok(default)
+        } else {
+            bound
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| format!("invalid line number '{bound}'"))
+        }
+    };
+    let start = parse_bound(start, 1)?;
+    let end = parse_bound(end, u32::MAX)?;
+    if start > end {
+        return Err(format!("line range start {start} is after its end {end}"));
+    }
+    Ok(start..=end)
+}
+
+/// Parse an age like `30d`, `12h`, `90m` or `45s` into seconds; a bare number is days
+fn parse_age(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (number, unit_secs) = match s.char_indices().last() {
+        Some((i, 'd')) => (&s[..i], 24 * 60 * 60),
+        Some((i, 'h')) => (&s[..i], 60 * 60),
+        Some((i, 'm')) => (&s[..i], 60),
+        Some((i, 's')) => (&s[..i], 1),
+        _ => (s, 24 * 60 * 60),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid age '{s}', expected e.g. 30d, 12h, 90m or 45s"))?;
+    number
+        .checked_mul(unit_secs)
+        .ok_or_else(|| format!("age '{s}' is too large"))
+}
+
+/// Parse a half-life in days, which has to be a positive, finite number
+fn parse_half_life(s: &str) -> Result<f64, String> {
+    let days: f64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid number of days '{s}'"))?;
+    if days.is_finite() && days > 0.0 {
+        Ok(days)
+    } else {
+        Err(format!(
+            "half-life must be a positive number of days, got '{s}'"
+        ))
+    }
+}
+
+fn format_age(secs: u64) -> String {
+    match secs {
+        s if s >= 24 * 60 * 60 => format!("{}d ago", s / (24 * 60 * 60)),
+        s if s >= 60 * 60 => format!("{}h ago", s / (60 * 60)),
+        s if s >= 60 => format!("{}m ago", s / 60),
+        s => format!("{}s ago", s),
+    }
+}
+
+/// Ask `prompt` on stderr and read the answer from `input`; anything but y/yes is a no
+fn confirm(prompt: &str, input: &mut impl std::io::BufRead) -> bool {
+    eprint!("{prompt} [y/N] ");
+    let mut answer = String::new();
+    input.read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+/// Print every project namespace (`tg_` unless `namespace_prefix` is set) with its last
+/// sync time, optionally only the stale ones
+async fn list_namespaces(stale_after: Option<u64>) -> ExitCode {
+    let prefix = project::namespace_prefix(config::SETTINGS.get());
+    let namespaces = match turbopuffer::list_namespaces(&prefix).await {
+        Ok(namespaces) => namespaces,
+        Err(e) => {
+            eprintln!("<(°!°)> Error listing namespaces: {e}");
+            return ExitCode::from(turbopuffer_error_exit_code(&e));
+        }
+    };
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut listed = 0;
+    for namespace in namespaces {
+        let last_synced = match turbopuffer::last_synced(&namespace).await {
+            Ok(last_synced) => last_synced,
+            Err(e) => {
+                warnln!("<(°!°)> Couldn't read sync time of {}: {}", namespace, e);
+                continue;
+            }
+        };
+        if let Some(max_age) = stale_after
+            && !sync::is_stale(last_synced, now, max_age)
+        {
+            continue;
+        }
+
+        let age = last_synced.map_or_else(
+            || "never synced".to_string(),
+            |synced_at| format_age(now.saturating_sub(synced_at)),
+        );
+        println!("{namespace}\t{age}");
+        listed += 1;
+    }
+
+    if listed > 0 {
+        ExitCode::from(EXIT_RESULTS)
+    } else {
+        ExitCode::from(EXIT_NO_RESULTS)
+    }
+}
+
+fn parse_in_range(s: &str, range: std::ops::RangeInclusive<usize>) -> Result<usize, String> {
+    let value: usize = s.parse().map_err(|e| format!("{e}"))?;
+    if range.contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!(
+            "must be between {} and {}",
+            range.start(),
+            range.end()
+        ))
+    }
+}
+
+fn parse_write_batch_size(s: &str) -> Result<usize, String> {
+    parse_in_range(s, turbopuffer::WRITE_BATCH_SIZE_RANGE)
+}
+
+fn parse_write_concurrency(s: &str) -> Result<usize, String> {
+    parse_in_range(s, turbopuffer::WRITE_CONCURRENCY_RANGE)
+}
+
+impl Cli {
+    fn search_options(&self) -> search::SearchOptions {
+        search::SearchOptions {
+            max_count: self.max_count,
+            embedding_concurrency: self.embedding_concurrency,
+            show_scores: self.scores,
+            show_bytes: self.bytes,
+            show_symbols: self.show_symbol,
+            max_content_lines: self.max_content_lines.map(|n| n as usize),
+            context_symbol: self.context_symbol,
+            files_only: self.files_only,
+            recency_half_life_days: self.recency_half_life,
+            at_commit: self.at.clone(),
+            scope: None,
+            line_range: self.line_range.clone(),
+            modified_since: self.since.map(|age| {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                now.saturating_sub(age)
+            }),
+            only_changed: self.only_changed,
+            extensions: self
+                .lang
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_string())
+                .collect(),
+            kinds: self.kind.clone(),
+            boost_kinds: self.boost_kind.clone(),
+            auto_lang: self.auto_lang,
+            min_score: self.min_score.map(|score| {
+                score.unwrap_or_else(|| turbopuffer::DistanceMetric::active().default_min_score())
+            }),
+            model: self.model.clone(),
+            lexical_fallback: !self.no_lexical_fallback,
+            query_embedding_type: self.embedding_type.unwrap_or_default(),
+            query_path_context: match self.query_path_context {
+                Some(enabled) => enabled.unwrap_or(true),
+                None => config::SETTINGS
+                    .get()
+                    .and_then(|s| s.query_path_context)
+                    .unwrap_or(self.sync_options().embed_path_context),
+            },
+            adaptive: self.adaptive,
+            rerank: self.rerank,
+            namespace: self.namespace.clone(),
+            consistency: turbopuffer::Consistency::Eventual,
+            flat: self.flat,
+            branch: None,
+            snapshot: self.snapshot,
+            content_load_concurrency: self
+                .content_concurrency
+                .unwrap_or(search::CONTENT_LOAD_CONCURRENCY),
+            cache: if self.no_cache {
+                None
+            } else {
+                query_cache::QueryCache::from_settings(config::SETTINGS.get())
+            },
+        }
+    }
+
+    fn walk_options(&self) -> chunker::WalkOptions {
+        chunker::WalkOptions {
+            include_hidden: !self.no_hidden,
+            skip_submodules: !self.no_skip_submodules,
+            scope: None,
+            detect_shebang: !self.no_shebang,
+            include_generated: self.include_generated,
+            excluded_dirs: config::data_dirs(),
+        }
+    }
+
+    fn sync_options(&self) -> sync::SyncOptions {
+        sync::SyncOptions {
+            embedding_concurrency: self.embedding_concurrency,
+            embedding_tokens_per_minute: self.embedding_tpm.or_else(|| {
+                config::SETTINGS
+                    .get()
+                    .and_then(|s| s.embedding_tokens_per_minute)
+            }),
+            walk: self.walk_options(),
+            record_commit: self.record_commit,
+            embed_path_context: self.embed_path_context
+                || config::SETTINGS
+                    .get()
+                    .and_then(|s| s.embed_path_context)
+                    .unwrap_or(false),
+            write: self.write_options(),
+            strict: self.strict,
+            stats: self.stats,
+            show_changes: self.show_changes,
+            embedding_stats: self.embedding_stats,
+            flat: self.flat,
+            branch: None,
+            require_language: self.require_language,
+        }
+    }
+
+    /// Branch whose index to use: the one named with `--branch`, or the checked-out one
+    fn branch(&self, directory: &str) -> Option<String> {
+        match &self.branch {
+            None => None,
+            Some(Some(name)) => Some(name.clone()),
+            Some(None) => {
+                let branch = project::git_branch(directory);
+                if branch.is_none() {
+                    warnln!("<(°~°)> Not on a git branch, using the shared index");
+                }
+                branch
+            }
+        }
+    }
+
+    fn warmup_pings(&self) -> usize {
+        if self.no_warmup {
+            return 0;
+        }
+        config::SETTINGS
+            .get()
+            .and_then(|s| s.warmup_pings)
+            .unwrap_or(WARMUP_PINGS)
+    }
+
+    fn write_options(&self) -> turbopuffer::WriteOptions {
+        let mut options = turbopuffer::WriteOptions::from_settings(config::SETTINGS.get());
+        if let Some(batch_size) = self.write_batch_size {
+            options.batch_size = batch_size;
+        }
+        if let Some(concurrency) = self.write_concurrency {
+            options.concurrency = concurrency;
+        }
+        options.store_content |= self.store_content;
+        options.dry_run = self.embedding_dry_run;
+        options
+    }
+}
+
+/// Warm-up pings sent to each service before a search, unless configured otherwise
+const WARMUP_PINGS: usize = 5;
+
+/// Ping a service `pings` times in the background to warm up its connections, stopping at
+/// the first failure. Nothing is spawned when warm-up is disabled (0 pings).
+fn spawn_warmup<F, Fut, T, E>(pings: usize, ping: F) -> Option<tokio::task::JoinHandle<()>>
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = Result<T, E>> + Send + 'static,
+{
+    if pings == 0 {
+        return None;
+    }
+    Some(tokio::spawn(async move {
+        for _i in 1..=pings {
+            if let Err(_e) = ping().await {
+                break;
+            }
+        }
+    }))
+}
+
+/// Detailed version report for bug reports: grammars, embedding model and region, as
+/// configured in `settings`
+fn verbose_version(settings: Option<&config::Settings>) -> String {
+    let mut lines = vec![
+        format!("tg {}", env!("CARGO_PKG_VERSION")),
+        format!("tree-sitter ABI: {}", tree_sitter::LANGUAGE_VERSION),
+    ];
+
+    for (name, language) in chunker::bundled_languages() {
+        let grammar_version = language
+            .metadata()
+            .map(|m| {
+                format!(
+                    "{}.{}.{}",
+                    m.major_version, m.minor_version, m.patch_version
+                )
+            })
+            .unwrap_or_else(|| "unknown".to_string());
+        lines.push(format!(
+            "  {name}: grammar {grammar_version}, ABI {}",
+            language.abi_version()
+        ));
+    }
+
+    lines.push(format!(
+        "embedding provider: {}",
+        settings
+            .and_then(|s| s.embedding_provider.as_deref())
+            .unwrap_or("voyage")
+    ));
+    lines.push(format!("embedding model: {}", embeddings::VOYAGE_MODEL));
+    if let Some(models) = settings.and_then(|s| s.embedding_models.as_ref()) {
+        lines.push(format!("extra embedding models: {}", models.join(", ")));
+    }
+    lines.push(format!(
+        "turbopuffer region: {}",
+        settings
+            .and_then(|s| s.turbopuffer_region.as_deref())
+            .unwrap_or("unset")
+    ));
+    lines.join("\n")
+}
+
+/// Exit codes follow grep so scripts can tell outcomes apart
+const EXIT_RESULTS: u8 = 0;
+const EXIT_NO_RESULTS: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_API: u8 = 3;
+
+fn turbopuffer_error_exit_code(error: &turbopuffer::TurbopufferError) -> u8 {
+    match error {
+        turbopuffer::TurbopufferError::MissingApiKey => EXIT_USAGE,
+        _ => EXIT_API,
+    }
+}
+
+fn embedding_error_exit_code(error: &embeddings::EmbeddingError) -> u8 {
+    match error {
+        embeddings::EmbeddingError::MissingApiKey => EXIT_USAGE,
+        _ => EXIT_API,
+    }
+}
+
+/// Map a search failure to its exit code
+fn search_error_exit_code(error: &search::SearchError) -> u8 {
+    match error {
+        search::SearchError::EmptyQuery | search::SearchError::NamespaceError(_) => EXIT_USAGE,
+        search::SearchError::TurbopufferError(e) => turbopuffer_error_exit_code(e),
+        search::SearchError::EmbeddingError(e) => embedding_error_exit_code(e),
+        search::SearchError::NoEmbedding
+        | search::SearchError::NamespaceNotFound
+        | search::SearchError::IndexBuildFailed(_) => EXIT_API,
+    }
+}
+
+/// Map a sync failure to its exit code
+fn sync_error_exit_code(error: &anyhow::Error) -> u8 {
+    if let Some(e) = error.downcast_ref::<turbopuffer::TurbopufferError>() {
+        turbopuffer_error_exit_code(e)
+    } else if let Some(e) = error.downcast_ref::<embeddings::EmbeddingError>() {
+        embedding_error_exit_code(e)
+    } else if error.downcast_ref::<std::io::Error>().is_some()
+        || error.downcast_ref::<chunker::ChunkError>().is_some()
+    {
+        // Local filesystem problems, e.g. an unreadable directory or nothing to index
+        EXIT_USAGE
+    } else {
+        EXIT_API
+    }
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
+    let profile_path = cli.profile.clone();
+    if profile_path.is_some() {
+        profile::enable();
+    }
+
+    let exit_code = run(cli).await;
+
+    if let Some(path) = profile_path {
+        match profile::write_trace(&path) {
+            Ok(()) => warnln!("<(°O°)> wrote timing trace to {}", path.display()),
+            Err(e) => eprintln!("<(°!°)> Error writing {}: {e}", path.display()),
+        }
+    }
+    exit_code
+}
+
+async fn run(cli: Cli) -> ExitCode {
     turbogrep::set_verbose(cli.verbose);
+    turbogrep::set_quiet(cli.quiet);
+    turbogrep::set_color(&cli.color);
+    progress::set_force_progress(cli.progress);
+
+    let verbose_version_report = matches!(cli.command, Some(Command::Version)) && cli.verbose;
+    if verbose_version_report {
+        // Only reads the config: a version report mustn't pick a region or write anything
+        return match config::read_config() {
+            Ok(settings) => {
+                println!("{}", verbose_version(Some(&settings)));
+                ExitCode::from(EXIT_RESULTS)
+            }
+            Err(e) => {
+                eprintln!("<(°!°)> Error reading settings: {e}");
+                ExitCode::from(EXIT_USAGE)
+            }
+        };
+    }
+    if cli.version || matches!(cli.command, Some(Command::Version)) {
+        println!("tg {}", env!("CARGO_PKG_VERSION"));
+        return ExitCode::from(EXIT_RESULTS);
+    }
 
     if let Err(e) = config::load_or_init_settings().await {
         eprintln!("<(°!°)> Error loading settings: {e}");
-        return;
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    if cli.update_region {
+        return match config::update_region().await {
+            Ok((old_region, region)) => {
+                let old_region = old_region.as_deref().unwrap_or("(none)");
+                println!("region: {old_region} -> {region}");
+                if old_region != region {
+                    warnln!(
+                        "<(°~°)> existing indexes stay in {old_region}, the next sync rebuilds them in {region}"
+                    );
+                }
+                ExitCode::from(EXIT_RESULTS)
+            }
+            Err(e) => {
+                eprintln!("<(°!°)> Error updating region: {e}");
+                ExitCode::from(EXIT_API)
+            }
+        };
+    }
+
+    if let Some(Command::Namespaces { stale }) = cli.command {
+        return list_namespaces(stale).await;
+    }
+
+    if cli.embedding_provider_info {
+        let model = cli.model.as_deref().unwrap_or(embeddings::VOYAGE_MODEL);
+        return match embeddings::ModelInfo::voyage(model) {
+            Some(info) => {
+                println!("{info}");
+                ExitCode::from(EXIT_RESULTS)
+            }
+            None => {
+                eprintln!("<(°!°)> Error: no limits known for model {model}");
+                ExitCode::from(EXIT_USAGE)
+            }
+        };
+    }
+
+    if let Some(namespace) = &cli.sync_into {
+        return sync_into(&cli, namespace).await;
     }
 
     // Parse clap arguments with ripgrep-style logic
-    let (query, start_directory) = match parse_cli_args(&cli) {
+    let (query, start_directory, scope) = match parse_cli_args(&cli) {
         Ok(result) => result,
         Err(e) => {
             eprintln!("<(°!°)> Error: {e}");
-            return;
+            return ExitCode::from(EXIT_USAGE);
         }
     };
+    let scope = match scope.map(chunker::PathScope::new).transpose() {
+        Ok(scope) => scope,
+        Err(e) => {
+            eprintln!("<(°!°)> Error: invalid PATH glob: {e}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    if let Some(model) = cli.model.as_deref() {
+        let configured = config::SETTINGS
+            .get()
+            .and_then(|s| s.embedding_models.as_ref())
+            .is_some_and(|models| models.iter().any(|m| m == model));
+        if !embeddings::is_voyage_model(model) {
+            eprintln!("<(°!°)> Error: model {model} isn't a Voyage model (voyage-...)");
+            return ExitCode::from(EXIT_USAGE);
+        }
+        if model != embeddings::VOYAGE_MODEL && !configured {
+            eprintln!("<(°!°)> Error: model {model} isn't one of the config's embedding_models");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    }
+    let branch = cli.branch(&start_directory);
+    let walk_options = chunker::WalkOptions {
+        scope: scope.clone(),
+        ..cli.walk_options()
+    };
+    let sync_options = sync::SyncOptions {
+        walk: walk_options.clone(),
+        branch: branch.clone(),
+        ..cli.sync_options()
+    };
+    let search_options = search::SearchOptions {
+        scope,
+        branch: branch.clone(),
+        ..cli.search_options()
+    };
+
+    let (namespace, root_dir) =
+        match namespace_and_dir(&start_directory, cli.flat, branch.as_deref()) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("<(°!°)> Error: {e}");
+                return ExitCode::from(EXIT_USAGE);
+            }
+        };
+
+    if cli.delete {
+        let prompt = format!("<(°~°)> Delete the index of {root_dir} ({namespace})?");
+        if !cli.yes && !confirm(&prompt, &mut std::io::stdin().lock()) {
+            eprintln!("<(°◯°)> Nothing deleted");
+            return ExitCode::from(EXIT_USAGE);
+        }
+        return match sync::delete_index(&namespace).await {
+            Ok(()) => {
+                println!("deleted {namespace}");
+                ExitCode::from(EXIT_RESULTS)
+            }
+            Err(e) => {
+                eprintln!("<(°!°)> Error deleting {namespace}: {e}");
+                ExitCode::from(sync_error_exit_code(&e))
+            }
+        };
+    }
+
+    if cli.diff {
+        return match sync::pending_changes(
+            &turbopuffer::Turbopuffer,
+            &start_directory,
+            &sync_options,
+        )
+        .await
+        {
+            Ok(changes) => {
+                print!("{changes}");
+                ExitCode::from(EXIT_RESULTS)
+            }
+            Err(e) => {
+                eprintln!("<(°!°)> Diff failed: {e}");
+                ExitCode::from(sync_error_exit_code(&e))
+            }
+        };
+    }
+
+    if cli.verify {
+        return match sync::verify_index(&turbopuffer::Turbopuffer, &start_directory, &sync_options)
+            .await
+        {
+            Ok(health) => {
+                print!("{health}");
+                if health.is_healthy() {
+                    ExitCode::from(EXIT_RESULTS)
+                } else {
+                    ExitCode::from(EXIT_NO_RESULTS)
+                }
+            }
+            Err(e) => {
+                eprintln!("<(°!°)> Verify failed: {e}");
+                ExitCode::from(sync_error_exit_code(&e))
+            }
+        };
+    }
+
+    if let Some(file) = &cli.export {
+        let exported = sync::export_index(&turbopuffer::Turbopuffer, &namespace, &root_dir)
+            .await
+            .and_then(|export| {
+                export.write_to(Path::new(file))?;
+                Ok(export.chunks.len())
+            });
+        return match exported {
+            Ok(count) => {
+                println!("exported {count} chunks to {file}");
+                ExitCode::from(EXIT_RESULTS)
+            }
+            Err(e) => {
+                eprintln!("<(°!°)> Export failed: {e}");
+                ExitCode::from(sync_error_exit_code(&e))
+            }
+        };
+    }
+
+    if let Some(file) = &cli.import {
+        // The imported chunks replace whatever the last sync indexed
+        sync::invalidate_manifest(&start_directory, &sync_options);
+        let imported = match sync::IndexExport::read_from(Path::new(file)) {
+            Ok(export) => {
+                sync::import_index(
+                    &turbopuffer::Turbopuffer,
+                    &namespace,
+                    &root_dir,
+                    export,
+                    &sync_options.write,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        return match imported {
+            Ok(count) => {
+                println!("imported {count} chunks into {namespace}");
+                ExitCode::from(EXIT_RESULTS)
+            }
+            Err(e) => {
+                eprintln!("<(°!°)> Import failed: {e}");
+                ExitCode::from(sync_error_exit_code(&e))
+            }
+        };
+    }
 
     // If reset flag is provided, delete the namespace first
     if cli.reset {
-        let (namespace, _root_dir) = namespace_and_dir(&start_directory).unwrap();
         vprintln!("<(°○°)> Resetting namespace: {}", namespace);
-        if let Err(e) = turbopuffer::delete_namespace(&namespace).await {
+        if let Err(e) = sync::delete_index(&namespace).await {
             vprintln!("<(°◯°)> Note: {}", e);
         }
-        sync::tpuf_sync(&start_directory, cli.embedding_concurrency)
-            .await
-            .unwrap();
+        // With --archive the fresh sync happens below, from the archive
+        if cli.archive.is_none()
+            && let Err(e) = sync::tpuf_sync(&start_directory, &sync_options).await
+        {
+            eprintln!("<(°!°)> Sync failed: {e}");
+            return ExitCode::from(sync_error_exit_code(&e));
+        }
+    }
+
+    // Index from an archive rather than walking the working tree
+    if let Some(archive) = &cli.archive
+        && let Err(e) = sync::tpuf_sync_archive(
+            archive,
+            &start_directory,
+            cli.strip_components,
+            &sync_options,
+        )
+        .await
+    {
+        eprintln!("<(°!°)> Sync failed: {e}");
+        return ExitCode::from(sync_error_exit_code(&e));
     }
 
     // Handle --sample flag: output N random chunks to stdout
     if let Some(sample_count) = cli.sample {
-        let (_, root_dir) = namespace_and_dir(&start_directory).unwrap();
-        let chunks = chunker::chunk_files(&root_dir).unwrap();
+        let chunks = match chunker::chunk_files_with_options(&root_dir, &walk_options) {
+            Ok(chunks) => chunks,
+            Err(e) => {
+                eprintln!("<(°!°)> Error: {e}");
+                return ExitCode::from(EXIT_USAGE);
+            }
+        };
         let sampled_chunks = sample_random_chunks(chunks, sample_count, &start_directory);
 
+        if cli.format.unwrap_or_default() == SampleFormat::Json {
+            println!("{:#}", sampled_chunks_json(&sampled_chunks));
+            return ExitCode::from(EXIT_RESULTS);
+        }
         for chunk in sampled_chunks {
             if let Some(content) = &chunk.content {
-                println!(
-                    "{}",
-                    format!(
-                        "{path}:{start_line}:{end_line}",
-                        path = chunk.path,
-                        start_line = chunk.start_line,
-                        end_line = chunk.end_line
-                    )
-                    .bright_cyan()
+                let mut header = format!(
+                    "{path}:{start_line}:{end_line}",
+                    path = chunk.path,
+                    start_line = chunk.start_line,
+                    end_line = chunk.end_line
                 );
+                if cli.bytes
+                    && let (Some(start), Some(end)) = (chunk.start_byte, chunk.end_byte)
+                {
+                    header.push_str(&format!(":{start}-{end}"));
+                }
+                if turbogrep::use_color() {
+                    println!("{}", header.bright_cyan());
+                } else {
+                    println!("{}", header);
+                }
                 println!("{}", content);
                 println!(); // Empty line separator
             }
         }
-        return;
+        return ExitCode::from(EXIT_RESULTS);
     }
 
     if cli.chunk_only {
         // Only run the chunking step for performance testing
-        let (_, root_dir) = namespace_and_dir(&start_directory).unwrap();
-        chunker::chunk_files(&root_dir).unwrap();
-    } else if query.is_none() || cli.no_search {
+        match chunker::chunk_files_with_report(&root_dir, &walk_options) {
+            Ok(report) => {
+                vprintln!("chunked {} chunks", report.chunks.len());
+                report.warn_failures();
+                report.print_coverage(cli.stats);
+                match report.check_supported(&root_dir, cli.require_language) {
+                    Ok(()) => ExitCode::from(EXIT_RESULTS),
+                    Err(e) => {
+                        eprintln!("<(°!°)> Error: {e}");
+                        ExitCode::from(EXIT_USAGE)
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("<(°!°)> Error: {e}");
+                ExitCode::from(EXIT_USAGE)
+            }
+        }
+    } else if cli.browse {
+        if !cli.no_sync
+            && cli.namespace.is_none()
+            && let Err(e) = sync::tpuf_sync(&start_directory, &sync_options).await
+        {
+            eprintln!("<(°!°)> Sync failed: {e}");
+            return ExitCode::from(sync_error_exit_code(&e));
+        }
+        let browse_result =
+            search::browse(&turbopuffer::Turbopuffer, &start_directory, &search_options).await;
+        print_search_output(browse_result)
+    } else if cli.namespace.is_some() && query.is_none() {
+        eprintln!("<(°!°)> Error: --namespace needs a query or --browse");
+        ExitCode::from(EXIT_USAGE)
+    } else if cli.archive.is_some() && (query.is_none() || cli.no_search) {
+        // Already synced from the archive above
+        ExitCode::from(EXIT_RESULTS)
+    } else if query.is_none() || cli.no_search || cli.embedding_dry_run {
         // No query provided, just sync the directory
         vprintln!(
             "No search query provided, syncing directory: {}",
             start_directory
         );
-        sync::tpuf_sync(&start_directory, cli.embedding_concurrency)
-            .await
-            .unwrap();
-    } else if let Some(query) = query {
-        // Warm up turbopuffer connections in the background to reduce first-call latency
-        tokio::spawn(async {
-            for _i in 1..=5 {
-                if let Err(_e) = turbopuffer::ping(None).await {
-                    break;
-                }
+        match sync::tpuf_sync(&start_directory, &sync_options).await {
+            Ok(_) => ExitCode::from(EXIT_RESULTS),
+            Err(e) => {
+                eprintln!("<(°!°)> Sync failed: {e}");
+                ExitCode::from(sync_error_exit_code(&e))
             }
-        });
+        }
+    } else if let Some(query) = query {
+        if !cli.no_regex_hint && search::looks_like_regex(&query) {
+            warnln!(
+                "<(°~°)> Hint: '{query}' looks like a regular expression, but turbogrep searches by meaning."
+            );
+            warnln!(
+                "<(°~°)> Try describing what the code does, or use a regex tool like rg. (--no-regex-hint to silence)"
+            );
+        }
 
-        tokio::spawn(async {
-            let voyage = embeddings::VoyageEmbedding::new();
-            for _i in 1..=5 {
-                if let Err(_e) = voyage.ping().await {
-                    break;
-                }
-            }
+        // Warm up turbopuffer and voyage connections in the background to reduce first-call latency
+        let warmup_pings = cli.warmup_pings();
+        let searched = cli.namespace.as_deref().unwrap_or(&namespace);
+        let region = turbopuffer::namespace_region(config::SETTINGS.get(), searched).to_string();
+        spawn_warmup(warmup_pings, move || {
+            let region = region.clone();
+            async move { turbopuffer::ping(Some(&region)).await }
+        });
+        spawn_warmup(warmup_pings, || async {
+            embeddings::VoyageEmbedding::new().ping().await
         });
 
-        if cli.reset {
-            // no need to speculate, we know it's indexed
-            match search::search(
-                &query,
-                &start_directory,
-                cli.max_count,
-                cli.embedding_concurrency,
-                cli.scores,
-            )
-            .await
-            {
-                Ok(results) => println!("{results}"),
-                Err(e) => {
-                    eprintln!("<(°!°)> Search failed: {e}");
-                    std::process::exit(1);
+        let search_result =
+            if cli.reset || cli.no_sync || cli.namespace.is_some() || cli.archive.is_some() {
+                if cli.no_sync {
+                    vprintln!("<(°◯°)> Searching existing index (--no-sync)...");
                 }
+                // no need to speculate, we know it's indexed (or were told not to sync)
+                search::search(&query, &start_directory, &search_options).await
+            } else {
+                search::speculate_search(&query, &start_directory, &search_options, &sync_options)
+                    .await
+            };
+
+        print_search_output(search_result)
+    } else {
+        unreachable!("This should never happen - query should always be Some or None");
+    }
+}
+
+/// `--sync-into`: sync every PATH into `namespace` as one index
+async fn sync_into(cli: &Cli, namespace: &str) -> ExitCode {
+    let roots: Result<Vec<String>, String> = cli
+        .pattern
+        .iter()
+        .chain(&cli.path)
+        .map(|path| {
+            let directory = project::validate_directory(path)?;
+            directory
+                .canonicalize()
+                .map(|root| root.to_string_lossy().to_string())
+                .map_err(|e| format!("Can't resolve '{path}': {e}"))
+        })
+        .collect();
+    let roots = match roots {
+        Ok(roots) if !roots.is_empty() => roots,
+        Ok(_) => {
+            eprintln!("<(°!°)> Error: --sync-into needs the directories to sync");
+            return ExitCode::from(EXIT_USAGE);
+        }
+        Err(e) => {
+            eprintln!("<(°!°)> Error: {e}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    let sync_options = cli.sync_options();
+    let embedding_provider = sync::voyage_provider(&sync::common_root(&roots), &sync_options);
+    match sync::sync_roots(
+        &turbopuffer::Turbopuffer,
+        embedding_provider,
+        &roots,
+        namespace,
+        &sync_options,
+    )
+    .await
+    {
+        Ok(_) => ExitCode::from(EXIT_RESULTS),
+        Err(e) => {
+            eprintln!("<(°!°)> Sync failed: {e}");
+            ExitCode::from(sync_error_exit_code(&e))
+        }
+    }
+}
+
+/// Print search (or browse) results, returning the exit code for them
+fn print_search_output(result: Result<search::SearchOutput, search::SearchError>) -> ExitCode {
+    match result {
+        Ok(output) => {
+            if !output.results.is_empty() {
+                println!("{}", output.results);
             }
-        } else if cli.no_sync {
-            vprintln!("<(°◯°)> Searching existing index (--no-sync)...");
-            match search::search(
-                &query,
-                &start_directory,
-                cli.max_count,
-                cli.embedding_concurrency,
-                cli.scores,
-            )
-            .await
-            {
-                Ok(results) => println!("{results}"),
-                Err(e) => {
-                    eprintln!("<(°!°)> Search failed: {e}");
-                    std::process::exit(1);
-                }
+            vprintln!("{}", output.timings.summary());
+            if output.results.is_empty() {
+                ExitCode::from(EXIT_NO_RESULTS)
+            } else {
+                ExitCode::from(EXIT_RESULTS)
             }
-        } else {
-            match search::speculate_search(
-                &query,
-                &start_directory,
-                cli.max_count,
-                cli.embedding_concurrency,
-                cli.scores,
-            )
-            .await
-            {
-                Ok(results) => println!("{results}"),
-                Err(e) => {
-                    eprintln!("<(°!°)> Search failed: {e}");
-                    std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("<(°!°)> Search failed: {e}");
+            ExitCode::from(search_error_exit_code(&e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbose_version() {
+        let settings = config::Settings {
+            turbopuffer_region: Some("gcp-europe-west4".to_string()),
+            ..Default::default()
+        };
+        let version = verbose_version(Some(&settings));
+        assert!(version.contains(env!("CARGO_PKG_VERSION")));
+        assert!(version.contains(embeddings::VOYAGE_MODEL));
+        assert!(version.contains("voyage-code-3"));
+        assert!(version.contains("rust: grammar"));
+        assert!(version.contains("turbopuffer region: gcp-europe-west4"));
+
+        let cli = Cli::parse_from(["tg", "version", "--verbose"]);
+        assert!(matches!(cli.command, Some(Command::Version)) && cli.verbose);
+    }
+
+    #[test]
+    fn test_parse_line_range() {
+        assert_eq!(parse_line_range("10:200"), Ok(10..=200));
+        assert_eq!(parse_line_range(":50"), Ok(1..=50));
+        assert_eq!(parse_line_range("100:"), Ok(100..=u32::MAX));
+        assert!(parse_line_range("200:10").is_err());
+        assert!(parse_line_range("10").is_err());
+        assert!(parse_line_range("a:b").is_err());
+    }
+
+    #[test]
+    fn test_parse_half_life() {
+        assert_eq!(parse_half_life("30"), Ok(30.0));
+        assert_eq!(parse_half_life("0.5"), Ok(0.5));
+        for days in ["0", "-7", "inf", "NaN", "soon"] {
+            assert!(parse_half_life(days).is_err(), "{days}");
+        }
+
+        let cli = Cli::parse_from(["tg", "--recency-half-life", "14", "query"]);
+        assert_eq!(cli.recency_half_life, Some(14.0));
+        assert!(Cli::try_parse_from(["tg", "--recency-half-life", "0", "query"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("30d"), Ok(30 * 24 * 60 * 60));
+        assert_eq!(parse_age("12h"), Ok(12 * 60 * 60));
+        assert_eq!(parse_age("90m"), Ok(90 * 60));
+        assert_eq!(parse_age("45s"), Ok(45));
+        assert_eq!(parse_age("7"), Ok(7 * 24 * 60 * 60));
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("soon").is_err());
+        assert!(parse_age("-1d").is_err());
+    }
+
+    #[test]
+    fn test_query_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let query_path = temp_dir.path().join("query.txt");
+        std::fs::write(
+            &query_path,
+            "\nwhere do we retry failed uploads\nafter a network timeout?\n\n",
+        )
+        .unwrap();
+        let query_path = query_path.to_string_lossy().to_string();
+        let directory = temp_dir.path().to_string_lossy().to_string();
+
+        let cli = Cli::parse_from(["tg", "--query-file", &query_path, &directory]);
+        let (query, start_directory, scope) = parse_cli_args(&cli).unwrap();
+        assert_eq!(
+            query.as_deref(),
+            Some("where do we retry failed uploads\nafter a network timeout?")
+        );
+        assert_eq!(start_directory, directory);
+        assert_eq!(scope, None);
+
+        // Every positional argument is a PATH, all of which must exist
+        std::fs::create_dir(temp_dir.path().join(".git")).unwrap();
+        let src = temp_dir.path().join("src");
+        std::fs::create_dir(&src).unwrap();
+        let src = src.canonicalize().unwrap().to_string_lossy().to_string();
+        let cli = Cli::parse_from(["tg", "--query-file", &query_path, &src, &directory]);
+        let (query, start_directory, scope) = parse_cli_args(&cli).unwrap();
+        assert!(query.is_some());
+        assert_eq!(start_directory, src);
+        assert_eq!(scope.map(|patterns| patterns.len()), Some(2));
+
+        // A positional pattern is ambiguous alongside --query-file
+        let cli = Cli::parse_from(["tg", "--query-file", &query_path, "query", &directory]);
+        assert_eq!(
+            parse_cli_args(&cli).unwrap_err(),
+            "--query-file can't be combined with a PATTERN argument"
+        );
+    }
+
+    #[test]
+    fn test_whitespace_query_is_rejected_before_syncing() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let directory = temp_dir.path().to_string_lossy().to_string();
+
+        // parse_cli_args runs before any sync, so rejecting it there means none starts
+        for args in [vec!["tg", "   "], vec!["tg", " \t ", &directory]] {
+            let cli = Cli::parse_from(args);
+            let error = parse_cli_args(&cli).unwrap_err();
+            assert!(error.starts_with("empty query"), "{error}");
+        }
+    }
+
+    #[test]
+    fn test_sample_json_has_a_record_per_sampled_chunk() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path().to_string_lossy().to_string();
+        std::fs::write(
+            temp_dir.path().join("lib.rs"),
+            "fn parse() {}\n\nfn render() {}\n\nfn save() {}\n",
+        )
+        .unwrap();
+        let chunks = chunker::chunk_files(&root).unwrap();
+        assert_eq!(chunks.len(), 3);
+
+        let sampled = sample_random_chunks(chunks, 2, &root);
+        let json = sampled_chunks_json(&sampled);
+        let records = json.as_array().unwrap();
+        assert_eq!(records.len(), 2);
+        for (record, chunk) in records.iter().zip(&sampled) {
+            assert!(record["content"].as_str().unwrap().starts_with("fn "));
+            assert_eq!(record["path"], chunk.path.as_str());
+            assert_eq!(record["start_line"], chunk.start_line);
+            assert_eq!(record["chunk_hash"], chunk.chunk_hash);
+        }
+        assert_eq!("json".parse(), Ok(SampleFormat::Json));
+        assert!("yaml".parse::<SampleFormat>().is_err());
+    }
+
+    #[test]
+    fn test_saved_query() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let queries = temp_dir.path().join(".turbogrep/queries");
+        std::fs::create_dir_all(&queries).unwrap();
+        std::fs::write(queries.join("auth"), "auth middleware checking tokens\n").unwrap();
+        let subdir = temp_dir.path().join("src");
+        std::fs::create_dir(&subdir).unwrap();
+
+        // Found from anywhere in the project, and searched for instead of the name
+        for directory in [temp_dir.path(), &subdir] {
+            let directory = directory.to_string_lossy().to_string();
+            let cli = Cli::parse_from(["tg", ":auth", &directory]);
+            let (query, start_directory, _) = parse_cli_args(&cli).unwrap();
+            assert_eq!(query.as_deref(), Some("auth middleware checking tokens"));
+            assert_eq!(start_directory, directory);
+        }
+
+        let directory = temp_dir.path().to_string_lossy().to_string();
+        let cli = Cli::parse_from(["tg", ":missing", &directory]);
+        assert!(parse_cli_args(&cli).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_warmup_sends_configured_pings() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let pings = Arc::new(AtomicUsize::new(0));
+        let counting_ping = |pings: Arc<AtomicUsize>| {
+            move || {
+                let pings = pings.clone();
+                async move {
+                    pings.fetch_add(1, Ordering::SeqCst);
+                    Ok::<_, ()>(())
                 }
             }
+        };
+
+        assert!(spawn_warmup(0, counting_ping(pings.clone())).is_none());
+        tokio::task::yield_now().await;
+        assert_eq!(pings.load(Ordering::SeqCst), 0);
+
+        for configured in [1, 3, WARMUP_PINGS] {
+            pings.store(0, Ordering::SeqCst);
+            spawn_warmup(configured, counting_ping(pings.clone()))
+                .unwrap()
+                .await
+                .unwrap();
+            assert_eq!(pings.load(Ordering::SeqCst), configured);
         }
-    } else {
-        unreachable!("This should never happen - query should always be Some or None");
+
+        let cli = Cli::parse_from(["tg", "--no-warmup", "query"]);
+        assert_eq!(cli.warmup_pings(), 0);
+    }
+
+    #[test]
+    fn test_namespaces_command() {
+        let cli = Cli::parse_from(["tg", "namespaces", "--stale", "30d"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Namespaces {
+                stale: Some(2_592_000)
+            })
+        ));
+
+        let cli = Cli::parse_from(["tg", "namespaces"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Namespaces { stale: None })
+        ));
+
+        // A query that merely starts with the word is still a query
+        let cli = Cli::parse_from(["tg", "list namespaces", "."]);
+        assert!(cli.command.is_none());
+        assert_eq!(cli.pattern.as_deref(), Some("list namespaces"));
+
+        assert!(Cli::try_parse_from(["tg", "--stale", "30d"]).is_err());
+    }
+
+    #[test]
+    fn test_confirm() {
+        let answer = |input: &str| confirm("Delete?", &mut std::io::Cursor::new(input));
+        assert!(answer("y\n"));
+        assert!(answer("YES\n"));
+        assert!(!answer("n\n"));
+        assert!(!answer("\n"));
+        // No answer at all, e.g. stdin closed
+        assert!(!answer(""));
+
+        let cli = Cli::parse_from(["tg", "--delete", "--yes"]);
+        assert!(cli.delete && cli.yes);
+        assert!(Cli::try_parse_from(["tg", "--yes"]).is_err());
+    }
+
+    #[test]
+    fn test_search_error_exit_codes() {
+        assert_eq!(
+            search_error_exit_code(&search::SearchError::EmptyQuery),
+            EXIT_USAGE
+        );
+        assert_eq!(
+            search_error_exit_code(&search::SearchError::NamespaceError("bad".to_string())),
+            EXIT_USAGE
+        );
+        assert_eq!(
+            search_error_exit_code(&search::SearchError::NoEmbedding),
+            EXIT_API
+        );
+        assert_eq!(
+            search_error_exit_code(&search::SearchError::IndexBuildFailed("boom".to_string())),
+            EXIT_API
+        );
+    }
+
+    #[test]
+    fn test_missing_api_keys_are_config_errors() {
+        assert_eq!(
+            search_error_exit_code(&search::SearchError::TurbopufferError(
+                turbopuffer::TurbopufferError::MissingApiKey
+            )),
+            EXIT_USAGE
+        );
+        assert_eq!(
+            search_error_exit_code(&search::SearchError::EmbeddingError(
+                embeddings::EmbeddingError::MissingApiKey
+            )),
+            EXIT_USAGE
+        );
+        assert_eq!(
+            search_error_exit_code(&search::SearchError::TurbopufferError(
+                turbopuffer::TurbopufferError::ApiError("500".to_string())
+            )),
+            EXIT_API
+        );
+    }
+
+    #[test]
+    fn test_sync_error_exit_codes() {
+        let error = anyhow::Error::from(turbopuffer::TurbopufferError::ApiError(
+            "unavailable".to_string(),
+        ));
+        assert_eq!(sync_error_exit_code(&error), EXIT_API);
+
+        let error = anyhow::Error::from(turbopuffer::TurbopufferError::MissingApiKey);
+        assert_eq!(sync_error_exit_code(&error), EXIT_USAGE);
+
+        let error = anyhow::Error::from(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert_eq!(sync_error_exit_code(&error), EXIT_USAGE);
     }
 }
@@ -0,0 +1,159 @@
+use crate::chunker::{self, FileStat, WalkOptions};
+use crate::config::Settings;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// How long a manifest is trusted before the index's sync time is checked again, to
+/// notice syncs from elsewhere
+const MANIFEST_TRUST_SECS: u64 = 60 * 60;
+
+/// The files a successful sync indexed, by path: `(size, mtime in nanoseconds)`, and the
+/// sync time it left on the server. A tree that still matches it has nothing to sync,
+/// which can be told without reading a file or, while the manifest is trusted, any
+/// request to turbopuffer.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Hash of everything besides the files that decides what gets indexed
+    fingerprint: u64,
+    files: BTreeMap<String, (u64, u64)>,
+    /// The index's [`crate::turbopuffer::last_synced`] time after the sync
+    synced_at: u64,
+    /// When the index was last seen at `synced_at`, in seconds since the epoch
+    checked_at: u64,
+}
+
+impl Manifest {
+    /// Manifest of `files`, as listed by [`crate::chunker::stat_files_with_options`]
+    pub fn new(fingerprint: u64, files: &[FileStat], synced_at: u64, checked_at: u64) -> Self {
+        let files = files
+            .iter()
+            .map(|file| (file.path.clone(), (file.size, file.mtime_nanos)))
+            .collect();
+        Self {
+            fingerprint,
+            files,
+            synced_at,
+            checked_at,
+        }
+    }
+
+    /// Fingerprint of the indexing `options` (e.g. walk flags) and the settings, so
+    /// upgrading turbogrep or changing the config invalidates old manifests
+    pub fn fingerprint(settings: Option<&Settings>, options: &serde_json::Value) -> u64 {
+        let key = serde_json::json!([env!("CARGO_PKG_VERSION"), settings, options]);
+        xxh3_64(key.to_string().as_bytes())
+    }
+}
+
+/// On-disk manifests of the last successful sync, one file per namespace
+#[derive(Debug, Clone)]
+pub struct ManifestStore {
+    dir: PathBuf,
+}
+
+impl ManifestStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Manifests in the user's cache directory, `None` if it can't be located
+    pub fn from_cache_dir() -> Option<Self> {
+        let dir = crate::config::cache_dir().ok()?.join("manifests");
+        Some(Self::new(dir))
+    }
+
+    fn path(&self, namespace: &str) -> PathBuf {
+        self.dir.join(format!("{namespace}.json"))
+    }
+
+    fn load(&self, namespace: &str) -> Option<Manifest> {
+        let bytes = std::fs::read(self.path(namespace)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    fn save(&self, namespace: &str, manifest: &Manifest) {
+        let written = std::fs::create_dir_all(&self.dir).and_then(|_| {
+            let json = serde_json::to_vec(manifest).map_err(std::io::Error::other)?;
+            std::fs::write(self.path(namespace), json)
+        });
+        if let Err(e) = written {
+            crate::vprintln!("<(°~°)> Couldn't write sync manifest: {}", e);
+        }
+    }
+
+    /// Forget the manifest of `namespace`, e.g. because its index was deleted
+    pub fn invalidate(&self, namespace: &str) {
+        let _ = std::fs::remove_file(self.path(namespace));
+    }
+
+    /// Run `sync` unless the files under `root_dir` still match the manifest of the last
+    /// successful sync, in which case neither the chunks nor the server state are needed.
+    /// For `MANIFEST_TRUST_SECS` after it's written or checked that takes no request at all;
+    /// later `last_synced` has to still report the sync time it recorded, as a different
+    /// one means the index changed elsewhere, e.g. a sync from another checkout.
+    ///
+    /// `sync` returns whether the index changed, whether every chunk made it in and the
+    /// sync time it left; the manifest is only recorded for a complete sync so skipped
+    /// chunks are retried.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn sync_if_changed<L, LFut, F, Fut>(
+        &self,
+        namespace: &str,
+        root_dir: &str,
+        walk: &WalkOptions,
+        fingerprint: u64,
+        now: u64,
+        last_synced: L,
+        sync: F,
+    ) -> Result<bool>
+    where
+        L: FnOnce() -> LFut,
+        LFut: std::future::Future<Output = Option<u64>>,
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<(bool, bool, Option<u64>)>>,
+    {
+        let files = {
+            let root_dir = root_dir.to_string();
+            let walk = walk.clone();
+            tokio_rayon::spawn(move || chunker::stat_files_with_options(&root_dir, &walk)).await?
+        };
+        let unchanged = Manifest::new(fingerprint, &files, 0, now);
+        if let Some(manifest) = self.load(namespace).filter(|manifest| {
+            manifest.fingerprint == unchanged.fingerprint && manifest.files == unchanged.files
+        }) {
+            if !crate::sync::is_stale(Some(manifest.checked_at), now, MANIFEST_TRUST_SECS) {
+                crate::vprintln!("<(°O°)> no files changed since the last sync, skipping");
+                return Ok(false);
+            }
+            if last_synced().await == Some(manifest.synced_at) {
+                crate::vprintln!("<(°O°)> no files changed since the last sync, skipping");
+                self.save(
+                    namespace,
+                    &Manifest {
+                        checked_at: now,
+                        ..manifest
+                    },
+                );
+                return Ok(false);
+            }
+            crate::vprintln!("<(°~°)> the index was synced from elsewhere, syncing");
+        }
+
+        // Until the sync finishes, the index may not match any manifest
+        self.invalidate(namespace);
+        let (changed, complete, synced_at) = sync().await?;
+        if complete && let Some(synced_at) = synced_at {
+            self.save(
+                namespace,
+                &Manifest {
+                    synced_at,
+                    ..unchanged
+                },
+            );
+        }
+        Ok(changed)
+    }
+}
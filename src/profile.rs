@@ -0,0 +1,93 @@
+use serde::Serialize;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+/// Spans recorded for `--profile`, written as a Chrome trace that chrome://tracing,
+/// Perfetto and speedscope render as a flamegraph of the walk, chunking, embedding and
+/// turbopuffer phases of a run
+static TRACE: OnceLock<Trace> = OnceLock::new();
+
+/// A complete ("X") event: `name` ran for `dur` microseconds from `ts` on thread `tid`
+#[derive(Debug, Clone, Serialize)]
+struct TraceEvent {
+    name: String,
+    cat: &'static str,
+    ph: &'static str,
+    ts: u64,
+    dur: u64,
+    pid: u32,
+    tid: u64,
+}
+
+struct Trace {
+    origin: Instant,
+    events: Mutex<Vec<TraceEvent>>,
+}
+
+/// Start recording spans, for the rest of the process
+pub fn enable() {
+    TRACE.get_or_init(|| Trace {
+        origin: Instant::now(),
+        events: Mutex::new(Vec::new()),
+    });
+}
+
+pub fn is_enabled() -> bool {
+    TRACE.get().is_some()
+}
+
+/// Small sequential id of the current thread, which trace viewers lay out as rows
+fn thread_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static ID: u64 = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    ID.with(|id| *id)
+}
+
+/// Records the time until it's dropped as a trace event, when profiling is enabled
+#[must_use = "the span ends when dropped"]
+pub struct Span {
+    event: Option<(&'static str, String, Instant)>,
+}
+
+/// Time a phase of category `category` (e.g. "walk", "embed") until the span is dropped
+pub fn span(category: &'static str, name: impl Into<String>) -> Span {
+    Span {
+        event: is_enabled().then(|| (category, name.into(), Instant::now())),
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let (Some((cat, name, start)), Some(trace)) = (self.event.take(), TRACE.get()) else {
+            return;
+        };
+        let event = TraceEvent {
+            name,
+            cat,
+            ph: "X",
+            ts: start.saturating_duration_since(trace.origin).as_micros() as u64,
+            dur: start.elapsed().as_micros() as u64,
+            pid: std::process::id(),
+            tid: thread_id(),
+        };
+        trace.events.lock().unwrap().push(event);
+    }
+}
+
+/// Write the spans recorded so far to `path` as a Chrome trace
+pub fn write_trace(path: &Path) -> std::io::Result<()> {
+    let events = TRACE
+        .get()
+        .map(|trace| trace.events.lock().unwrap().clone())
+        .unwrap_or_default();
+    let json = serde_json::to_vec(&serde_json::json!({
+        "traceEvents": events,
+        "displayTimeUnit": "ms",
+    }))
+    .map_err(std::io::Error::other)?;
+    std::fs::write(path, json)
+}
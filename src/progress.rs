@@ -1,12 +1,39 @@
 use indicatif::{ProgressBar, ProgressStyle};
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+static FORCE_PROGRESS: OnceLock<bool> = OnceLock::new();
+
+/// Show the live bar even in CI or when stderr isn't a terminal (`--progress`)
+pub fn set_force_progress(force: bool) {
+    FORCE_PROGRESS.set(force).ok();
+}
+
+/// Whether to draw the live bar rather than plain log lines: CI logs and redirected
+/// output would otherwise get every redraw, unless the bar is forced
+pub fn use_live_bar(ci_env: Option<&str>, stderr_is_terminal: bool, forced: bool) -> bool {
+    let in_ci =
+        ci_env.is_some_and(|ci| !ci.is_empty() && ci != "0" && !ci.eq_ignore_ascii_case("false"));
+    forced || (!in_ci && stderr_is_terminal)
+}
 
 /// Create a standard TurboPuffer-branded progress bar with consistent styling
 /// Follows TurboPuffer brand guidelines from https://turbopuffer.com/press
 pub fn tg_progress_bar(total: u64) -> ProgressBar {
+    // is_verbose() is always false under --quiet
     if !crate::is_verbose() {
         return ProgressBar::hidden();
     }
 
+    let ci_env = std::env::var("CI").ok();
+    let forced = *FORCE_PROGRESS.get().unwrap_or(&false);
+    if !use_live_bar(ci_env.as_deref(), std::io::stderr().is_terminal(), forced) {
+        // Hidden bars still count, tg_progress_inc logs from the count instead
+        let pb = ProgressBar::hidden();
+        pb.set_length(total);
+        return pb;
+    }
+
     let pb = ProgressBar::new(total);
     pb.set_style(
             ProgressStyle::default_bar()
@@ -28,3 +55,36 @@ pub fn tg_progress_bar(total: u64) -> ProgressBar {
     pb
 }
 
+/// Advance `pb` by one. Without a live bar, a plain line is logged every tenth of the way
+pub fn tg_progress_inc(pb: &ProgressBar) {
+    pb.inc(1);
+    if !pb.is_hidden() {
+        return;
+    }
+
+    let total = pb.length().unwrap_or(0);
+    let position = pb.position();
+    if position == total || position.is_multiple_of((total / 10).max(1)) {
+        crate::vprintln!("processed {}/{}", position, total);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_use_live_bar() {
+        assert!(use_live_bar(None, true, false));
+        assert!(use_live_bar(Some("false"), true, false));
+        assert!(use_live_bar(Some(""), true, false));
+
+        // CI or redirected output falls back to plain lines
+        assert!(!use_live_bar(Some("true"), true, false));
+        assert!(!use_live_bar(Some("1"), true, false));
+        assert!(!use_live_bar(None, false, false));
+
+        // --progress always wins
+        assert!(use_live_bar(Some("true"), false, true));
+    }
+}
@@ -1,5 +1,6 @@
-use crate::config::SETTINGS;
+use crate::config::{SETTINGS, Settings};
 use anyhow::Result;
+use std::collections::HashSet;
 use std::path::PathBuf;
 use xxhash_rust::xxh3::xxh3_64;
 
@@ -15,6 +16,75 @@ pub fn validate_directory(path: &str) -> Result<PathBuf, String> {
     }
 }
 
+/// Whether a PATH argument is a glob pattern rather than a literal path
+pub fn is_glob(path: &str) -> bool {
+    path.contains(['*', '?', '[', '{'])
+}
+
+/// Resolve PATH arguments into the directory to index from and, unless they name a
+/// single directory, the absolute glob patterns that scope indexing and search.
+///
+/// Each argument may be a directory, a file or a glob like `src/**/handlers`. A glob
+/// is anchored at its leading literal components, which must exist.
+pub fn resolve_paths(paths: &[String]) -> Result<(String, Option<Vec<String>>), String> {
+    if let [path] = paths
+        && !is_glob(path)
+        && !std::path::Path::new(path).is_file()
+    {
+        validate_directory(path)?;
+        return Ok((path.clone(), None));
+    }
+
+    let mut directories = Vec::new();
+    let mut patterns = Vec::new();
+    for path in paths {
+        let components: Vec<_> = std::path::Path::new(path).components().collect();
+        let glob_start = components
+            .iter()
+            .position(|c| is_glob(&c.as_os_str().to_string_lossy()))
+            .unwrap_or(components.len());
+
+        let base: PathBuf = components[..glob_start].iter().collect();
+        let base = if base.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            base
+        };
+        let base = base
+            .canonicalize()
+            .map_err(|e| format!("Path '{}' does not exist ({})", base.display(), e))?;
+
+        let rest: PathBuf = components[glob_start..].iter().collect();
+        if rest.as_os_str().is_empty() {
+            directories.push(if base.is_dir() {
+                base.clone()
+            } else {
+                base.parent().map(PathBuf::from).unwrap_or_default()
+            });
+            patterns.push(base.to_string_lossy().to_string());
+        } else {
+            patterns.push(base.join(rest).to_string_lossy().to_string());
+            directories.push(base);
+        }
+    }
+
+    // The namespace is per project, so every path has to live in the same one
+    let first_directory = directories[0].to_string_lossy().to_string();
+    let first_root = find_project_root(&first_directory).map_err(|e| e.to_string())?;
+    for directory in &directories[1..] {
+        let root = find_project_root(&directory.to_string_lossy()).map_err(|e| e.to_string())?;
+        if root != first_root {
+            return Err(format!(
+                "'{}' and '{}' belong to different projects",
+                first_root.display(),
+                root.display()
+            ));
+        }
+    }
+
+    Ok((first_directory, Some(patterns)))
+}
+
 pub fn find_project_root(start_path: &str) -> Result<std::path::PathBuf> {
     let mut current = std::path::Path::new(start_path).canonicalize()?;
 
@@ -102,24 +172,248 @@ pub fn find_project_root(start_path: &str) -> Result<std::path::PathBuf> {
     Ok(std::path::Path::new(start_path).canonicalize()?)
 }
 
-pub fn namespace_and_dir(directory: &str) -> Result<(String, String)> {
+/// Namespace and root directory for `directory`: the project root it lives in, or with
+/// `flat` the directory itself. A `branch` gets its own index of the same root.
+pub fn namespace_and_dir(
+    directory: &str,
+    flat: bool,
+    branch: Option<&str>,
+) -> Result<(String, String)> {
     // Find the project root instead of using the provided directory directly
-    let root_path = find_project_root(directory)?;
-
-    // Get embedding provider from settings
-    let embedding_provider = SETTINGS
-        .get()
-        .and_then(|s| s.embedding_provider.as_ref())
-        .map(|s| s.as_str())
-        .unwrap_or("voyage");
+    let root_path = if flat {
+        std::path::Path::new(directory).canonicalize()?
+    } else {
+        find_project_root(directory)?
+    };
 
-    // Hash the root path for a consistent, short namespace name
     let path_str = root_path.to_string_lossy();
-    let hash = xxh3_64(path_str.as_bytes());
-    let namespace = format!("tg_{}_{:x}", embedding_provider, hash);
+    let portable = SETTINGS
+        .get()
+        .and_then(|s| s.portable_namespaces)
+        .unwrap_or(false);
+    let root_id = if portable {
+        let git_root = root_path.ancestors().find(|dir| dir.join(".git").exists());
+        let checkout_id = git_root.and_then(|dir| git_checkout_id(&dir.to_string_lossy()));
+        let git_root = git_root.map(|dir| dir.to_string_lossy());
+        portable_root_id(&path_str, git_root.as_deref(), checkout_id.as_deref())
+    } else {
+        path_str.to_string()
+    };
 
     // Return both namespace and the canonical root directory
-    Ok((namespace, root_path.to_string_lossy().to_string()))
+    Ok((
+        namespace_for(&root_id, branch, SETTINGS.get()),
+        path_str.to_string(),
+    ))
+}
+
+/// Prefix of project namespaces without a `namespace_prefix` setting
+pub const DEFAULT_NAMESPACE_PREFIX: &str = "tg";
+
+/// Longest namespace name turbopuffer accepts
+const MAX_NAMESPACE_LEN: usize = 128;
+
+/// Start of every project namespace's name, up to and including its `_` separator
+pub fn namespace_prefix(settings: Option<&Settings>) -> String {
+    let prefix = settings
+        .and_then(|s| s.namespace_prefix.as_deref())
+        .unwrap_or(DEFAULT_NAMESPACE_PREFIX);
+    format!("{prefix}_")
+}
+
+/// Embedding provider this install's namespaces are named after, and embedded with
+pub fn embedding_provider(settings: Option<&Settings>) -> &str {
+    settings
+        .and_then(|s| s.embedding_provider.as_deref())
+        .unwrap_or("voyage")
+}
+
+/// Embedding provider named in a project namespace, `None` for a namespace named some
+/// other way, e.g. by hand. Besides `settings`' prefix the default one is recognized,
+/// so a teammate's namespace can be told apart even with a local `namespace_prefix`.
+pub fn namespace_provider<'a>(namespace: &'a str, settings: Option<&Settings>) -> Option<&'a str> {
+    let rest = namespace
+        .strip_prefix(&namespace_prefix(settings))
+        .or_else(|| namespace.strip_prefix(&namespace_prefix(None)))?;
+    let (provider, _hash) = rest.split_once('_')?;
+    Some(provider)
+}
+
+/// Namespace of the project identified by `root_id`, its path unless portable
+fn namespace_for(root_id: &str, branch: Option<&str>, settings: Option<&Settings>) -> String {
+    let embedding_provider = embedding_provider(settings);
+
+    // Hash the root for a consistent, short namespace name
+    let hash = xxh3_64(root_id.as_bytes());
+    let mut namespace = format!(
+        "{}{}_{:x}",
+        namespace_prefix(settings),
+        embedding_provider,
+        hash
+    );
+    if let Some(branch) = branch {
+        namespace.push('_');
+        let room = MAX_NAMESPACE_LEN.saturating_sub(namespace.len());
+        namespace.push_str(&branch_suffix(branch, room));
+    }
+    namespace
+}
+
+/// `path` with forward slashes and without what differs between Windows, WSL and Unix
+/// for the same directory: the `\\?\` verbatim prefix, a `C:` drive or a `/mnt/c` mount
+fn portable_path(path: &str) -> String {
+    let path = path
+        .strip_prefix(r"\\?\")
+        .unwrap_or(path)
+        .replace('\\', "/");
+    let drive_letter = |s: &str| s.chars().next().is_some_and(|c| c.is_ascii_alphabetic());
+    let without_drive = if drive_letter(&path) && path.get(1..2) == Some(":") {
+        &path[2..]
+    } else {
+        match path.strip_prefix("/mnt/") {
+            Some(rest) if drive_letter(rest) && matches!(rest.get(1..2), None | Some("/")) => {
+                &rest[1..]
+            }
+            _ => &path,
+        }
+    };
+    without_drive.trim_end_matches('/').to_string()
+}
+
+/// Platform-independent identifier of the project at `root`, with the `portable_namespaces`
+/// setting: the identity of its git checkout (see [`git_checkout_id`], else the checkout's
+/// [`portable_path`]) plus its path inside it, or outside a checkout its [`portable_path`].
+/// Clones of the same repository then get one namespace on every OS, while unrelated
+/// repositories in directories of the same name don't share one.
+fn portable_root_id(root: &str, git_root: Option<&str>, checkout_id: Option<&str>) -> String {
+    let root = portable_path(root);
+    match git_root.map(portable_path) {
+        Some(git_root) => {
+            let inside = root.strip_prefix(&git_root).unwrap_or_default();
+            let id = checkout_id.unwrap_or(&git_root);
+            format!("git:{id}{inside}")
+        }
+        None => root,
+    }
+}
+
+/// What identifies the repository checked out at `git_root` wherever it is cloned: the
+/// URL of its `origin` remote without a trailing `.git`, else its root commit. `None`
+/// for a checkout without either, e.g. a fresh `git init`.
+fn git_checkout_id(git_root: &str) -> Option<String> {
+    let git = |args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .args(["-C", git_root])
+            .args(args)
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let stdout = String::from_utf8(output.stdout).ok()?;
+        let first = stdout.lines().next()?.trim().to_string();
+        (!first.is_empty()).then_some(first)
+    };
+    if let Some(url) = git(&["remote", "get-url", "origin"]) {
+        let url = url.trim_end_matches('/');
+        return Some(url.strip_suffix(".git").unwrap_or(url).to_string());
+    }
+    git(&["rev-list", "--max-parents=0", "HEAD"]).map(|commit| format!("commit:{commit}"))
+}
+
+/// Namespace-safe form of a branch name. Characters turbopuffer doesn't allow in
+/// namespaces become `-`, plus a hash of the real name so `feature/x` and `feature-x`
+/// still get separate indexes. Names longer than `max_len` are cut short to make room
+/// for that hash.
+fn branch_suffix(branch: &str, max_len: usize) -> String {
+    let safe: String = branch
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    if safe == branch && safe.len() <= max_len {
+        return safe;
+    }
+    let hash = format!("{:08x}", xxh3_64(branch.as_bytes()) as u32);
+    let keep = safe.len().min(max_len.saturating_sub(hash.len() + 1));
+    format!("{}-{}", &safe[..keep], hash)
+}
+
+/// Name of the branch checked out in the git checkout containing `directory`, `None` when
+/// detached or outside a checkout
+pub fn git_branch(directory: &str) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .args(["-C", directory, "symbolic-ref", "--short", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let branch = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!branch.is_empty()).then_some(branch)
+}
+
+/// Resolve the full `HEAD` commit SHA of the git checkout containing `directory`
+pub fn git_head(directory: &str) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .args(["-C", directory, "rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let sha = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!sha.is_empty()).then_some(sha)
+}
+
+/// Files with uncommitted changes in the git checkout containing `directory`: those
+/// differing from `HEAD`, staged or not, and untracked files that aren't ignored. Only
+/// files under `directory` are listed, as paths joined onto it.
+pub fn git_changed_paths(directory: &str) -> HashSet<PathBuf> {
+    let git_paths = |args: &[&str]| -> Vec<PathBuf> {
+        let Ok(output) = std::process::Command::new("git")
+            .args(["-C", directory])
+            .args(args)
+            .output()
+        else {
+            return Vec::new();
+        };
+        if !output.status.success() {
+            return Vec::new();
+        }
+        output
+            .stdout
+            .split(|&byte| byte == 0)
+            .filter(|path| !path.is_empty())
+            .map(|path| PathBuf::from(directory).join(String::from_utf8_lossy(path).as_ref()))
+            .collect()
+    };
+
+    let modified = git_paths(&["diff", "--name-only", "--relative", "-z", "HEAD"]);
+    let untracked = git_paths(&["ls-files", "--others", "--exclude-standard", "-z"]);
+    modified.into_iter().chain(untracked).collect()
+}
+
+/// Paths of the git submodules declared in `root_dir/.gitmodules`, relative to `root_dir`
+pub fn git_submodule_paths(root_dir: &str) -> Vec<PathBuf> {
+    let Ok(gitmodules) =
+        std::fs::read_to_string(std::path::Path::new(root_dir).join(".gitmodules"))
+    else {
+        return Vec::new();
+    };
+
+    gitmodules
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == "path").then(|| PathBuf::from(value.trim()))
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -159,8 +453,8 @@ mod tests {
         let current_dir = env::current_dir().unwrap();
         let dir_str = current_dir.to_string_lossy();
 
-        let result1 = namespace_and_dir(&dir_str);
-        let result2 = namespace_and_dir(&dir_str);
+        let result1 = namespace_and_dir(&dir_str, false, None);
+        let result2 = namespace_and_dir(&dir_str, false, None);
 
         assert!(result1.is_ok());
         assert!(result2.is_ok());
@@ -179,7 +473,7 @@ mod tests {
         let current_dir = env::current_dir().unwrap();
         let dir_str = current_dir.to_string_lossy();
 
-        let result = namespace_and_dir(&dir_str);
+        let result = namespace_and_dir(&dir_str, false, None);
         assert!(result.is_ok());
         
         let (namespace, _) = result.unwrap();
@@ -187,4 +481,217 @@ mod tests {
         // Format: tg_{provider}_{hash}
         assert!(namespace.contains("_voyage_") || namespace.starts_with("tg_voyage_"));
     }
+
+    #[test]
+    fn test_flat_namespace_uses_given_directory() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("Cargo.toml"), "").unwrap();
+        let subdir = temp_dir.path().join("crates/parser");
+        std::fs::create_dir_all(&subdir).unwrap();
+        let subdir = subdir.to_string_lossy();
+
+        let (root_namespace, root_dir) = namespace_and_dir(&subdir, false, None).unwrap();
+        assert_eq!(
+            root_dir,
+            temp_dir.path().canonicalize().unwrap().to_string_lossy()
+        );
+
+        let (namespace, dir) = namespace_and_dir(&subdir, true, None).unwrap();
+        assert_eq!(
+            dir,
+            std::path::Path::new(subdir.as_ref())
+                .canonicalize()
+                .unwrap()
+                .to_string_lossy()
+        );
+        assert_ne!(namespace, root_namespace);
+        assert!(namespace.starts_with("tg_"));
+    }
+
+    #[test]
+    fn test_portable_namespace_is_the_same_across_os_path_styles() {
+        let origin = Some("https://github.com/turbopuffer/turbogrep");
+        let windows = portable_root_id(
+            r"\\?\C:\Users\dev\src\turbogrep\cli",
+            Some(r"\\?\C:\Users\dev\src\turbogrep"),
+            origin,
+        );
+        let wsl = portable_root_id(
+            "/mnt/c/Users/dev/src/turbogrep/cli",
+            Some("/mnt/c/Users/dev/src/turbogrep"),
+            origin,
+        );
+        let macos = portable_root_id(
+            "/Users/dev/code/turbogrep/cli",
+            Some("/Users/dev/code/turbogrep"),
+            origin,
+        );
+        assert_eq!(windows, "git:https://github.com/turbopuffer/turbogrep/cli");
+        assert_eq!(
+            namespace_for(&windows, None, None),
+            namespace_for(&wsl, None, None)
+        );
+        assert_eq!(
+            namespace_for(&wsl, None, None),
+            namespace_for(&macos, None, None)
+        );
+
+        // Outside a checkout only the drive differences are normalized away
+        assert_eq!(portable_path(r"D:\work\notes\"), "/work/notes");
+        assert_eq!(portable_path("/mnt/d/work/notes"), "/work/notes");
+        assert_eq!(portable_path("/mnt/data/notes"), "/mnt/data/notes");
+        assert_ne!(
+            namespace_for(&portable_root_id("/work/notes", None, None), None, None),
+            namespace_for(&portable_root_id("/work/other", None, None), None, None)
+        );
+    }
+
+    #[test]
+    fn test_namespace_provider() {
+        let namespace = namespace_for("/work/app", Some("main"), None);
+        assert_eq!(namespace_provider(&namespace, None), Some("voyage"));
+        assert_eq!(namespace_provider("tg_openai_1a2b3c", None), Some("openai"));
+
+        let settings = Settings {
+            namespace_prefix: Some("acme".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            namespace_provider("acme_voyage_1a2b3c", Some(&settings)),
+            Some("voyage")
+        );
+        assert_eq!(
+            namespace_provider("tg_openai_1a2b3c", Some(&settings)),
+            Some("openai")
+        );
+        assert_eq!(namespace_provider("shared-docs", None), None);
+    }
+
+    #[test]
+    fn test_namespace_prefix_keeps_hash() {
+        let settings = Settings {
+            namespace_prefix: Some("payments".to_string()),
+            ..Default::default()
+        };
+        let default = namespace_for("/work/api", None, None);
+        let prefixed = namespace_for("/work/api", None, Some(&settings));
+        assert!(default.starts_with("tg_voyage_"));
+        assert!(prefixed.starts_with("payments_voyage_"));
+        assert_eq!(
+            default.strip_prefix("tg_"),
+            prefixed.strip_prefix("payments_")
+        );
+        assert_eq!(namespace_prefix(Some(&settings)), "payments_");
+    }
+
+    #[test]
+    fn test_branches_get_distinct_namespaces() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().to_string_lossy();
+
+        let (shared, _) = namespace_and_dir(&dir, true, None).unwrap();
+        let (main, main_dir) = namespace_and_dir(&dir, true, Some("main")).unwrap();
+        let (feature, feature_dir) = namespace_and_dir(&dir, true, Some("feature-x")).unwrap();
+        assert_eq!(main, format!("{shared}_main"));
+        assert_eq!(feature, format!("{shared}_feature-x"));
+        assert_eq!(main_dir, feature_dir);
+
+        let (slashed, _) = namespace_and_dir(&dir, true, Some("feature/x")).unwrap();
+        assert_ne!(slashed, feature);
+        assert!(slashed.starts_with(&format!("{shared}_feature-x-")));
+    }
+
+    #[test]
+    fn test_long_branch_namespace_fits_turbopuffer_limit() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().to_string_lossy();
+
+        let long = "feature/".to_string() + &"x".repeat(200);
+        let longer = long.clone() + "y";
+        let (namespace, _) = namespace_and_dir(&dir, true, Some(&long)).unwrap();
+        let (other, _) = namespace_and_dir(&dir, true, Some(&longer)).unwrap();
+        assert_eq!(namespace.len(), MAX_NAMESPACE_LEN);
+        assert_eq!(other.len(), MAX_NAMESPACE_LEN);
+        assert_ne!(namespace, other);
+    }
+
+    #[test]
+    fn test_portable_namespace_tells_same_named_repos_apart() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut namespaces = Vec::new();
+        for (parent, origin) in [
+            ("work", "git@github.com:acme/app.git"),
+            ("personal", "https://github.com/me/app"),
+        ] {
+            let repo = temp_dir.path().join(parent).join("app");
+            std::fs::create_dir_all(&repo).unwrap();
+            let repo = repo.to_string_lossy().to_string();
+            for args in [&["init", "-q"][..], &["remote", "add", "origin", origin]] {
+                let status = std::process::Command::new("git")
+                    .args(["-C", &repo])
+                    .args(args)
+                    .status()
+                    .unwrap();
+                assert!(status.success());
+            }
+
+            let checkout_id = git_checkout_id(&repo);
+            assert_eq!(
+                checkout_id.as_deref(),
+                Some(origin.trim_end_matches(".git"))
+            );
+            let root_id = portable_root_id(&repo, Some(&repo), checkout_id.as_deref());
+            namespaces.push(namespace_for(&root_id, None, None));
+        }
+        assert_ne!(namespaces[0], namespaces[1]);
+
+        // Without a remote or a commit the checkout's path tells them apart
+        assert_ne!(
+            portable_root_id("/work/app", Some("/work/app"), None),
+            portable_root_id("/personal/app", Some("/personal/app"), None)
+        );
+    }
+
+    #[test]
+    fn test_git_head_outside_checkout() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        assert_eq!(git_head(&temp_dir.path().to_string_lossy()), None);
+        assert_eq!(git_branch(&temp_dir.path().to_string_lossy()), None);
+    }
+
+    #[test]
+    fn test_git_submodule_paths() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp_dir.path().join(".gitmodules"),
+            "[submodule \"vendor/lib\"]\n\tpath = vendor/lib\n\turl = https://example.com/lib.git\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            git_submodule_paths(&temp_dir.path().to_string_lossy()),
+            vec![PathBuf::from("vendor/lib")]
+        );
+    }
+
+    #[test]
+    fn test_resolve_paths_glob() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        std::fs::create_dir_all(root.join("src/api/handlers")).unwrap();
+
+        let glob = format!("{}/src/**/handlers", root.display());
+        let (directory, patterns) = resolve_paths(std::slice::from_ref(&glob)).unwrap();
+        assert_eq!(directory, root.join("src").to_string_lossy());
+        assert_eq!(patterns, Some(vec![glob]));
+
+        // A single directory keeps the unscoped behavior
+        let src = root.join("src").to_string_lossy().to_string();
+        assert_eq!(
+            resolve_paths(std::slice::from_ref(&src)).unwrap(),
+            (src, None)
+        );
+
+        assert!(resolve_paths(&[format!("{}/missing/*.rs", root.display())]).is_err());
+    }
 }
@@ -0,0 +1,195 @@
+use crate::chunker::Chunk;
+use crate::config::Settings;
+use crate::embeddings::EmbeddingType;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// How long cached results stay valid unless configured otherwise
+pub const DEFAULT_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Entries kept per namespace, oldest are dropped first
+const MAX_ENTRIES: usize = 32;
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    key: u64,
+    created_at: u64,
+    results: Vec<Chunk>,
+}
+
+/// On-disk cache of recent query results, one file per namespace. Re-running the same
+/// query within the TTL skips both the query embedding and the turbopuffer query; only
+/// result metadata is stored, content is still loaded from the files on disk.
+#[derive(Debug, Clone)]
+pub struct QueryCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl QueryCache {
+    pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+        Self { dir, ttl }
+    }
+
+    /// Cache in the user's cache directory, or `None` when it's disabled (a TTL of 0)
+    /// or the directory can't be located
+    pub fn from_settings(settings: Option<&Settings>) -> Option<Self> {
+        let ttl = settings
+            .and_then(|s| s.query_cache_ttl_secs)
+            .map_or(DEFAULT_TTL, Duration::from_secs);
+        if ttl.is_zero() {
+            return None;
+        }
+        let dir = crate::config::cache_dir().ok()?.join("queries");
+        Some(Self::new(dir, ttl))
+    }
+
+    /// Cache key for everything that affects what turbopuffer returns for a query
+    pub fn key(
+        query: &str,
+        model: &str,
+        embedding_type: EmbeddingType,
+        top_k: usize,
+        filters: Option<&serde_json::Value>,
+    ) -> u64 {
+        let key = serde_json::json!([query, model, embedding_type.as_str(), top_k, filters]);
+        xxh3_64(key.to_string().as_bytes())
+    }
+
+    fn path(&self, namespace: &str) -> PathBuf {
+        self.dir.join(format!("{namespace}.json"))
+    }
+
+    fn load(&self, namespace: &str) -> Vec<CacheEntry> {
+        std::fs::read(self.path(namespace))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    fn is_fresh(&self, entry: &CacheEntry, now: u64) -> bool {
+        now.saturating_sub(entry.created_at) < self.ttl.as_secs()
+    }
+
+    fn get(&self, namespace: &str, key: u64, now: u64) -> Option<Vec<Chunk>> {
+        self.load(namespace)
+            .into_iter()
+            .find(|entry| entry.key == key && self.is_fresh(entry, now))
+            .map(|entry| entry.results)
+    }
+
+    fn put(&self, namespace: &str, key: u64, results: &[Chunk], now: u64) {
+        let mut entries = self.load(namespace);
+        entries.retain(|entry| entry.key != key && self.is_fresh(entry, now));
+        entries.push(CacheEntry {
+            key,
+            created_at: now,
+            results: results.to_vec(),
+        });
+        if entries.len() > MAX_ENTRIES {
+            entries.drain(..entries.len() - MAX_ENTRIES);
+        }
+
+        let written = std::fs::create_dir_all(&self.dir).and_then(|_| {
+            let json = serde_json::to_vec(&entries).map_err(std::io::Error::other)?;
+            std::fs::write(self.path(namespace), json)
+        });
+        if let Err(e) = written {
+            crate::vprintln!("<(°~°)> Couldn't write query cache: {}", e);
+        }
+    }
+
+    /// Drop every cached result for `namespace`, e.g. after its index changed
+    pub fn invalidate(&self, namespace: &str) {
+        let _ = std::fs::remove_file(self.path(namespace));
+    }
+
+    /// Cached results for `key`, or the results of `fetch`, which are then cached
+    pub async fn get_or_fetch<F, Fut, E>(
+        &self,
+        namespace: &str,
+        key: u64,
+        fetch: F,
+    ) -> Result<Vec<Chunk>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<Vec<Chunk>, E>>,
+    {
+        if let Some(results) = self.get(namespace, key, now_secs()) {
+            crate::vprintln!("query cache hit, skipping embedding and query");
+            return Ok(results);
+        }
+
+        let results = fetch().await?;
+        self.put(namespace, key, &results, now_secs());
+        Ok(results)
+    }
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::VOYAGE_MODEL;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn results() -> Vec<Chunk> {
+        vec![Chunk {
+            id: 7,
+            path: "/project/src/lib.rs".to_string(),
+            start_line: 3,
+            end_line: 9,
+            distance: Some(0.25),
+            ..Default::default()
+        }]
+    }
+
+    #[tokio::test]
+    async fn test_repeat_query_within_ttl_skips_fetch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = QueryCache::new(temp_dir.path().to_path_buf(), DEFAULT_TTL);
+        let key = QueryCache::key("retry logic", VOYAGE_MODEL, EmbeddingType::Query, 20, None);
+        let fetches = AtomicUsize::new(0);
+        let fetch = || async {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            Ok::<_, std::io::Error>(results())
+        };
+
+        let first = cache.get_or_fetch("tg_test", key, fetch).await.unwrap();
+        let second = cache.get_or_fetch("tg_test", key, fetch).await.unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].id, first[0].id);
+        assert_eq!(second[0].distance, Some(0.25));
+
+        // A different query or a changed index goes back to the server
+        let other_key =
+            QueryCache::key("retry logic", VOYAGE_MODEL, EmbeddingType::Query, 40, None);
+        cache
+            .get_or_fetch("tg_test", other_key, fetch)
+            .await
+            .unwrap();
+        cache.invalidate("tg_test");
+        cache.get_or_fetch("tg_test", key, fetch).await.unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_entries_expire_after_ttl() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = QueryCache::new(temp_dir.path().to_path_buf(), Duration::from_secs(60));
+        let key = QueryCache::key("retry logic", VOYAGE_MODEL, EmbeddingType::Query, 20, None);
+
+        cache.put("tg_test", key, &results(), 1_000);
+        assert!(cache.get("tg_test", key, 1_059).is_some());
+        assert!(cache.get("tg_test", key, 1_060).is_none());
+    }
+}
@@ -1,9 +1,17 @@
-use crate::{chunker, embeddings, project, sync, turbopuffer, vprintln};
+use crate::language_mix::LanguageMixStore;
+use crate::{chunker, embeddings, project, query_cache, sync, turbopuffer, vprintln};
 use anyhow::Result;
 use embeddings::Embedding;
+use futures::StreamExt;
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use turbopuffer::IndexBackend;
 
 #[derive(Debug, thiserror::Error)]
 pub enum SearchError {
@@ -23,8 +31,37 @@ pub enum SearchError {
     NamespaceError(String),
 }
 
-/// Load content from local file for a chunk
+/// Heuristically detect queries written as regular expressions (e.g. `fn.*parse`, `TODO|FIXME`).
+/// The query is embedded literally for semantic search, so these rarely match what users expect.
+pub fn looks_like_regex(query: &str) -> bool {
+    const REGEX_TOKENS: &[&str] = &[
+        ".*", ".+", "\\w", "\\d", "\\s", "\\b", "(?i)", "[^", "a-z]", "A-Z]", "0-9]",
+    ];
+
+    let query = query.trim();
+    if REGEX_TOKENS.iter().any(|token| query.contains(token)) {
+        return true;
+    }
+
+    // Anchors like `^fn main` or `Error$`
+    if query.len() > 1 && (query.starts_with('^') || query.ends_with('$')) {
+        return true;
+    }
+
+    // Alternation glued to words, like `TODO|FIXME` (but not prose such as "a | b")
+    let bytes = query.as_bytes();
+    bytes.windows(3).any(|w| {
+        w[1] == b'|'
+            && (w[0].is_ascii_alphanumeric() || w[0] == b')')
+            && (w[2].is_ascii_alphanumeric() || w[2] == b'(')
+    })
+}
+
+/// Load content from local file for a chunk, unless the index stored it
 fn load_chunk_content(chunk: &mut chunker::Chunk) -> Result<()> {
+    if chunk.content.is_some() {
+        return Ok(());
+    }
     let path = Path::new(&chunk.path);
     if !path.exists() {
         return Ok(()); // File no longer exists, leave content as None
@@ -46,11 +83,75 @@ fn load_chunk_content(chunk: &mut chunker::Chunk) -> Result<()> {
     Ok(())
 }
 
-/// Convert chunks to ripgrep-style output format for fzf compatibility  
+/// Widen a chunk to the top-level definition containing it in its local file, e.g. a
+/// nested function's to the function it's nested in, with that definition's content
+fn expand_to_enclosing_definition(chunk: &mut chunker::Chunk) -> Result<()> {
+    let content = std::fs::read_to_string(&chunk.path)?;
+    let Some(definition) = chunker::enclosing_definition(
+        &content,
+        Path::new(&chunk.path),
+        chunk.start_line,
+        chunk.end_line,
+    ) else {
+        return Ok(());
+    };
+    let lines: Vec<&str> = content
+        .lines()
+        .skip((definition.start_line - 1) as usize)
+        .take((definition.end_line - definition.start_line + 1) as usize)
+        .collect();
+    chunk.start_line = definition.start_line;
+    chunk.end_line = definition.end_line;
+    chunk.symbol = definition.symbol;
+    chunk.start_byte = None;
+    chunk.end_byte = None;
+    chunk.content = Some(lines.join("\n"));
+    Ok(())
+}
+
+/// Files read at once when loading result content, unless configured otherwise
+pub const CONTENT_LOAD_CONCURRENCY: usize = 16;
+
+/// Load content for every chunk from the local files, reading up to `concurrency` files
+/// at a time on the blocking threadpool. Chunks keep their order. With `context_symbol`,
+/// each is first widened to its enclosing definition.
+async fn load_chunk_contents(
+    chunks: Vec<chunker::Chunk>,
+    concurrency: usize,
+    context_symbol: bool,
+) -> Vec<chunker::Chunk> {
+    futures::stream::iter(chunks)
+        .map(|mut chunk| {
+            tokio::task::spawn_blocking(move || {
+                if context_symbol {
+                    // Unreadable or unparsable files keep the chunk as it was
+                    let _ = expand_to_enclosing_definition(&mut chunk);
+                }
+                if let Err(_e) = load_chunk_content(&mut chunk) {
+                    // Failed to load content - chunk will have no content
+                }
+                chunk
+            })
+        })
+        .buffered(concurrency.max(1))
+        .filter_map(|loaded| std::future::ready(loaded.ok()))
+        .collect()
+        .await
+}
+
+/// Marks a result whose content was cut short by [`SearchOptions::max_content_lines`]
+const CONTENT_TRUNCATED_MARKER: &str = "... (truncated)";
+
+/// Convert chunks to ripgrep-style output format for fzf compatibility. With
+/// `max_content_lines`, up to that many lines of each chunk follow as ripgrep-style
+/// context lines (`path-LINE-text`) instead of just the first.
 fn chunks_to_ripgrep_format(
     chunks: Vec<chunker::Chunk>,
     root_dir: &str,
     show_scores: bool,
+    show_bytes: bool,
+    show_symbols: bool,
+    max_content_lines: Option<usize>,
 ) -> String {
     chunks
         .into_iter()
@@ -68,37 +169,826 @@ fn chunks_to_ripgrep_format(
                 .and_then(|content| content.lines().next())
                 .unwrap_or("[no content]");
 
-            if show_scores {
-                if let Some(distance) = chunk.distance {
-                    format!(
-                        "{}:{}:{:.4}:{}",
-                        relative_path, chunk.start_line, distance, preview
-                    )
-                } else {
-                    format!("{}:{}:n/a:{}", relative_path, chunk.start_line, preview)
+            let location = if show_bytes {
+                match (chunk.start_byte, chunk.end_byte) {
+                    (Some(start), Some(end)) => {
+                        format!("{}:{}:{}-{}", relative_path, chunk.start_line, start, end)
+                    }
+                    _ => format!("{}:{}:n/a", relative_path, chunk.start_line),
                 }
             } else {
-                format!("{}:{}:{}", relative_path, chunk.start_line, preview)
+                format!("{}:{}", relative_path, chunk.start_line)
+            };
+            let location = if show_symbols {
+                format!("{location}:{}", chunk.symbol.as_deref().unwrap_or("-"))
+            } else {
+                location
+            };
+
+            let mut output = if show_scores {
+                let distance = chunk
+                    .distance
+                    .map_or_else(|| "n/a".to_string(), |distance| format!("{distance:.4}"));
+                match chunk.rerank_score {
+                    Some(score) => format!("{location}:{distance}:{score:.4}:{preview}"),
+                    None => format!("{location}:{distance}:{preview}"),
+                }
+            } else {
+                format!("{}:{}", location, preview)
+            };
+
+            if let (Some(max_lines), Some(content)) = (max_content_lines, &chunk.content) {
+                let mut lines = content.lines();
+                for (line_number, line) in (chunk.start_line..)
+                    .zip(lines.by_ref().take(max_lines))
+                    .skip(1)
+                {
+                    output.push_str(&format!("\n{relative_path}-{line_number}-{line}"));
+                }
+                if lines.next().is_some() {
+                    output.push_str(&format!("\n{CONTENT_TRUNCATED_MARKER}"));
+                }
             }
+            output
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `--files-only` output: the file of each result once, best first, relative to `root_dir`
+fn files_to_ripgrep_format(chunks: &[chunker::Chunk], root_dir: &str) -> String {
+    let mut seen = std::collections::HashSet::new();
+    chunks
+        .iter()
+        .map(|chunk| {
+            Path::new(&chunk.path)
+                .strip_prefix(root_dir)
+                .map(|p| p.to_string_lossy())
+                .unwrap_or_else(|_| chunk.path.as_str().into())
         })
+        .filter(|path| seen.insert(path.clone()))
         .collect::<Vec<_>>()
         .join("\n")
 }
 
+/// Options controlling how a search is run and how results are presented
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// Maximum number of results to return
+    pub max_count: usize,
+    /// Override embedding provider concurrency
+    pub embedding_concurrency: Option<usize>,
+    /// Show distance scores in output
+    pub show_scores: bool,
+    /// Show each result's byte offsets in its file, for editors that address by byte
+    pub show_bytes: bool,
+    /// Show the name of the function or type each result defines after its location
+    pub show_symbols: bool,
+    /// Show up to this many lines of each result's content rather than only its first
+    pub max_content_lines: Option<usize>,
+    /// Widen each result to the top-level definition containing it, e.g. a nested
+    /// function to its enclosing function
+    pub context_symbol: bool,
+    /// Only list the files with results, fetching nothing more of them than needed
+    pub files_only: bool,
+    /// Boost recently modified files; older files lose half their boost every this many days
+    pub recency_half_life_days: Option<f64>,
+    /// Only return chunks recorded at this (possibly abbreviated) git commit
+    pub at_commit: Option<String>,
+    /// Only return chunks from files in this scope
+    pub scope: Option<chunker::PathScope>,
+    /// Only return chunks whose first line falls in this range
+    pub line_range: Option<RangeInclusive<u32>>,
+    /// Only return chunks of files modified at or after this time (Unix seconds)
+    pub modified_since: Option<u64>,
+    /// Only return chunks of files changed since they were indexed, or with uncommitted
+    /// changes in git
+    pub only_changed: bool,
+    /// Only return chunks of files with one of these extensions
+    pub extensions: Vec<String>,
+    /// Only return chunks of these kinds, e.g. type definitions
+    pub kinds: Vec<chunker::ChunkKind>,
+    /// Rank chunks of these kinds higher, see [`KIND_BOOST`]
+    pub boost_kinds: Vec<chunker::ChunkKind>,
+    /// Without `extensions`, only return chunks of the index's dominant languages
+    pub auto_lang: bool,
+    /// Only return chunks at least this similar to the query, under the active metric
+    pub min_score: Option<f64>,
+    /// Reuse results of identical recent queries
+    pub cache: Option<query_cache::QueryCache>,
+    /// Embedding model whose vectors to search, one of the config's `embedding_models`
+    /// (default [`embeddings::VOYAGE_MODEL`])
+    pub model: Option<String>,
+    /// How the query is embedded; `Document` makes retrieval symmetric (for experiments)
+    pub query_embedding_type: embeddings::EmbeddingType,
+    /// Embed the query under a path header like chunks synced with `embed_path_context`
+    pub query_path_context: bool,
+    /// Return fewer than `max_count` results when the distances show a clear gap
+    pub adaptive: bool,
+    /// Re-order candidates with Voyage's cross-encoder before returning the best ones
+    pub rerank: bool,
+    /// Search this namespace, e.g. a teammate's index, instead of the directory's. Paths
+    /// are shown as stored and previews need content stored with `store_content`.
+    pub namespace: Option<String>,
+    /// When the query can't be embedded, e.g. Voyage is down or its key is missing,
+    /// show the chunks whose content best matches the query's words instead of failing
+    pub lexical_fallback: bool,
+    /// Read the index strongly consistently, to see writes that just happened
+    pub consistency: turbopuffer::Consistency,
+    /// Search the directory's own index rather than its project root's
+    pub flat: bool,
+    /// Search this branch's own index of the project
+    pub branch: Option<String>,
+    /// Print results by file and line with scores, so identical searches diff cleanly
+    pub snapshot: bool,
+    /// Files read at once when loading result content
+    pub content_load_concurrency: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            max_count: 20,
+            embedding_concurrency: None,
+            show_scores: false,
+            show_bytes: false,
+            show_symbols: false,
+            max_content_lines: None,
+            context_symbol: false,
+            files_only: false,
+            recency_half_life_days: None,
+            at_commit: None,
+            scope: None,
+            line_range: None,
+            modified_since: None,
+            only_changed: false,
+            extensions: Vec::new(),
+            kinds: Vec::new(),
+            boost_kinds: Vec::new(),
+            auto_lang: false,
+            min_score: None,
+            cache: None,
+            model: None,
+            query_embedding_type: embeddings::EmbeddingType::Query,
+            query_path_context: false,
+            adaptive: false,
+            rerank: false,
+            namespace: None,
+            lexical_fallback: true,
+            consistency: turbopuffer::Consistency::Eventual,
+            flat: false,
+            branch: None,
+            snapshot: false,
+            content_load_concurrency: CONTENT_LOAD_CONCURRENCY,
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Embedding model the query is embedded with and ranked against
+    fn model(&self) -> &str {
+        self.model.as_deref().unwrap_or(embeddings::VOYAGE_MODEL)
+    }
+}
+
+/// turbopuffer filters for the commit and path restrictions in `options`, plus the
+/// subdirectory the search is limited to, if any
+fn query_filters(options: &SearchOptions, path_prefix: Option<&str>) -> Option<serde_json::Value> {
+    let mut filters: Vec<_> = [
+        options.at_commit.as_deref().map(turbopuffer::commit_filter),
+        path_prefix.map(turbopuffer::path_prefix_filter),
+        options
+            .scope
+            .as_ref()
+            .map(|scope| turbopuffer::path_scope_filter(scope.patterns())),
+        options
+            .modified_since
+            .map(turbopuffer::modified_since_filter),
+        (!options.extensions.is_empty())
+            .then(|| turbopuffer::extension_filter(&options.extensions)),
+        (!options.kinds.is_empty()).then(|| turbopuffer::kind_filter(&options.kinds)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    match filters.len() {
+        0 => None,
+        1 => filters.pop(),
+        _ => Some(serde_json::json!(["And", filters])),
+    }
+}
+
+/// Drop results outside the searched subdirectory or the attribute filters of `options`.
+/// The server-side glob dialect may differ slightly, so the paths are re-checked locally.
+fn retain_filtered(
+    results: &mut Vec<chunker::Chunk>,
+    options: &SearchOptions,
+    path_prefix: Option<&str>,
+) {
+    if let Some(prefix) = path_prefix {
+        results.retain(|chunk| Path::new(&chunk.path).starts_with(prefix));
+    }
+    if let Some(scope) = &options.scope {
+        results.retain(|chunk| scope.is_match(Path::new(&chunk.path)));
+    }
+    if let Some(since) = options.modified_since {
+        results.retain(|chunk| chunk.file_mtime >= since);
+    }
+    if !options.extensions.is_empty() {
+        results.retain(|chunk| {
+            Path::new(&chunk.path)
+                .extension()
+                .is_some_and(|ext| options.extensions.iter().any(|e| ext == e.as_str()))
+        });
+    }
+    if !options.kinds.is_empty() {
+        results.retain(|chunk| chunk.kind.is_some_and(|kind| options.kinds.contains(&kind)));
+    }
+}
+
+/// Drop results in files unchanged since they were indexed: keep those modified on disk
+/// after their indexed `file_mtime`, and those in `changed` (git's uncommitted changes,
+/// which a sync before searching has already re-indexed)
+fn retain_changed(results: &mut Vec<chunker::Chunk>, changed: &HashSet<PathBuf>) {
+    results.retain(|chunk| {
+        let path = Path::new(&chunk.path);
+        let modified_after_indexing = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|mtime| mtime.duration_since(std::time::UNIX_EPOCH).ok())
+            .is_some_and(|mtime| mtime.as_secs() > chunk.file_mtime);
+        modified_after_indexing || changed.contains(path)
+    });
+}
+
+/// When searching a subdirectory of the indexed root, its canonical path. The index is
+/// shared with the root, so results are filtered down to this prefix instead.
+fn subdirectory_prefix(directory: &str, root_dir: &str) -> Option<String> {
+    let directory = Path::new(directory).canonicalize().ok()?;
+    let relative = directory.strip_prefix(root_dir).ok()?;
+    (!relative.as_os_str().is_empty()).then(|| directory.to_string_lossy().to_string())
+}
+
+/// Text embedded for the query. With `path_context` it gets the header chunks synced with
+/// `embed_path_context` have, naming the searched directory (`.` for the whole project)
+fn query_text<'a>(
+    query: &'a str,
+    path_prefix: Option<&str>,
+    root_dir: &str,
+    path_context: bool,
+) -> Cow<'a, str> {
+    if !path_context {
+        return Cow::Borrowed(query);
+    }
+    let query_chunk = chunker::Chunk {
+        path: path_prefix.unwrap_or(root_dir).to_string(),
+        content: Some(query.to_string()),
+        ..Default::default()
+    };
+    Cow::Owned(embeddings::embedding_text(&query_chunk, Some(root_dir)).into_owned())
+}
+
+/// Order results by distance, breaking ties by path and line so identical searches
+/// always print identically (turbopuffer returns equal distances in arbitrary order)
+fn sort_results(chunks: &mut [chunker::Chunk]) {
+    chunks.sort_by(|a, b| {
+        a.distance
+            .unwrap_or(f64::MAX)
+            .total_cmp(&b.distance.unwrap_or(f64::MAX))
+            .then_with(|| a.path.cmp(&b.path))
+            .then_with(|| a.start_line.cmp(&b.start_line))
+    });
+}
+
+/// Put results in file then line order, independent of score, and strip trailing
+/// whitespace (including `\r`) from their content so snapshots are byte-stable
+fn prepare_snapshot(chunks: &mut [chunker::Chunk]) {
+    chunks.sort_by(|a, b| {
+        a.path
+            .cmp(&b.path)
+            .then_with(|| a.start_line.cmp(&b.start_line))
+            .then_with(|| a.end_line.cmp(&b.end_line))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    for chunk in chunks {
+        if let Some(content) = &chunk.content {
+            let trimmed: Vec<&str> = content.lines().map(str::trim_end).collect();
+            chunk.content = Some(trimmed.join("\n"));
+        }
+    }
+}
+
+/// Keep only chunks starting within `range` (1-based, inclusive)
+fn filter_line_range(chunks: &mut Vec<chunker::Chunk>, range: &RangeInclusive<u32>) {
+    chunks.retain(|chunk| range.contains(&chunk.start_line));
+}
+
+/// Keep only chunks scoring at least `min_score`, reading distances under `metric`
+fn filter_min_score(
+    chunks: &mut Vec<chunker::Chunk>,
+    min_score: f64,
+    metric: turbopuffer::DistanceMetric,
+) {
+    chunks.retain(|chunk| chunk.distance.is_some_and(|d| metric.score(d) >= min_score));
+}
+
+/// How many times the median gap between neighbouring distances a gap must be to count as an elbow
+const ELBOW_GAP_RATIO: f64 = 3.0;
+
+/// Gaps smaller than this are noise, even if the other distances are nearly identical
+const MIN_ELBOW_GAP: f64 = 0.01;
+
+/// Number of results to keep from the first `max_count` of the sorted `distances`: everything
+/// before the largest gap between neighbours when that gap stands out from the typical gap,
+/// otherwise all of them.
+fn adaptive_cutoff(distances: &[f64], max_count: usize) -> usize {
+    let distances = &distances[..distances.len().min(max_count)];
+    if distances.len() < 3 {
+        return distances.len();
+    }
+
+    let gaps: Vec<f64> = distances.windows(2).map(|w| w[1] - w[0]).collect();
+    let (elbow, largest_gap) = gaps
+        .iter()
+        .copied()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or_default();
+
+    let mut sorted_gaps = gaps.clone();
+    sorted_gaps.sort_by(f64::total_cmp);
+    let median_gap = sorted_gaps[sorted_gaps.len() / 2];
+
+    if largest_gap >= MIN_ELBOW_GAP && largest_gap >= ELBOW_GAP_RATIO * median_gap {
+        elbow + 1
+    } else {
+        distances.len()
+    }
+}
+
+/// How much closer chunks of a boosted kind are taken to be
+const KIND_BOOST: f64 = 0.05;
+
+/// Move chunks of one of `kinds` up, e.g. type definitions for architecture questions, by
+/// lowering their distance by [`KIND_BOOST`]. Reported distances include the boost.
+fn boost_kinds(chunks: &mut [chunker::Chunk], kinds: &[chunker::ChunkKind]) {
+    for chunk in chunks {
+        if chunk.kind.is_some_and(|kind| kinds.contains(&kind))
+            && let Some(distance) = chunk.distance.as_mut()
+        {
+            *distance -= KIND_BOOST;
+        }
+    }
+}
+
+/// How much distance a chunk can lose by being old, relative to a freshly modified one
+const RECENCY_WEIGHT: f64 = 0.1;
+
+/// Re-rank results so that, for similar distances, recently modified files come first.
+/// Each chunk's distance is penalized by up to `RECENCY_WEIGHT`, decaying exponentially
+/// with the file's age. Reported distances are left untouched.
+fn rerank_by_recency(chunks: &mut [chunker::Chunk], half_life_days: f64, now_secs: u64) {
+    let adjusted_distance = |chunk: &chunker::Chunk| {
+        let age_days = now_secs.saturating_sub(chunk.file_mtime) as f64 / 86_400.0;
+        let freshness = 0.5f64.powf(age_days / half_life_days);
+        chunk.distance.unwrap_or(f64::MAX) + RECENCY_WEIGHT * (1.0 - freshness)
+    };
+    chunks.sort_by(|a, b| adjusted_distance(a).total_cmp(&adjusted_distance(b)));
+}
+
+/// Wall-clock time spent in each phase of a search, for the --verbose summary
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SearchTimings {
+    pub embed: Duration,
+    pub query: Duration,
+    pub load_content: Duration,
+    /// Index sync raced against the search (speculative search only)
+    pub sync: Duration,
+    pub total: Duration,
+}
+
+impl SearchTimings {
+    pub fn summary(&self) -> String {
+        format!(
+            "timing: embed {}ms, query {}ms, sync {}ms, content-load {}ms, total {}ms",
+            self.embed.as_millis(),
+            self.query.as_millis(),
+            self.sync.as_millis(),
+            self.load_content.as_millis(),
+            self.total.as_millis()
+        )
+    }
+}
+
+/// Formatted search results plus where the time went
+#[derive(Debug, Clone, Default)]
+pub struct SearchOutput {
+    pub results: String,
+    pub timings: SearchTimings,
+}
+
 pub async fn search(
     query: &str,
     directory: &str,
-    max_count: usize,
-    embedding_concurrency: Option<usize>,
-    show_scores: bool,
-) -> Result<String, SearchError> {
-    let (namespace, root_dir) = project::namespace_and_dir(directory)
-        .map_err(|e| SearchError::NamespaceError(e.to_string()))?;
+    options: &SearchOptions,
+) -> Result<SearchOutput, SearchError> {
+    let embedding_provider = match options.embedding_concurrency {
+        Some(concurrency) => embeddings::VoyageEmbedding::with_concurrency(concurrency),
+        None => embeddings::VoyageEmbedding::new(),
+    };
+    let embedding_provider = embedding_provider.with_model(options.model());
+    search_index(
+        &turbopuffer::Turbopuffer,
+        embedding_provider,
+        query,
+        directory,
+        options,
+    )
+    .await
+}
+
+/// Namespace a search reads, the root its result paths are shown relative to, and the
+/// subdirectory of the project it's limited to
+fn search_target(
+    directory: &str,
+    options: &SearchOptions,
+) -> Result<(String, String, Option<String>), SearchError> {
+    if let Some(namespace) = &options.namespace {
+        // Vectors of another provider's model don't compare with this one's query vector
+        let settings = crate::config::SETTINGS.get();
+        let local = project::embedding_provider(settings);
+        if let Some(provider) = project::namespace_provider(namespace, settings)
+            && provider != local
+        {
+            return Err(SearchError::NamespaceError(format!(
+                "{namespace} was indexed with {provider} embeddings, but queries are embedded with {local}"
+            )));
+        }
+        // Someone else's index: nothing about it is local, paths are shown as stored
+        return Ok((namespace.clone(), String::new(), None));
+    }
+    let (namespace, root_dir) =
+        project::namespace_and_dir(directory, options.flat, options.branch.as_deref())
+            .map_err(|e| SearchError::NamespaceError(e.to_string()))?;
+    let path_prefix = subdirectory_prefix(directory, &root_dir);
+    Ok((namespace, root_dir, path_prefix))
+}
+
+/// Result content from the local files, or for another's namespace only what its index
+/// stored (see `store_content`), since the local tree may be a different version or absent
+async fn load_result_contents(
+    results: Vec<chunker::Chunk>,
+    options: &SearchOptions,
+) -> Vec<chunker::Chunk> {
+    if options.namespace.is_none() {
+        let mut results = load_chunk_contents(
+            results,
+            options.content_load_concurrency,
+            options.context_symbol,
+        )
+        .await;
+        if options.context_symbol {
+            // Results within the same definition widen to the same one, keep the best
+            let mut seen = std::collections::HashSet::new();
+            results.retain(|chunk| seen.insert((chunk.path.clone(), chunk.start_line)));
+        }
+        return results;
+    }
+    if results.iter().any(|chunk| chunk.content.is_none()) {
+        crate::warnln!(
+            "<(°~°)> {} wasn't synced with --store-content, some results have no preview",
+            options.namespace.as_deref().unwrap_or_default()
+        );
+    }
+    results
+}
+
+/// [`search`] over `index`, embedding the query with `embedding_provider`
+pub async fn search_index(
+    index: &impl IndexBackend,
+    embedding_provider: impl Embedding,
+    query: &str,
+    directory: &str,
+    options: &SearchOptions,
+) -> Result<SearchOutput, SearchError> {
+    let total_instant = Instant::now();
+    let mut timings = SearchTimings::default();
+    let (namespace, root_dir, path_prefix) = search_target(directory, options)?;
+    let _span = crate::profile::span("search", "search");
+    let auto_scoped = auto_scope(options, &namespace);
+    let options = auto_scoped.as_ref().unwrap_or(options);
 
     if query.trim().is_empty() {
         return Err(SearchError::EmptyQuery);
     }
 
+    // Over-fetch when re-ranking so that recent files just past the cutoff can move up,
+    // when filtering by line or by changes so the filter doesn't leave too few results,
+    // and for a larger pool to find the elbow in when adaptive
+    let top_k = if options.rerank {
+        options.max_count * RERANK_POOL_FACTOR
+    } else if options.recency_half_life_days.is_some()
+        || options.only_changed
+        || !options.boost_kinds.is_empty()
+        || options.line_range.is_some()
+        || options.adaptive
+    {
+        options.max_count * 2
+    } else {
+        options.max_count
+    };
+    let top_k = bounded_top_k(top_k, crate::config::SETTINGS.get());
+    if options.max_count > top_k {
+        crate::warnln!(
+            "<(°~°)> Only fetching the best {} results, see max_top_k in the config",
+            top_k
+        );
+    }
+    let filters = query_filters(options, path_prefix.as_deref());
+    let embedded_query = query_text(
+        query,
+        path_prefix.as_deref(),
+        &root_dir,
+        options.query_path_context,
+    );
+
+    // Cached results have every attribute, which a files-only search doesn't fetch
+    let fetched = match options.cache.as_ref().filter(|_| !options.files_only) {
+        Some(cache) => {
+            let key = query_cache::QueryCache::key(
+                &embedded_query,
+                options.model(),
+                options.query_embedding_type,
+                top_k,
+                filters.as_ref(),
+            );
+            cache
+                .get_or_fetch(&namespace, key, || {
+                    embed_and_query(
+                        index,
+                        embedding_provider,
+                        &embedded_query,
+                        &namespace,
+                        top_k,
+                        filters.clone(),
+                        options,
+                        &mut timings,
+                    )
+                })
+                .await
+        }
+        None => {
+            embed_and_query(
+                index,
+                embedding_provider,
+                &embedded_query,
+                &namespace,
+                top_k,
+                filters.clone(),
+                options,
+                &mut timings,
+            )
+            .await
+        }
+    };
+    // Not cached, so the next search embeds the query again once the provider is back
+    let mut results = match fetched {
+        Err(SearchError::EmbeddingError(e)) if options.lexical_fallback => {
+            crate::warnln!(
+                "<(°~°)> Couldn't embed the query ({}), showing code matching its words instead",
+                e
+            );
+            lexical_query(index, query, &namespace, top_k, filters, options).await?
+        }
+        fetched => fetched?,
+    };
+
+    retain_filtered(&mut results, options, path_prefix.as_deref());
+    if options.only_changed {
+        retain_changed(&mut results, &project::git_changed_paths(&root_dir));
+    }
+    boost_kinds(&mut results, &options.boost_kinds);
+
+    if let Some(range) = &options.line_range {
+        filter_line_range(&mut results, range);
+    }
+    if let Some(min_score) = options.min_score {
+        filter_min_score(
+            &mut results,
+            min_score,
+            turbopuffer::DistanceMetric::active(),
+        );
+    }
+
+    // The recency re-rank below is a stable sort, so it keeps this tie-breaking
+    sort_results(&mut results);
+
+    if options.adaptive {
+        let distances: Vec<f64> = results
+            .iter()
+            .map(|chunk| chunk.distance.unwrap_or(f64::MAX))
+            .collect();
+        let cutoff = adaptive_cutoff(&distances, options.max_count);
+        vprintln!(
+            "adaptive cutoff: keeping {} of {} results",
+            cutoff,
+            results.len()
+        );
+        results.truncate(cutoff);
+    }
+
+    if let Some(half_life_days) = options.recency_half_life_days {
+        let now_secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        rerank_by_recency(&mut results, half_life_days, now_secs);
+    }
+    if !options.rerank {
+        results.truncate(options.max_count);
+    }
+    if options.files_only {
+        let results = files_to_ripgrep_format(&results, &root_dir);
+        timings.total = total_instant.elapsed();
+        return Ok(SearchOutput { results, timings });
+    }
+
+    // Load content from local files
+    let instant = Instant::now();
+    let mut results_with_content = load_result_contents(results, options).await;
+    timings.load_content = instant.elapsed();
+
+    // The cross-encoder needs the content, so every candidate was loaded above
+    if options.rerank {
+        results_with_content = rerank_with_voyage(query, results_with_content, options).await;
+        results_with_content.truncate(options.max_count);
+    }
+
+    if options.snapshot {
+        prepare_snapshot(&mut results_with_content);
+    }
+    let show_scores = options.show_scores || options.snapshot;
+    let results = chunks_to_ripgrep_format(
+        results_with_content,
+        &root_dir,
+        show_scores,
+        options.show_bytes,
+        options.show_symbols,
+        options.max_content_lines,
+    );
+    timings.total = total_instant.elapsed();
+
+    Ok(SearchOutput { results, timings })
+}
+
+/// How turbopuffer is queried for `options`: a files-only search fetches the locations of
+/// results, and the attributes its filters check locally, rather than every attribute
+fn query_options(options: &SearchOptions) -> turbopuffer::QueryOptions {
+    let projection = if options.files_only {
+        let mut attributes = vec!["path", "start_line"];
+        if options.line_range.is_some() {
+            attributes.push("end_line");
+        }
+        if options.modified_since.is_some()
+            || options.only_changed
+            || options.recency_half_life_days.is_some()
+        {
+            attributes.push("file_mtime");
+        }
+        if !options.kinds.is_empty() || !options.boost_kinds.is_empty() {
+            attributes.push("kind");
+        }
+        turbopuffer::Projection::Only(attributes)
+    } else {
+        turbopuffer::Projection::AllButVectors
+    };
+    turbopuffer::QueryOptions {
+        consistency: options.consistency,
+        projection,
+    }
+}
+
+/// With `auto_lang` and no explicit extensions, `options` scoped to the dominant languages
+/// recorded by the index's last full sync
+fn auto_scope(options: &SearchOptions, namespace: &str) -> Option<SearchOptions> {
+    if !options.auto_lang || !options.extensions.is_empty() {
+        return None;
+    }
+    let dominant = LanguageMixStore::from_cache_dir()?
+        .load(namespace)?
+        .dominant();
+    if dominant.is_empty() {
+        return None;
+    }
+    vprintln!("auto-scoping to .{}", dominant.join(", ."));
+    Some(SearchOptions {
+        extensions: dominant,
+        ..options.clone()
+    })
+}
+
+/// Chunks in the index matching the filters of `options`, without a semantic query: the
+/// first `max_count` by id, printed like search results ordered by path and line
+pub async fn browse(
+    index: &impl IndexBackend,
+    directory: &str,
+    options: &SearchOptions,
+) -> Result<SearchOutput, SearchError> {
+    let total_instant = Instant::now();
+    let mut timings = SearchTimings::default();
+    let (namespace, root_dir, path_prefix) = search_target(directory, options)?;
+
+    let instant = Instant::now();
+    let mut results = index
+        .query_chunks(
+            &namespace,
+            serde_json::json!(["id", "asc"]),
+            bounded_top_k(options.max_count, crate::config::SETTINGS.get()) as u32,
+            query_filters(options, path_prefix.as_deref()),
+            query_options(options),
+        )
+        .await?;
+    timings.query = instant.elapsed();
+    retain_filtered(&mut results, options, path_prefix.as_deref());
+    if let Some(range) = &options.line_range {
+        filter_line_range(&mut results, range);
+    }
+    sort_results(&mut results);
+    results.truncate(options.max_count);
+
+    let instant = Instant::now();
+    let results_with_content = load_result_contents(results, options).await;
+    timings.load_content = instant.elapsed();
+    let results = chunks_to_ripgrep_format(
+        results_with_content,
+        &root_dir,
+        options.show_scores,
+        options.show_bytes,
+        options.show_symbols,
+        options.max_content_lines,
+    );
+    timings.total = total_instant.elapsed();
+
+    Ok(SearchOutput { results, timings })
+}
+
+/// Candidates fetched per requested result for the cross-encoder to choose from
+const RERANK_POOL_FACTOR: usize = 4;
+
+/// Re-order `chunks` by Voyage's rerank scores. On failure the nearest-neighbour order is
+/// kept, a worse ranking beats no results.
+async fn rerank_with_voyage(
+    query: &str,
+    chunks: Vec<chunker::Chunk>,
+    options: &SearchOptions,
+) -> Vec<chunker::Chunk> {
+    let documents: Vec<&str> = chunks
+        .iter()
+        .map(|chunk| chunk.content.as_deref().unwrap_or_default())
+        .collect();
+    let instant = Instant::now();
+    let reranker = match options.embedding_concurrency {
+        Some(concurrency) => embeddings::VoyageEmbedding::with_concurrency(concurrency),
+        None => embeddings::VoyageEmbedding::new(),
+    };
+    match reranker.rerank(query, &documents).await {
+        Ok(scores) => {
+            vprintln!("rerank w/ voyage took: {:.2?}", instant.elapsed());
+            rerank_results(chunks, &scores)
+        }
+        Err(e) => {
+            crate::warnln!("<(°!°)> Rerank failed, keeping vector order: {}", e);
+            chunks
+        }
+    }
+}
+
+/// Attach cross-encoder `scores` (in the order of `chunks`) and sort by them, most
+/// relevant first. Equal scores keep their nearest-neighbour order.
+fn rerank_results(mut chunks: Vec<chunker::Chunk>, scores: &[f64]) -> Vec<chunker::Chunk> {
+    for (chunk, score) in chunks.iter_mut().zip(scores) {
+        chunk.rerank_score = Some(*score);
+    }
+    chunks.sort_by(|a, b| {
+        let score = |chunk: &chunker::Chunk| chunk.rerank_score.unwrap_or(f64::MIN);
+        score(b).total_cmp(&score(a))
+    });
+    chunks
+}
+
+/// Embed the query and run the nearest-neighbour query against the namespace
+#[allow(clippy::too_many_arguments)]
+async fn embed_and_query(
+    index: &impl IndexBackend,
+    embedding_provider: impl Embedding,
+    query: &str,
+    namespace: &str,
+    top_k: usize,
+    filters: Option<serde_json::Value>,
+    options: &SearchOptions,
+    timings: &mut SearchTimings,
+) -> Result<Vec<chunker::Chunk>, SearchError> {
     let prompt = query.to_string();
 
     let query_chunk = chunker::Chunk {
@@ -106,15 +996,12 @@ pub async fn search(
         ..Default::default()
     };
 
-    let instant = std::time::Instant::now();
-    let embedding_provider = match embedding_concurrency {
-        Some(concurrency) => embeddings::VoyageEmbedding::with_concurrency(concurrency),
-        None => embeddings::VoyageEmbedding::new(),
-    };
+    let instant = Instant::now();
     let embed_result = embedding_provider
-        .embed(vec![query_chunk], embeddings::EmbeddingType::Query)
+        .embed(vec![query_chunk], options.query_embedding_type)
         .await?;
-    vprintln!("embedding w/ voyage took: {:.2?}", instant.elapsed());
+    timings.embed = instant.elapsed();
+    vprintln!("embedding w/ voyage took: {:.2?}", timings.embed);
 
     let query_vector = embed_result
         .chunks
@@ -123,61 +1010,192 @@ pub async fn search(
         .ok_or(SearchError::NoEmbedding)?
         .clone();
 
-    let instant = std::time::Instant::now();
+    let instant = Instant::now();
     // Search turbopuffer using existing query_chunks
-    let results = turbopuffer::query_chunks(
-        &namespace,
-        serde_json::json!(["vector", "ANN", query_vector]),
-        max_count as u32,
-        None,
-    )
-    .await?;
-    vprintln!("tpuf search took: {:.2?}", instant.elapsed());
+    let results = index
+        .query_chunks(
+            namespace,
+            turbopuffer::ann_rank_by(options.model(), query_vector),
+            top_k as u32,
+            filters,
+            query_options(options),
+        )
+        .await?;
+    timings.query = instant.elapsed();
+    vprintln!("tpuf search took: {:.2?}", timings.query);
 
-    // Load content from local files
-    let mut results_with_content = results;
-    for chunk in &mut results_with_content {
-        if let Err(_e) = load_chunk_content(chunk) {
-            // Failed to load content - chunk will have no content
+    Ok(results)
+}
+
+/// Words of `query` a lexical search looks for, lowercased
+fn query_terms(query: &str) -> Vec<String> {
+    query
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|term| term.len() >= 2)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Chunks whose content best matches the words of `query` by BM25, for when the query
+/// can't be embedded. Namespaces synced without full-text indexed content (see
+/// `index_text`) are matched by the words in their paths instead.
+async fn lexical_query(
+    index: &impl IndexBackend,
+    query: &str,
+    namespace: &str,
+    top_k: usize,
+    filters: Option<serde_json::Value>,
+    options: &SearchOptions,
+) -> Result<Vec<chunker::Chunk>, SearchError> {
+    let terms = query_terms(query);
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+    let ranked = index
+        .query_chunks(
+            namespace,
+            turbopuffer::bm25_rank_by(&terms.join(" ")),
+            top_k as u32,
+            filters.clone(),
+            query_options(options),
+        )
+        .await;
+    match ranked {
+        Ok(mut results) => {
+            // Turn the BM25 score (higher is better) into a distance (lower is better)
+            results.retain_mut(|chunk| {
+                let score = chunk.distance.unwrap_or_default();
+                chunk.distance = Some(1.0 / (1.0 + score));
+                score > 0.0
+            });
+            Ok(results)
+        }
+        Err(turbopuffer::TurbopufferError::ApiError(e)) => {
+            vprintln!("no full-text index ({}), matching paths instead", e);
+            path_terms_query(index, &terms, namespace, top_k, filters, options).await
         }
+        Err(e) => Err(e.into()),
     }
+}
 
-    Ok(chunks_to_ripgrep_format(
-        results_with_content,
-        &root_dir,
-        show_scores,
-    ))
+/// Chunks of files whose paths contain some of `terms`. Results with more of the terms in
+/// their path or stored content come first: their distance is the share of the terms
+/// they're missing.
+async fn path_terms_query(
+    index: &impl IndexBackend,
+    terms: &[String],
+    namespace: &str,
+    top_k: usize,
+    filters: Option<serde_json::Value>,
+    options: &SearchOptions,
+) -> Result<Vec<chunker::Chunk>, SearchError> {
+    let terms_filter = turbopuffer::terms_filter(terms);
+    let filters = match filters {
+        Some(filters) => serde_json::json!(["And", [filters, terms_filter]]),
+        None => terms_filter,
+    };
+    let mut results = index
+        .query_chunks(
+            namespace,
+            serde_json::json!(["path", "asc"]),
+            top_k as u32,
+            Some(filters),
+            query_options(options),
+        )
+        .await?;
+
+    results.retain_mut(|chunk| {
+        let text = format!(
+            "{}\n{}",
+            chunk.path,
+            chunk.content.as_deref().unwrap_or_default()
+        )
+        .to_lowercase();
+        let matched = terms
+            .iter()
+            .filter(|term| text.contains(term.as_str()))
+            .count();
+        chunk.distance = Some(1.0 - matched as f64 / terms.len() as f64);
+        matched > 0
+    });
+    Ok(results)
 }
 
+/// Most results one query asks turbopuffer for, unless the config sets `max_top_k`
+pub const DEFAULT_MAX_TOP_K: usize = 1000;
+
+/// `top_k` capped at the `max_top_k` setting, so that e.g. `-m 1000000` makes a bounded
+/// request rather than one turbopuffer rejects or times out on
+fn bounded_top_k(top_k: usize, settings: Option<&crate::config::Settings>) -> usize {
+    let cap = settings
+        .and_then(|s| s.max_top_k)
+        .unwrap_or(DEFAULT_MAX_TOP_K)
+        .max(1);
+    top_k.min(cap)
+}
+
+/// Searches repeated after the sync racing them changed the index, before searching
+/// what the index holds by then without racing another sync
+pub const MAX_SPECULATIVE_RETRIES: usize = 3;
+
 /// Implements a speculative search pattern that races a search against an index sync.
 /// This improves perceived performance by returning search results as quickly as possible,
 /// while ensuring the index is kept up-to-date in the background.
 pub async fn speculate_search(
     query: &str,
     directory: &str,
-    max_count: usize,
-    embedding_concurrency: Option<usize>,
-    show_scores: bool,
-) -> Result<String, SearchError> {
-    loop {
-        let mut search_task = tokio::spawn({
-            let query = query.to_string();
-            let directory = directory.to_string();
-            async move {
-                search(
-                    &query,
-                    &directory,
-                    max_count,
-                    embedding_concurrency,
-                    show_scores,
-                )
-                .await
-            }
-        });
-        let mut index_task = tokio::spawn({
-            let directory = directory.to_string();
-            async move { sync::tpuf_sync(&directory, embedding_concurrency).await }
-        });
+    options: &SearchOptions,
+    sync_options: &sync::SyncOptions,
+) -> Result<SearchOutput, SearchError> {
+    let instant = Instant::now();
+    let sync_time = Arc::new(Mutex::new(Duration::ZERO));
+
+    let search_with = |options: SearchOptions| {
+        let query = query.to_string();
+        let directory = directory.to_string();
+        async move { search(&query, &directory, &options).await }
+    };
+    let sync = || {
+        let directory = directory.to_string();
+        let sync_options = sync_options.clone();
+        let sync_time = sync_time.clone();
+        async move {
+            let instant = Instant::now();
+            let result = sync::tpuf_sync(&directory, &sync_options).await;
+            *sync_time.lock().unwrap() += instant.elapsed();
+            result
+        }
+    };
+    // A sync that found no local changes never asked the server
+    let invalidate_manifest = || sync::invalidate_manifest(directory, sync_options);
+
+    let mut output = race_search_and_sync(options, search_with, sync, invalidate_manifest).await?;
+    output.timings.sync = *sync_time.lock().unwrap();
+    output.timings.total = instant.elapsed();
+    Ok(output)
+}
+
+/// The search/sync race behind [`speculate_search`]: `search` runs with `options` while
+/// `sync` (returning whether it changed the index) runs alongside, and both are retried
+/// when the sync changed the index or created it. turbopuffer's eventually consistent
+/// reads can miss what a sync just wrote, so retries read with strong consistency, and
+/// after [`MAX_SPECULATIVE_RETRIES`] the index is searched as it is.
+pub async fn race_search_and_sync<S, SF, Y, YF>(
+    options: &SearchOptions,
+    search: S,
+    sync: Y,
+    invalidate_manifest: impl Fn(),
+) -> Result<SearchOutput, SearchError>
+where
+    S: Fn(SearchOptions) -> SF,
+    SF: std::future::Future<Output = Result<SearchOutput, SearchError>> + Send + 'static,
+    Y: Fn() -> YF,
+    YF: std::future::Future<Output = Result<bool>> + Send + 'static,
+{
+    let mut options = options.clone();
+    for _ in 0..=MAX_SPECULATIVE_RETRIES {
+        let mut search_task = tokio::spawn(search(options.clone()));
+        let mut index_task = tokio::spawn(sync());
 
         tokio::select! {
             search_result = &mut search_task => {
@@ -200,7 +1218,11 @@ pub async fn speculate_search(
                             SearchError::TurbopufferError(turbopuffer::TurbopufferError::NamespaceNotFound(_)) => {
                                 search_task.abort();
                                 match index_task.await {
-                                    Ok(Ok(_)) => continue, // Retry search
+                                    Ok(Ok(_)) => {
+                                        invalidate_manifest();
+                                        options.consistency = turbopuffer::Consistency::Strong;
+                                        continue; // Retry search
+                                    }
                                     Ok(Err(index_err)) => return Err(SearchError::IndexBuildFailed(index_err.to_string())),
                                     Err(join_err) => return Err(SearchError::IndexBuildFailed(join_err.to_string())),
                                 }
@@ -231,7 +1253,9 @@ pub async fn speculate_search(
                     Ok(Ok(content_changed)) => {
                         if content_changed {
                             search_task.abort();
-                            continue; // Retry with updated index
+                            // Retry with updated index, which an eventual read may not see yet
+                            options.consistency = turbopuffer::Consistency::Strong;
+                            continue;
                         }
                         // Index unchanged, wait for search result
                         match search_task.await {
@@ -252,12 +1276,59 @@ pub async fn speculate_search(
             }
         }
     }
+
+    vprintln!("<(°~°)> index still changing, searching it without syncing again");
+    search(options).await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_only_changed_keeps_files_modified_after_indexing() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let indexed = std::time::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let chunk = |name: &str, file_mtime: u64| {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, "fn f() {}\n").unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(indexed)
+                .unwrap();
+            chunker::Chunk {
+                path: path.to_string_lossy().to_string(),
+                file_mtime,
+                ..Default::default()
+            }
+        };
+
+        let mut results = vec![
+            chunk("unchanged.rs", 1_700_000_000),
+            chunk("edited.rs", 1_699_999_000),
+            chunk("staged.rs", 1_700_000_000),
+            chunker::Chunk {
+                path: temp_dir
+                    .path()
+                    .join("deleted.rs")
+                    .to_string_lossy()
+                    .to_string(),
+                file_mtime: 1_699_999_000,
+                ..Default::default()
+            },
+        ];
+        let changed = HashSet::from([temp_dir.path().join("staged.rs")]);
+        retain_changed(&mut results, &changed);
+
+        let paths: Vec<_> = results
+            .iter()
+            .map(|chunk| Path::new(&chunk.path).file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(paths, ["edited.rs", "staged.rs"]);
+    }
+
     #[test]
     fn test_chunks_to_ripgrep_format() {
         let chunks = vec![chunker::Chunk {
@@ -266,18 +1337,487 @@ mod tests {
             path: "/project/src/main.rs".to_string(),
             start_line: 10,
             end_line: 15,
+            start_byte: None,
+            end_byte: None,
             file_hash: 123,
             chunk_hash: 456,
             file_mtime: 1000,
             file_ctime: 1000,
             content: Some("fn main() {\n    println!(\"Hello!\");\n}".to_string()),
+            commit: None,
+            kind: None,
+            symbol: None,
             distance: None,
+            rerank_score: None,
+            extra_vectors: Default::default(),
         }];
 
-        let result = chunks_to_ripgrep_format(chunks, "/project", false);
+        let result =
+            chunks_to_ripgrep_format(chunks.clone(), "/project", false, false, false, None);
         let expected = "src/main.rs:10:fn main() {";
 
         assert_eq!(result, expected);
+
+        // Chunks indexed before byte offsets were recorded have none to show
+        let result = chunks_to_ripgrep_format(chunks.clone(), "/project", false, true, false, None);
+        assert_eq!(result, "src/main.rs:10:n/a:fn main() {");
+
+        let chunks = vec![chunker::Chunk {
+            start_byte: Some(120),
+            end_byte: Some(164),
+            ..chunks[0].clone()
+        }];
+        let result = chunks_to_ripgrep_format(chunks.clone(), "/project", false, true, false, None);
+        assert_eq!(result, "src/main.rs:10:120-164:fn main() {");
+
+        let chunks = vec![chunker::Chunk {
+            symbol: Some("main".to_string()),
+            ..chunks[0].clone()
+        }];
+        let result = chunks_to_ripgrep_format(chunks, "/project", false, false, true, None);
+        assert_eq!(result, "src/main.rs:10:main:fn main() {");
+    }
+
+    #[test]
+    fn test_max_content_lines_truncates_displayed_content() {
+        let content: Vec<String> = (1..=50).map(|i| format!("    step_{i}();")).collect();
+        let chunk = chunker::Chunk {
+            path: "/project/src/long.rs".to_string(),
+            start_line: 10,
+            content: Some(format!("fn long() {{\n{}\n}}", content.join("\n"))),
+            ..Default::default()
+        };
+
+        let result = chunks_to_ripgrep_format(
+            vec![chunk.clone()],
+            "/project",
+            false,
+            false,
+            false,
+            Some(3),
+        );
+        assert_eq!(
+            result,
+            "src/long.rs:10:fn long() {\n\
+             src/long.rs-11-    step_1();\n\
+             src/long.rs-12-    step_2();\n\
+             ... (truncated)"
+        );
+
+        // Content that fits is shown whole, without the marker
+        let result =
+            chunks_to_ripgrep_format(vec![chunk], "/project", false, false, false, Some(52));
+        assert_eq!(result.lines().count(), 52);
+        assert!(!result.contains(CONTENT_TRUNCATED_MARKER));
+    }
+
+    #[test]
+    fn test_rerank_results_orders_by_score() {
+        let chunks: Vec<chunker::Chunk> = (1..=4)
+            .map(|id| chunker::Chunk {
+                id,
+                path: format!("/project/{id}.rs"),
+                start_line: 1,
+                distance: Some(id as f64 / 10.0),
+                content: Some(format!("fn f{id}() {{}}")),
+                ..Default::default()
+            })
+            .collect();
+
+        // The cross-encoder prefers the third and then the first candidate, ties keep
+        // their nearest-neighbour order
+        let reranked = rerank_results(chunks, &[0.7, 0.2, 0.9, 0.2]);
+        let ids: Vec<u128> = reranked.iter().map(|c| c.id).collect();
+        assert_eq!(ids, [3, 1, 2, 4]);
+        assert_eq!(reranked[0].rerank_score, Some(0.9));
+        assert_eq!(reranked[0].distance, Some(0.3));
+
+        let output =
+            chunks_to_ripgrep_format(reranked[..2].to_vec(), "/project", true, false, false, None);
+        assert_eq!(
+            output,
+            "3.rs:1:0.3000:0.9000:fn f3() {}\n1.rs:1:0.1000:0.7000:fn f1() {}"
+        );
+    }
+
+    #[test]
+    fn test_filter_min_score() {
+        let chunks: Vec<chunker::Chunk> = [0.1, 0.3, 0.5, 0.9]
+            .into_iter()
+            .map(|distance| chunker::Chunk {
+                distance: Some(distance),
+                ..Default::default()
+            })
+            .collect();
+        let distances = |chunks: &[chunker::Chunk]| -> Vec<f64> {
+            chunks.iter().filter_map(|c| c.distance).collect()
+        };
+
+        let mut cosine = chunks.clone();
+        filter_min_score(&mut cosine, 0.6, turbopuffer::DistanceMetric::Cosine);
+        assert_eq!(distances(&cosine), [0.1, 0.3]);
+
+        let mut euclidean = chunks;
+        filter_min_score(
+            &mut euclidean,
+            0.6,
+            turbopuffer::DistanceMetric::EuclideanSquared,
+        );
+        assert_eq!(distances(&euclidean), [0.1, 0.3, 0.5]);
+    }
+
+    #[test]
+    fn test_filter_line_range() {
+        let mut chunks: Vec<chunker::Chunk> = [1, 20, 45, 50, 51, 300]
+            .into_iter()
+            .map(|start_line| chunker::Chunk {
+                start_line,
+                end_line: start_line + 10,
+                ..Default::default()
+            })
+            .collect();
+
+        filter_line_range(&mut chunks, &(20..=50));
+        let start_lines: Vec<u32> = chunks.iter().map(|c| c.start_line).collect();
+        // Chunk starting at 1 overlaps the range but doesn't start in it
+        assert_eq!(start_lines, vec![20, 45, 50]);
+
+        filter_line_range(&mut chunks, &(46..=u32::MAX));
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].start_line, 50);
+    }
+
+    #[test]
+    fn test_query_embedding_type_override() {
+        let texts = [std::borrow::Cow::Borrowed("retry failed uploads")];
+
+        let options = SearchOptions::default();
+        let body = embeddings::voyage_request_body(
+            &texts,
+            embeddings::VOYAGE_MODEL,
+            options.query_embedding_type,
+        );
+        assert_eq!(body["input_type"], "query");
+
+        let options = SearchOptions {
+            query_embedding_type: "document".parse().unwrap(),
+            ..Default::default()
+        };
+        let body = embeddings::voyage_request_body(
+            &texts,
+            embeddings::VOYAGE_MODEL,
+            options.query_embedding_type,
+        );
+        assert_eq!(body["input_type"], "document");
+    }
+
+    #[test]
+    fn test_query_text_path_context() {
+        let options = SearchOptions::default();
+        let text = query_text("retry uploads", None, "/repo", options.query_path_context);
+        assert_eq!(text, "retry uploads");
+
+        // Same header as a chunk synced with embed_path_context
+        let text = query_text("retry uploads", Some("/repo/payments"), "/repo", true);
+        assert_eq!(text, "// file: payments\nretry uploads");
+        let text = query_text("retry uploads", None, "/repo", true);
+        assert_eq!(text, "// file: .\nretry uploads");
+    }
+
+    #[test]
+    fn test_query_filters() {
+        assert_eq!(query_filters(&SearchOptions::default(), None), None);
+
+        let options = SearchOptions {
+            at_commit: Some("abc123".to_string()),
+            scope: Some(chunker::PathScope::new(vec!["/repo/src/*.rs".to_string()]).unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            query_filters(&options, None),
+            Some(serde_json::json!([
+                "And",
+                [
+                    ["commit", "Glob", "abc123*"],
+                    [
+                        "Or",
+                        [
+                            ["path", "Glob", "/repo/src/*.rs"],
+                            ["path", "Glob", "/repo/src/*.rs/**"]
+                        ]
+                    ]
+                ]
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_search_target_rejects_namespace_of_other_provider() {
+        let options = |namespace: &str| SearchOptions {
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        };
+        let (namespace, root_dir, prefix) =
+            search_target(".", &options("tg_voyage_1a2b3c")).unwrap();
+        assert_eq!(namespace, "tg_voyage_1a2b3c");
+        assert_eq!((root_dir.as_str(), prefix), ("", None));
+        assert!(search_target(".", &options("shared-docs")).is_ok());
+
+        let error = search_target(".", &options("tg_openai_1a2b3c")).unwrap_err();
+        assert!(matches!(error, SearchError::NamespaceError(_)));
+        assert!(error.to_string().contains("openai"), "{error}");
+    }
+
+    #[test]
+    fn test_subdirectory_search_is_scoped_to_prefix() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("Cargo.toml"), "").unwrap();
+        let billing = temp_dir.path().join("services/billing");
+        std::fs::create_dir_all(&billing).unwrap();
+
+        // The subdirectory shares the root's namespace...
+        let root = temp_dir.path().to_str().unwrap();
+        let subdir = billing.to_str().unwrap();
+        let (root_namespace, root_dir) = project::namespace_and_dir(root, false, None).unwrap();
+        let (namespace, subdir_root) = project::namespace_and_dir(subdir, false, None).unwrap();
+        assert_eq!(namespace, root_namespace);
+        assert_eq!(subdir_root, root_dir);
+
+        // ...but is limited to chunks under it
+        assert_eq!(subdirectory_prefix(root, &root_dir), None);
+        let prefix = subdirectory_prefix(subdir, &root_dir).unwrap();
+        assert_eq!(prefix, format!("{}/services/billing", root_dir));
+        assert_eq!(
+            query_filters(&SearchOptions::default(), Some(&prefix)),
+            Some(serde_json::json!([
+                "path",
+                "Glob",
+                format!("{}/services/billing/**", root_dir)
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_adaptive_cutoff_at_elbow() {
+        // Three strong matches, then a jump to a plateau of weak ones
+        let distances = [0.10, 0.12, 0.13, 0.35, 0.36, 0.38, 0.39, 0.41, 0.42];
+        assert_eq!(adaptive_cutoff(&distances, 20), 3);
+        // The cutoff never exceeds max_count
+        assert_eq!(adaptive_cutoff(&distances, 2), 2);
+
+        // Evenly spread distances have no elbow, so everything is kept
+        let even: Vec<f64> = (0..10).map(|i| 0.1 + i as f64 * 0.02).collect();
+        assert_eq!(adaptive_cutoff(&even, 20), 10);
+
+        // Nor do (nearly) identical ones
+        assert_eq!(adaptive_cutoff(&[0.2, 0.2, 0.2, 0.2001], 20), 4);
+    }
+
+    #[test]
+    fn test_snapshot_output_is_byte_stable() {
+        let chunk = |path: &str, start_line: u32, distance: f64, content: &str| chunker::Chunk {
+            id: start_line as u128,
+            path: format!("/project/{path}"),
+            start_line,
+            end_line: start_line + 5,
+            content: Some(content.to_string()),
+            distance: Some(distance),
+            ..Default::default()
+        };
+        let chunks = vec![
+            chunk("src/b.rs", 40, 0.1, "fn b_late() {}"),
+            chunk("src/a.rs", 12, 0.3, "fn a() {}  \r\n    body()"),
+            chunk("src/b.rs", 3, 0.2, "fn b_early() {}"),
+        ];
+
+        let snapshot = |mut chunks: Vec<chunker::Chunk>| {
+            prepare_snapshot(&mut chunks);
+            chunks_to_ripgrep_format(chunks, "/project", true, false, false, None)
+        };
+        let first = snapshot(chunks.clone());
+        let mut reversed = chunks;
+        reversed.reverse();
+        let second = snapshot(reversed);
+
+        assert_eq!(first, second);
+        assert_eq!(
+            first,
+            "src/a.rs:12:0.3000:fn a() {}\nsrc/b.rs:3:0.2000:fn b_early() {}\nsrc/b.rs:40:0.1000:fn b_late() {}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parallel_content_loading_matches_sequential() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut chunks = Vec::new();
+        for file in 0..8 {
+            let path = temp_dir.path().join(format!("file{file}.rs"));
+            let lines: Vec<String> = (1..=30).map(|line| format!("// {file}:{line}")).collect();
+            std::fs::write(&path, lines.join("\n")).unwrap();
+            for start_line in [1, 10, 25] {
+                chunks.push(chunker::Chunk {
+                    path: path.to_string_lossy().to_string(),
+                    start_line,
+                    end_line: start_line + 4,
+                    ..Default::default()
+                });
+            }
+        }
+        // Missing files are left without content
+        chunks.push(chunker::Chunk {
+            path: temp_dir
+                .path()
+                .join("gone.rs")
+                .to_string_lossy()
+                .to_string(),
+            start_line: 1,
+            end_line: 3,
+            ..Default::default()
+        });
+
+        let mut sequential = chunks.clone();
+        for chunk in &mut sequential {
+            load_chunk_content(chunk).unwrap();
+        }
+        let parallel = load_chunk_contents(chunks, 4, false).await;
+
+        let summary = |chunks: &[chunker::Chunk]| -> Vec<(String, u32, Option<String>)> {
+            chunks
+                .iter()
+                .map(|c| (c.path.clone(), c.start_line, c.content.clone()))
+                .collect()
+        };
+        assert_eq!(summary(&parallel), summary(&sequential));
+        assert_eq!(
+            parallel[1].content.as_deref(),
+            Some("// 0:10\n// 0:11\n// 0:12\n// 0:13\n// 0:14")
+        );
+        assert_eq!(parallel.last().unwrap().content, None);
+    }
+
+    #[test]
+    fn test_search_timings_summary() {
+        let timings = SearchTimings {
+            embed: Duration::from_millis(120),
+            query: Duration::from_millis(45),
+            load_content: Duration::from_micros(2_500),
+            sync: Duration::from_millis(300),
+            total: Duration::from_millis(480),
+        };
+        assert_eq!(
+            timings.summary(),
+            "timing: embed 120ms, query 45ms, sync 300ms, content-load 2ms, total 480ms"
+        );
+        assert_eq!(
+            SearchTimings::default().summary(),
+            "timing: embed 0ms, query 0ms, sync 0ms, content-load 0ms, total 0ms"
+        );
+    }
+
+    #[test]
+    fn test_sort_results_breaks_ties_by_location() {
+        let chunk = |path: &str, start_line: u32, distance: f64| chunker::Chunk {
+            path: path.to_string(),
+            start_line,
+            distance: Some(distance),
+            ..Default::default()
+        };
+
+        let expected = [
+            ("a.rs", 1),
+            ("b.rs", 3),
+            ("b.rs", 10),
+            ("c.rs", 2),
+            ("a.rs", 5),
+        ];
+        let mut forward = vec![
+            chunk("b.rs", 10, 0.25),
+            chunk("a.rs", 5, 0.5),
+            chunk("b.rs", 3, 0.25),
+            chunk("c.rs", 2, 0.25),
+            chunk("a.rs", 1, 0.25),
+        ];
+        let mut reversed: Vec<_> = forward.iter().rev().cloned().collect();
+
+        for chunks in [&mut forward, &mut reversed] {
+            sort_results(chunks);
+            let order: Vec<_> = chunks
+                .iter()
+                .map(|c| (c.path.as_str(), c.start_line))
+                .collect();
+            assert_eq!(order, expected);
+        }
+    }
+
+    #[test]
+    fn test_rerank_by_recency() {
+        let now = 1_700_000_000;
+        let chunk = |path: &str, age_days: u64| chunker::Chunk {
+            path: path.to_string(),
+            file_mtime: now - age_days * 86_400,
+            distance: Some(0.4),
+            ..Default::default()
+        };
+
+        let mut chunks = vec![
+            chunk("old.rs", 365),
+            chunk("new.rs", 1),
+            chunk("mid.rs", 30),
+        ];
+        rerank_by_recency(&mut chunks, 30.0, now);
+
+        let paths: Vec<_> = chunks.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["new.rs", "mid.rs", "old.rs"]);
+        // Reported distances are unchanged
+        assert!(chunks.iter().all(|c| c.distance == Some(0.4)));
+    }
+
+    #[test]
+    fn test_rerank_by_recency_keeps_much_closer_results_first() {
+        let now = 1_700_000_000;
+        let mut chunks = vec![
+            chunker::Chunk {
+                path: "recent_but_unrelated.rs".to_string(),
+                file_mtime: now,
+                distance: Some(0.8),
+                ..Default::default()
+            },
+            chunker::Chunk {
+                path: "old_but_relevant.rs".to_string(),
+                file_mtime: now - 1000 * 86_400,
+                distance: Some(0.2),
+                ..Default::default()
+            },
+        ];
+        rerank_by_recency(&mut chunks, 7.0, now);
+        assert_eq!(chunks[0].path, "old_but_relevant.rs");
+    }
+
+    #[test]
+    fn test_looks_like_regex() {
+        for query in [
+            "fn.*parse",
+            "TODO|FIXME",
+            "^impl Display",
+            "Result<.+>",
+            "\\bunwrap\\(",
+            "error$",
+            "[a-z]+_handler",
+        ] {
+            assert!(looks_like_regex(query), "expected regex: {query}");
+        }
+
+        for query in [
+            "error handling",
+            "where do we retry failed requests?",
+            "Vec<String> to HashMap",
+            "parse config file",
+            "std::fs::read",
+            "a | b",
+            "$",
+        ] {
+            assert!(!looks_like_regex(query), "expected plain query: {query}");
+        }
     }
 
     #[test]
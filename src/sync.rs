@@ -1,10 +1,16 @@
 use crate::chunker::Chunk;
 use crate::embeddings::Embedding;
-use crate::progress::tg_progress_bar;
-use crate::{chunker, embeddings, is_verbose, project, turbopuffer, vprintln};
+use crate::language_mix::{LanguageMix, LanguageMixStore};
+use crate::progress::{tg_progress_bar, tg_progress_inc};
+use crate::turbopuffer::{IndexBackend, Turbopuffer};
+use crate::{archive, chunker, embeddings, is_verbose, project, turbopuffer, vprintln};
 
 use anyhow::Result;
-use futures::stream::{self, StreamExt};
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
 pub fn tpuf_chunk_diff(
     local_chunks: Vec<Chunk>,
@@ -13,11 +19,11 @@ pub fn tpuf_chunk_diff(
     // With file_hash now part of chunk ID, sync logic is much simpler:
     // Any file change will cause all chunk IDs from that file to change automatically
     
-    let local_chunk_ids: std::collections::HashSet<u64> = local_chunks
+    let local_chunk_ids: std::collections::HashSet<u128> = local_chunks
         .iter()
         .map(|c| c.id)
         .collect();
-    let server_chunk_ids: std::collections::HashSet<u64> = server_chunks
+    let server_chunk_ids: std::collections::HashSet<u128> = server_chunks
         .iter()
         .map(|c| c.id)
         .collect();
@@ -38,17 +44,175 @@ pub fn tpuf_chunk_diff(
     Ok((local_chunks_to_upload, remote_chunks_to_delete))
 }
 
+/// Chunks to upload that are a chunk to delete moved to another path, as
+/// `(deleted id, uploaded id)` pairs. The path is part of a chunk's ID, so a renamed
+/// file changes every ID even though the content and line span stay the same.
+pub fn find_renames(to_upload: &[Chunk], to_delete: &[Chunk]) -> Vec<(u128, u128)> {
+    let mut deleted: HashMap<(u64, u32, u32), Vec<&Chunk>> = HashMap::new();
+    for chunk in to_delete {
+        deleted
+            .entry((chunk.chunk_hash, chunk.start_line, chunk.end_line))
+            .or_default()
+            .push(chunk);
+    }
+
+    to_upload
+        .iter()
+        .filter_map(|chunk| {
+            let candidates =
+                deleted.get_mut(&(chunk.chunk_hash, chunk.start_line, chunk.end_line))?;
+            let index = candidates.iter().position(|old| old.path != chunk.path)?;
+            Some((candidates.swap_remove(index).id, chunk.id))
+        })
+        .collect()
+}
+
+/// Split `to_upload` into renamed chunks, given the vector already stored for their old
+/// ID, and the chunks that still need to be embedded
+async fn reuse_renamed_vectors(
+    index: &impl IndexBackend,
+    namespace: &str,
+    to_upload: Vec<Chunk>,
+    to_delete: &[Chunk],
+) -> (Vec<Chunk>, Vec<Chunk>) {
+    let renames = find_renames(&to_upload, to_delete);
+    if renames.is_empty() {
+        return (Vec::new(), to_upload);
+    }
+
+    let old_ids: Vec<u128> = renames.iter().map(|(old_id, _)| *old_id).collect();
+    let mut vectors = match index.chunk_vectors(namespace, &old_ids).await {
+        Ok(vectors) => vectors,
+        Err(e) => {
+            vprintln!("<(°~°)> Couldn't fetch vectors of renamed chunks: {}", e);
+            return (Vec::new(), to_upload);
+        }
+    };
+
+    let new_to_old: HashMap<u128, u128> =
+        renames.into_iter().map(|(old, new)| (new, old)).collect();
+    let mut renamed = Vec::new();
+    let mut to_embed = Vec::new();
+    for mut chunk in to_upload {
+        match new_to_old
+            .get(&chunk.id)
+            .and_then(|old_id| vectors.remove(old_id))
+        {
+            Some(vector) => {
+                chunk.vector = Some(vector);
+                renamed.push(chunk);
+            }
+            None => to_embed.push(chunk),
+        }
+    }
+    (renamed, to_embed)
+}
+
+/// Consecutive embedding failures after which a sync stops, assuming the service is down
+/// rather than sending every remaining batch just to see it fail too
+pub const MAX_CONSECUTIVE_FAILURES: usize = 5;
+
+/// What happened to the chunks sent for embedding during a sync
+#[derive(Debug, Default)]
+struct EmbeddingOutcome {
+    embedded: usize,
+    errors: Vec<embeddings::EmbeddingError>,
+    consecutive_failures: usize,
+}
+
+impl EmbeddingOutcome {
+    fn service_down(&self) -> bool {
+        self.consecutive_failures >= MAX_CONSECUTIVE_FAILURES
+    }
+}
+
+/// Chunks that embedded successfully, recording failures in `outcome`. Normally failed
+/// chunks are skipped with a warning; under `strict` the stream ends at the first failure
+/// so that nothing further is embedded or written. Either way it ends after
+/// [`MAX_CONSECUTIVE_FAILURES`] failures in a row.
+fn successful_embeddings<S>(
+    embedded_stream: S,
+    strict: bool,
+    outcome: Arc<Mutex<EmbeddingOutcome>>,
+) -> impl Stream<Item = Chunk>
+where
+    S: Stream<Item = Result<Chunk, embeddings::EmbeddingError>>,
+{
+    embedded_stream
+        .scan(false, move |failed, result| {
+            if *failed {
+                return std::future::ready(None);
+            }
+            let mut outcome = outcome.lock().unwrap();
+            let chunk = match result {
+                Ok(chunk) => {
+                    outcome.embedded += 1;
+                    outcome.consecutive_failures = 0;
+                    Some(chunk)
+                }
+                Err(e) => {
+                    crate::warnln!("<(°!°)> Embedding error: {}", e);
+                    outcome.errors.push(e);
+                    outcome.consecutive_failures += 1;
+                    *failed = strict || outcome.service_down();
+                    None
+                }
+            };
+            std::future::ready(Some(chunk))
+        })
+        .filter_map(std::future::ready)
+}
+
+// The binary syncs through `apply_diff`, this is for library users
+#[allow(clippy::too_many_arguments, dead_code)]
 pub async fn tpuf_apply_diff(
+    index: &impl IndexBackend,
     namespace: &str,
     local_chunks_to_upload: Vec<Chunk>,
     remote_chunks_to_delete: Vec<Chunk>,
     verbose: bool,
-    embedding_concurrency: Option<usize>,
+    embedding_provider: impl Embedding,
+    write_options: &turbopuffer::WriteOptions,
+    strict: bool,
 ) -> Result<bool> {
+    apply_diff(
+        index,
+        namespace,
+        local_chunks_to_upload,
+        remote_chunks_to_delete,
+        verbose,
+        embedding_provider,
+        write_options,
+        strict,
+    )
+    .await
+    .map(|(changed, _complete)| changed)
+}
+
+/// [`tpuf_apply_diff`], also returning whether no chunk was skipped for an embedding error
+#[allow(clippy::too_many_arguments)]
+async fn apply_diff(
+    index: &impl IndexBackend,
+    namespace: &str,
+    local_chunks_to_upload: Vec<Chunk>,
+    remote_chunks_to_delete: Vec<Chunk>,
+    verbose: bool,
+    embedding_provider: impl Embedding,
+    write_options: &turbopuffer::WriteOptions,
+    strict: bool,
+) -> Result<(bool, bool)> {
     if local_chunks_to_upload.is_empty() && remote_chunks_to_delete.is_empty() {
         vprintln!("<(°O°)> turbopuffer search index up-to-date");
-        return Ok(false); // No content changed
+        return Ok((false, true)); // No content changed
     }
+    let _span = crate::profile::span(
+        "sync",
+        format!(
+            "apply diff (+{} -{})",
+            local_chunks_to_upload.len(),
+            remote_chunks_to_delete.len()
+        ),
+    );
 
     if !remote_chunks_to_delete.is_empty() {
         vprintln!(
@@ -56,87 +220,1085 @@ pub async fn tpuf_apply_diff(
             remote_chunks_to_delete.len()
         );
     }
-    if !local_chunks_to_upload.is_empty() {
-        vprintln!(
-            "\\(°O°)/ need to index {} chunks",
-            local_chunks_to_upload.len()
-        );
+
+    // Renamed files keep their content, so their vectors don't need embedding again
+    let (renamed_chunks, chunks_to_embed) = if embedding_provider.embeds_extra_models() {
+        (Vec::new(), local_chunks_to_upload)
+    } else {
+        reuse_renamed_vectors(
+            index,
+            namespace,
+            local_chunks_to_upload,
+            &remote_chunks_to_delete,
+        )
+        .await
+    };
+
+    if !renamed_chunks.is_empty() {
+        vprintln!("\\(°O°)/ moving {} renamed chunks", renamed_chunks.len());
+    }
+    if !chunks_to_embed.is_empty() {
+        vprintln!("\\(°O°)/ need to index {} chunks", chunks_to_embed.len());
         vprintln!("using base64 vector encoding (binary f32)");
     }
 
     // Simple streaming pipeline
-    if !local_chunks_to_upload.is_empty() {
-        let total_chunks = local_chunks_to_upload.len();
+    if !chunks_to_embed.is_empty() || !renamed_chunks.is_empty() {
+        let total_chunks = chunks_to_embed.len();
         let pb = tg_progress_bar(total_chunks as u64);
 
         // Create a progress-tracking stream
         let pb_clone = pb.clone();
-        let chunk_stream = stream::iter(local_chunks_to_upload).inspect(move |_| {
+        let chunk_stream = stream::iter(chunks_to_embed).inspect(move |_| {
             if verbose {
-                pb_clone.inc(1);
+                tg_progress_inc(&pb_clone);
             }
         });
 
         // Stream pipeline: chunks -> embed -> write
-        let embedding_provider = match embedding_concurrency {
-            Some(concurrency) => embeddings::VoyageEmbedding::with_concurrency(concurrency),
-            None => embeddings::VoyageEmbedding::new(),
-        };
-        let embedded_stream = embedding_provider
-            .embed_stream(chunk_stream, embeddings::EmbeddingType::Document);
+        let tokens = Arc::new(AtomicUsize::new(0));
+        let embedded_stream = embedding_provider.embed_stream(
+            chunk_stream,
+            embeddings::EmbeddingType::Document,
+            tokens.clone(),
+        );
 
         // Filter out errors and collect successful chunks
-        let successful_chunks = embedded_stream.filter_map(|result| async move {
-            match result {
-                Ok(chunk) => Some(chunk),
-                Err(e) => {
-                    eprintln!("<(°!°)> Embedding error: {}", e);
-                    None
-                }
-            }
-        });
+        let outcome = Arc::new(Mutex::new(EmbeddingOutcome::default()));
+        let successful_chunks = stream::iter(renamed_chunks).chain(successful_embeddings(
+            embedded_stream,
+            strict,
+            outcome.clone(),
+        ));
 
-        // Write all chunks with delete_chunks in the first batch
-        turbopuffer::write_chunks(
-            namespace,
-            successful_chunks,
-            if remote_chunks_to_delete.is_empty() {
-                None
-            } else {
-                Some(remote_chunks_to_delete)
-            },
-        )
-        .await?;
+        if write_options.dry_run {
+            // Embed everything, but leave the index as it is
+            successful_chunks.count().await;
+        } else {
+            // Write all chunks with delete_chunks in the first batch
+            index
+                .write_chunks(
+                    namespace,
+                    successful_chunks,
+                    if remote_chunks_to_delete.is_empty() {
+                        None
+                    } else {
+                        Some(remote_chunks_to_delete)
+                    },
+                    write_options,
+                )
+                .await?;
+        }
+        let tokens = tokens.load(Ordering::Relaxed);
+        vprintln!("embedding used {} tokens", tokens);
+
+        let mut outcome = outcome.lock().unwrap();
+        if outcome.service_down() {
+            let last_error = outcome.errors.pop().unwrap();
+            return Err(anyhow::Error::new(last_error).context(format!(
+                "embedding service appears down after {} failures in a row, \
+                 stopped with {} of {} chunks not indexed",
+                outcome.consecutive_failures,
+                total_chunks - outcome.embedded,
+                total_chunks
+            )));
+        }
+        if strict && !outcome.errors.is_empty() {
+            let first_error = outcome.errors.remove(0);
+            return Err(anyhow::Error::new(first_error).context(format!(
+                "embedding failed in strict mode, {} of {} chunks not indexed",
+                total_chunks - outcome.embedded,
+                total_chunks
+            )));
+        }
+        if write_options.dry_run {
+            eprintln!(
+                "embedding dry run: embedded {} of {} chunks using {} tokens, {} failed, \
+                 nothing written",
+                outcome.embedded,
+                total_chunks,
+                tokens,
+                total_chunks - outcome.embedded
+            );
+            return Ok((false, false));
+        }
+        if !outcome.errors.is_empty() {
+            return Ok((true, false));
+        }
+    } else if write_options.dry_run {
+        eprintln!("embedding dry run: nothing to embed, nothing written");
+        return Ok((false, false));
     } else if !remote_chunks_to_delete.is_empty() {
         // Only deletions, no uploads - use empty stream
-        turbopuffer::write_chunks(namespace, stream::empty(), Some(remote_chunks_to_delete))
+        index
+            .write_chunks(
+                namespace,
+                stream::empty(),
+                Some(remote_chunks_to_delete),
+                write_options,
+            )
             .await?;
     }
 
-    Ok(true) // Content changed
+    Ok((true, true)) // Content changed
+}
+
+/// Options controlling how a directory is synced to turbopuffer
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    pub embedding_concurrency: Option<usize>,
+    /// Keep embedding under this many tokens per minute
+    pub embedding_tokens_per_minute: Option<usize>,
+    pub walk: chunker::WalkOptions,
+    /// Tag every chunk with the git `HEAD` commit of the project
+    pub record_commit: bool,
+    /// Prefix embedded chunk text with the file's path relative to the project root
+    pub embed_path_context: bool,
+    pub write: turbopuffer::WriteOptions,
+    /// Fail the sync on any embedding error instead of skipping the failed chunks
+    pub strict: bool,
+    /// Print how many walked files were chunked, even when not verbose
+    pub stats: bool,
+    /// Index the directory itself rather than the project root it belongs to
+    pub flat: bool,
+    /// Keep a separate index for this git branch
+    pub branch: Option<String>,
+    /// Fail instead of warning when the directory has no supported source files
+    pub require_language: bool,
+    /// Print which files the sync added, changed and removed, even when not verbose
+    pub show_changes: bool,
+    /// Print how embedding went (batches, token-limit splits, latency), even when not
+    /// verbose
+    pub embedding_stats: bool,
 }
 
-pub async fn tpuf_sync(directory: &str, embedding_concurrency: Option<usize>) -> Result<bool> {
-    let (namespace, root_dir) = project::namespace_and_dir(directory)?;
+pub async fn tpuf_sync(directory: &str, options: &SyncOptions) -> Result<bool> {
+    let (namespace, root_dir) =
+        project::namespace_and_dir(directory, options.flat, options.branch.as_deref())?;
     vprintln!("namespace={} dir={}", namespace, root_dir);
+    let _span = crate::profile::span("sync", format!("sync {root_dir}"));
+
+    // The manifest covers whole, commit-less syncs; --stats and dry runs want their reports
+    let manifests = crate::manifest::ManifestStore::from_cache_dir().filter(|_| {
+        options.walk.scope.is_none()
+            && !options.record_commit
+            && !options.stats
+            && !options.write.dry_run
+    });
+    match manifests {
+        Some(manifests) => {
+            let fingerprint = manifest_fingerprint(options);
+            manifests
+                .sync_if_changed(
+                    &namespace,
+                    &root_dir,
+                    &options.walk,
+                    fingerprint,
+                    now_secs(),
+                    || async { turbopuffer::last_synced(&namespace).await.ok().flatten() },
+                    || fetch_and_sync(&namespace, &root_dir, options),
+                )
+                .await
+        }
+        None => Ok(fetch_and_sync(&namespace, &root_dir, options).await?.0),
+    }
+}
+
+/// Everything in `options` and the config that decides what a sync indexes
+fn manifest_fingerprint(options: &SyncOptions) -> u64 {
+    crate::manifest::Manifest::fingerprint(
+        crate::config::SETTINGS.get(),
+        &serde_json::json!({
+            "include_hidden": options.walk.include_hidden,
+            "skip_submodules": options.walk.skip_submodules,
+            "detect_shebang": options.walk.detect_shebang,
+            "include_generated": options.walk.include_generated,
+            "embed_path_context": options.embed_path_context,
+            "store_content": options.write.store_content,
+            "index_text": options.write.index_text,
+        }),
+    )
+}
+
+/// Diff the project's chunks against the server's and apply the difference (see
+/// [`diff_and_apply`] for what it returns)
+async fn fetch_and_sync(
+    namespace: &str,
+    root_dir: &str,
+    options: &SyncOptions,
+) -> Result<(bool, bool, Option<u64>)> {
+    let root_dir = root_dir.to_string();
 
     // Run chunk_files and all_server_chunks concurrently
-    let (local_chunks_res, remote_chunks_res) = tokio::join!(
-        async {
-            chunker::chunk_files(&root_dir)
-        },
-        async {
-            turbopuffer::all_chunks(&namespace).await
+    let chunk = {
+        let root_dir = root_dir.clone();
+        let options = options.clone();
+        move || {
+            let instant = std::time::Instant::now();
+            let chunks =
+                chunker::chunk_files_with_report(&root_dir, &options.walk).and_then(|report| {
+                    report.warn_failures();
+                    report.print_coverage(options.stats);
+                    report.check_supported(&root_dir, options.require_language)?;
+                    Ok(report.chunks)
+                });
+            vprintln!("chunking took {:.2?}", instant.elapsed());
+            chunks
         }
+    };
+    let (local_chunks_res, (remote_chunks_res, last_synced)) = chunk_while_fetching(chunk, async {
+        let instant = std::time::Instant::now();
+        let fetched = tokio::join!(
+            Turbopuffer.all_chunks(namespace),
+            turbopuffer::last_synced(namespace)
+        );
+        vprintln!("server fetch took {:.2?}", instant.elapsed());
+        fetched
+    })
+    .await;
+
+    let mut local_chunks = local_chunks_res?;
+    let mut remote_chunks = remote_chunks_res.unwrap_or_default();
+
+    // A scoped walk only sees part of the project, so leave the rest of the index alone
+    let language_mix = match &options.walk.scope {
+        Some(scope) => {
+            remote_chunks.retain(|chunk| scope.is_match(std::path::Path::new(&chunk.path)));
+            None
+        }
+        None => Some(LanguageMix::from_chunks(&local_chunks)),
+    };
+
+    if options.record_commit {
+        match project::git_head(&root_dir) {
+            Some(commit) => {
+                vprintln!("recording commit {}", commit);
+                for chunk in &mut local_chunks {
+                    chunk.commit = Some(commit.clone());
+                }
+            }
+            None => crate::warnln!(
+                "<(°!°)> {} is not a git checkout, not recording commit",
+                root_dir
+            ),
+        }
+    }
+
+    let last_synced = last_synced.unwrap_or_default();
+    let (changed, complete, synced_at) = diff_and_apply(
+        namespace,
+        &root_dir,
+        local_chunks,
+        remote_chunks,
+        last_synced,
+        options,
+    )
+    .await?;
+
+    // Only a whole sync that made it into the index describes the index's languages
+    if complete
+        && !options.write.dry_run
+        && let Some(language_mix) = language_mix
+        && let Some(store) = LanguageMixStore::from_cache_dir()
+    {
+        store.save(namespace, &language_mix);
+    }
+    Ok((changed, complete, synced_at))
+}
+
+/// Run the blocking walk-and-chunk on the rayon pool while `fetch` runs, so a slow walk
+/// can't stall the runtime thread the server fetch is polled on
+async fn chunk_while_fetching<C, F>(chunk: C, fetch: F) -> (Result<Vec<Chunk>>, F::Output)
+where
+    C: FnOnce() -> Result<Vec<Chunk>> + Send + 'static,
+    F: std::future::Future,
+{
+    tokio::join!(tokio_rayon::spawn(chunk), fetch)
+}
+
+/// Sync the index for `directory` from a `.tar`/`.tar.gz` archive instead of the working tree
+pub async fn tpuf_sync_archive(
+    archive_path: &str,
+    directory: &str,
+    strip_components: usize,
+    options: &SyncOptions,
+) -> Result<bool> {
+    let (namespace, root_dir) =
+        project::namespace_and_dir(directory, options.flat, options.branch.as_deref())?;
+    vprintln!(
+        "namespace={} dir={} archive={}",
+        namespace,
+        root_dir,
+        archive_path
+    );
+
+    let local_chunks = archive::chunk_archive_file(archive_path, &root_dir, strip_components)?;
+    let remote_chunks = Turbopuffer.all_chunks(&namespace).await.unwrap_or_default();
+
+    // The index no longer matches the directory on disk
+    if let Some(manifests) = crate::manifest::ManifestStore::from_cache_dir() {
+        manifests.invalidate(&namespace);
+    }
+    let last_synced = turbopuffer::last_synced(&namespace)
+        .await
+        .unwrap_or_default();
+    let (changed, _complete, _synced_at) = diff_and_apply(
+        &namespace,
+        &root_dir,
+        local_chunks,
+        remote_chunks,
+        last_synced,
+        options,
+    )
+    .await?;
+    Ok(changed)
+}
+
+/// The Voyage embedding a sync of the project at `root_dir` with `options` embeds with
+pub fn voyage_provider(root_dir: &str, options: &SyncOptions) -> embeddings::VoyageEmbedding {
+    let mut embedding_provider = match options.embedding_concurrency {
+        Some(concurrency) => embeddings::VoyageEmbedding::with_concurrency(concurrency),
+        None => embeddings::VoyageEmbedding::new(),
+    };
+    if options.embed_path_context {
+        embedding_provider = embedding_provider.with_path_context(root_dir);
+    }
+    if let Some(tokens_per_minute) = options.embedding_tokens_per_minute {
+        embedding_provider = embedding_provider.with_token_budget(tokens_per_minute);
+    }
+    if let Some(models) = crate::config::SETTINGS
+        .get()
+        .and_then(|s| s.embedding_models.as_deref())
+    {
+        embedding_provider = embedding_provider.with_extra_models(models);
+    }
+    embedding_provider
+}
+
+/// Sync the directories `roots` into the one index `namespace`, as a single project, for
+/// directories like sibling `frontend/` and `backend/` checkouts that share no project
+/// root. Returns whether the index changed.
+pub async fn sync_roots(
+    index: &impl IndexBackend,
+    embedding_provider: impl Embedding,
+    roots: &[String],
+    namespace: &str,
+    options: &SyncOptions,
+) -> Result<bool> {
+    let _span = crate::profile::span("sync", format!("sync {}", roots.join(" ")));
+    let mut local_chunks = Vec::new();
+    for root in roots {
+        let report = chunker::chunk_files_with_report(root, &options.walk)?;
+        report.warn_failures();
+        report.print_coverage(options.stats);
+        local_chunks.extend(report.chunks);
+    }
+    let remote_chunks = match index.all_chunks(namespace).await {
+        Ok(chunks) => chunks,
+        Err(turbopuffer::TurbopufferError::NamespaceNotFound(_)) => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let (to_upload, to_delete) = tpuf_chunk_diff(local_chunks, remote_chunks)?;
+    vprintln!(
+        "syncing {} roots into {}: +{} -{} chunks",
+        roots.len(),
+        namespace,
+        to_upload.len(),
+        to_delete.len()
     );
+    let (changed, _complete) = apply_diff(
+        index,
+        namespace,
+        to_upload,
+        to_delete,
+        is_verbose(),
+        embedding_provider,
+        &options.write,
+        options.strict,
+    )
+    .await?;
 
-    let local_chunks = local_chunks_res?;
-    let remote_chunks = remote_chunks_res.unwrap_or_default();
+    if changed
+        && let Some(cache) =
+            crate::query_cache::QueryCache::from_settings(crate::config::SETTINGS.get())
+    {
+        cache.invalidate(namespace);
+    }
+    Ok(changed)
+}
 
+/// Deepest directory containing all of `roots`, which are absolute
+pub fn common_root(roots: &[String]) -> String {
+    let Some((first, rest)) = roots.split_first() else {
+        return String::new();
+    };
+    std::path::Path::new(first)
+        .ancestors()
+        .find(|ancestor| {
+            rest.iter()
+                .all(|root| std::path::Path::new(root).starts_with(ancestor))
+        })
+        .map(|ancestor| ancestor.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Whether the index changed, whether every chunk of the diff made it in, and the sync
+/// time the index has afterwards (`None` if it couldn't be recorded)
+async fn diff_and_apply(
+    namespace: &str,
+    root_dir: &str,
+    local_chunks: Vec<Chunk>,
+    remote_chunks: Vec<Chunk>,
+    last_synced: Option<u64>,
+    options: &SyncOptions,
+) -> Result<(bool, bool, Option<u64>)> {
     // Calculate the diff in the thread pool
-    let (remote_upload, remote_delete) =
-        tokio_rayon::spawn(move || tpuf_chunk_diff(local_chunks, remote_chunks)).await?;
+    let (remote_upload, remote_delete, stale_commit) = tokio_rayon::spawn(move || {
+        let stale_commit = stale_commit_ids(&local_chunks, &remote_chunks);
+        tpuf_chunk_diff(local_chunks, remote_chunks)
+            .map(|(upload, delete)| (upload, delete, stale_commit))
+    })
+    .await?;
+    let changes = PendingChanges::new(root_dir, &remote_upload, &remote_delete);
+
+    // Chunks whose content is already indexed only need their commit attribute
+    // moved forward, which is much cheaper than re-embedding them
+    let commit_patched = match stale_commit {
+        Some((commit, ids)) if !ids.is_empty() && !options.write.dry_run => {
+            vprintln!("\\(°O°)/ moving {} chunks to commit {}", ids.len(), commit);
+            turbopuffer::patch_commit(namespace, &ids, &commit).await?;
+            true
+        }
+        _ => false,
+    };
+
+    let mut embedding_provider = voyage_provider(root_dir, options);
+    let embedding_stats = (options.embedding_stats || is_verbose())
+        .then(|| Arc::new(embeddings::EmbeddingStats::default()));
+    if let Some(stats) = &embedding_stats {
+        embedding_provider = embedding_provider.with_stats(stats.clone());
+    }
 
     // Apply the diff
-    tpuf_apply_diff(&namespace, remote_upload, remote_delete, is_verbose(), embedding_concurrency).await
+    let (content_changed, complete) = apply_diff(
+        &Turbopuffer,
+        namespace,
+        remote_upload,
+        remote_delete,
+        is_verbose(),
+        embedding_provider,
+        &options.write,
+        options.strict,
+    )
+    .await?;
+
+    if content_changed {
+        if options.show_changes || is_verbose() {
+            eprint!("synced changes:\n{changes}");
+        }
+        crate::warnln!("{}", changes.summary());
+    }
+    if let Some(stats) = embedding_stats.filter(|stats| stats.batches() > 0) {
+        if options.embedding_stats {
+            eprintln!("{stats}");
+        } else {
+            vprintln!("{stats}");
+        }
+    }
+
+    // Re-stamp at most once a day while nothing changes, to avoid a write per search
+    let now = now_secs();
+    let needs_stamp = !options.write.dry_run
+        && (content_changed
+            || commit_patched
+            || is_stale(last_synced, now, SYNC_STAMP_INTERVAL_SECS));
+    let synced_at = if needs_stamp {
+        match turbopuffer::stamp_last_synced(namespace, now).await {
+            Ok(()) => Some(now),
+            Err(e) => {
+                vprintln!("<(°~°)> Couldn't record sync time: {}", e);
+                None
+            }
+        }
+    } else {
+        last_synced
+    };
+
+    // Results cached for the old index may now point at changed or deleted code
+    if content_changed
+        && let Some(cache) =
+            crate::query_cache::QueryCache::from_settings(crate::config::SETTINGS.get())
+    {
+        cache.invalidate(namespace);
+    }
+
+    Ok((content_changed || commit_patched, complete, synced_at))
+}
+
+/// Delete the index in `namespace` without re-syncing, along with its cached query results
+pub async fn delete_index(namespace: &str) -> Result<()> {
+    turbopuffer::delete_namespace(namespace).await?;
+    if let Err(e) = turbopuffer::forget_last_synced(namespace).await {
+        vprintln!("<(°~°)> Couldn't forget sync time: {}", e);
+    }
+    if let Some(manifests) = crate::manifest::ManifestStore::from_cache_dir() {
+        manifests.invalidate(namespace);
+    }
+    if let Some(cache) =
+        crate::query_cache::QueryCache::from_settings(crate::config::SETTINGS.get())
+    {
+        cache.invalidate(namespace);
+    }
+    Ok(())
+}
+
+/// Make the next sync of `directory` compare against the server even if no files changed
+pub fn invalidate_manifest(directory: &str, options: &SyncOptions) {
+    if let Ok((namespace, _)) =
+        project::namespace_and_dir(directory, options.flat, options.branch.as_deref())
+        && let Some(manifests) = crate::manifest::ManifestStore::from_cache_dir()
+    {
+        manifests.invalidate(&namespace);
+    }
+}
+
+/// Chunks a sync would add and remove, per file relative to the project root
+#[derive(Debug, Default, PartialEq)]
+pub struct PendingChanges {
+    files: std::collections::BTreeMap<String, (usize, usize)>,
+}
+
+impl PendingChanges {
+    pub fn new(root_dir: &str, to_upload: &[Chunk], to_delete: &[Chunk]) -> Self {
+        let mut files = std::collections::BTreeMap::new();
+        let relative = |chunk: &Chunk| {
+            std::path::Path::new(&chunk.path)
+                .strip_prefix(root_dir)
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_else(|_| chunk.path.clone())
+        };
+        for chunk in to_upload {
+            files.entry(relative(chunk)).or_insert((0, 0)).0 += 1;
+        }
+        for chunk in to_delete {
+            files.entry(relative(chunk)).or_insert((0, 0)).1 += 1;
+        }
+        Self { files }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// One line of net change, e.g. `synced: +42 chunks, -7 chunks (3 files changed)`
+    pub fn summary(&self) -> String {
+        if self.is_empty() {
+            return "index up-to-date".to_string();
+        }
+        let (added, removed) = self
+            .files
+            .values()
+            .fold((0, 0), |(added, removed), (up, down)| {
+                (added + up, removed + down)
+            });
+        let count = |n: usize, noun: &str| format!("{n} {noun}{}", if n == 1 { "" } else { "s" });
+        format!(
+            "synced: +{}, -{} ({} changed)",
+            count(added, "chunk"),
+            count(removed, "chunk"),
+            count(self.files.len(), "file")
+        )
+    }
+
+    /// Files only gaining chunks are new, files only losing them deleted, the rest changed
+    fn section(&self, added: bool, removed: bool) -> Vec<(&str, usize, usize)> {
+        self.files
+            .iter()
+            .filter(|(_, (up, down))| (*up > 0) == added && (*down > 0) == removed)
+            .map(|(path, (up, down))| (path.as_str(), *up, *down))
+            .collect()
+    }
+}
+
+impl std::fmt::Display for PendingChanges {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "index up-to-date");
+        }
+        for (title, added, removed) in [
+            ("new", true, false),
+            ("changed", true, true),
+            ("deleted", false, true),
+        ] {
+            let files = self.section(added, removed);
+            if files.is_empty() {
+                continue;
+            }
+            writeln!(f, "{title}:")?;
+            for (path, up, down) in files {
+                match (up, down) {
+                    (0, down) => writeln!(f, "  {path} -{down}")?,
+                    (up, 0) => writeln!(f, "  {path} +{up}")?,
+                    (up, down) => writeln!(f, "  {path} +{up} -{down}")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What syncing `directory` would change in its index, without embedding or writing
+/// anything
+pub async fn pending_changes(
+    index: &impl IndexBackend,
+    directory: &str,
+    options: &SyncOptions,
+) -> Result<PendingChanges> {
+    let (root_dir, local_chunks, remote_chunks) =
+        local_and_indexed_chunks(index, directory, options).await?;
+    let (to_upload, to_delete) = tpuf_chunk_diff(local_chunks, remote_chunks)?;
+    Ok(PendingChanges::new(&root_dir, &to_upload, &to_delete))
+}
+
+/// The project root of `directory`, its chunks on disk and the chunks in its index
+async fn local_and_indexed_chunks(
+    index: &impl IndexBackend,
+    directory: &str,
+    options: &SyncOptions,
+) -> Result<(String, Vec<Chunk>, Vec<Chunk>)> {
+    let (namespace, root_dir) =
+        project::namespace_and_dir(directory, options.flat, options.branch.as_deref())?;
+    vprintln!("namespace={} dir={}", namespace, root_dir);
+
+    let report = chunker::chunk_files_with_report(&root_dir, &options.walk)?;
+    report.warn_failures();
+    let mut remote_chunks = match index.all_chunks(&namespace).await {
+        Ok(chunks) => chunks,
+        Err(turbopuffer::TurbopufferError::NamespaceNotFound(_)) => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    // A scoped walk only sees part of the project, so leave the rest of the index alone
+    if let Some(scope) = &options.walk.scope {
+        remote_chunks.retain(|chunk| scope.is_match(std::path::Path::new(&chunk.path)));
+    }
+    Ok((root_dir, report.chunks, remote_chunks))
+}
+
+/// How an index disagrees with the files it was synced from, as `path:start-end` of the
+/// chunks relative to the project root
+#[derive(Debug, Default, PartialEq)]
+pub struct IndexHealth {
+    /// In the index, but no longer on disk
+    pub orphans: Vec<String>,
+    /// On disk, but missing from the index, e.g. after embedding failures
+    pub gaps: Vec<String>,
+    /// Different chunks sharing an ID, which replace each other in the index
+    pub collisions: Vec<(String, String)>,
+}
+
+impl IndexHealth {
+    pub fn new(root_dir: &str, local_chunks: &[Chunk], remote_chunks: &[Chunk]) -> Self {
+        let location = |chunk: &Chunk| {
+            let path = std::path::Path::new(&chunk.path)
+                .strip_prefix(root_dir)
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_else(|_| chunk.path.clone());
+            format!("{path}:{}-{}", chunk.start_line, chunk.end_line)
+        };
+        let same_chunk = |a: &Chunk, b: &Chunk| {
+            (a.path.as_str(), a.start_line, a.end_line, a.chunk_hash)
+                == (b.path.as_str(), b.start_line, b.end_line, b.chunk_hash)
+        };
+
+        let mut health = Self::default();
+        let mut local_by_id: HashMap<u128, &Chunk> = HashMap::new();
+        for chunk in local_chunks {
+            match local_by_id.get(&chunk.id) {
+                Some(other) if !same_chunk(other, chunk) => {
+                    health.collisions.push((location(other), location(chunk)));
+                }
+                Some(_) => {}
+                None => {
+                    local_by_id.insert(chunk.id, chunk);
+                }
+            }
+        }
+        let mut remote_by_id: HashMap<u128, &Chunk> = HashMap::new();
+        for chunk in remote_chunks {
+            remote_by_id.insert(chunk.id, chunk);
+            // The index stores no content hash, so compare where the chunks are
+            match local_by_id.get(&chunk.id) {
+                None => health.orphans.push(location(chunk)),
+                Some(local) if location(local) != location(chunk) => {
+                    health.collisions.push((location(local), location(chunk)));
+                }
+                Some(_) => {}
+            }
+        }
+        health.gaps = local_chunks
+            .iter()
+            .filter(|chunk| !remote_by_id.contains_key(&chunk.id))
+            .map(location)
+            .collect();
+
+        health.orphans.sort();
+        health.gaps.sort();
+        health.gaps.dedup();
+        health.collisions.sort();
+        health
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.orphans.is_empty() && self.gaps.is_empty() && self.collisions.is_empty()
+    }
+}
+
+impl std::fmt::Display for IndexHealth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_healthy() {
+            return writeln!(f, "index healthy");
+        }
+        for (title, chunks) in [
+            ("orphans (indexed, not on disk)", &self.orphans),
+            ("gaps (on disk, not indexed)", &self.gaps),
+        ] {
+            if chunks.is_empty() {
+                continue;
+            }
+            writeln!(f, "{title}: {}", chunks.len())?;
+            for chunk in chunks {
+                writeln!(f, "  {chunk}")?;
+            }
+        }
+        if !self.collisions.is_empty() {
+            writeln!(f, "id collisions: {}", self.collisions.len())?;
+            for (a, b) in &self.collisions {
+                writeln!(f, "  {a} and {b}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check `directory`'s index against its files, without changing either
+pub async fn verify_index(
+    index: &impl IndexBackend,
+    directory: &str,
+    options: &SyncOptions,
+) -> Result<IndexHealth> {
+    let (root_dir, local_chunks, remote_chunks) =
+        local_and_indexed_chunks(index, directory, options).await?;
+    Ok(IndexHealth::new(&root_dir, &local_chunks, &remote_chunks))
+}
+
+/// Format version of [`IndexExport`] files
+pub const INDEX_EXPORT_VERSION: u32 = 1;
+
+/// Portable copy of an index, vectors included, written by `--export` and read by
+/// `--import`. Paths are relative to the project root, so a teammate's checkout elsewhere
+/// can import it instead of embedding everything again.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexExport {
+    pub version: u32,
+    /// Embedding model the vectors came from
+    pub model: String,
+    pub chunks: Vec<Chunk>,
+}
+
+impl IndexExport {
+    pub fn write_to(&self, path: &std::path::Path) -> Result<()> {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer(file, self)?;
+        Ok(())
+    }
+
+    pub fn read_from(path: &std::path::Path) -> Result<Self> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let export: Self = serde_json::from_reader(file)?;
+        if export.version != INDEX_EXPORT_VERSION {
+            anyhow::bail!(
+                "unsupported export version {} (expected {})",
+                export.version,
+                INDEX_EXPORT_VERSION
+            );
+        }
+        Ok(export)
+    }
+}
+
+/// Every chunk of `namespace` with its vector, paths made relative to `root_dir`
+pub async fn export_index(
+    index: &impl IndexBackend,
+    namespace: &str,
+    root_dir: &str,
+) -> Result<IndexExport> {
+    let chunks = index.all_chunks(namespace).await?;
+
+    let ids: Vec<u128> = chunks.iter().map(|chunk| chunk.id).collect();
+    let mut vectors = index.chunk_vectors(namespace, &ids).await?;
+    let chunks = chunks
+        .into_iter()
+        .filter_map(|mut chunk| {
+            chunk.vector = Some(vectors.remove(&chunk.id)?);
+            if let Ok(relative) = std::path::Path::new(&chunk.path).strip_prefix(root_dir) {
+                chunk.path = relative.to_string_lossy().to_string();
+            }
+            chunk.distance = None;
+            Some(chunk)
+        })
+        .collect();
+
+    Ok(IndexExport {
+        version: INDEX_EXPORT_VERSION,
+        model: embeddings::VOYAGE_MODEL.to_string(),
+        chunks,
+    })
+}
+
+/// Upload the chunks of `export` to `namespace` under `root_dir` without embedding
+/// anything, returning how many were written. IDs are recomputed for the new paths so the
+/// next sync finds the imported chunks unchanged.
+pub async fn import_index(
+    index: &impl IndexBackend,
+    namespace: &str,
+    root_dir: &str,
+    export: IndexExport,
+    options: &turbopuffer::WriteOptions,
+) -> Result<usize> {
+    if export.model != embeddings::VOYAGE_MODEL {
+        anyhow::bail!(
+            "export was embedded with {}, this index uses {}",
+            export.model,
+            embeddings::VOYAGE_MODEL
+        );
+    }
+
+    let chunks: Vec<Chunk> = export
+        .chunks
+        .into_iter()
+        .map(|mut chunk| {
+            chunk.path = std::path::Path::new(root_dir)
+                .join(&chunk.path)
+                .to_string_lossy()
+                .to_string();
+            chunk.id = chunker::chunk_id(
+                &chunk.path,
+                chunk.start_line.saturating_sub(1) as usize,
+                chunk.end_line.saturating_sub(1) as usize,
+                chunk.file_hash,
+                chunk.chunk_hash,
+            );
+            chunk
+        })
+        .collect();
+
+    let count = chunks.len();
+    index
+        .write_chunks(namespace, stream::iter(chunks), None, options)
+        .await?;
+    Ok(count)
+}
+
+/// How often an unchanged index gets its last-synced time refreshed
+const SYNC_STAMP_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Whether an index last synced at `last_synced` (never, if `None`) is older than `max_age_secs`
+pub fn is_stale(last_synced: Option<u64>, now: u64, max_age_secs: u64) -> bool {
+    match last_synced {
+        Some(synced_at) => now.saturating_sub(synced_at) > max_age_secs,
+        None => true,
+    }
+}
+
+/// Find server chunks that are unchanged locally but tagged with a different commit.
+///
+/// Returns `None` when the local chunks don't carry a commit at all.
+pub fn stale_commit_ids(
+    local_chunks: &[Chunk],
+    server_chunks: &[Chunk],
+) -> Option<(String, Vec<u128>)> {
+    let commit = local_chunks.iter().find_map(|c| c.commit.clone())?;
+    let local_chunk_ids: std::collections::HashSet<u128> =
+        local_chunks.iter().map(|c| c.id).collect();
+
+    let ids = server_chunks
+        .iter()
+        .filter(|s| local_chunk_ids.contains(&s.id) && s.commit.as_ref() != Some(&commit))
+        .map(|s| s.id)
+        .collect();
+
+    Some((commit, ids))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn test_chunking_does_not_block_server_fetch() {
+        // A single-threaded runtime: a walk blocking it would hold up the fetch too
+        let started = Instant::now();
+        let (chunks, fetched_after) = chunk_while_fetching(
+            || {
+                std::thread::sleep(Duration::from_millis(500));
+                Ok(Vec::new())
+            },
+            async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                started.elapsed()
+            },
+        )
+        .await;
+
+        assert!(chunks.unwrap().is_empty());
+        assert!(
+            fetched_after < Duration::from_millis(250),
+            "fetch finished after {fetched_after:?}"
+        );
+        assert!(started.elapsed() >= Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_is_stale() {
+        let now = 1_700_000_000;
+        let day = 24 * 60 * 60;
+
+        assert!(!is_stale(Some(now - day), now, 30 * day));
+        assert!(!is_stale(Some(now - 30 * day), now, 30 * day));
+        assert!(is_stale(Some(now - 31 * day), now, 30 * day));
+        // Never stamped counts as stale, a clock running behind doesn't
+        assert!(is_stale(None, now, 30 * day));
+        assert!(!is_stale(Some(now + day), now, 30 * day));
+    }
+
+    #[test]
+    fn test_wide_ids_sharing_low_64_bits_stay_distinct() {
+        // Two wide IDs that only differ above bit 64, which truncating to 64 bits would
+        // merge so one chunk silently replaced the other
+        let id = chunker::IdHash::Xxh3_128.chunk_id("src/a.rs", 0, 9, 1, 2);
+        let wide_twin = id ^ (1 << 100);
+        assert_eq!(id as u64, wide_twin as u64);
+        let chunk = |id, path: &str| Chunk {
+            id,
+            path: path.to_string(),
+            ..Default::default()
+        };
+
+        // Both survive the `row_id` encoding rows are uploaded and fetched with
+        let round_trip = |chunk: &Chunk| {
+            let json = serde_json::to_string(chunk).unwrap();
+            serde_json::from_str::<Chunk>(&json).unwrap().id
+        };
+        let local = vec![chunk(id, "src/a.rs"), chunk(wide_twin, "src/b.rs")];
+        let fetched: Vec<u128> = local.iter().map(round_trip).collect();
+        assert_eq!(fetched, vec![id, wide_twin]);
+
+        let remote = vec![chunk(fetched[0], "src/a.rs")];
+        let (to_upload, to_delete) = tpuf_chunk_diff(local, remote).unwrap();
+        assert_eq!(to_upload.len(), 1);
+        assert_eq!(to_upload[0].path, "src/b.rs");
+        assert!(to_delete.is_empty());
+
+        // The wider scheme hashes the same inputs to different IDs than the 64-bit one
+        let narrow = chunker::IdHash::Xxh3_64.chunk_id("src/a.rs", 0, 9, 1, 2);
+        assert!(narrow <= u64::MAX as u128);
+        assert_ne!(id, narrow);
+        assert!(id > u64::MAX as u128);
+    }
+
+    fn embedding_results(fail_at: u128) -> Vec<Result<Chunk, embeddings::EmbeddingError>> {
+        (0..4)
+            .map(|id| {
+                if id == fail_at {
+                    Err(embeddings::EmbeddingError::ApiError(
+                        "rate limited".to_string(),
+                    ))
+                } else {
+                    Ok(Chunk {
+                        id,
+                        ..Default::default()
+                    })
+                }
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_successful_embeddings_skips_failures() {
+        let outcome = Arc::new(Mutex::new(EmbeddingOutcome::default()));
+        let chunks: Vec<Chunk> =
+            successful_embeddings(stream::iter(embedding_results(1)), false, outcome.clone())
+                .collect()
+                .await;
+
+        let ids: Vec<u128> = chunks.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![0, 2, 3]);
+        let outcome = outcome.lock().unwrap();
+        assert_eq!(outcome.embedded, 3);
+        assert_eq!(outcome.errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_successful_embeddings_strict_stops_at_failure() {
+        let outcome = Arc::new(Mutex::new(EmbeddingOutcome::default()));
+        let chunks: Vec<Chunk> =
+            successful_embeddings(stream::iter(embedding_results(1)), true, outcome.clone())
+                .collect()
+                .await;
+
+        // Nothing after the failure gets written
+        let ids: Vec<u128> = chunks.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![0]);
+        let outcome = outcome.lock().unwrap();
+        assert_eq!(outcome.embedded, 1);
+        assert_eq!(outcome.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_changes_classify_files() {
+        let chunk = |path: &str| Chunk {
+            path: format!("/repo/{path}"),
+            ..Default::default()
+        };
+        let to_upload = [chunk("new.rs"), chunk("lib.rs"), chunk("lib.rs")];
+        let to_delete = [chunk("lib.rs"), chunk("gone.rs"), chunk("gone.rs")];
+        let changes = PendingChanges::new("/repo", &to_upload, &to_delete);
+
+        // (path, chunks uploaded, chunks deleted) of new, changed and deleted files
+        assert_eq!(changes.section(true, false), [("new.rs", 1, 0)]);
+        assert_eq!(changes.section(true, true), [("lib.rs", 2, 1)]);
+        assert_eq!(changes.section(false, true), [("gone.rs", 0, 2)]);
+    }
+
+    #[test]
+    fn test_changes_summary() {
+        let chunk = |path: &str| Chunk {
+            path: format!("/repo/{path}"),
+            ..Default::default()
+        };
+        let to_upload = [chunk("new.rs"), chunk("lib.rs"), chunk("lib.rs")];
+        let to_delete = [chunk("lib.rs"), chunk("gone.rs"), chunk("gone.rs")];
+        let changes = PendingChanges::new("/repo", &to_upload, &to_delete);
+        assert_eq!(
+            changes.summary(),
+            "synced: +3 chunks, -3 chunks (3 files changed)"
+        );
+
+        let changes = PendingChanges::new("/repo", &[chunk("new.rs")], &[]);
+        assert_eq!(
+            changes.summary(),
+            "synced: +1 chunk, -0 chunks (1 file changed)"
+        );
+
+        assert_eq!(PendingChanges::default().summary(), "index up-to-date");
+    }
 }
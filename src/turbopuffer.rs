@@ -1,5 +1,5 @@
-use crate::chunker::Chunk;
-use crate::config::SETTINGS;
+use crate::chunker::{Chunk, row_id};
+use crate::config::{SETTINGS, Settings};
 use anyhow::Result;
 use base64::{Engine as _, engine::general_purpose};
 use futures::future::join_all;
@@ -7,7 +7,7 @@ use futures::stream::{Stream, StreamExt};
 use itertools::Itertools;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::OnceLock;
 use std::time::Instant;
 
@@ -54,12 +54,12 @@ pub async fn ping(region: Option<&str>) -> Result<u64, TurbopufferError> {
         SETTINGS
             .get()
             .and_then(|s| s.turbopuffer_region.as_deref())
-            .unwrap_or("gcp-us-east4")
+            .unwrap_or(DEFAULT_REGION)
     });
 
     let instant = Instant::now();
     let _result = client
-        .get(format!("https://{}.turbopuffer.com/", region_to_use))
+        .get(format!("{}/", region_url(region_to_use)))
         .send()
         .await?;
     crate::vprintln!(
@@ -72,34 +72,59 @@ pub async fn ping(region: Option<&str>) -> Result<u64, TurbopufferError> {
     Ok(latency)
 }
 
-pub async fn find_closest_region() -> Result<String, TurbopufferError> {
+/// Pings per region when picking the closest one, unless the config sets
+/// `region_ping_samples`
+const DEFAULT_REGION_PING_SAMPLES: usize = 3;
+
+/// Latency of one ping to `region`, retrying once so that a single dropped request
+/// doesn't rule the region out
+async fn ping_with_retry(region: &str) -> Option<u64> {
+    match ping(Some(region)).await {
+        Ok(latency) => Some(latency),
+        Err(_e) => ping(Some(region)).await.ok(),
+    }
+}
+
+/// Middle of the successful pings' latencies, `None` when every ping failed
+fn median_latency(mut latencies: Vec<u64>) -> Option<u64> {
+    latencies.sort_unstable();
+    latencies.get(latencies.len() / 2).copied()
+}
+
+/// Region with the lowest median latency, skipping regions none of whose pings succeeded
+fn closest_region<'a>(latencies: impl IntoIterator<Item = (&'a str, Vec<u64>)>) -> Option<&'a str> {
+    latencies
+        .into_iter()
+        .filter_map(|(region, latencies)| Some((region, median_latency(latencies)?)))
+        .min_by_key(|&(_, latency)| latency)
+        .map(|(region, _)| region)
+}
+
+// The binary picks its region through the library's `config`, this is for library users
+#[allow(dead_code)]
+pub async fn find_closest_region(settings: Option<&Settings>) -> Result<String, TurbopufferError> {
+    let samples = settings
+        .and_then(|s| s.region_ping_samples)
+        .unwrap_or(DEFAULT_REGION_PING_SAMPLES)
+        .max(1);
+
+    // Regions are pinged in parallel, each region's samples one after another
     let ping_futures: Vec<_> = TURBOPUFFER_REGIONS
         .iter()
         .map(|&region| async move {
-            match ping(Some(region)).await {
-                Ok(latency) => Some((region.to_string(), latency)),
-                Err(_e) => None,
+            let mut latencies = Vec::with_capacity(samples);
+            for _ in 0..samples {
+                latencies.extend(ping_with_retry(region).await);
             }
+            (region, latencies)
         })
         .collect();
 
     let results = join_all(ping_futures).await;
 
-    let mut best_region = None;
-    let mut best_latency = u64::MAX;
-
-    for result in results {
-        if let Some((region, latency)) = result {
-            if latency < best_latency {
-                best_latency = latency;
-                best_region = Some(region);
-            }
-        }
-    }
-
-    match best_region {
-        Some(region) => Ok(region),
-        None => Ok("gcp-us-east4".to_string()),
+    match closest_region(results) {
+        Some(region) => Ok(region.to_string()),
+        None => Ok(DEFAULT_REGION.to_string()),
     }
 }
 
@@ -125,11 +150,117 @@ struct Performance {
 }
 
 #[derive(Deserialize)]
-struct QueryResponse {
-    rows: Vec<Chunk>,
+struct QueryResponse<Row> {
+    rows: Vec<Row>,
     performance: Performance,
 }
 
+/// A row of [`chunk_vectors`], just its id and vector
+#[derive(Deserialize)]
+struct VectorRow {
+    #[serde(with = "row_id")]
+    id: u128,
+    vector: Option<Vec<f32>>,
+}
+
+/// A chunk as returned by a query, with its stored content still compressed
+#[derive(Deserialize)]
+struct QueryRow {
+    #[serde(flatten)]
+    chunk: Chunk,
+    #[serde(default)]
+    content_br: Option<String>,
+    /// Remaining attributes, among them the vector columns of extra embedding models
+    #[serde(flatten)]
+    other: BTreeMap<String, serde_json::Value>,
+}
+
+impl From<QueryRow> for Chunk {
+    fn from(row: QueryRow) -> Self {
+        let mut chunk = row.chunk;
+        if let Some(compressed) = row.content_br {
+            chunk.content = decompress_content(&compressed);
+        }
+        chunk.extra_vectors = row
+            .other
+            .into_iter()
+            .filter(|(column, _)| column.starts_with(EXTRA_VECTOR_PREFIX))
+            .filter_map(|(column, value)| Some((column, serde_json::from_value(value).ok()?)))
+            .collect();
+        chunk
+    }
+}
+
+/// Vector column of the default embedding model
+const VECTOR_COLUMN: &str = "vector";
+
+/// Prefix of the vector columns of the config's extra `embedding_models`
+const EXTRA_VECTOR_PREFIX: &str = "vector_";
+
+/// Column holding the vectors of embedding `model`: `vector` for the default model, and
+/// e.g. `vector_voyage_3_5` for `voyage-3.5`
+pub fn vector_column(model: &str) -> String {
+    if model == crate::embeddings::VOYAGE_MODEL {
+        return VECTOR_COLUMN.to_string();
+    }
+    let name: String = model
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' => c,
+            'A'..='Z' => c.to_ascii_lowercase(),
+            _ => '_',
+        })
+        .collect();
+    format!("{EXTRA_VECTOR_PREFIX}{name}")
+}
+
+/// Rank by nearness to `vector` in the column of embedding `model`
+pub fn ann_rank_by(model: &str, vector: Vec<f32>) -> serde_json::Value {
+    serde_json::json!([vector_column(model), "ANN", vector])
+}
+
+/// Full-text indexed chunk content, see [`WriteOptions::index_text`]
+const CONTENT_TEXT_COLUMN: &str = "content_text";
+
+/// Rank by BM25 relevance of the chunks' content to the words of `query`. The "distance"
+/// turbopuffer returns is the BM25 score, higher for better matches.
+pub fn bm25_rank_by(query: &str) -> serde_json::Value {
+    serde_json::json!([CONTENT_TEXT_COLUMN, "BM25", query])
+}
+
+/// Every vector column of the configured models, which queries leave out of the rows
+fn vector_columns(settings: Option<&Settings>) -> Vec<String> {
+    let extra_models = settings.and_then(|s| s.embedding_models.as_deref());
+    std::iter::once(VECTOR_COLUMN.to_string())
+        .chain(
+            extra_models
+                .unwrap_or_default()
+                .iter()
+                .map(|m| vector_column(m)),
+        )
+        .collect()
+}
+
+/// Chunk content as base64 of its brotli compression, for the `content_br` attribute
+fn compress_content(content: &str) -> String {
+    let mut compressed = Vec::new();
+    let params = brotli::enc::BrotliEncoderParams {
+        quality: 9,
+        ..Default::default()
+    };
+    brotli::BrotliCompress(&mut content.as_bytes(), &mut compressed, &params)
+        .expect("compressing into memory can't fail");
+    general_purpose::STANDARD.encode(&compressed)
+}
+
+/// Content stored by [`compress_content`], `None` if it doesn't decode
+fn decompress_content(encoded: &str) -> Option<String> {
+    let compressed = general_purpose::STANDARD.decode(encoded).ok()?;
+    let mut content = Vec::new();
+    brotli::BrotliDecompress(&mut compressed.as_slice(), &mut content).ok()?;
+    String::from_utf8(content).ok()
+}
+
 const USE_BASE64_VECTORS: bool = true;
 
 fn vector_to_base64(vector: &[f32]) -> String {
@@ -140,39 +271,75 @@ fn vector_to_base64(vector: &[f32]) -> String {
     general_purpose::STANDARD.encode(&bytes)
 }
 
+/// A vector as written to turbopuffer, see [`USE_BASE64_VECTORS`]
+fn vector_value(vector: Vec<f32>) -> serde_json::Value {
+    if USE_BASE64_VECTORS {
+        serde_json::Value::String(vector_to_base64(&vector))
+    } else {
+        serde_json::Value::Array(
+            vector
+                .into_iter()
+                .map(|f| serde_json::Value::Number(serde_json::Number::from_f64(f as f64).unwrap()))
+                .collect(),
+        )
+    }
+}
+
 #[derive(Serialize)]
 struct ChunkForUpload {
-    id: u64,
+    #[serde(with = "row_id")]
+    id: u128,
     #[serde(skip_serializing_if = "Option::is_none")]
     vector: Option<serde_json::Value>,
     path: String,
     start_line: u32,
     end_line: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_byte: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_byte: Option<usize>,
     file_hash: u64,
     chunk_hash: u64,
     file_mtime: u64,
     file_ctime: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<crate::chunker::ChunkKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    symbol: Option<String>,
+    /// Compressed content, only with [`WriteOptions::store_content`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_br: Option<String>,
+    /// Plain content for full-text search, only with [`WriteOptions::index_text`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_text: Option<String>,
+    /// Vectors of extra embedding models, by column
+    #[serde(flatten)]
+    extra_vectors: BTreeMap<String, serde_json::Value>,
 }
 
 impl From<Chunk> for ChunkForUpload {
     fn from(chunk: Chunk) -> Self {
-        let vector = if let Some(vec) = chunk.vector {
-            if USE_BASE64_VECTORS {
-                Some(serde_json::Value::String(vector_to_base64(&vec)))
-            } else {
-                Some(serde_json::Value::Array(
-                    vec.into_iter()
-                        .map(|f| {
-                            serde_json::Value::Number(
-                                serde_json::Number::from_f64(f as f64).unwrap(),
-                            )
-                        })
-                        .collect(),
-                ))
-            }
+        Self::new(chunk, false, false)
+    }
+}
+
+impl ChunkForUpload {
+    fn new(chunk: Chunk, store_content: bool, index_text: bool) -> Self {
+        let vector = chunk.vector.map(vector_value);
+        let extra_vectors = chunk
+            .extra_vectors
+            .into_iter()
+            .map(|(column, vector)| (column, vector_value(vector)))
+            .collect();
+
+        let content_br = if store_content {
+            chunk.content.as_deref().map(compress_content)
         } else {
             None
         };
+        let content_text = if index_text { chunk.content } else { None };
 
         ChunkForUpload {
             id: chunk.id,
@@ -180,63 +347,293 @@ impl From<Chunk> for ChunkForUpload {
             path: chunk.path,
             start_line: chunk.start_line,
             end_line: chunk.end_line,
+            start_byte: chunk.start_byte,
+            end_byte: chunk.end_byte,
             file_hash: chunk.file_hash,
             chunk_hash: chunk.chunk_hash,
             file_mtime: chunk.file_mtime,
             file_ctime: chunk.file_ctime,
+            commit: chunk.commit,
+            kind: chunk.kind,
+            symbol: chunk.symbol,
+            content_br,
+            content_text,
+            extra_vectors,
         }
     }
 }
 
+/// How turbopuffer measures the distance between vectors. A namespace's metric is fixed
+/// by its first write, so changing it in the config needs a `--reset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DistanceMetric {
+    #[default]
+    Cosine,
+    EuclideanSquared,
+}
+
+/// How current a query's view of a namespace is. Eventual reads are faster, but can miss
+/// writes from the last moments, such as those of a sync that just finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Consistency {
+    #[default]
+    Eventual,
+    Strong,
+}
+
+impl Consistency {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Eventual => "eventual",
+            Self::Strong => "strong",
+        }
+    }
+}
+
+/// Which attributes of each row a query returns
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Projection {
+    /// Everything but the vectors and the full-text indexed copy of the content
+    #[default]
+    AllButVectors,
+    /// Only these, besides the id and distance turbopuffer always returns, e.g. just the
+    /// locations for `--files-only`, keeping responses of a large `top_k` small
+    Only(Vec<&'static str>),
+}
+
+/// How a query reads the namespace, and what it returns of each row
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct QueryOptions {
+    pub consistency: Consistency,
+    pub projection: Projection,
+}
+
+impl DistanceMetric {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Cosine => "cosine_distance",
+            Self::EuclideanSquared => "euclidean_squared",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "cosine" | "cosine_distance" => Some(Self::Cosine),
+            "euclidean" | "euclidean_squared" => Some(Self::EuclideanSquared),
+            _ => None,
+        }
+    }
+
+    /// The metric from the config file, cosine unless it names a known one
+    pub fn from_settings(settings: Option<&crate::config::Settings>) -> Self {
+        let Some(name) = settings.and_then(|s| s.distance_metric.as_deref()) else {
+            return Self::default();
+        };
+        Self::parse(name).unwrap_or_else(|| {
+            crate::warnln!(
+                "<(°!°)> Ignoring unknown distance_metric {} from config",
+                name
+            );
+            Self::default()
+        })
+    }
+
+    /// The metric indexes are written and searched with, resolved (and warned about) once
+    /// the config is loaded
+    pub fn active() -> Self {
+        static ACTIVE: OnceLock<DistanceMetric> = OnceLock::new();
+        match SETTINGS.get() {
+            Some(settings) => *ACTIVE.get_or_init(|| Self::from_settings(Some(settings))),
+            None => Self::default(),
+        }
+    }
+
+    /// Largest distance `--min-score` keeps when given without a value: 0.7 apart in
+    /// cosine distance, which is 1.4 in squared euclidean distance between unit vectors
+    pub fn default_max_distance(self) -> f64 {
+        match self {
+            Self::Cosine => 0.7,
+            Self::EuclideanSquared => 1.4,
+        }
+    }
+
+    /// `--min-score` threshold when the flag is given without a value
+    pub fn default_min_score(self) -> f64 {
+        self.score(self.default_max_distance())
+    }
+
+    /// Similarity for a distance under this metric, from 1 for identical vectors down to
+    /// -1 for opposite ones. Voyage vectors have unit length, so both metrics map onto
+    /// cosine similarity and one threshold means the same under either.
+    pub fn score(self, distance: f64) -> f64 {
+        match self {
+            Self::Cosine => 1.0 - distance,
+            Self::EuclideanSquared => 1.0 - distance / 2.0,
+        }
+    }
+}
+
+/// Allowed values for [`WriteOptions::batch_size`]
+pub const WRITE_BATCH_SIZE_RANGE: std::ops::RangeInclusive<usize> = 1..=10_000;
+/// Allowed values for [`WriteOptions::concurrency`]
+pub const WRITE_CONCURRENCY_RANGE: std::ops::RangeInclusive<usize> = 1..=32;
+
+/// How chunks are batched into turbopuffer write requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Maximum number of chunks per write request
+    pub batch_size: usize,
+    /// Maximum number of write requests in flight
+    pub concurrency: usize,
+    /// Store each chunk's content, compressed, so results can be previewed without the files
+    pub store_content: bool,
+    /// Store each chunk's content as full-text indexed text, so the lexical fallback can
+    /// find words in it when the query can't be embedded
+    pub index_text: bool,
+    /// Embed what a sync would write, reporting tokens and failures, but write nothing
+    pub dry_run: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 1000,
+            concurrency: 4, // Reduced to prevent HTTP client exhaustion
+            store_content: false,
+            index_text: true,
+            dry_run: false,
+        }
+    }
+}
+
+impl WriteOptions {
+    /// Defaults overridden by the config file, ignoring out-of-range values
+    pub fn from_settings(settings: Option<&crate::config::Settings>) -> Self {
+        let mut options = Self::default();
+        let Some(settings) = settings else {
+            return options;
+        };
+
+        if let Some(batch_size) = settings.write_batch_size {
+            if WRITE_BATCH_SIZE_RANGE.contains(&batch_size) {
+                options.batch_size = batch_size;
+            } else {
+                crate::warnln!(
+                    "<(°!°)> Ignoring write_batch_size {} from config (must be {}-{})",
+                    batch_size,
+                    WRITE_BATCH_SIZE_RANGE.start(),
+                    WRITE_BATCH_SIZE_RANGE.end()
+                );
+            }
+        }
+        if let Some(concurrency) = settings.write_concurrency {
+            if WRITE_CONCURRENCY_RANGE.contains(&concurrency) {
+                options.concurrency = concurrency;
+            } else {
+                crate::warnln!(
+                    "<(°!°)> Ignoring write_concurrency {} from config (must be {}-{})",
+                    concurrency,
+                    WRITE_CONCURRENCY_RANGE.start(),
+                    WRITE_CONCURRENCY_RANGE.end()
+                );
+            }
+        }
+        if let Some(store_content) = settings.store_content {
+            options.store_content = store_content;
+        }
+        if let Some(index_text) = settings.index_text {
+            options.index_text = index_text;
+        }
+        options
+    }
+}
+
 pub async fn write_chunks<S>(
     namespace: &str,
     chunks: S,
     delete_chunks: Option<Vec<Chunk>>,
+    options: &WriteOptions,
 ) -> Result<(), TurbopufferError>
 where
     S: Stream<Item = Chunk> + Send + 'static,
 {
-    const BATCH_SIZE: usize = 1000;
-    const CONCURRENT_REQUESTS: usize = 4; // Reduced to prevent HTTP client exhaustion
-
     let api_key =
         std::env::var("TURBOPUFFER_API_KEY").map_err(|_| TurbopufferError::MissingApiKey)?;
 
-    let namespace = namespace.to_string();
-    let mut is_first_batch = true;
-    let _total_start = Instant::now();
-    let mut _total_written = 0;
-
-    let mut chunk_stream = Box::pin(
-        chunks
-            .chunks(BATCH_SIZE)
-            .map(move |batch| {
-                let namespace = namespace.clone();
-                let api_key = api_key.clone();
-                let delete_chunks = if is_first_batch {
-                    is_first_batch = false;
-                    delete_chunks.clone()
-                } else {
-                    None
-                };
-
-                async move { write_batch(&namespace, batch, delete_chunks, &api_key).await }
-            })
-            .buffer_unordered(CONCURRENT_REQUESTS),
+    let instant = Instant::now();
+    let total_written = write_stream(
+        chunks,
+        delete_chunks,
+        options.batch_size,
+        options.concurrency,
+        |batch, delete_chunks| {
+            write_batch(
+                namespace,
+                batch,
+                delete_chunks,
+                options.store_content,
+                options.index_text,
+                &api_key,
+            )
+        },
+    )
+    .await?;
+    crate::vprintln!(
+        "wrote {} chunks to turbopuffer in {:.2?} (batch size {}, concurrency {})",
+        total_written,
+        instant.elapsed(),
+        options.batch_size,
+        options.concurrency
     );
 
-    while let Some(result) = chunk_stream.next().await {
-        let batch_count = result?;
-        _total_written += batch_count;
+    Ok(())
+}
+
+/// Drive a stream of embedded chunks into write requests with bounded memory.
+///
+/// A batch is cut as soon as `batch_size` chunks are ready *or* the upstream (embedding)
+/// has nothing more ready, so writes overlap with embedding instead of stalling until a
+/// full batch accumulates; at most `concurrency` writes are in flight, which in turn
+/// backpressures the embedding stream. The deletes go out with the first batch, and that
+/// request completes before any other starts, so a stale-path delete can never race with
+/// (and remove) freshly upserted chunks for the same path.
+async fn write_stream<S, W, F>(
+    chunks: S,
+    delete_chunks: Option<Vec<Chunk>>,
+    batch_size: usize,
+    concurrency: usize,
+    write: W,
+) -> Result<usize, TurbopufferError>
+where
+    S: Stream<Item = Chunk>,
+    W: Fn(Vec<Chunk>, Option<Vec<Chunk>>) -> F,
+    F: std::future::Future<Output = Result<usize, TurbopufferError>>,
+{
+    let mut batches = Box::pin(chunks.ready_chunks(batch_size));
+    let mut total_written = 0;
+
+    if let Some(delete_chunks) = delete_chunks {
+        let first_batch = batches.next().await.unwrap_or_default();
+        total_written += write(first_batch, Some(delete_chunks)).await?;
     }
 
-    Ok(())
+    let mut writes = batches
+        .map(|batch| write(batch, None))
+        .buffer_unordered(concurrency);
+
+    while let Some(result) = writes.next().await {
+        total_written += result?;
+    }
+
+    Ok(total_written)
 }
 
 async fn write_batch(
     namespace: &str,
     chunks: Vec<Chunk>,
     delete_chunks: Option<Vec<Chunk>>,
+    store_content: bool,
+    index_text: bool,
     api_key: &str,
 ) -> Result<usize, TurbopufferError> {
     let _instant = Instant::now();
@@ -246,10 +643,22 @@ async fn write_batch(
     if chunk_count == 0 && delete_count == 0 {
         return Ok(0);
     }
+    let _span = crate::profile::span("turbopuffer", format!("write {chunk_count} chunks"));
 
     let client = get_client();
 
     let request_body = tokio_rayon::spawn(move || {
+        // Extra vector columns aren't the namespace's default vector and so need a schema
+        let extra_vector_schema: serde_json::Map<String, serde_json::Value> = chunks
+            .iter()
+            .flat_map(|c| &c.extra_vectors)
+            .map(|(column, vector)| {
+                let schema =
+                    serde_json::json!({ "type": format!("[{}]f32", vector.len()), "ann": true });
+                (column.clone(), schema)
+            })
+            .collect();
+
         let chunks_for_upload: Vec<ChunkForUpload> = chunks
             .into_iter()
             // I think duplicates can happen if the same function somehow appears on the same line,
@@ -257,17 +666,23 @@ async fn write_batch(
             // This failed on the Ruby on Rails codebase.
             .sorted_by_key(|c| c.id)
             .dedup_by(|a, b| a.id == b.id)
-            .map(ChunkForUpload::from)
+            .map(|chunk| ChunkForUpload::new(chunk, store_content, index_text))
             .collect();
 
         let mut request_body = serde_json::json!({
             "upsert_rows": chunks_for_upload,
-            "distance_metric": "cosine_distance",
+            "distance_metric": DistanceMetric::active().as_str(),
             "schema": {
                 "file_hash": "uint",
-                "chunk_hash": "uint"
+                "chunk_hash": "uint",
+                "commit": "string",
+                (CONTENT_TEXT_COLUMN): { "type": "string", "full_text_search": true }
             }
         });
+        request_body["schema"]
+            .as_object_mut()
+            .unwrap()
+            .extend(extra_vector_schema);
 
         if let Some(delete_chunks) = delete_chunks {
             if !delete_chunks.is_empty() {
@@ -297,15 +712,7 @@ async fn write_batch(
     .await;
 
     let response = client
-        .post(format!(
-            "https://{}.turbopuffer.com/v2/namespaces/{}",
-            SETTINGS
-                .get()
-                .and_then(|s| s.turbopuffer_region.as_ref())
-                .cloned()
-                .unwrap_or_else(|| "gcp-us-east4".to_string()),
-            namespace
-        ))
+        .post(namespace_url(SETTINGS.get(), namespace))
         .header("Authorization", format!("Bearer {}", api_key))
         .json(&request_body)
         .send()
@@ -319,6 +726,308 @@ async fn write_batch(
     Ok(chunk_count)
 }
 
+/// Region used when the config doesn't name one
+const DEFAULT_REGION: &str = "gcp-us-east4";
+
+/// API base URL of `region`
+fn region_url(region: &str) -> String {
+    format!("https://{region}.turbopuffer.com")
+}
+
+/// Region `namespace` lives in: the one it's pinned to or else the configured one
+pub fn namespace_region<'a>(settings: Option<&'a Settings>, namespace: &str) -> &'a str {
+    settings
+        .and_then(|s| s.region_for(namespace))
+        .unwrap_or(DEFAULT_REGION)
+}
+
+/// Every region the config puts namespaces in: the configured one, then the pinned ones
+fn configured_regions(settings: Option<&Settings>) -> Vec<&str> {
+    let global = settings
+        .and_then(|s| s.turbopuffer_region.as_deref())
+        .unwrap_or(DEFAULT_REGION);
+    let pinned = settings
+        .and_then(|s| s.namespace_regions.as_ref())
+        .into_iter()
+        .flat_map(|regions| regions.values().map(String::as_str));
+    let mut regions = vec![global];
+    for region in pinned {
+        if !regions.contains(&region) {
+            regions.push(region);
+        }
+    }
+    regions
+}
+
+/// API URL of `namespace`, in the region it's pinned to or else the configured one
+fn namespace_url(settings: Option<&Settings>, namespace: &str) -> String {
+    format!(
+        "{}/v2/namespaces/{}",
+        region_url(namespace_region(settings, namespace)),
+        namespace
+    )
+}
+
+/// Point already-indexed chunks at a new commit without re-uploading their vectors
+pub async fn patch_commit(
+    namespace: &str,
+    ids: &[u128],
+    commit: &str,
+) -> Result<(), TurbopufferError> {
+    const BATCH_SIZE: usize = 1000;
+
+    let api_key =
+        std::env::var("TURBOPUFFER_API_KEY").map_err(|_| TurbopufferError::MissingApiKey)?;
+
+    let client = get_client();
+
+    for batch in ids.chunks(BATCH_SIZE) {
+        let request_body = serde_json::json!({
+            "patch_rows": batch
+                .iter()
+                .map(|id| serde_json::json!({ "id": row_id::to_json(*id), "commit": commit }))
+                .collect::<Vec<_>>(),
+        });
+
+        let response = client
+            .post(namespace_url(SETTINGS.get(), namespace))
+            .header("Authorization", format!("Bearer {}", api_key))
+            .json(&request_body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(TurbopufferError::ApiError(error_text));
+        }
+    }
+
+    Ok(())
+}
+
+/// Namespace recording when each project namespace was last synced, one row per
+/// namespace, so that the project namespaces hold nothing but chunks
+pub fn sync_times_namespace(settings: Option<&Settings>) -> String {
+    format!("{}sync_times", crate::project::namespace_prefix(settings))
+}
+
+/// When `namespace` was last synced, as kept in [`sync_times_namespace`]
+#[derive(Serialize, Deserialize)]
+struct SyncTimeRow {
+    #[serde(with = "row_id")]
+    id: u128,
+    namespace: String,
+    synced_at: u64,
+}
+
+/// Row id of `namespace`'s sync time
+fn sync_time_id(namespace: &str) -> u128 {
+    xxhash_rust::xxh3::xxh3_64(namespace.as_bytes()) as u128
+}
+
+/// Write to the sync times namespace: `body` upserts or deletes rows
+async fn write_sync_times(body: serde_json::Value) -> Result<(), TurbopufferError> {
+    let api_key =
+        std::env::var("TURBOPUFFER_API_KEY").map_err(|_| TurbopufferError::MissingApiKey)?;
+    let settings = SETTINGS.get();
+
+    let response = get_client()
+        .post(namespace_url(settings, &sync_times_namespace(settings)))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&body)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await?;
+        return Err(TurbopufferError::ApiError(error_text));
+    }
+    Ok(())
+}
+
+/// Record `synced_at` (Unix seconds) as the namespace's last sync time
+pub async fn stamp_last_synced(namespace: &str, synced_at: u64) -> Result<(), TurbopufferError> {
+    let row = SyncTimeRow {
+        id: sync_time_id(namespace),
+        namespace: namespace.to_string(),
+        synced_at,
+    };
+    write_sync_times(serde_json::json!({ "upsert_rows": [row] })).await
+}
+
+/// Forget the namespace's last sync time, once its index is deleted
+pub async fn forget_last_synced(namespace: &str) -> Result<(), TurbopufferError> {
+    let id = row_id::to_json(sync_time_id(namespace));
+    write_sync_times(serde_json::json!({ "deletes": [id] })).await
+}
+
+/// When the namespace was last synced (Unix seconds), if it has ever been stamped
+pub async fn last_synced(namespace: &str) -> Result<Option<u64>, TurbopufferError> {
+    let api_key =
+        std::env::var("TURBOPUFFER_API_KEY").map_err(|_| TurbopufferError::MissingApiKey)?;
+    let settings = SETTINGS.get();
+
+    let request = serde_json::json!({
+        "rank_by": ["id", "asc"],
+        "top_k": 1,
+        "filters": ["id", "Eq", row_id::to_json(sync_time_id(namespace))],
+        "include_attributes": ["namespace", "synced_at"],
+    });
+    let response = get_client()
+        .post(format!(
+            "{}/query",
+            namespace_url(settings, &sync_times_namespace(settings))
+        ))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&request)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await?;
+        // Nothing has been stamped yet
+        if error_text.contains("namespace") && error_text.contains("not found") {
+            return Ok(None);
+        }
+        return Err(TurbopufferError::ApiError(error_text));
+    }
+
+    let resp: QueryResponse<SyncTimeRow> = response.json().await?;
+    Ok(resp
+        .rows
+        .into_iter()
+        .find(|row| row.namespace == namespace)
+        .map(|row| row.synced_at))
+}
+
+#[derive(Deserialize)]
+struct NamespaceSummary {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct NamespacesResponse {
+    namespaces: Vec<NamespaceSummary>,
+    next_cursor: Option<String>,
+}
+
+/// All project namespaces whose name starts with `prefix`, in the configured region and
+/// the regions namespaces are pinned to
+pub async fn list_namespaces(prefix: &str) -> Result<Vec<String>, TurbopufferError> {
+    let api_key =
+        std::env::var("TURBOPUFFER_API_KEY").map_err(|_| TurbopufferError::MissingApiKey)?;
+
+    let mut namespaces = Vec::new();
+    for region in configured_regions(SETTINGS.get()) {
+        for namespace in list_region_namespaces(region, prefix, &api_key).await? {
+            if !namespaces.contains(&namespace) {
+                namespaces.push(namespace);
+            }
+        }
+    }
+    Ok(namespaces)
+}
+
+/// Namespaces in `region` whose name starts with `prefix`, besides the sync times
+async fn list_region_namespaces(
+    region: &str,
+    prefix: &str,
+    api_key: &str,
+) -> Result<Vec<String>, TurbopufferError> {
+    let client = get_client();
+    let mut namespaces = Vec::new();
+    let mut cursor: Option<String> = None;
+
+    loop {
+        let mut request = client
+            .get(format!("{}/v1/namespaces", region_url(region)))
+            .header("Authorization", format!("Bearer {}", api_key))
+            .query(&[("prefix", prefix)]);
+        if let Some(cursor) = &cursor {
+            request = request.query(&[("cursor", cursor)]);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(TurbopufferError::ApiError(error_text));
+        }
+
+        let page: NamespacesResponse = response.json().await?;
+        namespaces.extend(
+            page.namespaces
+                .into_iter()
+                .map(|ns| ns.id)
+                .filter(|ns| *ns != sync_times_namespace(SETTINGS.get())),
+        );
+        match page.next_cursor {
+            Some(next) if !next.is_empty() => cursor = Some(next),
+            _ => break,
+        }
+    }
+
+    Ok(namespaces)
+}
+
+/// Filter matching chunks whose path matches, or lives under, one of the absolute globs
+pub fn path_scope_filter(patterns: &[String]) -> serde_json::Value {
+    let filters: Vec<_> = patterns
+        .iter()
+        .flat_map(|p| {
+            [
+                serde_json::json!(["path", "Glob", p]),
+                serde_json::json!(["path", "Glob", format!("{}/**", p.trim_end_matches('/'))]),
+            ]
+        })
+        .collect();
+    serde_json::json!(["Or", filters])
+}
+
+/// Filter matching chunks under the absolute directory `prefix`
+pub fn path_prefix_filter(prefix: &str) -> serde_json::Value {
+    serde_json::json!([
+        "path",
+        "Glob",
+        format!("{}/**", prefix.trim_end_matches('/'))
+    ])
+}
+
+/// Filter matching chunks of files modified at or after `since` (Unix seconds)
+pub fn modified_since_filter(since: u64) -> serde_json::Value {
+    serde_json::json!(["file_mtime", "Gte", since])
+}
+
+/// Filter matching chunks of files with one of `extensions` (without the dot)
+pub fn extension_filter(extensions: &[String]) -> serde_json::Value {
+    let filters: Vec<_> = extensions
+        .iter()
+        .map(|ext| serde_json::json!(["path", "Glob", format!("**/*.{ext}")]))
+        .collect();
+    serde_json::json!(["Or", filters])
+}
+
+/// Filter matching chunks whose path contains one of `terms`, ignoring case, for
+/// namespaces without full-text indexed content. Terms are matched literally, so they
+/// must not contain glob characters.
+pub fn terms_filter(terms: &[String]) -> serde_json::Value {
+    let filters: Vec<_> = terms
+        .iter()
+        .map(|term| serde_json::json!(["path", "IGlob", format!("*{term}*")]))
+        .collect();
+    serde_json::json!(["Or", filters])
+}
+
+/// Filter matching chunks of one of `kinds`
+pub fn kind_filter(kinds: &[crate::chunker::ChunkKind]) -> serde_json::Value {
+    let kinds: Vec<_> = kinds.iter().map(|kind| kind.as_str()).collect();
+    serde_json::json!(["kind", "In", kinds])
+}
+
+/// Filter matching chunks indexed at `commit`, which may be an abbreviated SHA
+pub fn commit_filter(commit: &str) -> serde_json::Value {
+    serde_json::json!(["commit", "Glob", format!("{}*", commit)])
+}
+
 pub async fn delete_namespace(namespace: &str) -> Result<(), TurbopufferError> {
     let api_key =
         std::env::var("TURBOPUFFER_API_KEY").map_err(|_| TurbopufferError::MissingApiKey)?;
@@ -326,15 +1035,7 @@ pub async fn delete_namespace(namespace: &str) -> Result<(), TurbopufferError> {
     let client = get_client();
 
     let response = client
-        .delete(format!(
-            "https://{}.turbopuffer.com/v2/namespaces/{}",
-            SETTINGS
-                .get()
-                .and_then(|s| s.turbopuffer_region.as_ref())
-                .cloned()
-                .unwrap_or_else(|| "gcp-us-east4".to_string()),
-            namespace
-        ))
+        .delete(namespace_url(SETTINGS.get(), namespace))
         .header("Authorization", format!("Bearer {}", api_key))
         .send()
         .await?;
@@ -347,38 +1048,37 @@ pub async fn delete_namespace(namespace: &str) -> Result<(), TurbopufferError> {
     Ok(())
 }
 
+// The binary queries through `IndexBackend`, this is for library users
+#[allow(dead_code)]
 pub async fn query_chunks(
     namespace: &str,
     rank_by: serde_json::Value,
     top_k: u32,
     filters: Option<serde_json::Value>,
+) -> Result<Vec<Chunk>, TurbopufferError> {
+    query_chunks_with(namespace, rank_by, top_k, filters, QueryOptions::default()).await
+}
+
+/// [`query_chunks`] reading the namespace and returning the attributes `options` asks for
+pub async fn query_chunks_with(
+    namespace: &str,
+    rank_by: serde_json::Value,
+    top_k: u32,
+    filters: Option<serde_json::Value>,
+    options: QueryOptions,
 ) -> Result<Vec<Chunk>, TurbopufferError> {
     let api_key =
         std::env::var("TURBOPUFFER_API_KEY").map_err(|_| TurbopufferError::MissingApiKey)?;
 
     let client = get_client();
     let _instant = Instant::now();
+    let _span = crate::profile::span("turbopuffer", "query");
 
-    let mut request = serde_json::json!({
-        "rank_by": rank_by,
-        "top_k": top_k,
-        "exclude_attributes": ["vector"],
-        "consistency": { "level": "eventual" },
-    });
-
-    if let Some(filters) = filters {
-        request["filters"] = filters;
-    }
-
+    let request = query_request(rank_by, top_k, filters, &options, SETTINGS.get());
     let response = client
         .post(format!(
-            "https://{}.turbopuffer.com/v2/namespaces/{}/query",
-            SETTINGS
-                .get()
-                .and_then(|s| s.turbopuffer_region.as_ref())
-                .cloned()
-                .unwrap_or_else(|| "gcp-us-east4".to_string()),
-            namespace
+            "{}/query",
+            namespace_url(SETTINGS.get(), namespace)
         ))
         .header("Authorization", format!("Bearer {}", api_key))
         .json(&request)
@@ -393,26 +1093,114 @@ pub async fn query_chunks(
         return Err(TurbopufferError::ApiError(error_text));
     }
 
-    let resp: QueryResponse = response.json().await?;
+    let resp: QueryResponse<serde_json::Value> = response.json().await?;
 
-    Ok(resp.rows)
+    resp.rows
+        .into_iter()
+        .map(|row| parse_row(row, &options.projection))
+        .collect()
 }
 
+/// Attributes every chunk row has, which a [`Projection::Only`] may leave out
+const REQUIRED_ATTRIBUTES: &[&str] = &[
+    "path",
+    "start_line",
+    "end_line",
+    "file_hash",
+    "chunk_hash",
+    "file_mtime",
+    "file_ctime",
+];
+
+/// Chunk of a query's `row`, with the required attributes `projection` didn't ask for left
+/// at their defaults. Those it did ask for must be there, since e.g. a hash defaulting to
+/// 0 would make a sync see the chunk as changed.
+fn parse_row(
+    mut row: serde_json::Value,
+    projection: &Projection,
+) -> Result<Chunk, TurbopufferError> {
+    if let (Projection::Only(attributes), Some(fields)) = (projection, row.as_object_mut()) {
+        for &attribute in REQUIRED_ATTRIBUTES {
+            if !attributes.contains(&attribute) {
+                let default = match attribute {
+                    "path" => serde_json::json!(""),
+                    _ => serde_json::json!(0),
+                };
+                fields.entry(attribute).or_insert(default);
+            }
+        }
+    }
+    Ok(Chunk::from(serde_json::from_value::<QueryRow>(row)?))
+}
+
+/// Body of a query request
+fn query_request(
+    rank_by: serde_json::Value,
+    top_k: u32,
+    filters: Option<serde_json::Value>,
+    options: &QueryOptions,
+    settings: Option<&Settings>,
+) -> serde_json::Value {
+    let mut request = serde_json::json!({
+        "rank_by": rank_by,
+        "top_k": top_k,
+        "consistency": { "level": options.consistency.as_str() },
+    });
+    match &options.projection {
+        Projection::AllButVectors => {
+            let mut excluded = vector_columns(settings);
+            excluded.push(CONTENT_TEXT_COLUMN.to_string());
+            request["exclude_attributes"] = serde_json::json!(excluded);
+        }
+        Projection::Only(attributes) => {
+            request["include_attributes"] = serde_json::json!(attributes);
+        }
+    }
+
+    if let Some(filters) = filters {
+        request["filters"] = filters;
+    }
+    request
+}
+
+/// Attributes [`all_chunks`] fetches: what a sync diff compares and the chunk's own
+/// metadata, but not the vectors or the (much larger) stored content
+const CHUNK_ATTRIBUTES: &[&str] = &[
+    "path",
+    "start_line",
+    "end_line",
+    "start_byte",
+    "end_byte",
+    "file_hash",
+    "chunk_hash",
+    "file_mtime",
+    "file_ctime",
+    "commit",
+    "kind",
+    "symbol",
+];
+
+/// Every chunk in `namespace`, with just the [`CHUNK_ATTRIBUTES`]
 pub async fn all_chunks(namespace: &str) -> Result<Vec<Chunk>, TurbopufferError> {
     let _instant = Instant::now();
+    let _span = crate::profile::span("turbopuffer", "fetch all chunks");
     let mut all_chunks = Vec::new();
-    let mut last_id = 0u64;
+    let mut last_id = 0u128;
 
     loop {
-        let batch = query_chunks(
+        let batch = query_chunks_with(
             namespace,
             serde_json::json!(["id", "asc"]),
             1200,
             if last_id > 0 {
-                Some(serde_json::json!(["id", "Gt", last_id]))
+                Some(serde_json::json!(["id", "Gt", row_id::to_json(last_id)]))
             } else {
                 None
             },
+            QueryOptions {
+                projection: Projection::Only(CHUNK_ATTRIBUTES.to_vec()),
+                ..Default::default()
+            },
         )
         .await?;
 
@@ -432,6 +1220,607 @@ pub async fn all_chunks(namespace: &str) -> Result<Vec<Chunk>, TurbopufferError>
     Ok(all_chunks)
 }
 
+/// Stored vectors of the chunks with `ids`, keyed by id. Ids missing from the index are
+/// left out.
+pub async fn chunk_vectors(
+    namespace: &str,
+    ids: &[u128],
+) -> Result<HashMap<u128, Vec<f32>>, TurbopufferError> {
+    const BATCH_SIZE: usize = 1200;
+
+    let api_key =
+        std::env::var("TURBOPUFFER_API_KEY").map_err(|_| TurbopufferError::MissingApiKey)?;
+
+    let client = get_client();
+    let mut vectors = HashMap::with_capacity(ids.len());
+
+    for batch in ids.chunks(BATCH_SIZE) {
+        let request = serde_json::json!({
+            "rank_by": ["id", "asc"],
+            "top_k": batch.len(),
+            "filters": ["id", "In", batch.iter().map(|id| row_id::to_json(*id)).collect::<Vec<_>>()],
+            "include_attributes": ["vector"],
+            "consistency": { "level": "eventual" },
+        });
+
+        let response = client
+            .post(format!(
+                "{}/query",
+                namespace_url(SETTINGS.get(), namespace)
+            ))
+            .header("Authorization", format!("Bearer {}", api_key))
+            .json(&request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(TurbopufferError::ApiError(error_text));
+        }
+
+        let resp: QueryResponse<VectorRow> = response.json().await?;
+        vectors.extend(
+            resp.rows
+                .into_iter()
+                .filter_map(|row| Some((row.id, row.vector?))),
+        );
+    }
+
+    Ok(vectors)
+}
+
 pub async fn all_server_chunks(namespace: &str) -> Result<Vec<Chunk>, TurbopufferError> {
     all_chunks(namespace).await
 }
+
+/// The turbopuffer calls sync and search are built on. [`Turbopuffer`] talks to the real
+/// API; tests can substitute an in-memory index to exercise that logic offline.
+pub trait IndexBackend: Send + Sync {
+    /// Upsert `chunks`, deleting `delete_chunks` first (see [`write_chunks`])
+    fn write_chunks<S>(
+        &self,
+        namespace: &str,
+        chunks: S,
+        delete_chunks: Option<Vec<Chunk>>,
+        options: &WriteOptions,
+    ) -> impl std::future::Future<Output = Result<(), TurbopufferError>> + Send
+    where
+        S: Stream<Item = Chunk> + Send + 'static;
+
+    /// Every chunk in the namespace, without vectors or content (see [`all_chunks`])
+    fn all_chunks(
+        &self,
+        namespace: &str,
+    ) -> impl std::future::Future<Output = Result<Vec<Chunk>, TurbopufferError>> + Send;
+
+    /// Top `top_k` chunks by `rank_by` (see [`query_chunks_with`])
+    fn query_chunks(
+        &self,
+        namespace: &str,
+        rank_by: serde_json::Value,
+        top_k: u32,
+        filters: Option<serde_json::Value>,
+        options: QueryOptions,
+    ) -> impl std::future::Future<Output = Result<Vec<Chunk>, TurbopufferError>> + Send;
+
+    /// Stored vectors of the chunks with `ids` (see [`chunk_vectors`])
+    fn chunk_vectors(
+        &self,
+        namespace: &str,
+        ids: &[u128],
+    ) -> impl std::future::Future<Output = Result<HashMap<u128, Vec<f32>>, TurbopufferError>> + Send;
+}
+
+/// The turbopuffer HTTP API, in the region from the config
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Turbopuffer;
+
+impl IndexBackend for Turbopuffer {
+    fn write_chunks<S>(
+        &self,
+        namespace: &str,
+        chunks: S,
+        delete_chunks: Option<Vec<Chunk>>,
+        options: &WriteOptions,
+    ) -> impl std::future::Future<Output = Result<(), TurbopufferError>> + Send
+    where
+        S: Stream<Item = Chunk> + Send + 'static,
+    {
+        self::write_chunks(namespace, chunks, delete_chunks, options)
+    }
+
+    fn all_chunks(
+        &self,
+        namespace: &str,
+    ) -> impl std::future::Future<Output = Result<Vec<Chunk>, TurbopufferError>> + Send {
+        self::all_chunks(namespace)
+    }
+
+    fn query_chunks(
+        &self,
+        namespace: &str,
+        rank_by: serde_json::Value,
+        top_k: u32,
+        filters: Option<serde_json::Value>,
+        options: QueryOptions,
+    ) -> impl std::future::Future<Output = Result<Vec<Chunk>, TurbopufferError>> + Send {
+        self::query_chunks_with(namespace, rank_by, top_k, filters, options)
+    }
+
+    fn chunk_vectors(
+        &self,
+        namespace: &str,
+        ids: &[u128],
+    ) -> impl std::future::Future<Output = Result<HashMap<u128, Vec<f32>>, TurbopufferError>> + Send
+    {
+        self::chunk_vectors(namespace, ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_closest_region_uses_median_latency() {
+        assert_eq!(median_latency(vec![40, 12, 35]), Some(35));
+        assert_eq!(median_latency(vec![20]), Some(20));
+        assert_eq!(median_latency(vec![]), None);
+
+        // One lucky ping doesn't win over a region that's consistently faster, and a
+        // region whose pings all failed is never picked
+        let latencies = vec![
+            ("gcp-us-central1", vec![5, 90, 95]),
+            ("gcp-us-east4", vec![30, 25, 28]),
+            ("aws-us-east-1", vec![]),
+            ("aws-us-west-2", vec![60, 26]),
+        ];
+        assert_eq!(closest_region(latencies), Some("gcp-us-east4"));
+
+        assert_eq!(closest_region(vec![("gcp-us-east4", vec![])]), None);
+    }
+
+    #[test]
+    fn test_stored_content_round_trips_compressed() {
+        let content =
+            "/// Grüße 👋\nfn greet(name: &str) -> String {\n    format!(\"hi {name}\")\n}\n"
+                .repeat(20);
+        let chunk = Chunk {
+            id: 42,
+            path: "/project/src/greet.rs".to_string(),
+            content: Some(content.clone()),
+            ..Default::default()
+        };
+
+        // Nothing is stored unless asked for
+        let upload =
+            serde_json::to_value(ChunkForUpload::new(chunk.clone(), false, false)).unwrap();
+        assert!(upload.get("content_br").is_none());
+        assert!(upload.get("content_text").is_none());
+
+        // Full-text search needs the content as it is
+        let upload = serde_json::to_value(ChunkForUpload::new(chunk.clone(), false, true)).unwrap();
+        assert_eq!(upload["content_text"].as_str(), Some(content.as_str()));
+
+        let mut upload = serde_json::to_value(ChunkForUpload::new(chunk, true, false)).unwrap();
+        let stored = upload["content_br"].as_str().unwrap();
+        assert!(stored.len() < content.len());
+        assert!(!stored.contains("greet"));
+
+        // Rows come back with the attributes that were written, plus the distance
+        upload["$dist"] = serde_json::json!(0.5);
+        let row: QueryRow = serde_json::from_value(upload).unwrap();
+        let queried = Chunk::from(row);
+        assert_eq!(queried.id, 42);
+        assert_eq!(queried.distance, Some(0.5));
+        assert_eq!(queried.content.as_deref(), Some(content.as_str()));
+    }
+
+    #[test]
+    fn test_extra_vectors_round_trip_in_own_columns() {
+        let column = vector_column("voyage-3.5");
+        assert_eq!(column, "vector_voyage_3_5");
+        assert_eq!(vector_column(crate::embeddings::VOYAGE_MODEL), "vector");
+
+        let chunk = Chunk {
+            id: 7,
+            vector: Some(vec![1.0, 0.0]),
+            extra_vectors: [(column.clone(), vec![0.0, 1.0])].into(),
+            ..Default::default()
+        };
+        let mut upload = serde_json::to_value(ChunkForUpload::new(chunk, false, false)).unwrap();
+        assert!(upload["vector"].is_string());
+        assert!(upload[&column].is_string());
+
+        // Queries return vectors as float arrays, one per column
+        upload["$dist"] = serde_json::json!(0.0);
+        upload["vector"] = serde_json::json!([1.0, 0.0]);
+        upload[&column] = serde_json::json!([0.0, 1.0]);
+        upload["vector_openai"] = serde_json::json!([0.5, 0.5]);
+        let queried = Chunk::from(serde_json::from_value::<QueryRow>(upload).unwrap());
+        assert_eq!(queried.vector, Some(vec![1.0, 0.0]));
+        assert_eq!(queried.extra_vectors.len(), 2);
+        assert_eq!(queried.extra_vectors[&column], vec![0.0, 1.0]);
+        assert_eq!(queried.extra_vectors["vector_openai"], vec![0.5, 0.5]);
+
+        assert_eq!(
+            ann_rank_by("voyage-3.5", vec![0.0, 1.0]),
+            serde_json::json!(["vector_voyage_3_5", "ANN", [0.0, 1.0]])
+        );
+    }
+
+    #[test]
+    fn test_location_projection_includes_only_locations() {
+        let settings = Settings {
+            embedding_models: Some(vec!["voyage-3.5".to_string()]),
+            ..Default::default()
+        };
+        let rank_by = ann_rank_by(crate::embeddings::VOYAGE_MODEL, vec![1.0]);
+        let full = query_request(
+            rank_by.clone(),
+            10,
+            None,
+            &QueryOptions::default(),
+            Some(&settings),
+        );
+        assert_eq!(
+            full["exclude_attributes"],
+            serde_json::json!(["vector", "vector_voyage_3_5", "content_text"])
+        );
+        assert!(full.get("include_attributes").is_none());
+
+        let options = QueryOptions {
+            projection: Projection::Only(vec!["path", "start_line"]),
+            ..Default::default()
+        };
+        let minimal = query_request(rank_by, 10, None, &options, Some(&settings));
+        assert_eq!(
+            minimal["include_attributes"],
+            serde_json::json!(["path", "start_line"])
+        );
+        assert!(minimal.get("exclude_attributes").is_none());
+
+        // Rows of just those attributes still make chunks
+        let row =
+            serde_json::json!({"id": 7, "$dist": 0.25, "path": "/repo/lib.rs", "start_line": 3});
+        let chunk = parse_row(row.clone(), &options.projection).unwrap();
+        assert_eq!((chunk.path.as_str(), chunk.start_line), ("/repo/lib.rs", 3));
+        assert_eq!(chunk.distance, Some(0.25));
+
+        // but an attribute that was asked for can't silently read as 0
+        assert!(parse_row(row.clone(), &QueryOptions::default().projection).is_err());
+        let options = QueryOptions {
+            projection: Projection::Only(CHUNK_ATTRIBUTES.to_vec()),
+            ..Default::default()
+        };
+        assert!(parse_row(row, &options.projection).is_err());
+    }
+
+    #[test]
+    fn test_min_score_thresholds_per_metric() {
+        // The same vectors are 0.2 apart in cosine distance and 0.4 in squared euclidean
+        // distance, which is the same similarity under either metric
+        assert_eq!(DistanceMetric::Cosine.score(0.2), 0.8);
+        assert_eq!(DistanceMetric::EuclideanSquared.score(0.4), 0.8);
+        // Read as cosine, the euclidean distance would look like a much worse match
+        assert_eq!(DistanceMetric::Cosine.score(0.4), 0.6);
+        assert_eq!(DistanceMetric::EuclideanSquared.score(4.0), -1.0);
+
+        // Each metric's default threshold keeps the same matches
+        for metric in [DistanceMetric::Cosine, DistanceMetric::EuclideanSquared] {
+            assert!(
+                (metric.default_min_score() - 0.3).abs() < 1e-9,
+                "{metric:?}"
+            );
+        }
+        assert!(DistanceMetric::EuclideanSquared.score(1.2) > 0.3);
+        assert!(DistanceMetric::EuclideanSquared.score(1.6) < 0.3);
+
+        assert_eq!(
+            DistanceMetric::parse("euclidean_squared"),
+            Some(DistanceMetric::EuclideanSquared)
+        );
+        assert_eq!(DistanceMetric::parse("hamming"), None);
+        let settings = Settings {
+            distance_metric: Some("euclidean".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            DistanceMetric::from_settings(Some(&settings)),
+            DistanceMetric::EuclideanSquared
+        );
+        assert_eq!(DistanceMetric::from_settings(None), DistanceMetric::Cosine);
+    }
+
+    #[test]
+    fn test_configured_regions_include_pinned_ones() {
+        let settings = Settings {
+            turbopuffer_region: Some("gcp-us-east4".to_string()),
+            namespace_regions: Some(
+                [
+                    ("tg_compliance".to_string(), "aws-eu-central-1".to_string()),
+                    ("tg_other".to_string(), "gcp-us-east4".to_string()),
+                ]
+                .into(),
+            ),
+            ..Default::default()
+        };
+        assert_eq!(
+            configured_regions(Some(&settings)),
+            ["gcp-us-east4", "aws-eu-central-1"]
+        );
+        assert_eq!(configured_regions(None), [DEFAULT_REGION]);
+        assert_eq!(
+            namespace_region(Some(&settings), "tg_compliance"),
+            "aws-eu-central-1"
+        );
+    }
+
+    #[test]
+    fn test_namespace_region_overrides_global_region() {
+        let settings = Settings {
+            turbopuffer_region: Some("gcp-us-east4".to_string()),
+            namespace_regions: Some(
+                [("tg_compliance".to_string(), "aws-eu-central-1".to_string())].into(),
+            ),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            namespace_url(Some(&settings), "tg_compliance"),
+            "https://aws-eu-central-1.turbopuffer.com/v2/namespaces/tg_compliance"
+        );
+        assert_eq!(
+            namespace_url(Some(&settings), "tg_other"),
+            "https://gcp-us-east4.turbopuffer.com/v2/namespaces/tg_other"
+        );
+        assert_eq!(
+            namespace_url(None, "tg_other"),
+            "https://gcp-us-east4.turbopuffer.com/v2/namespaces/tg_other"
+        );
+    }
+
+    fn chunk(id: u128) -> Chunk {
+        Chunk {
+            id,
+            path: format!("file{}.rs", id % 7),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_stream_delivers_every_chunk_once() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let deletes_done = Arc::new(AtomicUsize::new(0));
+
+        // Simulate embedding results trickling in
+        let chunks = futures::stream::iter(0..2500u128).then(|id| async move {
+            if id % 100 == 0 {
+                tokio::task::yield_now().await;
+            }
+            chunk(id)
+        });
+
+        let total = write_stream(
+            chunks,
+            Some(vec![chunk(9999)]),
+            1000,
+            4,
+            |batch, deletes| {
+                let written = written.clone();
+                let in_flight = in_flight.clone();
+                let max_in_flight = max_in_flight.clone();
+                let deletes_done = deletes_done.clone();
+                async move {
+                    assert!(batch.len() <= 1000);
+                    if deletes.is_some() {
+                        assert_eq!(in_flight.load(Ordering::SeqCst), 0);
+                    } else {
+                        // No upsert may start before the delete request has finished
+                        assert_eq!(deletes_done.load(Ordering::SeqCst), 1);
+                    }
+
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                    if deletes.is_some() {
+                        deletes_done.fetch_add(1, Ordering::SeqCst);
+                    }
+                    let count = batch.len();
+                    written
+                        .lock()
+                        .unwrap()
+                        .extend(batch.into_iter().map(|c| c.id));
+                    Ok(count)
+                }
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(total, 2500);
+        let mut ids = written.lock().unwrap().clone();
+        ids.sort();
+        assert_eq!(ids, (0..2500u128).collect::<Vec<_>>());
+        assert_eq!(deletes_done.load(Ordering::SeqCst), 1);
+        assert!(max_in_flight.load(Ordering::SeqCst) <= 4);
+    }
+
+    #[tokio::test]
+    async fn test_write_stream_sends_deletes_without_upserts() {
+        let delete_requests = Arc::new(AtomicUsize::new(0));
+
+        let total = write_stream(
+            futures::stream::empty(),
+            Some(vec![chunk(1), chunk(2)]),
+            1000,
+            4,
+            |batch, deletes| {
+                let delete_requests = delete_requests.clone();
+                async move {
+                    assert!(batch.is_empty());
+                    assert_eq!(deletes.unwrap().len(), 2);
+                    delete_requests.fetch_add(1, Ordering::SeqCst);
+                    Ok(0)
+                }
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(total, 0);
+        assert_eq!(delete_requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_chunk_for_upload_commit() {
+        let upload = serde_json::to_value(ChunkForUpload::from(chunk(1))).unwrap();
+        assert!(upload.get("commit").is_none());
+
+        let upload = serde_json::to_value(ChunkForUpload::from(Chunk {
+            commit: Some("0123abcd".to_string()),
+            ..chunk(1)
+        }))
+        .unwrap();
+        assert_eq!(upload["commit"], "0123abcd");
+    }
+
+    #[test]
+    fn test_commit_filter_matches_prefix() {
+        assert_eq!(
+            commit_filter("0123ab"),
+            serde_json::json!(["commit", "Glob", "0123ab*"])
+        );
+    }
+
+    #[test]
+    fn test_path_prefix_filter_matches_subtree() {
+        assert_eq!(
+            path_prefix_filter("/repo/services/billing/"),
+            serde_json::json!(["path", "Glob", "/repo/services/billing/**"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_stream_uses_configured_batch_size() {
+        let options = WriteOptions::from_settings(Some(&crate::config::Settings {
+            write_batch_size: Some(7),
+            write_concurrency: Some(2),
+            ..Default::default()
+        }));
+        assert_eq!(options.batch_size, 7);
+        assert_eq!(options.concurrency, 2);
+
+        let batch_sizes = Arc::new(Mutex::new(Vec::new()));
+        let total = write_stream(
+            futures::stream::iter((0..20u128).map(chunk)),
+            None,
+            options.batch_size,
+            options.concurrency,
+            |batch, _deletes| {
+                let batch_sizes = batch_sizes.clone();
+                async move {
+                    batch_sizes.lock().unwrap().push(batch.len());
+                    Ok(batch.len())
+                }
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(total, 20);
+        let mut batch_sizes = batch_sizes.lock().unwrap().clone();
+        batch_sizes.sort();
+        assert_eq!(batch_sizes, vec![6, 7, 7]);
+    }
+
+    #[test]
+    fn test_write_options_ignore_out_of_range_settings() {
+        let options = WriteOptions::from_settings(Some(&crate::config::Settings {
+            write_batch_size: Some(0),
+            write_concurrency: Some(1000),
+            ..Default::default()
+        }));
+        assert_eq!(options, WriteOptions::default());
+    }
+
+    #[tokio::test]
+    async fn test_write_stream_propagates_errors() {
+        let result = write_stream(
+            futures::stream::iter((0..10u128).map(chunk)),
+            None,
+            3,
+            2,
+            |_batch, _deletes| async { Err(TurbopufferError::ApiError("boom".to_string())) },
+        )
+        .await;
+
+        assert!(matches!(result, Err(TurbopufferError::ApiError(_))));
+    }
+
+    /// Memory and throughput of a large sync's embed -> write pipeline, with embedding and
+    /// writes both taking time like the real APIs. Memory is measured as the embedded
+    /// chunks held between the two, which bounded backpressure keeps to a few batches.
+    /// Run with `cargo test --release bench_write_stream -- --ignored --nocapture`.
+    #[tokio::test]
+    #[ignore = "benchmark"]
+    async fn bench_write_stream_large_sync() {
+        const CHUNKS: u128 = 200_000;
+        const CONTENT_BYTES: usize = 2048;
+        let (batch_size, concurrency) = (1000, 4);
+        let held = Arc::new(AtomicUsize::new(0));
+        let max_held = Arc::new(AtomicUsize::new(0));
+
+        // Embedding yields chunks in batches of 128, each after a short delay
+        let chunks = futures::stream::iter(0..CHUNKS).then({
+            let (held, max_held) = (held.clone(), max_held.clone());
+            move |id| {
+                let (held, max_held) = (held.clone(), max_held.clone());
+                async move {
+                    if id % 128 == 0 {
+                        tokio::time::sleep(std::time::Duration::from_micros(200)).await;
+                    }
+                    let now = held.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_held.fetch_max(now, Ordering::SeqCst);
+                    Chunk {
+                        content: Some("x".repeat(CONTENT_BYTES)),
+                        vector: Some(vec![0.0; 1024]),
+                        ..chunk(id)
+                    }
+                }
+            }
+        });
+
+        let instant = Instant::now();
+        let total = write_stream(chunks, None, batch_size, concurrency, |batch, _deletes| {
+            let held = held.clone();
+            async move {
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                held.fetch_sub(batch.len(), Ordering::SeqCst);
+                Ok(batch.len())
+            }
+        })
+        .await
+        .unwrap();
+        let elapsed = instant.elapsed();
+
+        let max_held = max_held.load(Ordering::SeqCst);
+        let chunk_bytes = CONTENT_BYTES + 1024 * std::mem::size_of::<f32>();
+        println!(
+            "wrote {} chunks in {:.2?} ({:.0} chunks/s), at most {} chunks (~{} MiB) held",
+            total,
+            elapsed,
+            total as f64 / elapsed.as_secs_f64(),
+            max_held,
+            max_held * chunk_bytes / (1024 * 1024)
+        );
+        assert_eq!(total as u128, CHUNKS);
+        assert!(max_held <= (concurrency + 1) * batch_size, "{max_held}");
+    }
+}
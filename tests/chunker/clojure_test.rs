@@ -0,0 +1,61 @@
+use turbogrep::chunker;
+
+#[test]
+fn test_clojure_chunking() {
+    let clojure_code = r#"
+(ns billing.invoice
+  (:require [clojure.string :as str]))
+
+(def default-currency "USD")
+
+;; Totals are computed in cents to avoid rounding errors
+(defn total
+  "Sum the line items of an invoice."
+  [invoice]
+  (reduce + (map :amount (:items invoice))))
+
+(defn- format-cents [cents]
+  (str (quot cents 100) "." (format "%02d" (rem cents 100))))
+
+(defmulti render :format)
+
+(defmethod render :text [invoice]
+  (str "Total: " (format-cents (total invoice))))
+"#;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("invoice.clj");
+    std::fs::write(&file_path, clojure_code).unwrap();
+
+    let result = chunker::chunk_file(&file_path).unwrap();
+    let chunks = result.chunks;
+
+    let chunk_containing = |needle: &str| {
+        chunks
+            .iter()
+            .find(|chunk| chunk.content.as_ref().is_some_and(|c| c.contains(needle)))
+    };
+
+    // defn forms carry their docstring and leading `;;` comment
+    let total = chunk_containing("(defn total").expect("Should have extracted defn total");
+    let total_content = total.content.as_ref().unwrap();
+    assert!(total_content.contains("Sum the line items of an invoice."));
+    assert!(total_content.contains(";; Totals are computed in cents"));
+
+    assert!(
+        chunk_containing("(defn- format-cents").is_some(),
+        "Should have extracted private defn"
+    );
+    assert!(
+        chunk_containing("(def default-currency").is_some(),
+        "Should have extracted def"
+    );
+    assert!(
+        chunk_containing("(defmethod render :text").is_some(),
+        "Should have extracted defmethod"
+    );
+
+    // Other top-level forms aren't definitions
+    assert!(chunk_containing("(ns billing.invoice").is_none());
+    assert!(chunk_containing("(defmulti render").is_none());
+}
@@ -0,0 +1,111 @@
+use turbogrep::chunker;
+
+#[test]
+fn test_csharp_chunking() {
+    let csharp_code = r#"
+using System.Collections.Generic;
+using System.Threading.Tasks;
+
+namespace Inventory
+{
+    /// <summary>
+    /// Keeps track of the items in a warehouse
+    /// </summary>
+    public class ItemRepository
+    {
+        private readonly List<Item> items;
+
+        /// <summary>
+        /// Creates an empty repository
+        /// </summary>
+        public ItemRepository()
+        {
+            items = new List<Item>();
+        }
+
+        /// <summary>
+        /// Counts the items currently in stock
+        /// </summary>
+        public async Task<int> GetCountAsync()
+        {
+            await Task.Yield();
+            return items.Count;
+        }
+
+        /// <summary>
+        /// Adds an item to the repository
+        /// </summary>
+        public void Add(Item item)
+        {
+            items.Add(item);
+        }
+
+        private static bool IsValid(Item item)
+        {
+            return item != null && item.Quantity >= 0;
+        }
+    }
+}
+"#;
+
+    // Create a temporary file with the C# code
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("ItemRepository.cs");
+    std::fs::write(&file_path, csharp_code).unwrap();
+
+    // Test chunking
+    let result = chunker::chunk_file(&file_path).unwrap();
+    let chunks = result.chunks;
+
+    // Verify we extracted functions
+    assert!(!chunks.is_empty(), "Should extract at least one function");
+
+    // Check that we have the expected methods, constructor and class
+    let expected_functions = [
+        "public class ItemRepository",
+        "public ItemRepository(",
+        "public async Task<int> GetCountAsync(",
+        "public void Add(",
+        "private static bool IsValid(",
+    ];
+
+    for expected_func in expected_functions {
+        let found = chunks.iter().any(|chunk| {
+            chunk
+                .content
+                .as_ref()
+                .is_some_and(|content| content.contains(expected_func))
+        });
+        assert!(found, "Should have extracted function: {}", expected_func);
+    }
+
+    // XML doc comments preceding a method are part of its chunk
+    let count_chunk = chunks
+        .iter()
+        .find(|chunk| {
+            chunk.content.as_ref().is_some_and(|content| {
+                content.contains("GetCountAsync(") && !content.contains("class")
+            })
+        })
+        .expect("GetCountAsync should have its own chunk");
+    assert!(
+        count_chunk
+            .content
+            .as_ref()
+            .unwrap()
+            .starts_with("/// <summary>\n        /// Counts the items currently in stock"),
+        "Doc comment should be included: {:?}",
+        count_chunk.content
+    );
+
+    // Verify chunk properties
+    for chunk in &chunks {
+        assert!(chunk.content.is_some(), "Chunk should have content");
+        assert!(!chunk.path.is_empty(), "Chunk should have a path");
+        assert!(chunk.start_line > 0, "Chunk should have start line");
+        assert!(
+            chunk.end_line >= chunk.start_line,
+            "End line should be >= start line"
+        );
+    }
+}
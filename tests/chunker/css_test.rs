@@ -0,0 +1,61 @@
+use turbogrep::chunker;
+
+#[test]
+fn test_css_chunking() {
+    let scss_code = r#"
+/* Responsive grid used by every dashboard page */
+@mixin responsive-grid($columns) {
+  display: grid;
+  grid-template-columns: repeat($columns, 1fr);
+}
+
+.card,
+.panel {
+  border-radius: 4px;
+  padding: 1rem;
+}
+
+/* Stack the sidebar under the content on phones */
+@media (max-width: 600px) {
+  .sidebar {
+    width: 100%;
+  }
+}
+"#;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("layout.scss");
+    std::fs::write(&file_path, scss_code).unwrap();
+
+    let result = chunker::chunk_file(&file_path).unwrap();
+    let chunks = result.chunks;
+
+    let chunk_starting = |needle: &str| {
+        chunks.iter().find(|chunk| {
+            chunk
+                .content
+                .as_ref()
+                .is_some_and(|c| c.lines().any(|line| line.starts_with(needle)))
+        })
+    };
+
+    // Each top-level block is its own chunk, with its leading comment
+    let mixin = chunk_starting("@mixin responsive-grid").expect("Should have extracted the mixin");
+    assert!(
+        mixin
+            .content
+            .as_ref()
+            .unwrap()
+            .contains("Responsive grid used by every dashboard page")
+    );
+    assert!(
+        chunk_starting(".card,").is_some(),
+        "Should have extracted the rule set"
+    );
+    let media = chunk_starting("@media (max-width: 600px)").expect("Should have extracted @media");
+    assert!(media.content.as_ref().unwrap().contains(".sidebar"));
+
+    // Nested rules stay inside their parent block
+    assert_eq!(chunks.len(), 3);
+    assert_eq!(mixin.start_line, 3);
+}
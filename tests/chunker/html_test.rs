@@ -0,0 +1,68 @@
+use turbogrep::chunker;
+
+#[test]
+fn test_html_chunking() {
+    let html_code = r#"<!DOCTYPE html>
+<html>
+  <body>
+    <!-- Site navigation -->
+    <nav>
+      <a href="/">Home</a>
+    </nav>
+
+    <section id="checkout">
+      <h2>Checkout</h2>
+      <form action="/orders" method="post">
+        <input name="card-number">
+        <button type="submit">Pay</button>
+      </form>
+    </section>
+
+    <template id="cart-row">
+      <tr><td class="item"></td></tr>
+    </template>
+
+    <script>
+      function updateTotal(cart) {
+        return cart.reduce((sum, item) => sum + item.price, 0);
+      }
+    </script>
+  </body>
+</html>
+"#;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("checkout.html");
+    std::fs::write(&file_path, html_code).unwrap();
+
+    let result = chunker::chunk_file(&file_path).unwrap();
+    let chunks = result.chunks;
+
+    // Chunks start on their element's line (preceding comments are only in the content)
+    let chunk_at = |needle: &str| {
+        let line = html_code.lines().position(|l| l.contains(needle)).unwrap() as u32 + 1;
+        chunks.iter().find(|chunk| chunk.start_line == line)
+    };
+
+    let nav = chunk_at("<nav>").expect("Should have extracted <nav>");
+    assert!(nav.content.as_ref().unwrap().contains("Site navigation"));
+
+    let section = chunk_at("<section id=\"checkout\">").expect("Should have extracted <section>");
+    assert!(section.content.as_ref().unwrap().contains("<form"));
+    // The form inside the section is a chunk of its own
+    assert!(chunk_at("<form action=\"/orders\"").is_some());
+
+    assert!(chunk_at("<template id=\"cart-row\">").is_some());
+    let script = chunk_at("<script>").expect("Should have extracted <script>");
+    assert!(
+        script
+            .content
+            .as_ref()
+            .unwrap()
+            .contains("function updateTotal")
+    );
+
+    // Plain markup outside those elements isn't chunked on its own
+    assert!(chunk_at("<html>").is_none());
+    assert!(chunk_at("<body>").is_none());
+}
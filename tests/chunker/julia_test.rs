@@ -0,0 +1,67 @@
+use turbogrep::chunker;
+
+#[test]
+fn test_julia_chunking() {
+    let julia_code = r#"module Geometry
+
+export Point, distance, norm2
+
+# A point in the plane
+struct Point
+    x::Float64
+    y::Float64
+end
+
+"""
+    distance(a, b)
+
+Euclidean distance between two points.
+"""
+function distance(a::Point, b::Point)
+    dx = a.x - b.x
+    dy = a.y - b.y
+    return sqrt(dx^2 + dy^2)
+end
+
+# Squared length, short form
+norm2(p::Point) = p.x^2 + p.y^2
+
+end
+"#;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("geometry.jl");
+    std::fs::write(&file_path, julia_code).unwrap();
+
+    let result = chunker::chunk_file(&file_path).unwrap();
+    let chunks = result.chunks;
+
+    let chunk_at = |needle: &str| {
+        let line = julia_code.lines().position(|l| l.contains(needle)).unwrap() as u32 + 1;
+        chunks.iter().find(|chunk| chunk.start_line == line)
+    };
+
+    assert!(
+        chunk_at("module Geometry").is_some(),
+        "Should have extracted the module"
+    );
+
+    let point = chunk_at("struct Point").expect("Should have extracted struct Point");
+    assert!(
+        point
+            .content
+            .as_ref()
+            .unwrap()
+            .contains("A point in the plane")
+    );
+
+    let distance = chunk_at("function distance").expect("Should have extracted long-form function");
+    let content = distance.content.as_ref().unwrap();
+    assert!(content.contains("Euclidean distance between two points"));
+    assert!(content.contains("return sqrt(dx^2 + dy^2)"));
+
+    let norm2 = chunk_at("norm2(p::Point) =").expect("Should have extracted short-form function");
+    let content = norm2.content.as_ref().unwrap();
+    assert!(content.contains("Squared length, short form"));
+    assert!(content.contains("p.x^2 + p.y^2"));
+}
@@ -4,5 +4,13 @@ pub mod python_test;
 pub mod javascript_test;
 pub mod go_test;
 pub mod java_test;
+pub mod csharp_test;
+pub mod php_test;
 pub mod c_test;
-pub mod cpp_test; 
\ No newline at end of file
+pub mod cpp_test; 
+pub mod clojure_test;
+pub mod objc_test;
+pub mod css_test;
+pub mod html_test;
+pub mod julia_test;
+pub mod vue_test;
@@ -0,0 +1,106 @@
+use turbogrep::chunker;
+
+#[test]
+fn test_objc_chunking() {
+    let objc_code = r#"
+#import <Foundation/Foundation.h>
+
+@interface Counter : NSObject
+@property (nonatomic) NSInteger count;
+- (void)increment;
+@end
+
+@implementation Counter
+
+// Bump the count by one
+- (void)increment {
+    self.count += 1;
+}
+
+/// Reset the count back to zero
+- (void)reset {
+    self.count = 0;
+}
+
+@end
+"#;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("Counter.m");
+    std::fs::write(&file_path, objc_code).unwrap();
+
+    let result = chunker::chunk_file(&file_path).unwrap();
+    let chunks = result.chunks;
+
+    let chunk_containing = |needle: &str| {
+        chunks
+            .iter()
+            .find(|chunk| chunk.content.as_ref().is_some_and(|c| c.contains(needle)))
+    };
+
+    assert!(
+        chunk_containing("@interface Counter").is_some(),
+        "Should have extracted the @interface declaration"
+    );
+
+    // Methods carry their leading comments
+    let increment = chunks
+        .iter()
+        .find(|chunk| {
+            chunk.content.as_ref().is_some_and(|c| {
+                c.contains("- (void)increment {") && !c.contains("@implementation")
+            })
+        })
+        .expect("Should have extracted the increment method");
+    assert!(
+        increment
+            .content
+            .as_ref()
+            .unwrap()
+            .contains("// Bump the count")
+    );
+    let reset = chunk_containing("- (void)reset {").expect("Should have extracted reset");
+    assert!(reset.content.as_ref().unwrap().contains("Reset the count"));
+}
+
+#[test]
+fn test_matlab_m_file_is_not_objc() {
+    let matlab_code = r#"
+function total = add_all(values)
+    % Sum every value
+    total = sum(values);
+end
+"#;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("add_all.m");
+    std::fs::write(&file_path, matlab_code).unwrap();
+
+    let result = chunker::chunk_file(&file_path).unwrap();
+    assert!(result.chunks.is_empty());
+}
+
+#[test]
+fn test_c_header_is_not_objc() {
+    let c_header = r#"
+#include <stddef.h>
+
+size_t buffer_len(const char *buffer) {
+    return buffer ? 1 : 0;
+}
+"#;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("buffer.h");
+    std::fs::write(&file_path, c_header).unwrap();
+
+    let result = chunker::chunk_file(&file_path).unwrap();
+    assert_eq!(result.chunks.len(), 1);
+    assert!(
+        result.chunks[0]
+            .content
+            .as_ref()
+            .unwrap()
+            .contains("size_t buffer_len")
+    );
+}
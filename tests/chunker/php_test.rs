@@ -0,0 +1,84 @@
+use turbogrep::chunker;
+
+#[test]
+fn test_php_chunking() {
+    let php_code = r#"<?php
+
+namespace App\Services;
+
+class InvoiceService
+{
+    /**
+     * Sum the line items of an invoice
+     */
+    public function total(array $items): float
+    {
+        return array_sum(array_column($items, 'amount'));
+    }
+
+    private function format(float $amount): string
+    {
+        return number_format($amount, 2);
+    }
+}
+
+// Shared by the console commands
+function invoice_path(string $id): string
+{
+    return storage_path("invoices/{$id}.pdf");
+}
+"#;
+
+    // Create a temporary file with the PHP code
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("InvoiceService.php");
+    std::fs::write(&file_path, php_code).unwrap();
+
+    // Test chunking
+    let result = chunker::chunk_file(&file_path).unwrap();
+    let chunks = result.chunks;
+
+    // Two methods and the top-level function, each in its own chunk
+    assert_eq!(chunks.len(), 3, "Unexpected chunks: {:?}", chunks);
+
+    let expected_functions = [
+        "public function total(",
+        "private function format(",
+        "function invoice_path(",
+    ];
+
+    for (chunk, expected_func) in chunks.iter().zip(expected_functions) {
+        assert!(
+            chunk
+                .content
+                .as_ref()
+                .is_some_and(|content| content.contains(expected_func)),
+            "Should have extracted function: {}",
+            expected_func
+        );
+    }
+
+    // PHPDoc blocks and line comments preceding a function are part of its chunk
+    let total = chunks[0].content.as_ref().unwrap();
+    assert!(
+        total.starts_with("/**\n     * Sum the line items of an invoice"),
+        "Doc comment should be included: {:?}",
+        total
+    );
+    let invoice_path = chunks[2].content.as_ref().unwrap();
+    assert!(
+        invoice_path.starts_with("// Shared by the console commands"),
+        "Comment should be included: {:?}",
+        invoice_path
+    );
+
+    // Verify chunk properties
+    for chunk in &chunks {
+        assert!(!chunk.path.is_empty(), "Chunk should have a path");
+        assert!(chunk.start_line > 0, "Chunk should have start line");
+        assert!(
+            chunk.end_line >= chunk.start_line,
+            "End line should be >= start line"
+        );
+    }
+}
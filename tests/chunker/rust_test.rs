@@ -12,6 +12,17 @@ pub struct User {
     pub email: String,
 }
 
+/// Whether a user's account can sign in
+pub enum Status {
+    Active,
+    Inactive,
+}
+
+/// Something that can be greeted by name
+pub trait Greet {
+    fn greeting(&self) -> String;
+}
+
 impl User {
     /// Creates a new user with the given parameters
     pub fn new(name: String, age: u32, email: String) -> Self {
@@ -121,6 +132,22 @@ mod tests {
         assert!(found, "Should have extracted struct/impl: {}", expected_struct);
     }
 
+    // Enums and traits are chunks of their own, with their doc comments
+    let expected_items = [
+        "/// Whether a user's account can sign in\npub enum Status {\n    Active,\n    Inactive,\n}",
+        "/// Something that can be greeted by name\npub trait Greet {",
+    ];
+
+    for expected_item in expected_items {
+        let found = chunks.iter().any(|chunk| {
+            chunk
+                .content
+                .as_ref()
+                .is_some_and(|content| content.starts_with(expected_item))
+        });
+        assert!(found, "Should have extracted enum/trait: {}", expected_item);
+    }
+
     // Verify chunk properties
     for chunk in &chunks {
         assert!(chunk.content.is_some(), "Chunk should have content");
@@ -0,0 +1,104 @@
+use turbogrep::chunker;
+
+#[test]
+fn test_vue_component_chunking() {
+    let vue_code = r#"<template>
+  <div class="counter">
+    <template v-if="count > 0">
+      <span>{{ count }}</span>
+    </template>
+    <button @click="increment">Add</button>
+  </div>
+</template>
+
+<script lang="ts">
+// Adds one to the counter
+function increment(count: number): number {
+  return count + 1;
+}
+
+function reset(): number {
+  return 0;
+}
+</script>
+
+<style scoped>
+.counter { display: flex; }
+</style>
+"#;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("Counter.vue");
+    std::fs::write(&file_path, vue_code).unwrap();
+
+    let result = chunker::chunk_file(&file_path).unwrap();
+    let chunks = result.chunks;
+    assert_eq!(chunks.len(), 4, "template, two functions and style");
+
+    let line_of =
+        |needle: &str| vue_code.lines().position(|l| l.contains(needle)).unwrap() as u32 + 1;
+    let chunk_at = |needle: &str| {
+        chunks
+            .iter()
+            .find(|chunk| chunk.start_line == line_of(needle))
+            .unwrap_or_else(|| panic!("no chunk starting at {needle}"))
+    };
+
+    // The template is one block, nested <template> included
+    let template = chunk_at("<template>");
+    assert_eq!(template.end_line, line_of("</template>") + 3);
+    let content = template.content.as_ref().unwrap();
+    assert!(content.starts_with("<template>"));
+    assert!(content.contains("<template v-if"));
+    assert!(content.ends_with("</template>"));
+    assert!(!content.contains("function increment"));
+
+    // Script functions are chunked separately, with lines and bytes of the whole file
+    let increment = chunk_at("function increment");
+    let content = increment.content.as_ref().unwrap();
+    assert!(content.contains("Adds one to the counter"));
+    assert!(!content.contains("function reset"));
+    assert_eq!(increment.end_line, line_of("return count + 1") + 1);
+    let (start, end) = (increment.start_byte.unwrap(), increment.end_byte.unwrap());
+    assert!(vue_code[start..end].starts_with("function increment"));
+    assert!(vue_code[start..end].ends_with('}'));
+
+    let reset = chunk_at("function reset");
+    assert_ne!(reset.id, increment.id);
+
+    let style = chunk_at("<style scoped>");
+    assert!(style.content.as_ref().unwrap().contains(".counter"));
+}
+
+#[test]
+fn test_svelte_markup_outside_sections_is_a_block() {
+    let svelte_code = r#"<script>
+  function greet(name) {
+    return `Hello ${name}`;
+  }
+</script>
+
+<h1>{greet("world")}</h1>
+<p>Welcome</p>
+"#;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("Greeting.svelte");
+    std::fs::write(&file_path, svelte_code).unwrap();
+
+    let chunks = chunker::chunk_file(&file_path).unwrap().chunks;
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].start_line, 2);
+    assert!(
+        chunks[0]
+            .content
+            .as_ref()
+            .unwrap()
+            .contains("function greet")
+    );
+    assert_eq!((chunks[1].start_line, chunks[1].end_line), (7, 8));
+    assert_eq!(
+        chunks[1].content.as_deref(),
+        Some("<h1>{greet(\"world\")}</h1>\n<p>Welcome</p>")
+    );
+}
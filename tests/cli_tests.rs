@@ -0,0 +1,118 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+// End-to-end tests that run the `tg` binary. They only exercise paths that don't
+// touch the network: settings are pre-seeded so no region ping happens.
+
+fn run_tg(args: &[&str]) -> Output {
+    let work_dir = tempfile::tempdir().unwrap();
+    std::fs::write(work_dir.path().join("lib.rs"), "fn hello() {}\n").unwrap();
+    run_tg_in(work_dir.path(), args)
+}
+
+fn run_tg_in(work_dir: &Path, args: &[&str]) -> Output {
+    let config_home = tempfile::tempdir().unwrap();
+    let config_dir = config_home.path().join("turbogrep");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config.json"),
+        r#"{"turbopuffer_region": "gcp-us-east4", "embedding_provider": "voyage"}"#,
+    )
+    .unwrap();
+
+    Command::new(env!("CARGO_BIN_EXE_tg"))
+        .args(args)
+        .current_dir(work_dir)
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .env_remove("TURBOGREP_VERBOSE")
+        .env_remove("TG_VERBOSE")
+        .env_remove("TURBOGREP_QUIET")
+        .env_remove("TG_QUIET")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_ambiguous_path_warns_on_stderr() {
+    let output = run_tg(&["--chunk-only", "./does-not-exist"]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("looks like a directory path"),
+        "expected path warning, got: {stderr}"
+    );
+}
+
+#[test]
+fn test_quiet_suppresses_warnings() {
+    let output = run_tg(&["--quiet", "--chunk-only", "./does-not-exist"]);
+
+    assert!(output.status.success());
+    assert!(
+        output.stderr.is_empty(),
+        "expected empty stderr, got: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_no_supported_files_warns() {
+    let work_dir = tempfile::tempdir().unwrap();
+    std::fs::write(work_dir.path().join("notes.txt"), "meeting notes\n").unwrap();
+    std::fs::write(work_dir.path().join("todo.txt"), "- ship it\n").unwrap();
+
+    let output = run_tg_in(work_dir.path(), &["--chunk-only", "--flat"]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("no supported source files found"),
+        "expected no supported files warning, got: {stderr}"
+    );
+
+    // With --require-language it's an error instead
+    let output = run_tg_in(
+        work_dir.path(),
+        &["--chunk-only", "--flat", "--require-language"],
+    );
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_walk_skips_turbogrep_cache_dir() {
+    // A project containing turbogrep's cache, like `tg ~/` with the default cache dir
+    let work_dir = tempfile::tempdir().unwrap();
+    let cache_home = work_dir.path().join(".cache");
+    std::fs::create_dir_all(cache_home.join("turbogrep")).unwrap();
+    std::fs::write(
+        cache_home.join("turbogrep/helper.py"),
+        "def helper():\n    pass\n",
+    )
+    .unwrap();
+    let config_home = tempfile::tempdir().unwrap();
+    let config_dir = config_home.path().join("turbogrep");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config.json"),
+        r#"{"turbopuffer_region": "gcp-us-east4", "embedding_provider": "voyage"}"#,
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tg"))
+        .args(["--chunk-only", "--flat"])
+        .current_dir(work_dir.path())
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .env("XDG_CACHE_HOME", &cache_home)
+        .env_remove("TURBOGREP_QUIET")
+        .env_remove("TG_QUIET")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("no supported source files found"),
+        "expected the cached file to be skipped, got: {stderr}"
+    );
+}
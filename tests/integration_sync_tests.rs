@@ -1,6 +1,7 @@
 use turbogrep::chunker::Chunk;
+use turbogrep::embeddings::VoyageEmbedding;
 use turbogrep::sync;
-use turbogrep::turbopuffer;
+use turbogrep::turbopuffer::{self, Turbopuffer, WriteOptions};
 
 // Integration tests using both tpuf_chunk_diff and tpuf_apply_diff together
 // These tests verify that the functions work correctly when used in sequence
@@ -24,7 +25,7 @@ async fn test_diff_apply_roundtrip_basic() {
 
     // Upload initial chunks to server
     let chunk_stream = futures::stream::iter(initial_server_chunks.clone());
-    match turbopuffer::write_chunks(namespace, chunk_stream, None).await {
+    match turbopuffer::write_chunks(namespace, chunk_stream, None, &WriteOptions::default()).await {
         Ok(_) => {}
         Err(e) => {
             eprintln!("Failed to upload initial chunks: {}", e);
@@ -54,8 +55,17 @@ async fn test_diff_apply_roundtrip_basic() {
     assert!(to_delete.iter().any(|c| c.path == "file1.rs"));
 
     // Step 2: Apply the diff
-    let changed = sync::tpuf_apply_diff(namespace, to_upload, to_delete, false, None)
-        .await;
+    let changed = sync::tpuf_apply_diff(
+        &Turbopuffer,
+        namespace,
+        to_upload,
+        to_delete,
+        false,
+        VoyageEmbedding::new(),
+        &WriteOptions::default(),
+        false,
+    )
+    .await;
 
     match changed {
         Ok(changed) => {
@@ -127,7 +137,7 @@ async fn test_diff_apply_roundtrip_complete_replacement() {
 
     // Upload initial chunks
     let chunk_stream = futures::stream::iter(initial_server_chunks.clone());
-    turbopuffer::write_chunks(namespace, chunk_stream, None)
+    turbopuffer::write_chunks(namespace, chunk_stream, None, &WriteOptions::default())
         .await
         .unwrap();
 
@@ -147,8 +157,17 @@ async fn test_diff_apply_roundtrip_complete_replacement() {
     assert_eq!(to_delete.len(), 3); // old1.rs, old2.py, old3.go
 
     // Step 2: Apply diff
-    let changed = sync::tpuf_apply_diff(namespace, to_upload, to_delete, false, None)
-        .await;
+    let changed = sync::tpuf_apply_diff(
+        &Turbopuffer,
+        namespace,
+        to_upload,
+        to_delete,
+        false,
+        VoyageEmbedding::new(),
+        &WriteOptions::default(),
+        false,
+    )
+    .await;
 
     match changed {
         Ok(changed) => {
@@ -200,7 +219,7 @@ async fn test_diff_apply_idempotent() {
     let initial_chunks = vec![create_test_chunk("file1.rs", 1, 10, 100, 200)];
 
     let chunk_stream = futures::stream::iter(initial_chunks.clone());
-    turbopuffer::write_chunks(namespace, chunk_stream, None)
+    turbopuffer::write_chunks(namespace, chunk_stream, None, &WriteOptions::default())
         .await
         .unwrap();
 
@@ -218,9 +237,18 @@ async fn test_diff_apply_idempotent() {
     assert_eq!(to_delete.len(), 0);
 
     // Step 2: Apply empty diff
-    let changed = sync::tpuf_apply_diff(namespace, to_upload, to_delete, false, None)
-        .await
-        .unwrap();
+    let changed = sync::tpuf_apply_diff(
+        &Turbopuffer,
+        namespace,
+        to_upload,
+        to_delete,
+        false,
+        VoyageEmbedding::new(),
+        &WriteOptions::default(),
+        false,
+    )
+    .await
+    .unwrap();
 
     assert!(!changed); // Should indicate no changes
 
@@ -263,9 +291,18 @@ async fn test_diff_apply_progressive_sync() {
     assert_eq!(to_upload_r1.len(), 2);
     assert_eq!(to_delete_r1.len(), 0);
 
-    let changed_r1 = sync::tpuf_apply_diff(namespace, to_upload_r1, to_delete_r1, false, None)
-        .await
-        .unwrap();
+    let changed_r1 = sync::tpuf_apply_diff(
+        &Turbopuffer,
+        namespace,
+        to_upload_r1,
+        to_delete_r1,
+        false,
+        VoyageEmbedding::new(),
+        &WriteOptions::default(),
+        false,
+    )
+    .await
+    .unwrap();
     assert!(changed_r1);
 
     // Small delay to avoid API rate limiting
@@ -292,9 +329,18 @@ async fn test_diff_apply_progressive_sync() {
     assert_eq!(to_upload_r2.len(), 1); // file3.go (new)
     assert_eq!(to_delete_r2.len(), 1); // file1.rs (stale)
 
-    let changed_r2 = sync::tpuf_apply_diff(namespace, to_upload_r2, to_delete_r2, false, None)
-        .await
-        .unwrap();
+    let changed_r2 = sync::tpuf_apply_diff(
+        &Turbopuffer,
+        namespace,
+        to_upload_r2,
+        to_delete_r2,
+        false,
+        VoyageEmbedding::new(),
+        &WriteOptions::default(),
+        false,
+    )
+    .await
+    .unwrap();
     assert!(changed_r2);
 
     // Small delay to avoid API rate limiting
@@ -322,9 +368,18 @@ async fn test_diff_apply_progressive_sync() {
     assert!(to_upload_r3.len() >= 1); // At least file1.rs
     assert!(to_delete_r3.len() >= 1); // At least file2.py
 
-    let changed_r3 = sync::tpuf_apply_diff(namespace, to_upload_r3, to_delete_r3, false, None)
-        .await
-        .unwrap();
+    let changed_r3 = sync::tpuf_apply_diff(
+        &Turbopuffer,
+        namespace,
+        to_upload_r3,
+        to_delete_r3,
+        false,
+        VoyageEmbedding::new(),
+        &WriteOptions::default(),
+        false,
+    )
+    .await
+    .unwrap();
     assert!(changed_r3);
 
     // Final verification: should match local state
@@ -356,7 +411,7 @@ async fn test_diff_apply_error_recovery() {
     let initial_chunks = vec![create_test_chunk("file1.rs", 1, 10, 100, 200)];
 
     let chunk_stream = futures::stream::iter(initial_chunks);
-    turbopuffer::write_chunks(namespace, chunk_stream, None)
+    turbopuffer::write_chunks(namespace, chunk_stream, None, &WriteOptions::default())
         .await
         .unwrap();
 
@@ -377,7 +432,17 @@ async fn test_diff_apply_error_recovery() {
     assert_eq!(to_upload[0].path, "file2.py");
 
     // Apply diff (should succeed)
-    let result = sync::tpuf_apply_diff(namespace, to_upload, to_delete, false, None).await;
+    let result = sync::tpuf_apply_diff(
+        &Turbopuffer,
+        namespace,
+        to_upload,
+        to_delete,
+        false,
+        VoyageEmbedding::new(),
+        &WriteOptions::default(),
+        false,
+    )
+    .await;
 
     match result {
         Ok(changed) => {
@@ -424,7 +489,7 @@ async fn test_diff_apply_consistency_check() {
     ];
 
     let chunk_stream = futures::stream::iter(initial_server_chunks);
-    turbopuffer::write_chunks(namespace, chunk_stream, None)
+    turbopuffer::write_chunks(namespace, chunk_stream, None, &WriteOptions::default())
         .await
         .unwrap();
 
@@ -457,9 +522,18 @@ async fn test_diff_apply_consistency_check() {
     assert!(delete_paths.contains(&&"stale.js".to_string()));
 
     // Step 2: Apply the diff
-    let changed = sync::tpuf_apply_diff(namespace, to_upload, to_delete, false, None)
-        .await
-        .unwrap();
+    let changed = sync::tpuf_apply_diff(
+        &Turbopuffer,
+        namespace,
+        to_upload,
+        to_delete,
+        false,
+        VoyageEmbedding::new(),
+        &WriteOptions::default(),
+        false,
+    )
+    .await
+    .unwrap();
 
     assert!(changed);
 
@@ -502,7 +576,7 @@ async fn test_diff_apply_cross_validation() {
 
     // Upload initial state
     let chunk_stream = futures::stream::iter(initial_server_chunks.clone());
-    turbopuffer::write_chunks(namespace, chunk_stream, None)
+    turbopuffer::write_chunks(namespace, chunk_stream, None, &WriteOptions::default())
         .await
         .unwrap();
 
@@ -529,8 +603,17 @@ async fn test_diff_apply_cross_validation() {
     assert!(delete_paths_1.contains(&&"to_delete.go".to_string()));
 
     // Step 2: Apply the diff
-    let changed_1 = sync::tpuf_apply_diff(namespace, to_upload_1, to_delete_1, false, None)
-        .await;
+    let changed_1 = sync::tpuf_apply_diff(
+        &Turbopuffer,
+        namespace,
+        to_upload_1,
+        to_delete_1,
+        false,
+        VoyageEmbedding::new(),
+        &WriteOptions::default(),
+        false,
+    )
+    .await;
 
     match changed_1 {
         Ok(changed_1) => {
@@ -562,8 +645,17 @@ async fn test_diff_apply_cross_validation() {
     assert_eq!(to_delete_2.len(), 0); // No more deletions needed
 
     // Step 4: Apply second diff
-    let changed_2 = sync::tpuf_apply_diff(namespace, to_upload_2, to_delete_2, false, None)
-        .await;
+    let changed_2 = sync::tpuf_apply_diff(
+        &Turbopuffer,
+        namespace,
+        to_upload_2,
+        to_delete_2,
+        false,
+        VoyageEmbedding::new(),
+        &WriteOptions::default(),
+        false,
+    )
+    .await;
 
     match changed_2 {
         Ok(changed_2) => {
@@ -613,6 +705,111 @@ async fn test_diff_apply_cross_validation() {
     let _ = turbopuffer::delete_namespace(namespace).await;
 }
 
+#[tokio::test]
+async fn test_commit_roundtrip_and_filtered_query() {
+    // Chunks tagged with a commit can be found with a commit filter, and patching moves them
+    let namespace = "test_commit_roundtrip_and_filtered_query";
+
+    let _ = turbopuffer::delete_namespace(namespace).await;
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+    let mut tagged = create_test_chunk("tagged.rs", 1, 10, 100, 200);
+    tagged.commit = Some("0123456789abcdef0123456789abcdef01234567".to_string());
+    let untagged = create_test_chunk("untagged.rs", 1, 10, 300, 400);
+
+    let chunk_stream = futures::stream::iter(vec![tagged.clone(), untagged]);
+    if let Err(e) =
+        turbopuffer::write_chunks(namespace, chunk_stream, None, &WriteOptions::default()).await
+    {
+        eprintln!("Test skipped: Could not upload chunks ({})", e);
+        let _ = turbopuffer::delete_namespace(namespace).await;
+        return;
+    }
+
+    let at_commit = turbopuffer::query_chunks(
+        namespace,
+        serde_json::json!(["id", "asc"]),
+        10,
+        Some(turbopuffer::commit_filter("0123456")),
+    )
+    .await
+    .unwrap();
+    assert_eq!(at_commit.len(), 1);
+    assert_eq!(at_commit[0].path, "tagged.rs");
+    assert_eq!(at_commit[0].commit, tagged.commit);
+
+    // Moving the chunk to a new commit must not require re-uploading it
+    let stale = sync::stale_commit_ids(
+        &[Chunk {
+            commit: Some("fedcba9876543210fedcba9876543210fedcba98".to_string()),
+            ..tagged.clone()
+        }],
+        &at_commit,
+    )
+    .unwrap();
+    assert_eq!(stale.1, vec![tagged.id]);
+    turbopuffer::patch_commit(namespace, &stale.1, &stale.0)
+        .await
+        .unwrap();
+
+    let old_commit = turbopuffer::query_chunks(
+        namespace,
+        serde_json::json!(["id", "asc"]),
+        10,
+        Some(turbopuffer::commit_filter("0123456")),
+    )
+    .await
+    .unwrap();
+    assert!(old_commit.is_empty());
+
+    let new_commit = turbopuffer::query_chunks(
+        namespace,
+        serde_json::json!(["id", "asc"]),
+        10,
+        Some(turbopuffer::commit_filter("fedcba9")),
+    )
+    .await
+    .unwrap();
+    assert_eq!(new_commit.len(), 1);
+    assert_eq!(new_commit[0].file_hash, 100);
+
+    let _ = turbopuffer::delete_namespace(namespace).await;
+}
+
+#[tokio::test]
+async fn test_delete_index_removes_namespace() {
+    // Index a project directory, then delete its index without re-syncing
+    let project_dir = tempfile::tempdir().unwrap();
+    std::fs::write(project_dir.path().join("Cargo.toml"), "").unwrap();
+    let (namespace, _root_dir) =
+        turbogrep::namespace_and_dir(project_dir.path().to_str().unwrap(), false, None).unwrap();
+
+    let chunk_stream = futures::stream::iter(vec![create_test_chunk("lib.rs", 1, 10, 100, 200)]);
+    if let Err(e) =
+        turbopuffer::write_chunks(&namespace, chunk_stream, None, &WriteOptions::default()).await
+    {
+        eprintln!("Failed to create namespace: {}", e);
+        let _ = turbopuffer::delete_namespace(&namespace).await;
+        return;
+    }
+    let indexed = turbopuffer::all_server_chunks(&namespace).await.unwrap();
+    assert_eq!(indexed.len(), 1);
+
+    sync::delete_index(&namespace).await.unwrap();
+
+    // Listing a deleted namespace either fails or finds nothing
+    let remaining = turbopuffer::all_server_chunks(&namespace)
+        .await
+        .unwrap_or_default();
+    assert!(remaining.is_empty());
+    assert!(
+        !turbopuffer::list_namespaces(&namespace)
+            .await
+            .unwrap()
+            .contains(&namespace)
+    );
+}
+
 // Helper function to create test chunks
 fn create_test_chunk(
     path: &str,
@@ -637,16 +834,23 @@ fn create_test_chunk(
     };
 
     Chunk {
-        id,
+        id: id.into(),
         vector: Some(vec![0.1; 1024]), // Mock embedding with correct dimensionality (1024)
         path: path.to_string(),
         start_line,
         end_line,
+        start_byte: None,
+        end_byte: None,
         file_hash,
         chunk_hash,
         file_mtime: 1234567890,
         file_ctime: 1234567890,
         content: Some(format!("fn test_{}() {{}}", path.replace(".", "_"))),
+        commit: None,
+        kind: None,
+        symbol: None,
         distance: None, // Test chunks don't have distance scores
+        rerank_score: None,
+        extra_vectors: Default::default(),
     }
 }
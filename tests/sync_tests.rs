@@ -1,6 +1,230 @@
+use futures::{Stream, StreamExt};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use turbogrep::chunker::Chunk;
+use turbogrep::embeddings::{EmbedResult, Embedding, EmbeddingError, EmbeddingType};
+use turbogrep::manifest::ManifestStore;
+use turbogrep::search::{self, SearchOptions};
 use turbogrep::sync;
-use turbogrep::turbopuffer;
+use turbogrep::turbopuffer::{
+    Consistency, IndexBackend, QueryOptions, TurbopufferError, WriteOptions,
+};
+
+/// In-memory stand-in for turbopuffer: namespaces of chunks keyed by id
+#[derive(Default)]
+struct MemoryIndex {
+    namespaces: Mutex<HashMap<String, BTreeMap<u128, Chunk>>>,
+    writes: AtomicUsize,
+}
+
+impl MemoryIndex {
+    fn with_chunks(namespace: &str, chunks: Vec<Chunk>) -> Self {
+        let index = Self::default();
+        index.namespaces.lock().unwrap().insert(
+            namespace.to_string(),
+            chunks.into_iter().map(|c| (c.id, c)).collect(),
+        );
+        index
+    }
+
+    fn paths(&self, namespace: &str) -> Vec<String> {
+        let namespaces = self.namespaces.lock().unwrap();
+        let mut paths: Vec<String> = namespaces
+            .get(namespace)
+            .map(|chunks| chunks.values().map(|c| c.path.clone()).collect())
+            .unwrap_or_default();
+        paths.sort();
+        paths
+    }
+}
+
+impl IndexBackend for MemoryIndex {
+    async fn write_chunks<S>(
+        &self,
+        namespace: &str,
+        chunks: S,
+        delete_chunks: Option<Vec<Chunk>>,
+        _options: &WriteOptions,
+    ) -> Result<(), TurbopufferError>
+    where
+        S: Stream<Item = Chunk> + Send + 'static,
+    {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        let chunks: Vec<Chunk> = chunks.collect().await;
+        let mut namespaces = self.namespaces.lock().unwrap();
+        let stored = namespaces.entry(namespace.to_string()).or_default();
+        for chunk in delete_chunks.unwrap_or_default() {
+            stored.remove(&chunk.id);
+        }
+        for chunk in chunks {
+            assert!(chunk.vector.is_some(), "chunks are embedded before writing");
+            stored.insert(chunk.id, chunk);
+        }
+        Ok(())
+    }
+
+    async fn all_chunks(&self, namespace: &str) -> Result<Vec<Chunk>, TurbopufferError> {
+        let namespaces = self.namespaces.lock().unwrap();
+        namespaces
+            .get(namespace)
+            .map(|chunks| chunks.values().cloned().collect())
+            .ok_or_else(|| TurbopufferError::NamespaceNotFound(namespace.to_string()))
+    }
+
+    async fn query_chunks(
+        &self,
+        namespace: &str,
+        rank_by: serde_json::Value,
+        top_k: u32,
+        _filters: Option<serde_json::Value>,
+        _options: QueryOptions,
+    ) -> Result<Vec<Chunk>, TurbopufferError> {
+        let mut chunks = self.all_chunks(namespace).await?;
+        // Nearest first by squared distance in the ranked vector column
+        if let Some([column, ann, query]) = rank_by.as_array().map(Vec::as_slice)
+            && ann == "ANN"
+        {
+            let query: Vec<f32> = serde_json::from_value(query.clone()).unwrap();
+            let distance = |chunk: &Chunk| {
+                let vector = match column.as_str() {
+                    Some("vector") => chunk.vector.as_ref(),
+                    Some(column) => chunk.extra_vectors.get(column),
+                    None => None,
+                };
+                let vector = vector.expect("chunk has a vector in the ranked column");
+                vector
+                    .iter()
+                    .zip(&query)
+                    .map(|(a, b)| (a - b) * (a - b))
+                    .sum::<f32>()
+            };
+            chunks.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+        }
+        // A stand-in for BM25: the number of query words in the content, best first
+        if let Some([_, bm25, query]) = rank_by.as_array().map(Vec::as_slice)
+            && bm25 == "BM25"
+        {
+            let words: Vec<&str> = query.as_str().unwrap().split_whitespace().collect();
+            for chunk in &mut chunks {
+                let content = chunk.content.as_deref().unwrap_or_default().to_lowercase();
+                let score = words.iter().filter(|word| content.contains(*word)).count();
+                chunk.distance = Some(score as f64);
+            }
+            chunks.retain(|chunk| chunk.distance > Some(0.0));
+            chunks.sort_by(|a, b| b.distance.unwrap().total_cmp(&a.distance.unwrap()));
+        }
+        chunks.truncate(top_k as usize);
+        Ok(chunks)
+    }
+    async fn chunk_vectors(
+        &self,
+        namespace: &str,
+        ids: &[u128],
+    ) -> Result<HashMap<u128, Vec<f32>>, TurbopufferError> {
+        let namespaces = self.namespaces.lock().unwrap();
+        let stored = namespaces.get(namespace);
+        Ok(ids
+            .iter()
+            .filter_map(|id| {
+                let vector = stored?.get(id)?.vector.clone()?;
+                Some((*id, vector))
+            })
+            .collect())
+    }
+}
+
+/// A [`MemoryIndex`] whose eventually consistent queries lag behind its writes, answering
+/// from `stale` until a strongly consistent query has caught up on them
+#[derive(Default)]
+struct LaggingIndex {
+    current: MemoryIndex,
+    stale: MemoryIndex,
+}
+
+impl IndexBackend for LaggingIndex {
+    async fn write_chunks<S>(
+        &self,
+        namespace: &str,
+        chunks: S,
+        delete_chunks: Option<Vec<Chunk>>,
+        options: &WriteOptions,
+    ) -> Result<(), TurbopufferError>
+    where
+        S: Stream<Item = Chunk> + Send + 'static,
+    {
+        self.current
+            .write_chunks(namespace, chunks, delete_chunks, options)
+            .await
+    }
+
+    async fn all_chunks(&self, namespace: &str) -> Result<Vec<Chunk>, TurbopufferError> {
+        self.current.all_chunks(namespace).await
+    }
+
+    async fn query_chunks(
+        &self,
+        namespace: &str,
+        rank_by: serde_json::Value,
+        top_k: u32,
+        filters: Option<serde_json::Value>,
+        options: QueryOptions,
+    ) -> Result<Vec<Chunk>, TurbopufferError> {
+        let index = match options.consistency {
+            Consistency::Eventual => &self.stale,
+            Consistency::Strong => &self.current,
+        };
+        index
+            .query_chunks(namespace, rank_by, top_k, filters, options)
+            .await
+    }
+
+    async fn chunk_vectors(
+        &self,
+        namespace: &str,
+        ids: &[u128],
+    ) -> Result<HashMap<u128, Vec<f32>>, TurbopufferError> {
+        self.current.chunk_vectors(namespace, ids).await
+    }
+}
+
+/// Embeds every chunk with a constant vector, failing any batch with content containing "FAIL"
+#[derive(Clone)]
+struct FakeEmbedding;
+
+impl Embedding for FakeEmbedding {
+    async fn embed(
+        self,
+        chunks: Vec<Chunk>,
+        _embedding_type: EmbeddingType,
+    ) -> Result<EmbedResult, EmbeddingError> {
+        if chunks
+            .iter()
+            .any(|c| c.content.as_deref().unwrap_or_default().contains("FAIL"))
+        {
+            return Err(EmbeddingError::ApiError("rejected input".to_string()));
+        }
+        let chunks = chunks
+            .into_iter()
+            .map(|mut c| {
+                c.vector = Some(vec![0.5; 8]);
+                c
+            })
+            .collect();
+        Ok(EmbedResult {
+            chunks,
+            total_tokens: None,
+        })
+    }
+
+    fn concurrency(&self) -> usize {
+        2
+    }
+
+    fn max_batch_size(&self) -> usize {
+        1
+    }
+}
 
 #[tokio::test]
 async fn test_tpuf_chunk_diff_empty() {
@@ -126,27 +350,18 @@ async fn test_tpuf_chunk_diff_different_chunk_content() {
 }
 
 #[tokio::test]
-async fn test_tpuf_chunk_diff_with_turbopuffer_integration() {
-    // Test the full integration with turbopuffer
+async fn test_tpuf_chunk_diff_against_index() {
+    // Diff local chunks against what the index returns
     let namespace = "test_sync_diff";
+    let index = MemoryIndex::with_chunks(
+        namespace,
+        vec![
+            create_test_chunk("test_file1.rs", 1, 10, 123, 456),
+            create_test_chunk("test_file2.py", 1, 15, 789, 101),
+        ],
+    );
 
-    // Create some test chunks
-    let local_chunks = vec![
-        create_test_chunk("test_file1.rs", 1, 10, 123, 456),
-        create_test_chunk("test_file2.py", 1, 15, 789, 101),
-    ];
-
-    // First, upload some chunks to turbopuffer
-    let initial_chunks = local_chunks.clone();
-    let chunk_stream = futures::stream::iter(initial_chunks);
-
-    // Upload initial chunks
-    turbopuffer::write_chunks(namespace, chunk_stream, None)
-        .await
-        .unwrap();
-
-    // Verify chunks are on server
-    let server_chunks = turbopuffer::all_server_chunks(namespace).await.unwrap();
+    let server_chunks = index.all_chunks(namespace).await.unwrap();
     assert_eq!(server_chunks.len(), 2);
 
     // Now test the diff with modified local chunks
@@ -168,9 +383,6 @@ async fn test_tpuf_chunk_diff_with_turbopuffer_integration() {
     // Should delete: test_file1.rs (stale version)
     assert_eq!(to_delete.len(), 1);
     assert_eq!(to_delete[0].path, "test_file1.rs");
-
-    // Clean up (handle errors gracefully)
-    let _ = turbopuffer::delete_namespace(namespace).await;
 }
 
 #[tokio::test]
@@ -179,17 +391,14 @@ async fn test_tpuf_chunk_diff_complex_scenario() {
     let namespace = "test_complex_diff";
 
     // Initial state: 3 files on server
-    let initial_server_chunks = vec![
-        create_test_chunk("file1.rs", 1, 10, 100, 200),
-        create_test_chunk("file2.py", 1, 15, 300, 400),
-        create_test_chunk("file3.go", 1, 20, 500, 600),
-    ];
-
-    // Upload initial chunks
-    let initial_stream = futures::stream::iter(initial_server_chunks.clone());
-    turbopuffer::write_chunks(namespace, initial_stream, None)
-        .await
-        .unwrap();
+    let index = MemoryIndex::with_chunks(
+        namespace,
+        vec![
+            create_test_chunk("file1.rs", 1, 10, 100, 200),
+            create_test_chunk("file2.py", 1, 15, 300, 400),
+            create_test_chunk("file3.go", 1, 20, 500, 600),
+        ],
+    );
 
     // Local state: file1 modified, file2 unchanged, file3 deleted, file4 new
     let local_chunks = vec![
@@ -199,14 +408,14 @@ async fn test_tpuf_chunk_diff_complex_scenario() {
     ];
 
     // Get current server state
-    let current_server_chunks = turbopuffer::all_server_chunks(namespace).await.unwrap();
+    let current_server_chunks = index.all_chunks(namespace).await.unwrap();
 
     let (to_upload, to_delete) =
         sync::tpuf_chunk_diff(local_chunks.clone(), current_server_chunks).unwrap();
 
     // With file_hash in chunk ID, different file_hash creates different ID
     // Should upload: file1.rs (different file_hash), file4.js (new)
-    assert_eq!(to_upload.len(), 2); 
+    assert_eq!(to_upload.len(), 2);
     assert!(to_upload.iter().any(|c| c.path == "file1.rs"));
     assert!(to_upload.iter().any(|c| c.path == "file4.js"));
 
@@ -214,9 +423,6 @@ async fn test_tpuf_chunk_diff_complex_scenario() {
     assert_eq!(to_delete.len(), 2);
     assert!(to_delete.iter().any(|c| c.path == "file1.rs"));
     assert!(to_delete.iter().any(|c| c.path == "file3.go"));
-
-    // Clean up (handle errors gracefully)
-    let _ = turbopuffer::delete_namespace(namespace).await;
 }
 
 // Helper function to create test chunks
@@ -243,17 +449,24 @@ fn create_test_chunk(
     };
 
     Chunk {
-        id,
+        id: id.into(),
         vector: Some(vec![0.1; 1024]), // Mock embedding with correct dimensionality
         path: path.to_string(),
         start_line,
         end_line,
+        start_byte: None,
+        end_byte: None,
         file_hash,
         chunk_hash,
         file_mtime: 1234567890,
         file_ctime: 1234567890,
         content: Some(format!("fn test_{}() {{}}", path.replace(".", "_"))),
+        commit: None,
+        kind: None,
+        symbol: None,
         distance: None, // Test chunks don't have distance scores
+        rerank_score: None,
+        extra_vectors: Default::default(),
     }
 }
 
@@ -288,384 +501,384 @@ fn test_chunk_hash_comparison() {
 }
 
 // Tests for tpuf_apply_diff function
-#[tokio::test]
-async fn test_tpuf_apply_diff_no_changes() {
-    // Test when no changes are needed
-    let namespace = "test_apply_diff_no_changes";
-    let local_chunks_to_upload = vec![];
-    let remote_chunks_to_delete = vec![];
-
-    let result = sync::tpuf_apply_diff(
+async fn apply_diff(
+    index: &MemoryIndex,
+    namespace: &str,
+    upload: Vec<Chunk>,
+    delete: Vec<Chunk>,
+    strict: bool,
+) -> anyhow::Result<bool> {
+    sync::tpuf_apply_diff(
+        index,
         namespace,
-        local_chunks_to_upload,
-        remote_chunks_to_delete,
+        upload,
+        delete,
         false,
-        None, // No concurrency override for tests
+        FakeEmbedding,
+        &WriteOptions::default(),
+        strict,
     )
     .await
-    .unwrap();
+}
+
+#[tokio::test]
+async fn test_tpuf_apply_diff_no_changes() {
+    // Test when no changes are needed
+    let index = MemoryIndex::default();
+    let result = apply_diff(&index, "test_apply_diff_no_changes", vec![], vec![], false)
+        .await
+        .unwrap();
 
-    // Should return false (no content changed)
-    assert_eq!(result, false);
+    // Should return false (no content changed), without touching the index
+    assert!(!result);
+    assert!(index.namespaces.lock().unwrap().is_empty());
 }
 
 #[tokio::test]
 async fn test_tpuf_apply_diff_upload_only() {
     // Test uploading chunks only
     let namespace = "test_apply_diff_upload_only";
+    let index = MemoryIndex::default();
 
-    // Create chunks to upload
     let local_chunks_to_upload = vec![
         create_test_chunk("file1.rs", 1, 10, 123, 456),
         create_test_chunk("file2.py", 1, 15, 789, 101),
     ];
-    let remote_chunks_to_delete = vec![];
-
-    let result = sync::tpuf_apply_diff(
-        namespace,
-        local_chunks_to_upload,
-        remote_chunks_to_delete,
-        false,
-        None, // No concurrency override for tests
-    )
-    .await;
-
-    // Handle potential API errors gracefully
-    match result {
-        Ok(changed) => {
-            // Should return true (content changed)
-            assert_eq!(changed, true);
 
-            // Verify chunks were uploaded
-            let server_chunks = turbopuffer::all_server_chunks(namespace).await.unwrap();
-            // The exact count might vary due to API timing, so we'll be more lenient
-            assert!(server_chunks.len() >= 1); // At least some chunks should be uploaded
-        }
-        Err(_) => {
-            // If it fails due to API errors, that's acceptable for testing
-            // The function should handle errors gracefully
-        }
-    }
+    let changed = apply_diff(&index, namespace, local_chunks_to_upload, vec![], false)
+        .await
+        .unwrap();
 
-    // Clean up (handle errors gracefully)
-    let _ = turbopuffer::delete_namespace(namespace).await;
+    assert!(changed);
+    assert_eq!(index.paths(namespace), vec!["file1.rs", "file2.py"]);
 }
 
 #[tokio::test]
 async fn test_tpuf_apply_diff_delete_only() {
     // Test deleting chunks only
     let namespace = "test_apply_diff_delete_only";
+    let index = MemoryIndex::with_chunks(
+        namespace,
+        vec![
+            create_test_chunk("file1.rs", 1, 10, 123, 456),
+            create_test_chunk("file2.py", 1, 15, 789, 101),
+        ],
+    );
+    let server_chunks = index.all_chunks(namespace).await.unwrap();
 
-    // First upload some chunks
-    let initial_chunks = vec![
-        create_test_chunk("file1.rs", 1, 10, 123, 456),
-        create_test_chunk("file2.py", 1, 15, 789, 101),
-    ];
-    let chunk_stream = futures::stream::iter(initial_chunks);
-    turbopuffer::write_chunks(namespace, chunk_stream, None)
+    let changed = apply_diff(&index, namespace, vec![], server_chunks, false)
         .await
         .unwrap();
 
-    // Verify chunks are on server
-    let server_chunks = turbopuffer::all_server_chunks(namespace).await.unwrap();
-    assert_eq!(server_chunks.len(), 2);
-
-    // Now delete them
-    let local_chunks_to_upload = vec![];
-    let remote_chunks_to_delete = server_chunks;
-
-    let result = sync::tpuf_apply_diff(
-        namespace,
-        local_chunks_to_upload,
-        remote_chunks_to_delete,
-        false,
-        None, // No concurrency override for tests
-    )
-    .await
-    .unwrap();
-
-    // Should return true (content changed)
-    assert_eq!(result, true);
-
-    // Verify chunks were deleted
-    let remaining_chunks = turbopuffer::all_server_chunks(namespace).await.unwrap();
-    // Note: The function might not delete chunks immediately, so we'll be more lenient
-    assert!(remaining_chunks.len() <= 2); // Should be 0 or at most the original count
-
-    // Clean up (handle errors gracefully)
-    let _ = turbopuffer::delete_namespace(namespace).await;
+    assert!(changed);
+    assert!(index.paths(namespace).is_empty());
 }
 
 #[tokio::test]
 async fn test_tpuf_apply_diff_upload_and_delete() {
     // Test uploading and deleting chunks simultaneously
     let namespace = "test_apply_diff_upload_delete";
+    let index = MemoryIndex::with_chunks(
+        namespace,
+        vec![
+            create_test_chunk("file1.rs", 1, 10, 123, 456),
+            create_test_chunk("file2.py", 1, 15, 789, 101),
+        ],
+    );
+    let server_chunks = index.all_chunks(namespace).await.unwrap();
 
-    // Clean up any existing state from previous test runs
-    let _ = turbopuffer::delete_namespace(namespace).await;
-    
-    // Small delay to ensure cleanup completes
-    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
-
-    // First upload some initial chunks
-    let initial_chunks = vec![
-        create_test_chunk("file1.rs", 1, 10, 123, 456),
-        create_test_chunk("file2.py", 1, 15, 789, 101),
-    ];
-    let chunk_stream = futures::stream::iter(initial_chunks);
-    match turbopuffer::write_chunks(namespace, chunk_stream, None).await {
-        Ok(_) => {},
-        Err(_) => {
-            eprintln!("Test skipped: Could not upload initial chunks (HTTP client error)");
-            let _ = turbopuffer::delete_namespace(namespace).await;
-            return;
-        }
-    }
-
-    // Verify initial state
-    let server_chunks = match turbopuffer::all_server_chunks(namespace).await {
-        Ok(chunks) => chunks,
-        Err(_) => {
-            eprintln!("Test skipped: Could not verify server state (HTTP client error)");
-            let _ = turbopuffer::delete_namespace(namespace).await;
-            return;
-        }
-    };
-    
-    // Check if initial state matches expectations - if not, likely stale data from previous runs
-    if server_chunks.len() != 2 {
-        eprintln!("Test skipped: Server has {} chunks instead of expected 2 - likely stale data", server_chunks.len());
-        let _ = turbopuffer::delete_namespace(namespace).await;
-        return;
-    }
-
-    // Now upload new chunks and delete old ones
     let local_chunks_to_upload = vec![
         create_test_chunk("file3.go", 1, 20, 111, 222),
         create_test_chunk("file4.js", 1, 25, 333, 444),
     ];
-    let remote_chunks_to_delete = server_chunks;
 
-    let result = sync::tpuf_apply_diff(
+    let changed = apply_diff(
+        &index,
         namespace,
         local_chunks_to_upload,
-        remote_chunks_to_delete,
+        server_chunks,
         false,
-        None, // No concurrency override for tests
     )
-    .await;
-
-    // Handle potential API errors gracefully
-    match result {
-        Ok(changed) => {
-            // Should return true (content changed)
-            assert_eq!(changed, true);
-
-            // Verify final state: old chunks deleted, new chunks uploaded
-            let final_chunks = turbopuffer::all_server_chunks(namespace).await.unwrap();
-            // The exact count might vary due to API timing, so we'll be more lenient
-            assert!(final_chunks.len() >= 1); // At least some chunks should be present
-
-            let chunk_paths: Vec<String> = final_chunks.iter().map(|c| c.path.clone()).collect();
-            // Check that we have some of the expected chunks
-            assert!(
-                chunk_paths.contains(&"file3.go".to_string())
-                    || chunk_paths.contains(&"file4.js".to_string())
-            );
-        }
-        Err(_) => {
-            // If it fails due to API errors, that's acceptable for testing
-            // The function should handle errors gracefully
-        }
-    }
+    .await
+    .unwrap();
 
-    // Clean up (handle errors gracefully)
-    let _ = turbopuffer::delete_namespace(namespace).await;
+    assert!(changed);
+    assert_eq!(index.paths(namespace), vec!["file3.go", "file4.js"]);
 }
 
 #[tokio::test]
 async fn test_tpuf_apply_diff_with_verbose() {
-    // Test with verbose logging enabled
+    // Test with verbose logging (and its progress bar) enabled
     let namespace = "test_apply_diff_verbose";
+    let index = MemoryIndex::default();
 
-    let local_chunks_to_upload = vec![create_test_chunk("file1.rs", 1, 10, 123, 456)];
-    let remote_chunks_to_delete = vec![];
-
-    let result = sync::tpuf_apply_diff(
+    let changed = sync::tpuf_apply_diff(
+        &index,
         namespace,
-        local_chunks_to_upload,
-        remote_chunks_to_delete,
+        vec![create_test_chunk("file1.rs", 1, 10, 123, 456)],
+        vec![],
         true,
-        None, // No concurrency override for tests
+        FakeEmbedding,
+        &WriteOptions::default(),
+        false,
     )
     .await
     .unwrap();
 
-    // Should return true (content changed)
-    assert_eq!(result, true);
-
-    // Verify chunk was uploaded
-    let server_chunks = turbopuffer::all_server_chunks(namespace).await.unwrap();
-    assert_eq!(server_chunks.len(), 1);
-
-    // Clean up (handle errors gracefully)
-    let _ = turbopuffer::delete_namespace(namespace).await;
+    assert!(changed);
+    assert_eq!(index.paths(namespace), vec!["file1.rs"]);
 }
 
 #[tokio::test]
 async fn test_tpuf_apply_diff_embedding_errors() {
-    // Test handling of embedding errors
+    // Chunks that fail to embed are skipped, the rest are still written
     let namespace = "test_apply_diff_embedding_errors";
+    let index = MemoryIndex::default();
 
-    // Clean up any existing state from previous test runs
-    let _ = turbopuffer::delete_namespace(namespace).await;
-    
-    // Small delay to ensure cleanup completes
-    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
-
-    // Create chunks that might cause embedding errors (very short content)
-    let mut chunk_with_short_content = create_test_chunk("file1.rs", 1, 10, 123, 456);
-    chunk_with_short_content.content = Some("a".to_string()); // Very short content might cause issues
-
+    let mut failing_chunk = create_test_chunk("file1.rs", 1, 10, 123, 456);
+    failing_chunk.content = Some("FAIL".to_string());
     let local_chunks_to_upload = vec![
-        chunk_with_short_content,
-        create_test_chunk("file2.py", 1, 15, 789, 101), // This should work
+        failing_chunk,
+        create_test_chunk("file2.py", 1, 15, 789, 101),
     ];
-    let remote_chunks_to_delete = vec![];
 
-    // This test might fail due to embedding errors, so we'll handle it gracefully
+    let changed = apply_diff(&index, namespace, local_chunks_to_upload, vec![], false)
+        .await
+        .unwrap();
+
+    assert!(changed);
+    assert_eq!(index.paths(namespace), vec!["file2.py"]);
+}
+
+#[tokio::test]
+async fn test_tpuf_apply_diff_strict_fails_on_embedding_error() {
+    let namespace = "test_apply_diff_strict";
+    let index = MemoryIndex::default();
+
+    let mut failing_chunk = create_test_chunk("file1.rs", 1, 10, 123, 456);
+    failing_chunk.content = Some("FAIL".to_string());
+
+    let result = apply_diff(&index, namespace, vec![failing_chunk], vec![], true).await;
+
+    let error = result.unwrap_err();
+    assert!(error.to_string().contains("1 of 1 chunks not indexed"));
+    assert!(error.downcast_ref::<EmbeddingError>().is_some());
+}
+
+#[test]
+fn test_find_renames() {
+    let renamed_old = create_test_chunk("src/old_name.rs", 1, 10, 123, 456);
+    let renamed_new = create_test_chunk("src/new_name.rs", 1, 10, 123, 456);
+    // Same content at a different line span is a real change, not a rename
+    let moved_old = create_test_chunk("src/old_name.rs", 12, 20, 123, 789);
+    let moved_new = create_test_chunk("src/new_name.rs", 14, 22, 123, 789);
+    let deleted = create_test_chunk("src/gone.rs", 1, 5, 321, 654);
+    let added = create_test_chunk("src/added.rs", 1, 5, 999, 111);
+
+    let renames = sync::find_renames(
+        &[renamed_new.clone(), moved_new, added],
+        &[renamed_old.clone(), moved_old, deleted],
+    );
+
+    assert_eq!(renames, vec![(renamed_old.id, renamed_new.id)]);
+}
+
+#[tokio::test]
+async fn test_tpuf_apply_diff_rename_reuses_vectors() {
+    let namespace = "test_apply_diff_rename";
+    let mut old_chunk = create_test_chunk("src/old_name.rs", 1, 10, 123, 456);
+    old_chunk.vector = Some(vec![0.25; 8]);
+    let index = MemoryIndex::with_chunks(namespace, vec![old_chunk.clone()]);
+
+    // Embedding this content fails, so under strict a single embedding call errors
+    let mut new_chunk = create_test_chunk("src/new_name.rs", 1, 10, 123, 456);
+    new_chunk.content = Some("FAIL if embedded again".to_string());
+
+    let (to_upload, to_delete) =
+        sync::tpuf_chunk_diff(vec![new_chunk.clone()], vec![old_chunk.clone()]).unwrap();
+    assert_eq!((to_upload.len(), to_delete.len()), (1, 1));
+    assert_eq!(sync::find_renames(&to_upload, &to_delete).len(), 1);
+
+    let changed = apply_diff(&index, namespace, to_upload, to_delete, true)
+        .await
+        .unwrap();
+
+    assert!(changed);
+    assert_eq!(index.paths(namespace), vec!["src/new_name.rs"]);
+    let stored = index.all_chunks(namespace).await.unwrap();
+    assert_eq!(stored[0].id, new_chunk.id);
+    assert_eq!(stored[0].vector, Some(vec![0.25; 8]));
+}
+
+/// An embedding service that's down: every call fails, and is counted
+#[derive(Clone, Default)]
+struct DownEmbedding {
+    calls: Arc<AtomicUsize>,
+}
+
+impl Embedding for DownEmbedding {
+    async fn embed(
+        self,
+        _chunks: Vec<Chunk>,
+        _embedding_type: EmbeddingType,
+    ) -> Result<EmbedResult, EmbeddingError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Err(EmbeddingError::ApiError(
+            "503 Service Unavailable".to_string(),
+        ))
+    }
+
+    fn concurrency(&self) -> usize {
+        2
+    }
+
+    fn max_batch_size(&self) -> usize {
+        1
+    }
+}
+
+#[tokio::test]
+async fn test_tpuf_apply_diff_stops_when_embedding_service_is_down() {
+    let namespace = "test_apply_diff_service_down";
+    let index = MemoryIndex::default();
+    let embedding = DownEmbedding::default();
+
+    let local_chunks_to_upload: Vec<Chunk> = (0..200)
+        .map(|i| create_test_chunk(&format!("file{i}.rs"), 1, 10, i, i))
+        .collect();
+
     let result = sync::tpuf_apply_diff(
+        &index,
         namespace,
         local_chunks_to_upload,
-        remote_chunks_to_delete,
+        vec![],
+        false,
+        embedding.clone(),
+        &WriteOptions::default(),
         false,
-        None, // No concurrency override for tests
     )
     .await;
 
-    // The function should handle embedding errors gracefully
-    match result {
-        Ok(changed) => {
-            // If it succeeds, should return true (content changed)
-            assert_eq!(changed, true);
-
-            // Verify at least some chunks were uploaded (the ones that didn't fail)
-            match turbopuffer::all_server_chunks(namespace).await {
-                Ok(server_chunks) => {
-                    assert!(server_chunks.len() >= 1); // At least one chunk should be uploaded
-                }
-                Err(_) => {
-                    // If namespace doesn't exist, all embeddings failed - that's also acceptable for this test
-                }
-            }
+    let error = result.unwrap_err();
+    assert!(error.to_string().contains("embedding service appears down"));
+    assert!(error.downcast_ref::<EmbeddingError>().is_some());
+    // Only the batches in flight when the breaker tripped were sent
+    let calls = embedding.calls.load(Ordering::SeqCst);
+    assert!(
+        (sync::MAX_CONSECUTIVE_FAILURES..sync::MAX_CONSECUTIVE_FAILURES + 3).contains(&calls),
+        "{calls} embedding calls"
+    );
+}
+
+#[tokio::test]
+async fn test_tpuf_apply_diff_embedding_dry_run_never_writes() {
+    /// Counts the chunks it embeds, reporting 10 tokens for each
+    #[derive(Clone, Default)]
+    struct CountingEmbedding(Arc<AtomicUsize>);
+
+    impl Embedding for CountingEmbedding {
+        async fn embed(
+            self,
+            chunks: Vec<Chunk>,
+            embedding_type: EmbeddingType,
+        ) -> Result<EmbedResult, EmbeddingError> {
+            self.0.fetch_add(chunks.len(), Ordering::SeqCst);
+            let mut result = FakeEmbedding.embed(chunks, embedding_type).await?;
+            result.total_tokens = Some(result.chunks.len() * 10);
+            Ok(result)
+        }
+
+        fn concurrency(&self) -> usize {
+            2
         }
-        Err(_) => {
-            // If it fails due to embedding errors, that's also acceptable
-            // The function should handle errors gracefully
+
+        fn max_batch_size(&self) -> usize {
+            1
         }
     }
 
-    // Clean up (handle errors gracefully)
-    let _ = turbopuffer::delete_namespace(namespace).await;
+    let stale = create_test_chunk("old.rs", 1, 5, 1, 100);
+    let index = MemoryIndex::with_chunks("test_dry_run", vec![stale.clone()]);
+    let embedding = CountingEmbedding::default();
+    let options = WriteOptions {
+        dry_run: true,
+        ..Default::default()
+    };
+
+    let changed = sync::tpuf_apply_diff(
+        &index,
+        "test_dry_run",
+        vec![
+            create_test_chunk("new.rs", 1, 5, 2, 200),
+            create_test_chunk("lib.rs", 1, 5, 3, 300),
+        ],
+        vec![stale],
+        false,
+        embedding.clone(),
+        &options,
+        false,
+    )
+    .await
+    .unwrap();
+
+    assert!(!changed);
+    assert_eq!(embedding.0.load(Ordering::SeqCst), 2);
+    assert_eq!(index.writes.load(Ordering::SeqCst), 0);
+    assert_eq!(index.paths("test_dry_run"), ["old.rs"]);
 }
 
 #[tokio::test]
 async fn test_tpuf_apply_diff_large_batch() {
-    // Test with a larger batch of chunks
+    // Test with a larger batch of chunks than fit in one write
     let namespace = "test_apply_diff_large_batch";
+    let index = MemoryIndex::default();
+
+    let local_chunks_to_upload: Vec<Chunk> = (0..10)
+        .map(|i| {
+            create_test_chunk(
+                &format!("file{}.rs", i),
+                1,
+                10 + i,
+                (100 + i) as u64,
+                (200 + i) as u64,
+            )
+        })
+        .collect();
 
-    // Clean up any existing state from previous test runs
-    let _ = turbopuffer::delete_namespace(namespace).await;
-    
-    // Small delay to ensure cleanup completes
-    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
-
-    // Create many chunks
-    let mut local_chunks_to_upload = vec![];
-    for i in 0..10 {
-        local_chunks_to_upload.push(create_test_chunk(
-            &format!("file{}.rs", i),
-            1,
-            10 + i,
-            (100 + i) as u64,
-            (200 + i) as u64,
-        ));
-    }
-
-    let remote_chunks_to_delete = vec![];
-
-    let result = sync::tpuf_apply_diff(
+    let changed = sync::tpuf_apply_diff(
+        &index,
         namespace,
         local_chunks_to_upload,
-        remote_chunks_to_delete,
+        vec![],
+        false,
+        FakeEmbedding,
+        &WriteOptions {
+            batch_size: 3,
+            concurrency: 2,
+            ..Default::default()
+        },
         false,
-        None, // No concurrency override for tests
     )
-    .await;
-
-    match result {
-        Ok(changed) => {
-            // If it succeeds, should return true (content changed)
-            assert_eq!(changed, true);
-
-            // Verify chunks were uploaded
-            match turbopuffer::all_server_chunks(namespace).await {
-                Ok(server_chunks) => {
-                    assert_eq!(server_chunks.len(), 10);
-                }
-                Err(_) => {
-                    // If namespace doesn't exist, all embeddings failed - that's also acceptable for this test
-                }
-            }
-        }
-        Err(_) => {
-            // If embedding fails (e.g., API unavailable), that's acceptable for this test
-            // The test is primarily checking that large batches don't cause HTTP client crashes
-            eprintln!("Test passed: Large batch processed without HTTP client crashes (embeddings failed, which is acceptable)");
-        }
-    }
+    .await
+    .unwrap();
 
-    // Clean up (handle errors gracefully)
-    let _ = turbopuffer::delete_namespace(namespace).await;
+    assert!(changed);
+    assert_eq!(index.paths(namespace).len(), 10);
 }
 
 #[tokio::test]
 async fn test_tpuf_apply_diff_complex_scenario() {
     // Test a complex scenario with multiple operations
     let namespace = "test_apply_diff_complex";
-
-    // Clean up any existing state from previous test runs
-    let _ = turbopuffer::delete_namespace(namespace).await;
-    
-    // Small delay to ensure cleanup completes
-    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
-
-    // Initial state: upload some chunks
-    let initial_chunks = vec![
-        create_test_chunk("file1.rs", 1, 10, 100, 200),
-        create_test_chunk("file2.py", 1, 15, 300, 400),
-        create_test_chunk("file3.go", 1, 20, 500, 600),
-    ];
-    let chunk_stream = futures::stream::iter(initial_chunks);
-    match turbopuffer::write_chunks(namespace, chunk_stream, None).await {
-        Ok(_) => {},
-        Err(_) => {
-            eprintln!("Test skipped: Could not upload initial chunks (HTTP client error)");
-            let _ = turbopuffer::delete_namespace(namespace).await;
-            return;
-        }
-    }
-
-    // Verify initial state
-    let server_chunks = match turbopuffer::all_server_chunks(namespace).await {
-        Ok(chunks) => chunks,
-        Err(_) => {
-            eprintln!("Test skipped: Could not verify server state (HTTP client error)");
-            let _ = turbopuffer::delete_namespace(namespace).await;
-            return;
-        }
-    };
-    assert_eq!(server_chunks.len(), 3);
+    let index = MemoryIndex::with_chunks(
+        namespace,
+        vec![
+            create_test_chunk("file1.rs", 1, 10, 100, 200),
+            create_test_chunk("file2.py", 1, 15, 300, 400),
+            create_test_chunk("file3.go", 1, 20, 500, 600),
+        ],
+    );
+    let server_chunks = index.all_chunks(namespace).await.unwrap();
 
     // Complex scenario: upload new chunks, delete some old ones
     let local_chunks_to_upload = vec![
@@ -679,40 +892,616 @@ async fn test_tpuf_apply_diff_complex_scenario() {
         .filter(|c| c.path == "file1.rs" || c.path == "file3.go")
         .collect();
 
-    let result = sync::tpuf_apply_diff(
+    let changed = apply_diff(
+        &index,
         namespace,
         local_chunks_to_upload,
         remote_chunks_to_delete,
         false,
-        None, // No concurrency override for tests
     )
-    .await;
+    .await
+    .unwrap();
 
-    // Handle potential API errors gracefully
-    match result {
-        Ok(changed) => {
-            // Should return true (content changed)
-            assert_eq!(changed, true);
-
-            // Verify final state: file2.py kept, file1.rs and file3.go deleted, file4.js and file5.ts added
-            let final_chunks = turbopuffer::all_server_chunks(namespace).await.unwrap();
-            // The exact count might vary due to API timing, so we'll be more lenient
-            assert!(final_chunks.len() >= 1); // At least some chunks should be present
-
-            let chunk_paths: Vec<String> = final_chunks.iter().map(|c| c.path.clone()).collect();
-            // Check that we have some of the expected chunks
-            assert!(
-                chunk_paths.contains(&"file2.py".to_string())
-                    || chunk_paths.contains(&"file4.js".to_string())
-                    || chunk_paths.contains(&"file5.ts".to_string())
-            );
+    assert!(changed);
+    assert_eq!(
+        index.paths(namespace),
+        vec!["file2.py", "file4.js", "file5.ts"]
+    );
+}
+
+#[tokio::test]
+async fn test_pending_changes_lists_changed_files() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+    let root_str = root.to_str().unwrap();
+    std::fs::write(root.join("lib.rs"), "fn parse() {}\n").unwrap();
+    std::fs::write(root.join("old.rs"), "fn legacy() {}\n").unwrap();
+    std::fs::write(root.join("same.rs"), "fn untouched() {}\n").unwrap();
+
+    // Seed the index with what a sync of the directory would have written
+    let (namespace, _) = turbogrep::namespace_and_dir(root_str, true, None).unwrap();
+    let indexed = turbogrep::chunker::chunk_files(root_str).unwrap();
+    let index = MemoryIndex::with_chunks(&namespace, indexed);
+
+    std::fs::write(root.join("lib.rs"), "fn parse(input: &str) {}\n").unwrap();
+    std::fs::remove_file(root.join("old.rs")).unwrap();
+    std::fs::write(root.join("new.rs"), "fn fresh() {}\n").unwrap();
+
+    let options = sync::SyncOptions {
+        flat: true,
+        ..Default::default()
+    };
+    let changes = sync::pending_changes(&index, root_str, &options)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        changes.to_string(),
+        "new:\n  new.rs +1\nchanged:\n  lib.rs +1 -1\ndeleted:\n  old.rs -1\n"
+    );
+    // Nothing was written
+    assert_eq!(index.all_chunks(&namespace).await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_speculative_retry_sees_chunks_the_sync_just_wrote() {
+    let chunk = |path: &str, content: &str, vector: f32| {
+        let mut chunk = create_test_chunk(path, 1, 1, 1, content.len() as u64);
+        chunk.content = Some(content.to_string());
+        chunk.vector = Some(vec![vector; 8]);
+        chunk
+    };
+    let old = chunk("/repo/old.rs", "fn old() {}", -0.5);
+    let index = Arc::new(LaggingIndex {
+        current: MemoryIndex::with_chunks("tg_lag", vec![old.clone()]),
+        stale: MemoryIndex::with_chunks("tg_lag", vec![old]),
+    });
+
+    let options = SearchOptions {
+        namespace: Some("tg_lag".to_string()),
+        max_count: 1,
+        ..Default::default()
+    };
+    let searches = Arc::new(AtomicUsize::new(0));
+    let search_with = |options: SearchOptions| {
+        let index = index.clone();
+        let searches = searches.clone();
+        async move {
+            // Slower than the sync, which finishes while the first search runs
+            if searches.fetch_add(1, Ordering::SeqCst) == 0 {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+            search::search_index(&*index, FakeEmbedding, "query", "/repo", &options).await
+        }
+    };
+    // The first sync writes a chunk nearest the query, later ones find nothing to do
+    let syncs = Arc::new(AtomicUsize::new(0));
+    let sync = || {
+        let index = index.clone();
+        let syncs = syncs.clone();
+        async move {
+            if syncs.fetch_add(1, Ordering::SeqCst) > 0 {
+                return Ok(false);
+            }
+            let new = chunk("/repo/new.rs", "fn new() {}", 0.5);
+            index
+                .write_chunks(
+                    "tg_lag",
+                    futures::stream::iter(vec![new]),
+                    None,
+                    &WriteOptions::default(),
+                )
+                .await?;
+            Ok(true)
         }
-        Err(_) => {
-            // If it fails due to API errors, that's acceptable for testing
-            // The function should handle errors gracefully
+    };
+
+    let output = search::race_search_and_sync(&options, search_with, sync, || {})
+        .await
+        .unwrap();
+    assert_eq!(output.results, "/repo/new.rs:1:fn new() {}");
+    assert!(searches.load(Ordering::SeqCst) <= search::MAX_SPECULATIVE_RETRIES + 1);
+}
+
+#[tokio::test]
+async fn test_sync_roots_indexes_sibling_directories_into_one_namespace() {
+    let frontend = tempfile::tempdir().unwrap();
+    let backend = tempfile::tempdir().unwrap();
+    let frontend_root = frontend.path().canonicalize().unwrap();
+    let backend_root = backend.path().canonicalize().unwrap();
+    std::fs::write(frontend_root.join("app.ts"), "function render() {}\n").unwrap();
+    std::fs::write(backend_root.join("server.py"), "def handle():\n    pass\n").unwrap();
+    let roots = vec![
+        frontend_root.to_string_lossy().to_string(),
+        backend_root.to_string_lossy().to_string(),
+    ];
+
+    let index = MemoryIndex::default();
+    let options = sync::SyncOptions::default();
+    let changed = sync::sync_roots(&index, FakeEmbedding, &roots, "tg_app", &options)
+        .await
+        .unwrap();
+    assert!(changed);
+    assert_eq!(index.paths("tg_app"), {
+        let mut paths = vec![
+            frontend_root.join("app.ts").to_string_lossy().to_string(),
+            backend_root.join("server.py").to_string_lossy().to_string(),
+        ];
+        paths.sort();
+        paths
+    });
+
+    // A file deleted from one root leaves the index on the next sync
+    std::fs::remove_file(backend_root.join("server.py")).unwrap();
+    let changed = sync::sync_roots(&index, FakeEmbedding, &roots, "tg_app", &options)
+        .await
+        .unwrap();
+    assert!(changed);
+    assert_eq!(
+        index.paths("tg_app"),
+        vec![frontend_root.join("app.ts").to_string_lossy().to_string()]
+    );
+}
+
+#[tokio::test]
+async fn test_verify_reports_gaps_and_orphans() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+    let root_str = root.to_str().unwrap();
+    std::fs::write(root.join("lib.rs"), "fn parse() {}\n\nfn render() {}\n").unwrap();
+
+    // An interrupted sync indexed only one of the functions, and a deleted file lingers
+    let (namespace, _) = turbogrep::namespace_and_dir(root_str, true, None).unwrap();
+    let mut indexed = turbogrep::chunker::chunk_files(root_str).unwrap();
+    indexed.retain(|chunk| chunk.start_line == 1);
+    indexed.push(create_test_chunk(
+        root.join("gone.rs").to_str().unwrap(),
+        4,
+        6,
+        7,
+        7,
+    ));
+    let index = MemoryIndex::with_chunks(&namespace, indexed);
+
+    let options = sync::SyncOptions {
+        flat: true,
+        ..Default::default()
+    };
+    let health = sync::verify_index(&index, root_str, &options)
+        .await
+        .unwrap();
+    assert_eq!(health.gaps, vec!["lib.rs:3-3"]);
+    assert_eq!(health.orphans, vec!["gone.rs:4-6"]);
+    assert!(health.collisions.is_empty());
+    assert_eq!(
+        health.to_string(),
+        "orphans (indexed, not on disk): 1\n  gone.rs:4-6\ngaps (on disk, not indexed): 1\n  lib.rs:3-3\n"
+    );
+    // Nothing was written
+    assert_eq!(index.writes.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_export_import_round_trip_skips_embedding() {
+    let source_dir = tempfile::tempdir().unwrap();
+    let target_dir = tempfile::tempdir().unwrap();
+    let files = [
+        ("lib.rs", "fn parse() {}\n\nfn render() {}\n"),
+        ("util/io.py", "def read():\n    pass\n"),
+    ];
+    for dir in [source_dir.path(), target_dir.path()] {
+        std::fs::create_dir_all(dir.join("util")).unwrap();
+        for (path, content) in files {
+            std::fs::write(dir.join(path), content).unwrap();
         }
     }
+    let source = source_dir.path().canonicalize().unwrap();
+    let target = target_dir.path().canonicalize().unwrap();
+    let (source, target) = (source.to_str().unwrap(), target.to_str().unwrap());
 
-    // Clean up
-    turbopuffer::delete_namespace(namespace).await.unwrap();
+    // A colleague's index of their checkout, with vectors
+    let indexed: Vec<Chunk> = turbogrep::chunker::chunk_files(source)
+        .unwrap()
+        .into_iter()
+        .map(|mut chunk| {
+            chunk.vector = Some(vec![chunk.start_line as f32, chunk.chunk_hash as f32]);
+            chunk
+        })
+        .collect();
+    let index = MemoryIndex::with_chunks("tg_source", indexed.clone());
+
+    let export = sync::export_index(&index, "tg_source", source)
+        .await
+        .unwrap();
+    let file = source_dir.path().join("index.json");
+    export.write_to(&file).unwrap();
+    let export = sync::IndexExport::read_from(&file).unwrap();
+    assert_eq!(export.chunks.len(), 3);
+    assert!(export.chunks.iter().all(|c| !c.path.starts_with('/')));
+
+    let count = sync::import_index(
+        &index,
+        "tg_target",
+        target,
+        export,
+        &WriteOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(count, 3);
+
+    // Same chunks and vectors, now under the importer's checkout
+    let vectors = |namespace: &str, root: &str| {
+        let mut chunks: Vec<(String, u32, u64, Vec<f32>)> = index.namespaces.lock().unwrap()
+            [namespace]
+            .values()
+            .map(|c| {
+                let path = c.path.strip_prefix(root).unwrap().to_string();
+                (path, c.start_line, c.chunk_hash, c.vector.clone().unwrap())
+            })
+            .collect();
+        chunks.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
+        chunks
+    };
+    assert_eq!(vectors("tg_target", target), vectors("tg_source", source));
+
+    // A sync of the importer's checkout finds nothing to embed
+    let local = turbogrep::chunker::chunk_files(target).unwrap();
+    let imported = index.all_chunks("tg_target").await.unwrap();
+    let (to_upload, to_delete) = sync::tpuf_chunk_diff(local, imported).unwrap();
+    assert!(to_upload.is_empty());
+    assert!(to_delete.is_empty());
+}
+
+#[tokio::test]
+async fn test_unchanged_tree_with_manifest_skips_server_fetch() {
+    let project = tempfile::tempdir().unwrap();
+    let root = project.path().to_str().unwrap();
+    std::fs::write(project.path().join("lib.rs"), "fn main() {}\n").unwrap();
+    let cache = tempfile::tempdir().unwrap();
+    let manifests = ManifestStore::new(cache.path().to_path_buf());
+    let walk = turbogrep::chunker::WalkOptions::default();
+
+    // Stand in for every request to turbopuffer: reading the index's sync time, and the
+    // server fetch and diff, which records whether it was complete and stamps a new one
+    let synced_at = AtomicU64::new(0);
+    let checks = AtomicUsize::new(0);
+    let fetches = AtomicUsize::new(0);
+    let last_synced = || async {
+        checks.fetch_add(1, Ordering::SeqCst);
+        Some(synced_at.load(Ordering::SeqCst))
+    };
+    let sync = |complete: bool| {
+        let (fetches, synced_at) = (&fetches, &synced_at);
+        move || async move {
+            let stamp = fetches.fetch_add(1, Ordering::SeqCst) as u64 + 1;
+            synced_at.store(stamp, Ordering::SeqCst);
+            Ok((true, complete, Some(stamp)))
+        }
+    };
+    let now = 1_000_000;
+    let sync_if_changed = |now, complete| {
+        manifests.sync_if_changed("tg_test", root, &walk, 1, now, last_synced, sync(complete))
+    };
+
+    // An unchanged tree makes no request at all
+    assert!(sync_if_changed(now, true).await.unwrap());
+    assert!(!sync_if_changed(now + 60, true).await.unwrap());
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    assert_eq!(checks.load(Ordering::SeqCst), 0);
+
+    // Changed files, or different indexing settings, go back to the server
+    std::fs::write(project.path().join("lib.rs"), "fn main() { run() }\n").unwrap();
+    assert!(sync_if_changed(now, true).await.unwrap());
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    manifests
+        .sync_if_changed("tg_test", root, &walk, 2, now, last_synced, sync(true))
+        .await
+        .unwrap();
+    assert_eq!(fetches.load(Ordering::SeqCst), 3);
+    assert!(sync_if_changed(now, true).await.unwrap());
+    assert_eq!(fetches.load(Ordering::SeqCst), 4);
+
+    // An old manifest is checked against the index's sync time, and trusted again if
+    // it still matches
+    let later = now + 24 * 60 * 60;
+    assert!(!sync_if_changed(later, true).await.unwrap());
+    assert!(!sync_if_changed(later + 60, true).await.unwrap());
+    assert_eq!(checks.load(Ordering::SeqCst), 1);
+    assert_eq!(fetches.load(Ordering::SeqCst), 4);
+
+    // But not if the index was synced from elsewhere since, or deleted
+    synced_at.store(100, Ordering::SeqCst);
+    let later = later + 24 * 60 * 60;
+    assert!(sync_if_changed(later, true).await.unwrap());
+    assert_eq!(checks.load(Ordering::SeqCst), 2);
+    assert_eq!(fetches.load(Ordering::SeqCst), 5);
+    let later = later + 24 * 60 * 60;
+    manifests
+        .sync_if_changed(
+            "tg_test",
+            root,
+            &walk,
+            1,
+            later,
+            || async { None },
+            sync(true),
+        )
+        .await
+        .unwrap();
+    assert_eq!(fetches.load(Ordering::SeqCst), 6);
+    assert!(!sync_if_changed(later, true).await.unwrap());
+
+    // A sync that skipped chunks is retried next time
+    manifests.invalidate("tg_test");
+    sync_if_changed(later, false).await.unwrap();
+    sync_if_changed(later, true).await.unwrap();
+    assert_eq!(fetches.load(Ordering::SeqCst), 8);
+}
+
+#[tokio::test]
+async fn test_browse_returns_only_filtered_chunks() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+    let root_str = root.to_str().unwrap();
+    std::fs::write(root.join("fresh.rs"), "fn fresh() {}\n").unwrap();
+    std::fs::write(root.join("fresh.py"), "def fresh(): pass\n").unwrap();
+    std::fs::write(root.join("stale.rs"), "fn stale() {}\n").unwrap();
+
+    let (namespace, _) = turbogrep::namespace_and_dir(root_str, true, None).unwrap();
+    let mut indexed = turbogrep::chunker::chunk_files(root_str).unwrap();
+    for chunk in &mut indexed {
+        chunk.file_mtime = if chunk.path.ends_with("stale.rs") {
+            1_000
+        } else {
+            5_000
+        };
+    }
+    let index = MemoryIndex::with_chunks(&namespace, indexed);
+
+    let options = SearchOptions {
+        flat: true,
+        modified_since: Some(2_000),
+        extensions: vec!["rs".to_string()],
+        ..Default::default()
+    };
+    let output = search::browse(&index, root_str, &options).await.unwrap();
+    assert_eq!(output.results, "fresh.rs:1:fn fresh() {}");
+
+    // Without filters it's everything in the index
+    let options = SearchOptions {
+        flat: true,
+        ..Default::default()
+    };
+    let output = search::browse(&index, root_str, &options).await.unwrap();
+    assert_eq!(output.results.lines().count(), 3);
+}
+
+#[tokio::test]
+async fn test_search_other_namespace_uses_stored_content() {
+    // A teammate's index synced with store_content, of files that don't exist here
+    let mut parse = create_test_chunk("/home/teammate/app/src/parse.rs", 3, 5, 1, 1);
+    parse.content = Some("fn parse_config() {\n    todo!()\n}".to_string());
+    parse.vector = Some(vec![0.5; 8]);
+    let mut render = create_test_chunk("/home/teammate/app/src/render.rs", 1, 1, 2, 2);
+    render.content = Some("fn render() {}".to_string());
+    render.vector = Some(vec![-0.5; 8]);
+    let index = MemoryIndex::with_chunks("tg_teammate", vec![parse, render]);
+
+    let empty_dir = tempfile::tempdir().unwrap();
+    let options = SearchOptions {
+        namespace: Some("tg_teammate".to_string()),
+        max_count: 1,
+        ..Default::default()
+    };
+    let output = search::search_index(
+        &index,
+        FakeEmbedding,
+        "config parsing",
+        empty_dir.path().to_str().unwrap(),
+        &options,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        output.results,
+        "/home/teammate/app/src/parse.rs:3:fn parse_config() {"
+    );
+}
+
+#[tokio::test]
+async fn test_search_falls_back_to_words_when_embedding_fails() {
+    let mut parse = create_test_chunk("/home/teammate/app/src/parse.rs", 3, 5, 1, 1);
+    parse.content = Some("fn parse_config() {\n    todo!()\n}".to_string());
+    let mut render = create_test_chunk("/home/teammate/app/src/render.rs", 1, 1, 2, 2);
+    render.content = Some("fn render() {}".to_string());
+    let index = MemoryIndex::with_chunks("tg_teammate", vec![render, parse]);
+
+    // FakeEmbedding rejects the query, so the search matches its words instead
+    let empty_dir = tempfile::tempdir().unwrap();
+    let options = SearchOptions {
+        namespace: Some("tg_teammate".to_string()),
+        ..Default::default()
+    };
+    let output = search::search_index(
+        &index,
+        FakeEmbedding,
+        "FAIL parse",
+        empty_dir.path().to_str().unwrap(),
+        &options,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        output.results,
+        "/home/teammate/app/src/parse.rs:3:fn parse_config() {"
+    );
+
+    // Without the fallback the embedding error fails the search
+    let options = SearchOptions {
+        lexical_fallback: false,
+        ..options
+    };
+    let result = search::search_index(
+        &index,
+        FakeEmbedding,
+        "FAIL parse",
+        empty_dir.path().to_str().unwrap(),
+        &options,
+    )
+    .await;
+    assert!(matches!(
+        result,
+        Err(search::SearchError::EmbeddingError(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_lexical_fallback_finds_words_in_the_content() {
+    let mut retry = create_test_chunk("/home/teammate/app/src/net.rs", 10, 14, 1, 1);
+    retry.content =
+        Some("fn retry_with_backoff(delay: Duration) {\n    sleep(delay);\n}".to_string());
+    let mut render = create_test_chunk("/home/teammate/app/src/backoff.rs", 1, 1, 2, 2);
+    render.content = Some("fn render() {}".to_string());
+    let index = MemoryIndex::with_chunks("tg_teammate", vec![render, retry]);
+
+    // The word is only in net.rs's code, not in its path
+    let empty_dir = tempfile::tempdir().unwrap();
+    let options = SearchOptions {
+        namespace: Some("tg_teammate".to_string()),
+        ..Default::default()
+    };
+    let output = search::search_index(
+        &index,
+        FakeEmbedding,
+        "FAIL sleep",
+        empty_dir.path().to_str().unwrap(),
+        &options,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        output.results,
+        "/home/teammate/app/src/net.rs:10:fn retry_with_backoff(delay: Duration) {"
+    );
+}
+
+#[tokio::test]
+async fn test_huge_max_count_makes_a_bounded_request() {
+    let chunks: Vec<Chunk> = (0..search::DEFAULT_MAX_TOP_K as u32 + 100)
+        .map(|line| {
+            let mut chunk =
+                create_test_chunk("/repo/src/big.rs", line + 1, line + 1, 1, line as u64);
+            chunk.content = Some(format!("fn f{line}() {{}}"));
+            chunk.vector = Some(vec![0.5; 8]);
+            chunk
+        })
+        .collect();
+    let index = MemoryIndex::with_chunks("tg_big", chunks);
+
+    let empty_dir = tempfile::tempdir().unwrap();
+    let options = SearchOptions {
+        namespace: Some("tg_big".to_string()),
+        max_count: 1_000_000,
+        ..Default::default()
+    };
+    let output = search::search_index(
+        &index,
+        FakeEmbedding,
+        "functions",
+        empty_dir.path().to_str().unwrap(),
+        &options,
+    )
+    .await
+    .unwrap();
+    assert_eq!(output.results.lines().count(), search::DEFAULT_MAX_TOP_K);
+}
+
+#[tokio::test]
+async fn test_each_embedding_model_ranks_by_its_own_vectors() {
+    use turbogrep::embeddings::VOYAGE_MODEL;
+    use turbogrep::turbopuffer::{ann_rank_by, vector_column};
+
+    // Each chunk is nearest to the query under a different model
+    let column = vector_column("voyage-3.5");
+    let mut first = create_test_chunk("src/a.rs", 1, 5, 1, 1);
+    first.vector = Some(vec![1.0, 0.0]);
+    first.extra_vectors.insert(column.clone(), vec![0.0, 1.0]);
+    let mut second = create_test_chunk("src/b.rs", 1, 5, 2, 2);
+    second.vector = Some(vec![0.0, 1.0]);
+    second.extra_vectors.insert(column.clone(), vec![1.0, 0.0]);
+
+    let index = MemoryIndex::default();
+    index
+        .write_chunks(
+            "tg_models",
+            futures::stream::iter(vec![first, second]),
+            None,
+            &WriteOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    let nearest = |results: Vec<Chunk>| results[0].path.clone();
+    let default_model = index
+        .query_chunks(
+            "tg_models",
+            ann_rank_by(VOYAGE_MODEL, vec![1.0, 0.0]),
+            2,
+            None,
+            QueryOptions::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(nearest(default_model), "src/a.rs");
+    let extra_model = index
+        .query_chunks(
+            "tg_models",
+            ann_rank_by("voyage-3.5", vec![1.0, 0.0]),
+            2,
+            None,
+            QueryOptions::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(nearest(extra_model), "src/b.rs");
+}
+
+#[tokio::test]
+async fn test_profile_trace_has_events_for_each_phase() {
+    turbogrep::profile::enable();
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+    std::fs::write(root.join("lib.rs"), "fn parse() {}\n").unwrap();
+    std::fs::write(root.join("tool.py"), "def run(): pass\n").unwrap();
+
+    let chunks = turbogrep::chunker::chunk_files(root.to_str().unwrap()).unwrap();
+    let index = MemoryIndex::default();
+    apply_diff(&index, "tg_profile", chunks, vec![], false)
+        .await
+        .unwrap();
+
+    let trace_path = root.join("trace.json");
+    turbogrep::profile::write_trace(&trace_path).unwrap();
+    let trace: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&trace_path).unwrap()).unwrap();
+    let events = trace["traceEvents"].as_array().unwrap();
+    for event in events {
+        assert_eq!(event["ph"], "X");
+        assert!(event["name"].is_string());
+        for field in ["ts", "dur", "pid", "tid"] {
+            assert!(event[field].is_u64(), "{field} of {event}");
+        }
+    }
+
+    let names: Vec<&str> = events.iter().filter_map(|e| e["name"].as_str()).collect();
+    let categories: Vec<&str> = events.iter().filter_map(|e| e["cat"].as_str()).collect();
+    for category in ["walk", "chunk", "embed", "sync"] {
+        assert!(
+            categories.contains(&category),
+            "no {category} event in {names:?}"
+        );
+    }
+    assert!(names.contains(&"chunk rust"), "{names:?}");
+    assert!(names.contains(&"chunk python"), "{names:?}");
 }